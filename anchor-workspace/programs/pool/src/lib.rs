@@ -32,6 +32,10 @@ impl Pool {
     fn is_expired(&self, now: i64) -> bool {
         now > self.genesis + self.lockup_duration
     }
+
+    fn is_funded(&self) -> bool {
+        self.deposited_reward_amount != 0
+    }
 }
 
 #[account]
@@ -188,7 +192,11 @@ pub mod pool {
         let ticket = &mut ctx.accounts.ticket;
         let stake_vault = &mut ctx.accounts.stake_vault;
 
-        require!(pool.can_topup(now), PoolIsLocked);
+        // if the reward never arrived, stakers take their principal back once the pool expires
+        require!(
+            pool.can_topup(now) || (pool.is_expired(now) && !pool.is_funded()),
+            PoolIsLocked
+        );
 
         let transfer_amount = std::cmp::min(amount, ticket.staked_amount);
 
@@ -240,12 +248,13 @@ pub mod pool {
 
         use fixed::types::U64F64;
 
+        // an underfunded reward is shared pro rata, principal is never touched
         let staked_amount = U64F64::from_num(ticket.staked_amount);
         let stake_acquired_amount = U64F64::from_num(pool.stake_acquired_amount);
-        let reward_amount = U64F64::from_num(pool.reward_amount);
+        let deposited_reward_amount = U64F64::from_num(pool.deposited_reward_amount);
 
         let share = staked_amount / stake_acquired_amount;
-        let reward_share = share * reward_amount;
+        let reward_share = share * deposited_reward_amount;
 
         let transfer_amount = (staked_amount + reward_share)
             .checked_as::<u64>()
@@ -0,0 +1,342 @@
+use std::{fmt, str::FromStr};
+
+use parity_scale_codec::Encode;
+use solana_api_types::{system, AccountMeta, Instruction, Pubkey, Transaction};
+use solar::{input::MAX_ACCOUNTS, spl, time::SolTimestamp};
+
+use crate::{data::AccountType, find_program_authority, Method, TokenAmount, TokenLock};
+
+/// Token program, source wallet and source authority.
+const BATCH_FIXED_ACCOUNTS: usize = 3;
+
+/// Compute budget of a single instruction on the pinned runtime.
+const MAX_COMPUTE_UNITS: usize = 200_000;
+
+/// Decoding entries and loading the fixed accounts.
+const BATCH_BASE_COMPUTE_UNITS: usize = 10_000;
+
+/// Cost of a single entry: program authority derivation (1_500), token transfer
+/// CPI (~4_000 with the invoke cost), locker initialization and `LockCreated` event
+/// (~3_000), rounded up to leave some headroom.
+const COMPUTE_UNITS_PER_ENTRY: usize = 12_000;
+
+/// Every entry takes a locker and a vault account.
+const MAX_BATCH_ACCOUNTS_SIZE: usize = (MAX_ACCOUNTS - BATCH_FIXED_ACCOUNTS) / 2;
+
+const MAX_BATCH_COMPUTE_SIZE: usize =
+    (MAX_COMPUTE_UNITS - BATCH_BASE_COMPUTE_UNITS) / COMPUTE_UNITS_PER_ENTRY;
+
+/// The batch is limited both by the number of accounts the program is able to load
+/// and by the compute budget.
+pub const MAX_BATCH_SIZE: usize = if MAX_BATCH_ACCOUNTS_SIZE < MAX_BATCH_COMPUTE_SIZE {
+    MAX_BATCH_ACCOUNTS_SIZE
+} else {
+    MAX_BATCH_COMPUTE_SIZE
+};
+
+/// Maximum size of a serialized transaction.
+pub const PACKET_DATA_SIZE: usize = 1280 - 40 - 8;
+
+#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
+pub struct LockEntry {
+    pub owner: Pubkey,
+    pub amount: TokenAmount,
+    pub unlock_date: SolTimestamp,
+    pub program_authority_salt: u64,
+}
+
+/// Single `owner,amount,unlock_date` row of a distribution list.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Recipient {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub unlock_date: i64,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ParseRecipientsError {
+    pub line: usize,
+    pub reason: &'static str,
+}
+
+impl fmt::Display for ParseRecipientsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+impl std::error::Error for ParseRecipientsError {}
+
+/// Parses a CSV list of `owner,amount,unlock_date` rows.
+///
+/// Empty lines, `#` comments and an optional `owner,...` header are skipped.
+pub fn parse_recipients(csv: &str) -> Result<Vec<Recipient>, ParseRecipientsError> {
+    let mut recipients = vec![];
+
+    for (index, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || (index == 0 && line.starts_with("owner")) {
+            continue;
+        }
+
+        let error = |reason| ParseRecipientsError {
+            line: index + 1,
+            reason,
+        };
+
+        let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+        if fields.len() != 3 {
+            return Err(error("expected `owner,amount,unlock_date`"));
+        }
+
+        let owner = Pubkey::from_str(fields[0]).map_err(|_| error("invalid owner"))?;
+        let amount = fields[1]
+            .parse::<u64>()
+            .map_err(|_| error("invalid amount"))?;
+        let unlock_date = fields[2]
+            .parse::<i64>()
+            .map_err(|_| error("invalid unlock date"))?;
+
+        if amount == 0 {
+            return Err(error("amount should be positive"));
+        }
+
+        recipients.push(Recipient {
+            owner,
+            amount,
+            unlock_date,
+        });
+    }
+
+    Ok(recipients)
+}
+
+/// Builds `CreateLockBatch` instruction.
+///
+/// `lockers` are `(locker, vault)` pairs in the same order as `recipients`,
+/// vaults are expected to be owned by the program authority of the matching locker.
+pub fn create_lock_batch_instruction(
+    program_id: &Pubkey,
+    source_wallet: &Pubkey,
+    source_authority: &Pubkey,
+    recipients: &[Recipient],
+    lockers: &[(Pubkey, Pubkey)],
+) -> Instruction {
+    assert_eq!(recipients.len(), lockers.len());
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*solar::spl::ID, false),
+        AccountMeta::new(*source_wallet, false),
+        AccountMeta::new_readonly(*source_authority, true),
+    ];
+
+    let mut entries = vec![];
+    for (recipient, (locker, vault)) in recipients.iter().zip(lockers) {
+        let (_, program_authority_salt) = find_program_authority(program_id, locker);
+
+        accounts.push(AccountMeta::new(*locker, false));
+        accounts.push(AccountMeta::new(*vault, false));
+
+        entries.push(LockEntry {
+            owner: recipient.owner,
+            amount: recipient.amount.into(),
+            unlock_date: recipient.unlock_date.into(),
+            program_authority_salt,
+        });
+    }
+
+    Instruction {
+        program_id: *program_id,
+        accounts,
+        data: Method::CreateLockBatch { entries }.encode(),
+    }
+}
+
+/// Builds instructions creating `(locker, vault)` pairs expected by `CreateLockBatch`.
+///
+/// Payer funds the new accounts, lockers and vaults are expected to sign.
+pub fn create_batch_accounts_instructions(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    mint: &Pubkey,
+    lockers: &[(Pubkey, Pubkey)],
+) -> Vec<Instruction> {
+    let mut instructions = vec![];
+
+    for (locker, vault) in lockers {
+        let (program_authority, _) = find_program_authority(program_id, locker);
+
+        instructions.extend(spl::create_wallet(payer, vault, mint, &program_authority));
+        instructions.push(system::create_account(
+            payer,
+            locker,
+            TokenLock::default_lamports(),
+            TokenLock::default_size() as u64,
+            program_id,
+        ));
+    }
+
+    instructions
+}
+
+/// Distinct keys standing in for real accounts while measuring transactions.
+fn placeholder_key(tag: u8, index: usize) -> Pubkey {
+    let mut key = [tag; 32];
+    key[..8].copy_from_slice(&(index as u64).to_le_bytes());
+    Pubkey::new(key)
+}
+
+fn placeholder_lockers(size: usize) -> Vec<(Pubkey, Pubkey)> {
+    (0..size)
+        .map(|i| (placeholder_key(0xfc, i), placeholder_key(0xfb, i)))
+        .collect()
+}
+
+fn fits_into_transaction(instructions: &[Instruction], payer: &Pubkey) -> bool {
+    let transaction = Transaction::new_with_payer(instructions, Some(payer));
+
+    // signatures are prefixed with a single byte length
+    let length = 1 + transaction.signatures.len() * 64 + transaction.message_data().len();
+    length <= PACKET_DATA_SIZE
+}
+
+/// Largest number of entries that fits into a single transaction.
+pub fn max_entries_per_transaction() -> usize {
+    let program_id = placeholder_key(0xff, 0);
+    let source_wallet = placeholder_key(0xfe, 0);
+    let source_authority = placeholder_key(0xfd, 0);
+
+    (1..=MAX_BATCH_SIZE)
+        .rev()
+        .find(|&size| {
+            let recipients = (0..size)
+                .map(|i| Recipient {
+                    owner: placeholder_key(0xfa, i),
+                    amount: u64::MAX,
+                    unlock_date: i64::MAX,
+                })
+                .collect::<Vec<_>>();
+
+            let instruction = create_lock_batch_instruction(
+                &program_id,
+                &source_wallet,
+                &source_authority,
+                &recipients,
+                &placeholder_lockers(size),
+            );
+            fits_into_transaction(&[instruction], &source_authority)
+        })
+        .unwrap_or(1)
+}
+
+/// Largest number of `(locker, vault)` pairs that can be created in a single transaction.
+pub fn max_accounts_per_transaction() -> usize {
+    let program_id = placeholder_key(0xff, 0);
+    let payer = placeholder_key(0xfd, 0);
+    let mint = placeholder_key(0xf9, 0);
+
+    (1..=MAX_BATCH_SIZE)
+        .rev()
+        .find(|&size| {
+            let instructions = create_batch_accounts_instructions(
+                &program_id,
+                &payer,
+                &mint,
+                &placeholder_lockers(size),
+            );
+            fits_into_transaction(&instructions, &payer)
+        })
+        .unwrap_or(1)
+}
+
+/// Number of entries per `CreateLockBatch` transaction.
+///
+/// Accounts of a chunk are created by separate transactions beforehand, so the chunk is
+/// rounded down to fill all of them.
+pub fn chunk_size() -> usize {
+    let per_batch = max_entries_per_transaction();
+    let per_setup = max_accounts_per_transaction();
+
+    if per_setup >= per_batch {
+        per_batch
+    } else {
+        per_batch - per_batch % per_setup
+    }
+}
+
+/// Splits recipients into `CreateLockBatch` chunks, so that distribution takes
+/// the smallest number of transactions including the ones creating accounts.
+pub fn chunk_recipients(recipients: &[Recipient]) -> Vec<&[Recipient]> {
+    recipients.chunks(chunk_size()).collect()
+}
+
+/// Number of transactions distribution to `recipients` takes.
+pub fn transaction_count(recipients: &[Recipient]) -> usize {
+    let per_setup = max_accounts_per_transaction();
+
+    chunk_recipients(recipients)
+        .iter()
+        .map(|chunk| 1 + (chunk.len() + per_setup - 1) / per_setup)
+        .sum()
+}
+
+#[cfg(test)]
+mod test {
+    use solana_api_types::Pubkey;
+
+    use super::{
+        chunk_recipients, chunk_size, max_accounts_per_transaction, max_entries_per_transaction,
+        parse_recipients, transaction_count, ParseRecipientsError, MAX_BATCH_SIZE,
+    };
+
+    #[test]
+    fn parse_csv() {
+        let owner = Pubkey::new_unique();
+        let csv = format!(
+            "owner,amount,unlock_date\n\n# team\n{0},100,1700000000\n{0}, 5 , 1800000000\n",
+            owner
+        );
+
+        let recipients = parse_recipients(&csv).unwrap();
+        assert_eq!(recipients.len(), 2);
+        assert_eq!(recipients[0].owner, owner);
+        assert_eq!(recipients[1].amount, 5);
+        assert_eq!(recipients[1].unlock_date, 1_800_000_000);
+
+        let csv = format!("{},0,1700000000", owner);
+        assert_eq!(
+            parse_recipients(&csv),
+            Err(ParseRecipientsError {
+                line: 1,
+                reason: "amount should be positive"
+            })
+        );
+        assert!(parse_recipients("invalid,1,1").is_err());
+    }
+
+    #[test]
+    fn chunk_csv() {
+        let per_transaction = max_entries_per_transaction();
+        assert!(per_transaction > 1 && per_transaction <= MAX_BATCH_SIZE);
+
+        let per_setup = max_accounts_per_transaction();
+        let size = chunk_size();
+        assert!(size <= per_transaction);
+        assert!(size % per_setup == 0 || size == per_transaction);
+
+        let csv = (0..size * 2 + 1)
+            .map(|_| format!("{},1,1700000000\n", Pubkey::new_unique()))
+            .collect::<String>();
+        let recipients = parse_recipients(&csv).unwrap();
+
+        let chunks = chunk_recipients(&recipients);
+        assert_eq!(chunks.len(), 3);
+        assert_eq!(chunks[2].len(), 1);
+
+        let setup_per_chunk = (size + per_setup - 1) / per_setup;
+        assert_eq!(
+            transaction_count(&recipients),
+            2 * (1 + setup_per_chunk) + 2
+        );
+    }
+}
@@ -78,6 +78,9 @@ pub enum EntityKind {
     Vesting = 0x11,
 }
 
+/// Lockers and vestings are standalone accounts without a parent entity, so unlike
+/// the stake pool header, there is no root or parent id to record. Both are initialized
+/// with the id allocated from their own allocator and their kind.
 #[repr(C)]
 pub struct EntityHeader {
     pub id: EntityId,
@@ -100,9 +103,18 @@ where
     T: AccountType,
 {
     pub(crate) fn raw_any(program_id: &Pubkey, account: B) -> Result<Self, Error> {
-        let size = account.data().len();
+        let entity = Self::raw_header(program_id, account)?;
 
-        if size < HEADER_RESERVED || !T::is_valid_size(size - HEADER_RESERVED) {
+        if !T::is_valid_size(entity.account.data().len() - HEADER_RESERVED) {
+            return Err(Error::InvalidData);
+        }
+
+        Ok(entity)
+    }
+
+    /// Validates the account up to its header, leaving the size of `T` unchecked.
+    fn raw_header(program_id: &Pubkey, account: B) -> Result<Self, Error> {
+        if account.data().len() < HEADER_RESERVED {
             return Err(Error::InvalidData);
         }
 
@@ -140,12 +152,17 @@ where
     /// invariants required by `T`, so callers are required to ensure that this account is actually
     /// an instance of account type `T` before returning it elsewhere.
     pub(crate) fn raw_initialized(program_id: &Pubkey, account: B) -> Result<Self, Error> {
-        let entity = Self::raw_any(program_id, account)?;
+        let entity = Self::raw_header(program_id, account)?;
 
+        // kind is checked ahead of the size, so that an entity of another kind is reported as such
         if entity.header().kind != T::KIND {
             return Err(Error::InvalidKind);
         }
 
+        if !T::is_valid_size(entity.account.data().len() - HEADER_RESERVED) {
+            return Err(Error::InvalidData);
+        }
+
         Ok(entity)
     }
 
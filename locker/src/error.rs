@@ -1,11 +1,23 @@
-use solana_api_types::program::ProgramError;
+use std::convert::TryFrom;
+
+use solana_api_types::{program::ProgramError, InstructionError, TransactionError};
 use solar::spl::{SplReadError, TokenError};
 
-#[derive(Debug)]
+/// Number of bits reserved for the top-level error kind in a custom error code.
+///
+/// Nested errors (such as [`SplReadError`] and [`TokenError`]) store their own code
+/// in the bits above, so that `code = kind | (sub_code << ERROR_KIND_BITS)`.
+pub const ERROR_KIND_BITS: u32 = 8;
+const ERROR_KIND_MASK: u32 = (1 << ERROR_KIND_BITS) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
     InvalidData,
+    /// Not reachable on-chain, the loader always aligns account data.
     InvalidAlignment,
     InvalidOwner,
+    /// Not returned by the locker, entities have no parents. The code is kept so that
+    /// the following ones stay stable.
     InvalidParent,
     InvalidKind,
     InvalidAuthority,
@@ -18,7 +30,7 @@ pub enum Error {
 }
 
 impl Error {
-    fn code(&self) -> u32 {
+    pub fn kind_code(&self) -> u32 {
         match self {
             Error::InvalidData => 1,
             Error::InvalidAlignment => 2,
@@ -34,6 +46,54 @@ impl Error {
             Error::TokenError(_) => 12,
         }
     }
+
+    pub fn code(&self) -> u32 {
+        let sub_code = match self {
+            Error::SplReadError(error) => error.code(),
+            Error::TokenError(error) => error.code(),
+            _ => 0,
+        };
+
+        self.kind_code() | (sub_code << ERROR_KIND_BITS)
+    }
+
+    /// Decodes a custom error code produced by [`Error::code`].
+    pub fn from_code(code: u32) -> Option<Self> {
+        let sub_code = code >> ERROR_KIND_BITS;
+
+        let error = match code & ERROR_KIND_MASK {
+            1 => Error::InvalidData,
+            2 => Error::InvalidAlignment,
+            3 => Error::InvalidOwner,
+            4 => Error::InvalidParent,
+            5 => Error::InvalidKind,
+            6 => Error::InvalidAuthority,
+            7 => Error::InvalidMint,
+            8 => Error::InvalidAccount,
+            9 => Error::NotRentExempt,
+            10 => Error::Validation,
+            11 => return SplReadError::from_code(sub_code).map(Error::SplReadError),
+            12 => return TokenError::from_code(sub_code).map(Error::TokenError),
+            _ => return None,
+        };
+
+        if sub_code != 0 {
+            return None;
+        }
+
+        Some(error)
+    }
+
+    /// Extracts the locker error from a failed transaction, if the failure was caused
+    /// by a custom error code returned from the program.
+    pub fn from_transaction_error(error: &TransactionError) -> Option<Self> {
+        match error {
+            TransactionError::InstructionError(_, InstructionError::Custom(code)) => {
+                Self::from_code(*code)
+            }
+            _ => None,
+        }
+    }
 }
 
 impl From<SplReadError> for Error {
@@ -53,3 +113,85 @@ impl From<Error> for ProgramError {
         Self::Custom(e.code())
     }
 }
+
+impl TryFrom<ProgramError> for Error {
+    type Error = ProgramError;
+
+    fn try_from(error: ProgramError) -> Result<Self, Self::Error> {
+        match error {
+            ProgramError::Custom(code) => Self::from_code(code).ok_or(error),
+            error => Err(error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::convert::TryFrom;
+
+    use solana_api_types::{program::ProgramError, InstructionError, TransactionError};
+    use solar::spl::{SplReadError, TokenError};
+
+    use super::Error;
+
+    #[test]
+    fn code_roundtrip() {
+        let errors = [
+            Error::InvalidData,
+            Error::InvalidAlignment,
+            Error::InvalidOwner,
+            Error::InvalidParent,
+            Error::InvalidKind,
+            Error::InvalidAuthority,
+            Error::InvalidMint,
+            Error::InvalidAccount,
+            Error::NotRentExempt,
+            Error::Validation,
+            Error::SplReadError(SplReadError::InvalidData),
+            Error::SplReadError(SplReadError::InvalidOwner),
+            Error::SplReadError(SplReadError::InvalidMint),
+            Error::TokenError(TokenError::NotRentExempt),
+            Error::TokenError(TokenError::OwnerMismatch),
+            Error::TokenError(TokenError::Unknown),
+        ];
+
+        for error in errors.iter() {
+            assert_eq!(Error::from_code(error.code()), Some(*error));
+            assert_eq!(
+                Error::try_from(ProgramError::from(*error)),
+                Ok(*error),
+                "{:?}",
+                error
+            );
+        }
+
+        assert_eq!(
+            Error::SplReadError(SplReadError::InvalidOwner).code(),
+            11 | (1 << 8)
+        );
+        assert_eq!(
+            Error::TokenError(TokenError::OwnerMismatch).code(),
+            12 | (4 << 8)
+        );
+    }
+
+    #[test]
+    fn decode_unknown_codes() {
+        assert_eq!(Error::from_code(0), None);
+        assert_eq!(Error::from_code(13), None);
+        assert_eq!(Error::from_code(10 | (1 << 8)), None);
+        assert_eq!(Error::from_code(11 | (3 << 8)), None);
+        assert_eq!(Error::from_code(12 | (20 << 8)), None);
+        assert_eq!(
+            Error::from_transaction_error(&TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(Error::Validation.code())
+            )),
+            Some(Error::Validation)
+        );
+        assert_eq!(
+            Error::from_transaction_error(&TransactionError::AccountInUse),
+            None
+        );
+    }
+}
@@ -0,0 +1,21 @@
+//! Events emitted by the locker program through [`solar::log::emit`].
+
+use solana_api_types::Pubkey;
+use solar::time::SolTimestamp;
+
+use crate::TokenAmount;
+
+#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
+pub enum Event {
+    LockCreated {
+        locker: Pubkey,
+        owner: Pubkey,
+        mint: Pubkey,
+        amount: TokenAmount,
+        unlock_date: SolTimestamp,
+    },
+    Withdrawn {
+        locker: Pubkey,
+        amount: TokenAmount,
+    },
+}
@@ -1,16 +1,16 @@
 use std::mem::size_of;
 
 use fixed::types::U64F64;
-use parity_scale_codec::Decode;
-use std::convert::TryFrom;
+use parity_scale_codec::{Decode, Encode};
 
-use solana_api_types::{program::ProgramError, Instruction, Pubkey};
+use solana_api_types::{program::ProgramError, system, AccountMeta, Instruction, Pubkey};
 
 #[cfg(feature = "onchain")]
-use az::CheckedAs;
-
-#[cfg(feature = "onchain")]
-use solar::input::{account::onchain::Account, BpfProgramInput};
+use solar::{
+    account::onchain::Account,
+    input::{BpfProgramInput, Entrypoint, ProgramInput},
+    log::emit,
+};
 
 use solar::{
     account::{AccountFields, AccountFieldsMut},
@@ -18,13 +18,17 @@ use solar::{
     math::Checked,
     prelude::AccountBackend,
     qlog,
-    spl::{TokenProgram, WalletAccount},
+    reinterpret::as_bytes,
+    spl::{self, MintAccount, TokenProgram, WalletAccount},
     time::SolTimestamp,
-    util::{pubkey_eq, timestamp_now, ResultExt},
+    util::{is_zeroed, pubkey_eq, timestamp_now, ResultExt},
 };
 
+pub mod batch;
 pub mod data;
 pub mod error;
+pub mod event;
+pub mod vesting;
 
 #[macro_use]
 extern crate parity_scale_codec;
@@ -32,9 +36,14 @@ extern crate parity_scale_codec;
 #[macro_use]
 extern crate solar_macros;
 
+#[cfg(feature = "onchain")]
+use crate::event::Event;
 use crate::{
+    batch::{LockEntry, MAX_BATCH_SIZE},
     data::{AccountType, Entity, EntityAllocator, EntityKind, HEADER_RESERVED},
     error::Error,
+    impl_entity_simple_deref,
+    vesting::{CreateVestingArgs, VestingEntity},
 };
 
 pub type TokenAmount = Checked<u64>;
@@ -47,6 +56,9 @@ pub enum Method {
     CreateLock {
         unlock_date: SolTimestamp,
         amount: TokenAmount,
+        program_authority_salt: u64,
+        revoke_authority: Option<Pubkey>,
+        with_receipt: bool,
     },
     ReLock {
         unlock_date: SolTimestamp,
@@ -59,10 +71,19 @@ pub enum Method {
     },
     Split {
         amount: TokenAmount,
+        program_authority_salt: u64,
     },
     ChangeOwner {
-        amount: TokenAmount,
+        new_owner: Pubkey,
     },
+    CreateVesting(CreateVestingArgs),
+    Claim,
+    Revoke,
+    CreateLockBatch {
+        entries: Vec<LockEntry>,
+    },
+    Merge,
+    RevokeVesting,
 }
 
 #[repr(C)]
@@ -71,7 +92,13 @@ pub struct TokenLockState {
     pub mint: Pubkey,
     pub vault: Pubkey,
     pub program_authority: Pubkey,
+    pub program_authority_salt: u64,
     pub release_date: SolTimestamp,
+    /// Zeroed for locks that can't be revoked, otherwise able to send the locked funds
+    /// to a wallet of its choice before the release date.
+    pub revoke_authority: Pubkey,
+    /// One-of-one token held by the locker owner, zeroed if owner is not tokenized.
+    pub receipt_mint: Pubkey,
 
     pub allocator: EntityAllocator,
 }
@@ -79,8 +106,6 @@ pub struct TokenLockState {
 #[derive(Debug)]
 pub struct TokenLock;
 
-pub type TokenlockEntity<B> = Entity<B, TokenLock>;
-
 impl AccountType for TokenLock {
     const KIND: EntityKind = EntityKind::Locker;
 
@@ -97,33 +122,91 @@ impl_entity_simple_deref!(TokenLock, TokenLockState);
 
 #[derive(Debug)]
 pub struct CreateArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+
     pub locker: B, //(empty, uninitialized)
     pub source_spl_token_wallet: WalletAccount<B>,
     pub source_authority: B,                      //(signed)
     pub spl_token_wallet_vault: WalletAccount<B>, //(authority = program authority)
     pub program_authority: B,
     pub owner_authority: B, //withdraw authority
+    pub receipt: Option<ReceiptAccounts<B>>,
+}
+
+#[derive(Debug)]
+pub struct ReceiptAccounts<B: AccountBackend> {
+    pub mint: MintAccount<B>,
+    pub wallet: WalletAccount<B>,
 }
 
 impl<B: AccountBackend> CreateArgsAccounts<B> {
     #[inline]
-    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+    pub fn from_program_input<T: AccountSource<B>>(
+        input: &mut T,
+        with_receipt: bool,
+    ) -> Result<Self, Error> {
         parse_accounts! {
+            &token_program = TokenProgram::load(this)?,
+
             &mut locker,
-            &source_spl_token_wallet,
+            &mut source_spl_token_wallet = WalletAccount::any(this)?,
             &source_authority,
-            &spl_token_wallet_vault,
+            &mut spl_token_wallet_vault = WalletAccount::any(this)?,
             &program_authority,
             &owner_authority,
         }
 
+        let receipt = if with_receipt {
+            parse_accounts! {
+                &mut receipt_mint = MintAccount::any(this)?,
+                &mut receipt_wallet = receipt_mint.wallet(this)?,
+            }
+
+            Some(ReceiptAccounts {
+                mint: receipt_mint,
+                wallet: receipt_wallet,
+            })
+        } else {
+            None
+        };
+
         Ok(Self {
+            token_program,
+
             locker,
-            source_spl_token_wallet: WalletAccount::any(source_spl_token_wallet)?,
+            source_spl_token_wallet,
             source_authority,
-            spl_token_wallet_vault: WalletAccount::any(spl_token_wallet_vault)?,
+            spl_token_wallet_vault,
             program_authority,
             owner_authority,
+            receipt,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct CreateBatchArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+
+    pub source_spl_token_wallet: WalletAccount<B>,
+    pub source_authority: B, //(signed)
+}
+
+impl<B: AccountBackend> CreateBatchArgsAccounts<B> {
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        parse_accounts! {
+            &token_program = TokenProgram::load(this)?,
+
+            &mut source_spl_token_wallet = WalletAccount::any(this)?,
+            &source_authority,
+        }
+
+        Ok(Self {
+            token_program,
+
+            source_spl_token_wallet,
+            source_authority,
         })
     }
 }
@@ -132,6 +215,7 @@ impl<B: AccountBackend> CreateArgsAccounts<B> {
 pub struct ReLockArgsAccounts<B: AccountBackend> {
     pub locker: Entity<B, TokenLock>,
     pub owner_authority: B, //withdraw authority
+    pub receipt_wallet: Option<WalletAccount<B>>,
 }
 
 impl<B: AccountBackend> ReLockArgsAccounts<B> {
@@ -140,13 +224,24 @@ impl<B: AccountBackend> ReLockArgsAccounts<B> {
         let program_id = *input.program_id();
 
         parse_accounts! {
-            &mut locker = <Entity<B, TokenLock>>::raw_any(&program_id, this)?,
+            &mut locker = <Entity<B, TokenLock>>::load(&program_id, this)?,
             &owner_authority,
         }
 
+        let receipt_wallet = if locker.has_receipt() {
+            parse_accounts! {
+                &receipt_wallet = locker.receipt_wallet(this)?,
+            }
+
+            Some(receipt_wallet)
+        } else {
+            None
+        };
+
         Ok(Self {
             locker,
             owner_authority,
+            receipt_wallet,
         })
     }
 }
@@ -157,9 +252,10 @@ pub struct WithdrawArgsAccounts<B: AccountBackend> {
 
     pub locker: Entity<B, TokenLock>,
     pub spl_token_wallet_vault: WalletAccount<B>,
-    pub destination_spl_token_wallet: B,
+    pub destination_spl_token_wallet: WalletAccount<B>,
     pub program_authority: B,
     pub owner_authority: B,
+    pub receipt: Option<ReceiptAccounts<B>>,
 }
 
 impl<B: AccountBackend> WithdrawArgsAccounts<B> {
@@ -170,21 +266,36 @@ impl<B: AccountBackend> WithdrawArgsAccounts<B> {
         parse_accounts! {
             &token_program = TokenProgram::load(this)?,
 
-            &mut locker = <Entity<B, TokenLock>>::raw_any(&program_id, this)?,
-            &spl_token_wallet_vault,
-            &destination_spl_token_wallet,
-            &program_authority,
+            &locker = <Entity<B, TokenLock>>::load(&program_id, this)?,
+            &mut spl_token_wallet_vault = locker.vault(this)?,
+            &mut destination_spl_token_wallet = locker.wallet(this)?,
+            &program_authority = locker.program_authority(this)?,
             &owner_authority,
         }
 
+        let receipt = if locker.has_receipt() {
+            parse_accounts! {
+                &mut receipt_mint = locker.receipt_mint(this)?,
+                &mut receipt_wallet = receipt_mint.wallet(this)?,
+            }
+
+            Some(ReceiptAccounts {
+                mint: receipt_mint,
+                wallet: receipt_wallet,
+            })
+        } else {
+            None
+        };
+
         Ok(Self {
             token_program,
 
             locker,
-            spl_token_wallet_vault: WalletAccount::any(spl_token_wallet_vault)?,
+            spl_token_wallet_vault,
             destination_spl_token_wallet,
             program_authority,
             owner_authority,
+            receipt,
         })
     }
 }
@@ -195,7 +306,7 @@ pub struct IncrementArgsAccounts<B: AccountBackend> {
 
     pub locker: Entity<B, TokenLock>,
     pub spl_token_wallet_vault: WalletAccount<B>,
-    pub source_spl_token_wallet: B,
+    pub source_spl_token_wallet: WalletAccount<B>,
     pub source_authority: B,
 }
 
@@ -207,16 +318,16 @@ impl<B: AccountBackend> IncrementArgsAccounts<B> {
         parse_accounts! {
             &token_program = TokenProgram::load(this)?,
 
-            &locker = <Entity<B, TokenLock>>::raw_any(&program_id, this)?,
-            &spl_token_wallet_vault,
-            &source_spl_token_wallet,
+            &locker = <Entity<B, TokenLock>>::load(&program_id, this)?,
+            &mut spl_token_wallet_vault = locker.vault(this)?,
+            &mut source_spl_token_wallet = locker.wallet(this)?,
             &source_authority,
         }
 
         Ok(Self {
             token_program,
             locker,
-            spl_token_wallet_vault: WalletAccount::any(spl_token_wallet_vault)?,
+            spl_token_wallet_vault,
             source_spl_token_wallet,
             source_authority,
         })
@@ -229,8 +340,10 @@ pub struct SplitArgsAccounts<B: AccountBackend> {
 
     pub source_locker: Entity<B, TokenLock>,
     pub new_locker: B, //(empty, uninitialized)
+    pub program_authority: B,
     pub source_spl_token_wallet_vault: WalletAccount<B>,
     pub new_spl_token_wallet_vault: WalletAccount<B>,
+    pub owner_authority: B,
 }
 
 impl<B: AccountBackend> SplitArgsAccounts<B> {
@@ -240,10 +353,12 @@ impl<B: AccountBackend> SplitArgsAccounts<B> {
         parse_accounts! {
             &token_program = TokenProgram::load(this)?,
 
-            &source_locker = <Entity<B, TokenLock>>::raw_any(&program_id, this)?,
+            &source_locker = <Entity<B, TokenLock>>::load(&program_id, this)?,
             &mut new_locker,
-            &source_spl_token_wallet_vault,
-            &new_spl_token_wallet_vault,
+            &program_authority = source_locker.program_authority(this)?,
+            &mut source_spl_token_wallet_vault = source_locker.vault(this)?,
+            &mut new_spl_token_wallet_vault = source_locker.wallet(this)?,
+            &owner_authority,
         }
 
         Ok(Self {
@@ -251,8 +366,88 @@ impl<B: AccountBackend> SplitArgsAccounts<B> {
 
             source_locker,
             new_locker,
-            source_spl_token_wallet_vault: WalletAccount::any(source_spl_token_wallet_vault)?,
-            new_spl_token_wallet_vault: WalletAccount::any(new_spl_token_wallet_vault)?,
+            program_authority,
+            source_spl_token_wallet_vault,
+            new_spl_token_wallet_vault,
+            owner_authority,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct MergeArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+
+    pub target_locker: Entity<B, TokenLock>,
+    pub source_locker: Entity<B, TokenLock>,
+    pub program_authority: B,
+    pub source_spl_token_wallet_vault: WalletAccount<B>,
+    pub target_spl_token_wallet_vault: WalletAccount<B>,
+    pub owner_authority: B,
+}
+
+impl<B: AccountBackend> MergeArgsAccounts<B> {
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+        parse_accounts! {
+            &token_program = TokenProgram::load(this)?,
+
+            &mut target_locker = <Entity<B, TokenLock>>::load(&program_id, this)?,
+            &mut source_locker = <Entity<B, TokenLock>>::load(&program_id, this)?,
+            &program_authority = source_locker.program_authority(this)?,
+            &mut source_spl_token_wallet_vault = source_locker.vault(this)?,
+            &mut target_spl_token_wallet_vault = target_locker.vault(this)?,
+            &mut owner_authority,
+        }
+
+        Ok(Self {
+            token_program,
+
+            target_locker,
+            source_locker,
+            program_authority,
+            source_spl_token_wallet_vault,
+            target_spl_token_wallet_vault,
+            owner_authority,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct RevokeArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+
+    pub locker: Entity<B, TokenLock>,
+    pub spl_token_wallet_vault: WalletAccount<B>,
+    pub treasury_spl_token_wallet: WalletAccount<B>,
+    pub program_authority: B,
+    pub revoke_authority: B,
+}
+
+impl<B: AccountBackend> RevokeArgsAccounts<B> {
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts! {
+            &token_program = TokenProgram::load(this)?,
+
+            &mut locker = <Entity<B, TokenLock>>::load(&program_id, this)?,
+            &mut spl_token_wallet_vault = locker.vault(this)?,
+            &mut treasury_spl_token_wallet = locker.wallet(this)?,
+            &program_authority = locker.program_authority(this)?,
+            &revoke_authority,
+        }
+
+        Ok(Self {
+            token_program,
+
+            locker,
+            spl_token_wallet_vault,
+            treasury_spl_token_wallet,
+            program_authority,
+            revoke_authority,
         })
     }
 }
@@ -260,8 +455,7 @@ impl<B: AccountBackend> SplitArgsAccounts<B> {
 #[derive(Debug)]
 pub struct ChangeOwnerArgsAccounts<B: AccountBackend> {
     pub locker: Entity<B, TokenLock>,
-    pub source_owner_authority: B,
-    pub new_owner_authority: B,
+    pub owner_authority: B,
 }
 
 impl<B: AccountBackend> ChangeOwnerArgsAccounts<B> {
@@ -269,15 +463,13 @@ impl<B: AccountBackend> ChangeOwnerArgsAccounts<B> {
     pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
         let program_id = *input.program_id();
         parse_accounts! {
-            &mut locker = <Entity<B, TokenLock>>::raw_any(&program_id, this)?,
-            &source_owner_authority,
-            &new_owner_authority,
+            &mut locker = <Entity<B, TokenLock>>::load(&program_id, this)?,
+            &owner_authority,
         }
 
         Ok(Self {
             locker,
-            source_owner_authority,
-            new_owner_authority,
+            owner_authority,
         })
     }
 }
@@ -289,76 +481,278 @@ where
     /// Create a new locker.
     ///
     /// Account inputs:
+    /// SPL Token Program
     /// Locker (empty, uninitialized)
     /// SPL Token Wallet source
     /// Source Authority (signed)
     /// SPL Token Wallet vault (authority = program authority)
     /// Program Authority
     /// Owner (withdraw authority)
+    /// Receipt Mint (optional, decimals = 0, mint authority = program authority)
+    /// Receipt Wallet (optional)
+    ///
+    /// Locks created without a revoke authority can never be revoked.
+    ///
+    /// If the locker is created with a receipt, a single receipt token is minted and its
+    /// holder is authorized to withdraw and relock instead of the stored owner.
+    #[cfg(feature = "onchain")]
+    #[allow(clippy::too_many_arguments)]
     pub fn create<S>(
         mut input: S,
         unlock_date: SolTimestamp,
         amount: TokenAmount,
+        program_authority_salt: u64,
+        revoke_authority: Option<Pubkey>,
+        with_receipt: bool,
     ) -> Result<(), ProgramError>
     where
         S: AccountSource<B>,
-        B::Impl: AccountFieldsMut,
+        B: AccountBackend<Impl = Account>,
     {
         let CreateArgsAccounts {
+            token_program,
+
             locker,
-            source_spl_token_wallet,
+            mut source_spl_token_wallet,
             source_authority,
-            spl_token_wallet_vault,
+            mut spl_token_wallet_vault,
             program_authority,
             owner_authority,
-        } = CreateArgsAccounts::from_program_input(&mut input)?;
+            receipt,
+        } = CreateArgsAccounts::from_program_input(&mut input, with_receipt)?;
 
-        let mut entity = Self::raw_any(input.program_id(), locker)?;
+        if amount == 0.into() || source_spl_token_wallet.amount() < amount {
+            qlog!("not enough funds in wallet");
+            return Err(Error::Validation.into());
+        }
 
-        // entity.owner = *owner_authority.key();
-        // entity.mint = source_spl_token_wallet.mint();
-        // entity.vault = *spl_token_wallet_vault;
-        // entity.program_authority = *program_authority.key();
-        // entity.release_date = unlock_date;
+        let mut entity = Self::initialize(
+            input.program_id(),
+            locker,
+            &spl_token_wallet_vault,
+            source_spl_token_wallet.mint(),
+            owner_authority.key(),
+            unlock_date,
+            program_authority_salt,
+            revoke_authority,
+        )?;
 
-        // let id = entity.allocator.allocate_id();
-        let entity_key = *entity.account().key();
-        let header = entity.header_mut();
-        header.kind = EntityKind::Locker;
-        // header.id = id;
-        // header.parent_id = id;
-        // header.root = entity_key;
+        if !pubkey_eq(program_authority.key(), &entity.program_authority) {
+            qlog!("provided program authority does not match expected authority");
+            return Err(Error::InvalidAuthority.into());
+        }
+
+        if let Some(ReceiptAccounts {
+            mut mint,
+            mut wallet,
+        }) = receipt
+        {
+            if mint.decimals() != 0 || mint.supply() != 0.into() {
+                qlog!("receipt mint should have no decimals and no supply");
+                return Err(Error::InvalidMint.into());
+            }
+
+            if mint.mint_authority() != Some(&entity.program_authority)
+                || mint.freeze_authority().is_some()
+            {
+                qlog!("receipt mint should be controlled by program authority only");
+                return Err(Error::InvalidAuthority.into());
+            }
+
+            entity.receipt_mint = *mint.key();
+
+            let seeds = entity.authority_seeds();
+            token_program
+                .mint_to(&mut mint, &mut wallet, 1, &program_authority, &[&seeds])
+                .bpf_expect("call failed")
+                .bpf_expect("mint failed");
+
+            assert!(wallet.amount() == 1.into());
+        }
+
+        let amount_before = spl_token_wallet_vault.amount();
+        token_program
+            .transfer(
+                &mut source_spl_token_wallet,
+                &mut spl_token_wallet_vault,
+                amount.value(),
+                &source_authority,
+                &[],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("transfer failed");
+        let amount_after = spl_token_wallet_vault.amount();
+
+        assert!(amount_after - amount_before == amount);
+
+        emit(&Event::LockCreated {
+            locker: *entity.account().key(),
+            owner: entity.owner,
+            mint: entity.mint,
+            amount,
+            unlock_date,
+        });
+
+        Ok(())
+    }
+
+    /// Creates a locker for every entry, funding them all from a single source wallet.
+    ///
+    /// Account inputs:
+    /// SPL Token Program
+    /// SPL Token Wallet source
+    /// Source Authority (signed)
+    /// For each entry:
+    ///     Locker (empty, uninitialized)
+    ///     SPL Token Wallet vault (authority = program authority)
+    #[cfg(feature = "onchain")]
+    pub fn create_batch<S>(mut input: S, entries: Vec<LockEntry>) -> Result<(), ProgramError>
+    where
+        S: AccountSource<B>,
+        B: AccountBackend<Impl = Account>,
+    {
+        let CreateBatchArgsAccounts {
+            token_program,
+
+            mut source_spl_token_wallet,
+            source_authority,
+        } = CreateBatchArgsAccounts::from_program_input(&mut input)?;
+
+        if entries.is_empty() || entries.len() > MAX_BATCH_SIZE {
+            qlog!("invalid number of batch entries");
+            return Err(Error::InvalidData.into());
+        }
+
+        if input.remaining() != entries.len() * 2 {
+            qlog!("expected locker and vault accounts for every batch entry");
+            return Err(Error::InvalidData.into());
+        }
+
+        let mut total_amount = TokenAmount::from(0);
+        for entry in entries.iter() {
+            if entry.amount == 0.into() {
+                qlog!("batch entry amount should be positive");
+                return Err(Error::Validation.into());
+            }
+
+            total_amount += entry.amount;
+        }
+
+        if source_spl_token_wallet.amount() < total_amount {
+            qlog!("not enough funds in wallet");
+            return Err(Error::Validation.into());
+        }
+
+        let program_id = *input.program_id();
+        for entry in entries {
+            parse_accounts! {
+                &mut locker,
+                &mut spl_token_wallet_vault = WalletAccount::any(this)?,
+            }
+
+            let entity = Self::initialize(
+                &program_id,
+                locker,
+                &spl_token_wallet_vault,
+                source_spl_token_wallet.mint(),
+                &entry.owner,
+                entry.unlock_date,
+                entry.program_authority_salt,
+                None,
+            )?;
+
+            let amount_before = spl_token_wallet_vault.amount();
+            token_program
+                .transfer(
+                    &mut source_spl_token_wallet,
+                    &mut spl_token_wallet_vault,
+                    entry.amount.value(),
+                    &source_authority,
+                    &[],
+                )
+                .bpf_expect("call failed")
+                .bpf_expect("transfer failed");
+            let amount_after = spl_token_wallet_vault.amount();
+
+            assert!(amount_after - amount_before == entry.amount);
+
+            emit(&Event::LockCreated {
+                locker: *entity.account().key(),
+                owner: entry.owner,
+                mint: entity.mint,
+                amount: entry.amount,
+                unlock_date: entry.unlock_date,
+            });
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "onchain")]
+    #[allow(clippy::too_many_arguments)]
+    fn initialize(
+        program_id: &Pubkey,
+        locker: B,
+        spl_token_wallet_vault: &WalletAccount<B>,
+        mint: &Pubkey,
+        owner: &Pubkey,
+        unlock_date: SolTimestamp,
+        program_authority_salt: u64,
+        revoke_authority: Option<Pubkey>,
+    ) -> Result<Self, Error>
+    where
+        B: AccountBackend<Impl = Account>,
+    {
+        let mut entity = Self::raw_any(program_id, locker)?;
+
+        if entity.header().kind != EntityKind::None || !is_zeroed(entity.account().data()) {
+            qlog!("locker is already initialized");
+            return Err(Error::InvalidData);
+        }
 
         let expected_program_authority = Pubkey::create_program_address(
             &[
                 entity.account().key().as_ref(),
-                owner_authority.key().as_ref(),
+                &program_authority_salt.to_le_bytes(),
             ],
-            input.program_id(),
+            program_id,
         )
         .bpf_expect("couldn't derive program authority");
 
-        if !pubkey_eq(program_authority.key(), &expected_program_authority) {
-            qlog!("provided program authority does not match expected authority");
-            return Err(Error::InvalidAuthority.into());
-        }
-
         if !pubkey_eq(
             spl_token_wallet_vault.authority(),
             &expected_program_authority,
         ) {
             qlog!("spl token wallet vault authority does not match program authority");
-            return Err(Error::InvalidAuthority.into());
+            return Err(Error::InvalidAuthority);
+        }
+
+        if !pubkey_eq(spl_token_wallet_vault.mint(), mint) {
+            qlog!("spl token wallet vault mint does not match source wallet mint");
+            return Err(Error::InvalidMint);
         }
 
         let now = timestamp_now();
 
-        // if entity.release_date <= now {
-        //     qlog!("can`t initialize new locker with invalid unlock date");
-        //     return Err(Error::InvalidData.into());
-        // }
+        if unlock_date <= SolTimestamp::from(now.value()) {
+            qlog!("can`t initialize new locker with invalid unlock date");
+            return Err(Error::InvalidData);
+        }
 
-        Ok(())
+        entity.owner = *owner;
+        entity.mint = *mint;
+        entity.vault = *spl_token_wallet_vault.key();
+        entity.program_authority = expected_program_authority;
+        entity.program_authority_salt = program_authority_salt;
+        entity.release_date = unlock_date;
+        entity.revoke_authority = revoke_authority.unwrap_or_default();
+
+        let id = entity.allocator.allocate_id();
+        let header = entity.header_mut();
+        header.id = id;
+        header.kind = EntityKind::Locker;
+
+        Ok(entity)
     }
 
     /// Returns 4 instructions:
@@ -366,44 +760,345 @@ where
     /// SplToken::Initialize (locker vault)
     /// SystemProgram::Create (locker)
     /// Locker::Create (locker)
-    #[cfg(feature = "onchain")]
+    ///
+    /// Source authority pays for the new accounts, locker and vault are expected to sign.
+    #[allow(clippy::too_many_arguments)]
     pub fn create_instruction(
+        program_id: Pubkey,
         locker: Pubkey,
+        vault: Pubkey,
         owner: Pubkey,
         source_wallet: Pubkey,
         source_mint: Pubkey,
         source_authority: Pubkey,
+        unlock_date: SolTimestamp,
+        amount: TokenAmount,
+        revoke_authority: Option<Pubkey>,
     ) -> [Instruction; 4] {
-        let mut instructions = todo!();
+        let (program_authority, program_authority_salt) =
+            find_program_authority(&program_id, &locker);
+
+        let [create_vault, initialize_vault] =
+            spl::create_wallet(&source_authority, &vault, &source_mint, &program_authority);
+
+        [
+            create_vault,
+            initialize_vault,
+            system::create_account(
+                &source_authority,
+                &locker,
+                TokenLock::default_lamports(),
+                TokenLock::default_size() as u64,
+                &program_id,
+            ),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new_readonly(*spl::ID, false),
+                    AccountMeta::new(locker, false),
+                    AccountMeta::new(source_wallet, false),
+                    AccountMeta::new_readonly(source_authority, true),
+                    AccountMeta::new(vault, false),
+                    AccountMeta::new_readonly(program_authority, false),
+                    AccountMeta::new_readonly(owner, false),
+                ],
+                data: Method::CreateLock {
+                    unlock_date,
+                    amount,
+                    program_authority_salt,
+                    revoke_authority,
+                    with_receipt: false,
+                }
+                .encode(),
+            },
+        ]
     }
 
-    /// Relocks an existing locker with a new unlock date.
+    /// Returns 8 instructions:
+    /// SystemProgram::CreateAccount (locker vault)
+    /// SplToken::Initialize (locker vault)
+    /// SystemProgram::CreateAccount (receipt mint)
+    /// SplToken::InitializeMint (receipt mint)
+    /// SystemProgram::CreateAccount (owner receipt wallet)
+    /// SplToken::Initialize (owner receipt wallet)
+    /// SystemProgram::Create (locker)
+    /// Locker::Create (locker)
     ///
-    /// Input accounts:
-    /// Locker
-    /// Locker Owner (signed)
-    pub fn relock<S: AccountSource<B>>(
-        mut input: S,
-        unlock_date: SolTimestamp,
-    ) -> Result<(), ProgramError> {
-        let ReLockArgsAccounts {
+    /// Source authority pays for the new accounts, locker, vault, receipt mint and
+    /// receipt wallet are expected to sign.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_with_receipt_instruction(
+        program_id: Pubkey,
+        locker: Pubkey,
+        vault: Pubkey,
+        receipt_mint: Pubkey,
+        receipt_wallet: Pubkey,
+        owner: Pubkey,
+        source_wallet: Pubkey,
+        source_mint: Pubkey,
+        source_authority: Pubkey,
+        unlock_date: SolTimestamp,
+        amount: TokenAmount,
+        revoke_authority: Option<Pubkey>,
+    ) -> [Instruction; 8] {
+        let [create_vault, initialize_vault, create_locker, mut create] = Self::create_instruction(
+            program_id,
             locker,
+            vault,
+            owner,
+            source_wallet,
+            source_mint,
+            source_authority,
+            unlock_date,
+            amount,
+            revoke_authority,
+        );
+
+        let (program_authority, program_authority_salt) =
+            find_program_authority(&program_id, &locker);
+
+        create.accounts.push(AccountMeta::new(receipt_mint, false));
+        create
+            .accounts
+            .push(AccountMeta::new(receipt_wallet, false));
+        create.data = Method::CreateLock {
+            unlock_date,
+            amount,
+            program_authority_salt,
+            revoke_authority,
+            with_receipt: true,
+        }
+        .encode();
+
+        let [create_mint, initialize_mint] =
+            spl::create_mint(&source_authority, &receipt_mint, &program_authority, 0);
+        let [create_wallet, initialize_wallet] =
+            spl::create_wallet(&source_authority, &receipt_wallet, &receipt_mint, &owner);
+
+        [
+            create_vault,
+            initialize_vault,
+            create_mint,
+            initialize_mint,
+            create_wallet,
+            initialize_wallet,
+            create_locker,
+            create,
+        ]
+    }
+
+    pub fn relock_instruction(&self, unlock_date: SolTimestamp) -> Instruction {
+        Instruction {
+            program_id: *self.account().owner(),
+            accounts: vec![
+                AccountMeta::new(*self.account().key(), false),
+                AccountMeta::new_readonly(self.owner, true),
+            ],
+            data: Method::ReLock { unlock_date }.encode(),
+        }
+    }
+
+    /// Relocks a locker with receipt on behalf of the receipt holder.
+    pub fn relock_with_receipt_instruction(
+        &self,
+        holder: Pubkey,
+        receipt_wallet: Pubkey,
+        unlock_date: SolTimestamp,
+    ) -> Instruction {
+        Instruction {
+            program_id: *self.account().owner(),
+            accounts: vec![
+                AccountMeta::new(*self.account().key(), false),
+                AccountMeta::new_readonly(holder, true),
+                AccountMeta::new_readonly(receipt_wallet, false),
+            ],
+            data: Method::ReLock { unlock_date }.encode(),
+        }
+    }
+
+    pub fn withdraw_instruction(
+        &self,
+        destination_wallet: Pubkey,
+        amount: TokenAmount,
+    ) -> Instruction {
+        Instruction {
+            program_id: *self.account().owner(),
+            accounts: vec![
+                AccountMeta::new_readonly(*spl::ID, false),
+                AccountMeta::new_readonly(*self.account().key(), false),
+                AccountMeta::new(self.vault, false),
+                AccountMeta::new(destination_wallet, false),
+                AccountMeta::new_readonly(self.program_authority, false),
+                AccountMeta::new_readonly(self.owner, true),
+            ],
+            data: Method::Withdraw { amount }.encode(),
+        }
+    }
+
+    /// Withdraws from a locker with receipt on behalf of the receipt holder.
+    pub fn withdraw_with_receipt_instruction(
+        &self,
+        holder: Pubkey,
+        receipt_wallet: Pubkey,
+        destination_wallet: Pubkey,
+        amount: TokenAmount,
+    ) -> Instruction {
+        Instruction {
+            program_id: *self.account().owner(),
+            accounts: vec![
+                AccountMeta::new_readonly(*spl::ID, false),
+                AccountMeta::new_readonly(*self.account().key(), false),
+                AccountMeta::new(self.vault, false),
+                AccountMeta::new(destination_wallet, false),
+                AccountMeta::new_readonly(self.program_authority, false),
+                AccountMeta::new_readonly(holder, true),
+                AccountMeta::new(self.receipt_mint, false),
+                AccountMeta::new(receipt_wallet, false),
+            ],
+            data: Method::Withdraw { amount }.encode(),
+        }
+    }
+
+    pub fn increment_instruction(
+        &self,
+        source_wallet: Pubkey,
+        source_authority: Pubkey,
+        amount: TokenAmount,
+    ) -> Instruction {
+        Instruction {
+            program_id: *self.account().owner(),
+            accounts: vec![
+                AccountMeta::new_readonly(*spl::ID, false),
+                AccountMeta::new_readonly(*self.account().key(), false),
+                AccountMeta::new(self.vault, false),
+                AccountMeta::new(source_wallet, false),
+                AccountMeta::new_readonly(source_authority, true),
+            ],
+            data: Method::Increment { amount }.encode(),
+        }
+    }
+
+    /// Returns 4 instructions:
+    /// SystemProgram::CreateAccount (new locker vault)
+    /// SplToken::Initialize (new locker vault)
+    /// SystemProgram::Create (new locker)
+    /// Locker::Split
+    ///
+    /// Payer is charged for the new accounts, new locker and vault are expected to sign.
+    pub fn split_instruction(
+        &self,
+        payer: Pubkey,
+        new_locker: Pubkey,
+        new_vault: Pubkey,
+        amount: TokenAmount,
+    ) -> [Instruction; 4] {
+        let program_id = *self.account().owner();
+        let (program_authority, program_authority_salt) =
+            find_program_authority(&program_id, &new_locker);
+
+        let [create_vault, initialize_vault] =
+            spl::create_wallet(&payer, &new_vault, &self.mint, &program_authority);
+
+        [
+            create_vault,
+            initialize_vault,
+            system::create_account(
+                &payer,
+                &new_locker,
+                TokenLock::default_lamports(),
+                TokenLock::default_size() as u64,
+                &program_id,
+            ),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new_readonly(*spl::ID, false),
+                    AccountMeta::new_readonly(*self.account().key(), false),
+                    AccountMeta::new(new_locker, false),
+                    AccountMeta::new_readonly(self.program_authority, false),
+                    AccountMeta::new(self.vault, false),
+                    AccountMeta::new(new_vault, false),
+                    AccountMeta::new_readonly(self.owner, true),
+                ],
+                data: Method::Split {
+                    amount,
+                    program_authority_salt,
+                }
+                .encode(),
+            },
+        ]
+    }
+
+    /// Merges `source` into this locker.
+    pub fn merge_instruction(&self, source: &Self) -> Instruction {
+        Instruction {
+            program_id: *self.account().owner(),
+            accounts: vec![
+                AccountMeta::new_readonly(*spl::ID, false),
+                AccountMeta::new(*self.account().key(), false),
+                AccountMeta::new(*source.account().key(), false),
+                AccountMeta::new_readonly(source.program_authority, false),
+                AccountMeta::new(source.vault, false),
+                AccountMeta::new(self.vault, false),
+                AccountMeta::new(self.owner, true),
+            ],
+            data: Method::Merge.encode(),
+        }
+    }
+
+    pub fn revoke_instruction(&self, treasury_wallet: Pubkey) -> Instruction {
+        Instruction {
+            program_id: *self.account().owner(),
+            accounts: vec![
+                AccountMeta::new_readonly(*spl::ID, false),
+                AccountMeta::new(*self.account().key(), false),
+                AccountMeta::new(self.vault, false),
+                AccountMeta::new(treasury_wallet, false),
+                AccountMeta::new_readonly(self.program_authority, false),
+                AccountMeta::new_readonly(self.revoke_authority, true),
+            ],
+            data: Method::Revoke.encode(),
+        }
+    }
+
+    pub fn change_owner_instruction(&self, new_owner: Pubkey) -> Instruction {
+        Instruction {
+            program_id: *self.account().owner(),
+            accounts: vec![
+                AccountMeta::new(*self.account().key(), false),
+                AccountMeta::new_readonly(self.owner, true),
+            ],
+            data: Method::ChangeOwner { new_owner }.encode(),
+        }
+    }
+
+    /// Relocks an existing locker with a new unlock date.
+    ///
+    /// The release date can only be moved further into the future.
+    ///
+    /// Input accounts:
+    /// Locker
+    /// Locker Owner or Receipt Holder (signed)
+    /// Receipt Wallet (if locker has receipt)
+    pub fn relock<S>(mut input: S, unlock_date: SolTimestamp) -> Result<(), ProgramError>
+    where
+        S: AccountSource<B>,
+        B::Impl: AccountFieldsMut,
+    {
+        let ReLockArgsAccounts {
+            mut locker,
             owner_authority,
+            receipt_wallet,
         } = ReLockArgsAccounts::from_program_input(&mut input)?;
 
-        // if !pubkey_eq(locker.owner(), owner_authority.key()) {
-        //     qlog!("provided program authority does not match expected authority");
-        //     return Err(Error::InvalidAuthority.into());
-        // }
+        locker.authorize_owner(&owner_authority, receipt_wallet.as_ref())?;
 
-        /*
         if unlock_date <= locker.release_date {
-            qlog!("can`t initialize new locker with invalid unlock date");
+            qlog!("new unlock date should be later than the current one");
             return Err(Error::InvalidData.into());
         }
 
         locker.release_date = unlock_date;
-        */
 
         Ok(())
     }
@@ -413,34 +1108,81 @@ where
         SolTimestamp::from(now.value()) > self.release_date
     }
 
+    #[inline]
+    pub fn is_revocable(&self) -> bool {
+        !is_zeroed(self.revoke_authority.as_ref())
+    }
+
+    #[inline]
+    pub fn has_receipt(&self) -> bool {
+        !is_zeroed(self.receipt_mint.as_ref())
+    }
+
+    /// Checks that `authority` is allowed to manage locked funds: it should be the receipt holder
+    /// for lockers with receipt and the stored owner otherwise.
+    pub fn authorize_owner(
+        &self,
+        authority: &B,
+        receipt_wallet: Option<&WalletAccount<B>>,
+    ) -> Result<(), Error> {
+        match receipt_wallet {
+            Some(wallet) => {
+                if !pubkey_eq(wallet.authority(), authority.key()) || wallet.amount() != 1.into() {
+                    qlog!("provided authority does not hold the locker receipt");
+                    return Err(Error::InvalidAuthority);
+                }
+            }
+            None => {
+                if self.has_receipt() {
+                    qlog!("locker receipt wallet is expected");
+                    return Err(Error::InvalidAccount);
+                }
+
+                if !pubkey_eq(self.owner, authority.key()) {
+                    qlog!("provided owner authority does not match expected authority");
+                    return Err(Error::InvalidAuthority);
+                }
+            }
+        }
+
+        if !authority.is_signer() {
+            qlog!("the owner is expected to sign");
+            return Err(Error::Validation);
+        }
+
+        Ok(())
+    }
+
     /// Withdraw funds from locker.
     /// Input accounts:
     /// Locker
     /// SPL Token Wallet vault
     /// SPL Token Wallet destination
     /// Program Authority
-    /// Owner (signed)
+    /// Owner or Receipt Holder (signed)
+    /// Receipt Mint (if locker has receipt)
+    /// Receipt Wallet (if locker has receipt)
     #[cfg(feature = "onchain")]
-    pub fn withdraw<S: AccountSource<B>>(
-        mut input: S,
-        amount: TokenAmount,
-    ) -> Result<(), ProgramError> {
+    pub fn withdraw<S>(mut input: S, amount: TokenAmount) -> Result<(), ProgramError>
+    where
+        S: AccountSource<B>,
+        B: AccountBackend<Impl = Account>,
+    {
         let WithdrawArgsAccounts {
             token_program,
 
             locker,
-            spl_token_wallet_vault,
-            destination_spl_token_wallet,
+            mut spl_token_wallet_vault,
+            mut destination_spl_token_wallet,
             program_authority,
             owner_authority,
+            receipt,
         } = WithdrawArgsAccounts::from_program_input(&mut input)?;
 
         let now = timestamp_now();
 
-        if !pubkey_eq(locker.owner, owner_authority.key()) {
-            qlog!("provided owner authority does not match expected authority");
-            return Err(Error::InvalidAuthority.into());
-        }
+        let receipt_wallet = receipt.as_ref().map(|receipt| &receipt.wallet);
+        locker.authorize_owner(&owner_authority, receipt_wallet)?;
 
         if !locker.can_withdraw(now) {
             qlog!("can't withdraw until release date");
@@ -452,42 +1194,66 @@ where
             return Err(Error::Validation.into());
         }
 
+        let seeds = locker.authority_seeds();
         let amount_before = spl_token_wallet_vault.amount();
         token_program
             .transfer(
                 &mut spl_token_wallet_vault,
                 &mut destination_spl_token_wallet,
                 amount.value(),
-                &owner_authority,
-                &[],
+                &program_authority,
+                &[&seeds],
             )
             .bpf_expect("call failed")
             .bpf_expect("transfer failed");
         let amount_after = spl_token_wallet_vault.amount();
 
-        assert!(amount_after - amount_before == amount);
+        assert!(amount_before - amount_after == amount);
+
+        // receipt is no longer backed by anything once the locker is emptied
+        if let Some(ReceiptAccounts {
+            mut mint,
+            mut wallet,
+        }) = receipt
+        {
+            if amount_after == 0.into() {
+                token_program
+                    .burn(&mut wallet, &mut mint, 1, &owner_authority, &[])
+                    .bpf_expect("call failed")
+                    .bpf_expect("burn failed");
+            }
+        }
+
+        emit(&Event::Withdrawn {
+            locker: *locker.account().key(),
+            amount,
+        });
 
         Ok(())
     }
 
     /// Add funds to locker
     ///
+    /// Source authority is checked by the token program, its failures are reported
+    /// as `Error::TokenError`.
+    ///
     /// Input accounts:
     /// Locker
     /// SPL Token Wallet vault
     /// SPL Token Wallet source
     /// Source Authority
     #[cfg(feature = "onchain")]
-    pub fn increment<S: AccountSource<B>>(
-        mut input: S,
-        amount: TokenAmount,
-    ) -> Result<(), ProgramError> {
+    pub fn increment<S>(mut input: S, amount: TokenAmount) -> Result<(), ProgramError>
+    where
+        S: AccountSource<B>,
+        B: AccountBackend<Impl = Account>,
+    {
         let IncrementArgsAccounts {
             token_program,
 
             locker,
-            spl_token_wallet_vault,
-            source_spl_token_wallet,
+            mut spl_token_wallet_vault,
+            mut source_spl_token_wallet,
             source_authority,
         } = IncrementArgsAccounts::from_program_input(&mut input)?;
 
@@ -513,177 +1279,1749 @@ where
                 &[],
             )
             .bpf_expect("call failed")
-            .bpf_expect("transfer failed");
+            .map_err(Error::TokenError)?;
         let amount_after = source_spl_token_wallet.amount();
 
-        assert!(amount_after - amount_before == amount);
+        assert!(amount_before - amount_after == amount);
 
         Ok(())
     }
 
     /// Split locker
     ///
+    /// Moves `amount` into a new locker with its own vault and the same release date.
+    ///
     /// Input accounts:
+    /// SPL Token Program
     /// Source Locker
-    /// New Locker
-    /// Program Authority
+    /// New Locker (empty, uninitialized)
+    /// Program Authority (Source Locker)
     /// SPL Token Vault (Source Locker)
-    /// SPL Token Vault (New Locker)
+    /// SPL Token Vault (New Locker, authority = new locker program authority)
+    /// Owner (signed)
     #[cfg(feature = "onchain")]
-    pub fn split<S: AccountSource<B>>(
+    pub fn split<S>(
         mut input: S,
         amount: TokenAmount,
-    ) -> Result<(), ProgramError> {
+        program_authority_salt: u64,
+    ) -> Result<(), ProgramError>
+    where
+        S: AccountSource<B>,
+        B: AccountBackend<Impl = Account>,
+    {
         let SplitArgsAccounts {
             token_program,
 
             source_locker,
             new_locker,
-            source_spl_token_wallet_vault,
-            new_spl_token_wallet_vault,
+            program_authority,
+            mut source_spl_token_wallet_vault,
+            mut new_spl_token_wallet_vault,
+            owner_authority,
         } = SplitArgsAccounts::from_program_input(&mut input)?;
 
-        let now = timestamp_now();
+        if !pubkey_eq(source_locker.owner, owner_authority.key()) {
+            qlog!("locker owner authority does not match provided owner authority");
+            return Err(Error::InvalidAuthority.into());
+        }
 
-        if source_locker.can_withdraw(now) {
-            qlog!("unlocked and can't be splitted");
+        if !owner_authority.is_signer() {
+            qlog!("the owner is expected to sign");
             return Err(Error::Validation.into());
         }
 
-        if !pubkey_eq(source_locker.owner, new_spl_token_wallet_vault.owner()) {
-            qlog!("locker owner authority does not match destination wallet vault owner ");
-            return Err(Error::InvalidAuthority.into());
+        if source_locker.has_receipt() {
+            qlog!("locker with receipt can't be splitted");
+            return Err(Error::Validation.into());
         }
 
-        let mut entity = Self::raw_any(input.program_id(), new_locker)?;
+        let now = timestamp_now();
 
-        entity.owner = source_locker.owner;
-        entity.mint = source_locker.mint;
-        entity.vault = source_locker.vault;
-        entity.program_authority = source_locker.program_authority;
-        entity.release_date = source_locker.release_date;
+        if source_locker.can_withdraw(now) {
+            qlog!("unlocked and can't be splitted");
+            return Err(Error::Validation.into());
+        }
 
-        let id = entity.allocator.allocate_id();
-        let entity_key = *entity.account().key();
-        let header = entity.header_mut();
-        header.id = id;
-        header.kind = EntityKind::Locker;
+        if amount == 0.into() || source_spl_token_wallet_vault.amount() < amount {
+            qlog!("not enough funds in locker for split");
+            return Err(Error::Validation.into());
+        }
 
+        Self::initialize(
+            input.program_id(),
+            new_locker,
+            &new_spl_token_wallet_vault,
+            &source_locker.mint,
+            &source_locker.owner,
+            source_locker.release_date,
+            program_authority_salt,
+            Some(source_locker.revoke_authority),
+        )?;
+
+        let seeds = source_locker.authority_seeds();
         let amount_before = source_spl_token_wallet_vault.amount();
         token_program
             .transfer(
                 &mut source_spl_token_wallet_vault,
                 &mut new_spl_token_wallet_vault,
                 amount.value(),
-                &source_locker.owner,
-                &[],
+                &program_authority,
+                &[&seeds],
             )
             .bpf_expect("call failed")
             .bpf_expect("transfer failed");
         let amount_after = source_spl_token_wallet_vault.amount();
 
-        assert!(amount_after - amount_before == amount);
+        assert!(amount_before - amount_after == amount);
 
         Ok(())
     }
 
-    /// Change locker owner
+    /// Merge source locker into target locker
+    ///
+    /// Lockers should have the same owner, mint and revoke authority, the merged locker
+    /// keeps the later release date. Source locker and its vault are closed and their rent
+    /// goes to the owner.
     ///
     /// Input accounts:
-    /// Locker
+    /// SPL Token Program
+    /// Target Locker
+    /// Source Locker
+    /// Program Authority (Source Locker)
+    /// SPL Token Vault (Source Locker)
+    /// SPL Token Vault (Target Locker)
     /// Owner (signed)
-    /// New Owner
-    pub fn change_owner<S: AccountSource<B>>(
-        mut input: S,
-        amount: TokenAmount,
-    ) -> Result<(), ProgramError> {
-        let ChangeOwnerArgsAccounts {
-            locker,
-            source_owner_authority,
-            new_owner_authority,
-        } = ChangeOwnerArgsAccounts::from_program_input(&mut input)?;
+    #[cfg(feature = "onchain")]
+    pub fn merge<S>(mut input: S) -> Result<(), ProgramError>
+    where
+        S: AccountSource<B>,
+        B: AccountBackend<Impl = Account>,
+    {
+        let MergeArgsAccounts {
+            token_program,
 
-        if !pubkey_eq(locker.owner, source_owner_authority.key()) {
-            qlog!("locker owner authority does not match provided source owner autority");
-            return Err(Error::InvalidAuthority.into());
+            mut target_locker,
+            mut source_locker,
+            program_authority,
+            mut source_spl_token_wallet_vault,
+            mut target_spl_token_wallet_vault,
+            mut owner_authority,
+        } = MergeArgsAccounts::from_program_input(&mut input)?;
+
+        if pubkey_eq(target_locker.account().key(), source_locker.account().key()) {
+            qlog!("can't merge locker into itself");
+            return Err(Error::InvalidAccount.into());
         }
 
-        let now = timestamp_now();
+        if target_locker.has_receipt() || source_locker.has_receipt() {
+            qlog!("lockers with receipt can't be merged");
+            return Err(Error::Validation.into());
+        }
 
-        if locker.can_withdraw(now) {
-            qlog!("unlocked and can't change owner");
+        if !pubkey_eq(target_locker.owner, owner_authority.key())
+            || !pubkey_eq(source_locker.owner, owner_authority.key())
+        {
+            qlog!("locker owner authority does not match provided owner authority");
+            return Err(Error::InvalidAuthority.into());
+        }
+
+        if !owner_authority.is_signer() {
+            qlog!("the owner is expected to sign");
             return Err(Error::Validation.into());
         }
 
-        // locker.owner = *new_owner_authority.key();
+        if !pubkey_eq(target_locker.mint, source_locker.mint) {
+            qlog!("can't merge lockers with different mints");
+            return Err(Error::InvalidMint.into());
+        }
 
-        Ok(())
-    }
-}
+        if !pubkey_eq(
+            target_locker.revoke_authority,
+            source_locker.revoke_authority,
+        ) {
+            qlog!("can't merge lockers with different revoke authorities");
+            return Err(Error::InvalidAuthority.into());
+        }
 
-#[cfg(feature = "onchain")]
-pub fn main(mut input: BpfProgramInput) -> Result<(), ProgramError> {
-    let mut data = input.data();
-    let method = Method::decode(&mut data)
-        .ok()
-        .bpf_expect("couldn't parse method");
+        let amount = source_spl_token_wallet_vault.amount();
 
-    match method {
-        Method::CreateLock {
-            unlock_date,
-            amount,
-        } => TokenLock::create(input, unlock_date, amount).bpf_unwrap(),
-        Method::ReLock { unlock_date } => TokenLock::relock(input, unlock_date).bpf_unwrap(),
-        Method::Withdraw { amount } => withdraw(input, amount).bpf_unwrap(),
-        Method::Increment { amount } => increment(input, amount).bpf_unwrap(),
-        Method::Split { amount } => split(input, amount).bpf_unwrap(),
-        Method::ChangeOwner { amount } => change_owner(input, amount).bpf_unwrap(),
-    }
+        let seeds = source_locker.authority_seeds();
+        let amount_before = target_spl_token_wallet_vault.amount();
+        token_program
+            .transfer(
+                &mut source_spl_token_wallet_vault,
+                &mut target_spl_token_wallet_vault,
+                amount.value(),
+                &program_authority,
+                &[&seeds],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("transfer failed");
+        let amount_after = target_spl_token_wallet_vault.amount();
 
-    Ok(())
-}
+        assert!(amount_after - amount_before == amount);
 
-#[cfg(feature = "onchain")]
-#[cfg(test)]
-mod test {
+        token_program
+            .close_account(
+                &mut source_spl_token_wallet_vault,
+                &mut owner_authority,
+                &program_authority,
+                &[&seeds],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("close failed");
 
-    #[tokio::test]
-    async fn init_test() -> anyhow::Result<()> {
-        let mut program_test = ProgramTest::default();
-        let program_id = Pubkey::new_unique();
+        target_locker.release_date = target_locker.release_date.max(source_locker.release_date);
 
-        program_test.add_program(
-            "locker",
-            program_id,
-            Some(|a, b, c| {
-                builtin_process_instruction(wrapped_entrypoint::<super::Program>, a, b, c)
-            }),
-        );
+        source_locker.account_mut().data_mut().fill(0);
+        owner_authority
+            .set_lamports(owner_authority.lamports() + source_locker.account().lamports());
+        source_locker.account_mut().set_lamports(0);
 
-        let locker_key = Keypair::new();
-        let locker_owner_key = Keypair::new();
+        Ok(())
+    }
 
-        let mut salt: u64 = 0;
-        let locker_program_authority = loop {
-            let locker_program_authority = Pubkey::create_program_address(
-                &[
-                    locker_key.pubkey().as_ref(),
-                    locker_owner_key.pubkey().as_ref(),
-                ],
-                &program_id,
-            );
+    /// Revoke locker before its release date
+    ///
+    /// Locked tokens are not vested until the release date, so the whole vault is returned
+    /// to the treasury. Released funds stay withdrawable by the owner.
+    ///
+    /// The treasury is not recorded in the locker, the revoke authority picks any wallet
+    /// in the locker mint as destination when revoking.
+    ///
+    /// Input accounts:
+    /// SPL Token Program
+    /// Locker
+    /// SPL Token Wallet vault
+    /// SPL Token Wallet treasury
+    /// Program Authority
+    /// Revoke Authority (signed)
+    #[cfg(feature = "onchain")]
+    pub fn revoke<S>(mut input: S) -> Result<(), ProgramError>
+    where
+        S: AccountSource<B>,
+        B: AccountBackend<Impl = Account>,
+    {
+        let RevokeArgsAccounts {
+            token_program,
 
-            match locker_program_authority {
-                Some(s) => break s,
-                None => {
-                    salt += 1;
-                }
-            }
-        };
+            mut locker,
+            mut spl_token_wallet_vault,
+            mut treasury_spl_token_wallet,
+            program_authority,
+            revoke_authority,
+        } = RevokeArgsAccounts::from_program_input(&mut input)?;
 
-        let (mut client, payer, hash) = program_test.start().await;
+        if !locker.is_revocable() {
+            qlog!("locker is not revocable");
+            return Err(Error::Validation.into());
+        }
+
+        if !pubkey_eq(locker.revoke_authority, revoke_authority.key()) {
+            qlog!("provided revoke authority does not match expected authority");
+            return Err(Error::InvalidAuthority.into());
+        }
+
+        if !revoke_authority.is_signer() {
+            qlog!("the revoke authority is expected to sign");
+            return Err(Error::Validation.into());
+        }
 
-        todo!();
+        let now = timestamp_now();
+
+        if locker.can_withdraw(now) {
+            qlog!("unlocked funds are vested and can't be revoked");
+            return Err(Error::Validation.into());
+        }
+
+        let amount = spl_token_wallet_vault.amount();
+
+        let seeds = locker.authority_seeds();
+        token_program
+            .transfer(
+                &mut spl_token_wallet_vault,
+                &mut treasury_spl_token_wallet,
+                amount.value(),
+                &program_authority,
+                &[&seeds],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("transfer failed");
+
+        assert!(spl_token_wallet_vault.amount() == 0.into());
+
+        locker.revoke_authority = Pubkey::default();
+
+        Ok(())
+    }
+
+    /// Change locker owner
+    ///
+    /// Input accounts:
+    /// Locker
+    /// Owner (signed)
+    pub fn change_owner<S>(mut input: S, new_owner: Pubkey) -> Result<(), ProgramError>
+    where
+        S: AccountSource<B>,
+        B::Impl: AccountFieldsMut,
+    {
+        let ChangeOwnerArgsAccounts {
+            mut locker,
+            owner_authority,
+        } = ChangeOwnerArgsAccounts::from_program_input(&mut input)?;
+
+        if !pubkey_eq(locker.owner, owner_authority.key()) {
+            qlog!("locker owner authority does not match provided owner authority");
+            return Err(Error::InvalidAuthority.into());
+        }
+
+        if !owner_authority.is_signer() {
+            qlog!("the owner is expected to sign");
+            return Err(Error::Validation.into());
+        }
+
+        if locker.has_receipt() {
+            qlog!("locker with receipt changes owner by transferring the receipt");
+            return Err(Error::Validation.into());
+        }
+
+        let now = timestamp_now();
+
+        if locker.can_withdraw(now) {
+            qlog!("unlocked and can't change owner");
+            return Err(Error::Validation.into());
+        }
+
+        locker.owner = new_owner;
+
+        Ok(())
+    }
+
+    pub fn load(program_id: &Pubkey, account: B) -> Result<Self, Error> {
+        Self::raw_initialized(program_id, account)
+    }
+
+    #[inline]
+    pub fn authority_seeds(&self) -> [&[u8]; 2] {
+        [
+            self.account().key().as_ref(),
+            as_bytes(&self.program_authority_salt),
+        ]
+    }
+
+    #[inline]
+    pub fn program_authority(&self, account: B) -> Result<B, Error> {
+        if !pubkey_eq(&self.program_authority, account.key()) {
+            return Err(Error::InvalidAuthority);
+        }
+
+        Ok(account)
+    }
+
+    #[inline]
+    pub fn vault(&self, account: B) -> Result<WalletAccount<B>, Error> {
+        let wallet = WalletAccount::any(account)?;
+
+        if !pubkey_eq(&self.vault, wallet.key()) {
+            return Err(Error::InvalidAccount);
+        }
+
+        Ok(wallet)
+    }
+
+    #[inline]
+    pub fn wallet(&self, account: B) -> Result<WalletAccount<B>, Error> {
+        let wallet = WalletAccount::any(account)?;
+
+        if !pubkey_eq(&self.mint, wallet.mint()) {
+            return Err(Error::InvalidMint);
+        }
+
+        Ok(wallet)
+    }
+
+    #[inline]
+    pub fn receipt_mint(&self, account: B) -> Result<MintAccount<B>, Error> {
+        let mint = MintAccount::any(account)?;
+
+        if !pubkey_eq(&self.receipt_mint, mint.key()) {
+            return Err(Error::InvalidMint);
+        }
+
+        Ok(mint)
+    }
+
+    #[inline]
+    pub fn receipt_wallet(&self, account: B) -> Result<WalletAccount<B>, Error> {
+        let wallet = WalletAccount::any(account)?;
+
+        if !pubkey_eq(&self.receipt_mint, wallet.mint()) {
+            return Err(Error::InvalidMint);
+        }
+
+        Ok(wallet)
+    }
+}
+
+/// Finds program authority of the locker, returns the address and its salt.
+pub fn find_program_authority(program_id: &Pubkey, locker: &Pubkey) -> (Pubkey, u64) {
+    let mut salt: u64 = 0;
+    loop {
+        let program_authority =
+            Pubkey::create_program_address(&[locker.as_ref(), &salt.to_le_bytes()], program_id);
+
+        match program_authority {
+            Some(program_authority) => break (program_authority, salt),
+            None => {
+                salt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "onchain")]
+pub fn main(input: BpfProgramInput) -> Result<(), ProgramError> {
+    let mut data = input.data();
+    let method = Method::decode(&mut data)
+        .ok()
+        .bpf_expect("couldn't parse method");
+
+    match method {
+        Method::CreateLock {
+            unlock_date,
+            amount,
+            program_authority_salt,
+            revoke_authority,
+            with_receipt,
+        } => TokenLockEntity::create(
+            input,
+            unlock_date,
+            amount,
+            program_authority_salt,
+            revoke_authority,
+            with_receipt,
+        ),
+        Method::ReLock { unlock_date } => TokenLockEntity::relock(input, unlock_date),
+        Method::Withdraw { amount } => TokenLockEntity::withdraw(input, amount),
+        Method::Increment { amount } => TokenLockEntity::increment(input, amount),
+        Method::Split {
+            amount,
+            program_authority_salt,
+        } => TokenLockEntity::split(input, amount, program_authority_salt),
+        Method::ChangeOwner { new_owner } => TokenLockEntity::change_owner(input, new_owner),
+        Method::CreateVesting(args) => VestingEntity::create(input, args),
+        Method::Claim => VestingEntity::claim(input),
+        Method::Revoke => TokenLockEntity::revoke(input),
+        Method::CreateLockBatch { entries } => TokenLockEntity::create_batch(input, entries),
+        Method::Merge => TokenLockEntity::merge(input),
+        Method::RevokeVesting => VestingEntity::revoke(input),
+    }
+}
+
+pub struct Program;
+
+#[cfg(feature = "onchain")]
+impl Entrypoint for Program {
+    fn call(input: BpfProgramInput) -> Result<(), ProgramError> {
+        main(input)
+    }
+}
+
+#[cfg(feature = "onchain")]
+#[cfg(test)]
+mod test {
+    use solana_api_types::{
+        program_test::{ProgramTest, Runtime},
+        system, Account, AccountMeta, Instruction, InstructionError, Keypair, Pubkey, Signer,
+        Transaction, TransactionError,
+    };
+    use solana_program_test::builtin_process_instruction;
+    use solar::{
+        input::wrapped_entrypoint,
+        spl::{
+            self, create_mint, create_wallet, mint_to, MintAccount, SplReadError, TokenError,
+            TokenInstruction, WalletAccount,
+        },
+    };
+
+    use crate::{
+        batch::{create_batch_accounts_instructions, create_lock_batch_instruction, Recipient},
+        error::Error,
+        vesting::{CreateVestingArgs, VestingEntity},
+        TokenLockEntity,
+    };
+
+    const INITIAL_BALANCE: u64 = 10_000;
+    const LOCKED_AMOUNT: u64 = 1_000;
+    const LOCK_DURATION: i64 = 60;
+
+    struct TestContext {
+        runtime: Runtime,
+        payer: Keypair,
+        program_id: Pubkey,
+        mint: Keypair,
+        owner: Keypair,
+        owner_wallet: Keypair,
+    }
+
+    async fn setup() -> anyhow::Result<TestContext> {
+        let mut program_test = ProgramTest::default();
+        let program_id = Pubkey::new_unique();
+
+        program_test.add_program(
+            "locker",
+            program_id,
+            Some(|a, b, c| {
+                builtin_process_instruction(wrapped_entrypoint::<super::Program>, a, b, c)
+            }),
+        );
+
+        let (mut runtime, payer, _) = program_test.start_with_context().await;
+
+        let mint = Keypair::new();
+        let owner = Keypair::new();
+        let owner_wallet = Keypair::new();
+
+        let mut instrs = vec![system::transfer(
+            &payer.pubkey(),
+            &owner.pubkey(),
+            1_000_000_000,
+        )];
+        instrs.extend(create_mint(
+            &payer.pubkey(),
+            &mint.pubkey(),
+            &payer.pubkey(),
+            6,
+        ));
+        instrs.extend(create_wallet(
+            &payer.pubkey(),
+            &owner_wallet.pubkey(),
+            &mint.pubkey(),
+            &owner.pubkey(),
+        ));
+        instrs.push(mint_to(
+            &mint.pubkey(),
+            &owner_wallet.pubkey(),
+            &payer.pubkey(),
+            INITIAL_BALANCE,
+        ));
+
+        process(&mut runtime, &payer, &instrs, &[&mint, &owner_wallet])
+            .await?
+            .unwrap();
+
+        Ok(TestContext {
+            runtime,
+            payer,
+            program_id,
+            mint,
+            owner,
+            owner_wallet,
+        })
+    }
+
+    async fn process(
+        runtime: &mut Runtime,
+        payer: &Keypair,
+        instrs: &[Instruction],
+        signers: &[&Keypair],
+    ) -> anyhow::Result<Result<(), TransactionError>> {
+        let hash = runtime.get_recent_blockhash().await?;
+
+        let mut keypairs: Vec<&dyn Signer> = vec![payer];
+        keypairs.extend(signers.iter().map(|keypair| *keypair as &dyn Signer));
+
+        let trx =
+            Transaction::new_signed_with_payer(instrs, Some(&payer.pubkey()), &keypairs, hash);
+        runtime.try_process_transaction(trx).await
+    }
+
+    /// Creates a locker funded from the owner wallet, returns its key with the transaction result.
+    async fn create_lock(
+        context: &mut TestContext,
+        unlock_date: i64,
+        amount: u64,
+    ) -> anyhow::Result<(Pubkey, Result<(), TransactionError>)> {
+        let locker = Keypair::new();
+        let vault = Keypair::new();
+
+        let instrs = TokenLockEntity::<Box<Account>>::create_instruction(
+            context.program_id,
+            locker.pubkey(),
+            vault.pubkey(),
+            context.owner.pubkey(),
+            context.owner_wallet.pubkey(),
+            context.mint.pubkey(),
+            context.owner.pubkey(),
+            unlock_date.into(),
+            amount.into(),
+            None,
+        );
+
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&context.owner, &locker, &vault],
+        )
+        .await?;
+
+        Ok((locker.pubkey(), result))
+    }
+
+    async fn load_locker(
+        context: &mut TestContext,
+        locker: &Pubkey,
+    ) -> anyhow::Result<TokenLockEntity<Box<Account>>> {
+        let account = context.runtime.get_account(locker).await?.unwrap();
+
+        Ok(TokenLockEntity::load(&context.program_id, Box::new(account)).unwrap())
+    }
+
+    async fn balance(context: &mut TestContext, wallet: &Pubkey) -> anyhow::Result<u64> {
+        let account = context.runtime.get_account(wallet).await?.unwrap();
+
+        Ok(WalletAccount::any(Box::new(account))
+            .unwrap()
+            .amount()
+            .value())
+    }
+
+    async fn supply(context: &mut TestContext, mint: &Pubkey) -> anyhow::Result<u64> {
+        let account = context.runtime.get_account(mint).await?.unwrap();
+
+        Ok(MintAccount::any(Box::new(account))
+            .unwrap()
+            .supply()
+            .value())
+    }
+
+    async fn now(context: &mut TestContext) -> anyhow::Result<i64> {
+        Ok(context.runtime.get_clock().await?.unix_timestamp)
+    }
+
+    /// Moves the clock past `date`.
+    async fn warp_past(context: &mut TestContext, date: i64) -> anyhow::Result<()> {
+        let clock = context.runtime.get_clock().await?;
+        context.runtime.warp_to_slot(clock.slot + 1_000)?;
+
+        assert!(now(context).await? > date);
+        Ok(())
+    }
+
+    fn custom_error(index: u8, error: Error) -> Result<(), TransactionError> {
+        Err(TransactionError::InstructionError(
+            index,
+            InstructionError::Custom(error.code()),
+        ))
+    }
+
+    #[tokio::test]
+    async fn create_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let unlock_date = now(&mut context).await? + LOCK_DURATION;
+
+        let (locker_key, result) = create_lock(&mut context, unlock_date, LOCKED_AMOUNT).await?;
+        result.unwrap();
+
+        let locker = load_locker(&mut context, &locker_key).await?;
+        assert_eq!(locker.owner, context.owner.pubkey());
+        assert_eq!(locker.mint, context.mint.pubkey());
+        assert!(locker.release_date == unlock_date.into());
+        assert!(!locker.is_revocable());
+        assert!(!locker.has_receipt());
+
+        let vault = locker.vault;
+        assert_eq!(balance(&mut context, &vault).await?, LOCKED_AMOUNT);
+
+        let owner_wallet = context.owner_wallet.pubkey();
+        assert_eq!(
+            balance(&mut context, &owner_wallet).await?,
+            INITIAL_BALANCE - LOCKED_AMOUNT
+        );
+
+        // unlock date in the past
+        let (_, result) = create_lock(&mut context, unlock_date - 2 * LOCK_DURATION, 1).await?;
+        assert_eq!(result, custom_error(3, Error::InvalidData));
+
+        // more than the owner has
+        let (_, result) = create_lock(&mut context, unlock_date, INITIAL_BALANCE).await?;
+        assert_eq!(result, custom_error(3, Error::Validation));
+
+        // nothing to lock
+        let (_, result) = create_lock(&mut context, unlock_date + 1, 0).await?;
+        assert_eq!(result, custom_error(3, Error::Validation));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_with_invalid_vault_mint_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let unlock_date = now(&mut context).await? + LOCK_DURATION;
+
+        let other_mint = Keypair::new();
+        let locker = Keypair::new();
+        let vault = Keypair::new();
+
+        let [_, _, create_locker, create] = TokenLockEntity::<Box<Account>>::create_instruction(
+            context.program_id,
+            locker.pubkey(),
+            vault.pubkey(),
+            context.owner.pubkey(),
+            context.owner_wallet.pubkey(),
+            context.mint.pubkey(),
+            context.owner.pubkey(),
+            unlock_date.into(),
+            LOCKED_AMOUNT.into(),
+            None,
+        );
+
+        let (program_authority, _) =
+            crate::find_program_authority(&context.program_id, &locker.pubkey());
+
+        let mut instrs = vec![];
+        instrs.extend(create_mint(
+            &context.payer.pubkey(),
+            &other_mint.pubkey(),
+            &context.payer.pubkey(),
+            6,
+        ));
+        instrs.extend(create_wallet(
+            &context.payer.pubkey(),
+            &vault.pubkey(),
+            &other_mint.pubkey(),
+            &program_authority,
+        ));
+        instrs.push(create_locker);
+        instrs.push(create);
+
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&other_mint, &vault, &context.owner, &locker],
+        )
+        .await?;
+        assert_eq!(result, custom_error(5, Error::InvalidMint));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn withdraw_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let unlock_date = now(&mut context).await? + LOCK_DURATION;
+
+        let (locker_key, result) = create_lock(&mut context, unlock_date, LOCKED_AMOUNT).await?;
+        result.unwrap();
+
+        let locker = load_locker(&mut context, &locker_key).await?;
+        let owner_wallet = context.owner_wallet.pubkey();
+
+        // still locked
+        let instr = locker.withdraw_instruction(owner_wallet, 100.into());
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        warp_past(&mut context, unlock_date).await?;
+
+        // not the owner
+        let stranger = Keypair::new();
+        let mut instr = locker.withdraw_instruction(owner_wallet, 200.into());
+        instr.accounts[5].pubkey = stranger.pubkey();
+        let result = process(&mut context.runtime, &context.payer, &[instr], &[&stranger]).await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAuthority));
+
+        let instr = locker.withdraw_instruction(owner_wallet, 400.into());
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        result.unwrap();
+
+        let vault = locker.vault;
+        assert_eq!(balance(&mut context, &vault).await?, LOCKED_AMOUNT - 400);
+        assert_eq!(
+            balance(&mut context, &owner_wallet).await?,
+            INITIAL_BALANCE - LOCKED_AMOUNT + 400
+        );
+
+        // more than left in the locker
+        let instr = locker.withdraw_instruction(owner_wallet, (LOCKED_AMOUNT - 300).into());
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn increment_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let unlock_date = now(&mut context).await? + LOCK_DURATION;
+
+        let (locker_key, result) = create_lock(&mut context, unlock_date, LOCKED_AMOUNT).await?;
+        result.unwrap();
+
+        let locker = load_locker(&mut context, &locker_key).await?;
+        let owner = context.owner.pubkey();
+        let owner_wallet = context.owner_wallet.pubkey();
+
+        let instr = locker.increment_instruction(owner_wallet, owner, 500.into());
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        result.unwrap();
+
+        let vault = locker.vault;
+        assert_eq!(balance(&mut context, &vault).await?, LOCKED_AMOUNT + 500);
+
+        // more than the owner has
+        let instr = locker.increment_instruction(owner_wallet, owner, INITIAL_BALANCE.into());
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        warp_past(&mut context, unlock_date).await?;
+
+        // already unlocked
+        let instr = locker.increment_instruction(owner_wallet, owner, 100.into());
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn split_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let unlock_date = now(&mut context).await? + LOCK_DURATION;
+
+        let (locker_key, result) = create_lock(&mut context, unlock_date, LOCKED_AMOUNT).await?;
+        result.unwrap();
+
+        let locker = load_locker(&mut context, &locker_key).await?;
+        let payer = context.payer.pubkey();
+
+        let new_locker = Keypair::new();
+        let new_vault = Keypair::new();
+        let instrs =
+            locker.split_instruction(payer, new_locker.pubkey(), new_vault.pubkey(), 300.into());
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&new_vault, &new_locker, &context.owner],
+        )
+        .await?;
+        result.unwrap();
+
+        let split_locker = load_locker(&mut context, &new_locker.pubkey()).await?;
+        assert_eq!(split_locker.owner, locker.owner);
+        assert_eq!(split_locker.mint, locker.mint);
+        assert_eq!(split_locker.vault, new_vault.pubkey());
+        assert!(split_locker.release_date == locker.release_date);
+
+        let vault = locker.vault;
+        assert_eq!(balance(&mut context, &vault).await?, LOCKED_AMOUNT - 300);
+        assert_eq!(balance(&mut context, &new_vault.pubkey()).await?, 300);
+
+        // nothing to split
+        let new_locker = Keypair::new();
+        let new_vault = Keypair::new();
+        let instrs =
+            locker.split_instruction(payer, new_locker.pubkey(), new_vault.pubkey(), 0.into());
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&new_vault, &new_locker, &context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(3, Error::Validation));
+
+        // not the owner
+        let stranger = Keypair::new();
+        let mut instrs =
+            locker.split_instruction(payer, new_locker.pubkey(), new_vault.pubkey(), 100.into());
+        instrs[3].accounts[6].pubkey = stranger.pubkey();
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&new_vault, &new_locker, &stranger],
+        )
+        .await?;
+        assert_eq!(result, custom_error(3, Error::InvalidAuthority));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn change_owner_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let unlock_date = now(&mut context).await? + LOCK_DURATION;
+
+        let (locker_key, result) = create_lock(&mut context, unlock_date, LOCKED_AMOUNT).await?;
+        result.unwrap();
+
+        let locker = load_locker(&mut context, &locker_key).await?;
+        let new_owner = Keypair::new();
+
+        // not the owner
+        let mut instr = locker.change_owner_instruction(new_owner.pubkey());
+        instr.accounts[1].pubkey = new_owner.pubkey();
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&new_owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAuthority));
+
+        let instr = locker.change_owner_instruction(new_owner.pubkey());
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        result.unwrap();
+
+        let locker = load_locker(&mut context, &locker_key).await?;
+        assert_eq!(locker.owner, new_owner.pubkey());
+
+        warp_past(&mut context, unlock_date).await?;
+
+        // already unlocked
+        let instr = locker.change_owner_instruction(context.owner.pubkey());
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&new_owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        // previous owner can't withdraw anymore
+        let owner_wallet = context.owner_wallet.pubkey();
+        let mut instr = locker.withdraw_instruction(owner_wallet, 100.into());
+        instr.accounts[5].pubkey = context.owner.pubkey();
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAuthority));
+
+        let instr = locker.withdraw_instruction(owner_wallet, LOCKED_AMOUNT.into());
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&new_owner],
+        )
+        .await?;
+        result.unwrap();
+
+        assert_eq!(balance(&mut context, &owner_wallet).await?, INITIAL_BALANCE);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn revoke_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let unlock_date = now(&mut context).await? + LOCK_DURATION;
+
+        let authority = Keypair::new();
+        let treasury_wallet = Keypair::new();
+        let instrs = create_wallet(
+            &context.payer.pubkey(),
+            &treasury_wallet.pubkey(),
+            &context.mint.pubkey(),
+            &authority.pubkey(),
+        );
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&treasury_wallet],
+        )
+        .await?
+        .unwrap();
+
+        let (fixed_key, result) = create_lock(&mut context, unlock_date, LOCKED_AMOUNT).await?;
+        result.unwrap();
+        let fixed = load_locker(&mut context, &fixed_key).await?;
+
+        let mut lockers = vec![];
+        for _ in 0..2 {
+            let locker = Keypair::new();
+            let vault = Keypair::new();
+
+            let instrs = TokenLockEntity::<Box<Account>>::create_instruction(
+                context.program_id,
+                locker.pubkey(),
+                vault.pubkey(),
+                context.owner.pubkey(),
+                context.owner_wallet.pubkey(),
+                context.mint.pubkey(),
+                context.owner.pubkey(),
+                unlock_date.into(),
+                LOCKED_AMOUNT.into(),
+                Some(authority.pubkey()),
+            );
+            process(
+                &mut context.runtime,
+                &context.payer,
+                &instrs,
+                &[&context.owner, &locker, &vault],
+            )
+            .await?
+            .unwrap();
+
+            lockers.push(load_locker(&mut context, &locker.pubkey()).await?);
+        }
+
+        let treasury = treasury_wallet.pubkey();
+
+        // not revocable
+        let mut instr = fixed.revoke_instruction(treasury);
+        instr.accounts[5].pubkey = authority.pubkey();
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&authority],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        let instr = lockers[0].revoke_instruction(treasury);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&authority],
+        )
+        .await?;
+        result.unwrap();
+
+        let locker = load_locker(&mut context, lockers[0].account().key()).await?;
+        assert!(!locker.is_revocable());
+        assert_eq!(balance(&mut context, &treasury).await?, LOCKED_AMOUNT);
+        assert_eq!(balance(&mut context, &locker.vault).await?, 0);
+
+        // released funds belong to the owner
+        warp_past(&mut context, unlock_date).await?;
+
+        let instr = lockers[1].revoke_instruction(treasury);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&authority],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        assert_eq!(balance(&mut context, &treasury).await?, LOCKED_AMOUNT);
+        assert_eq!(
+            balance(&mut context, &lockers[1].vault).await?,
+            LOCKED_AMOUNT
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn revoke_vesting_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let now = now(&mut context).await?;
+
+        let authority = Keypair::new();
+        let treasury_wallet = Keypair::new();
+        let instrs = create_wallet(
+            &context.payer.pubkey(),
+            &treasury_wallet.pubkey(),
+            &context.mint.pubkey(),
+            &authority.pubkey(),
+        );
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&treasury_wallet],
+        )
+        .await?
+        .unwrap();
+
+        let mut vestings = vec![];
+        for &revoke_authority in &[None, Some(authority.pubkey())] {
+            let vesting = Keypair::new();
+            let vault = Keypair::new();
+
+            let instrs = VestingEntity::<Box<Account>>::create_instruction(
+                context.program_id,
+                vesting.pubkey(),
+                vault.pubkey(),
+                context.owner.pubkey(),
+                context.owner_wallet.pubkey(),
+                context.mint.pubkey(),
+                context.owner.pubkey(),
+                CreateVestingArgs {
+                    amount: LOCKED_AMOUNT.into(),
+                    start_date: (now - 100).into(),
+                    cliff_date: (now - 100).into(),
+                    end_date: (now + 100).into(),
+                    period: None,
+                    program_authority_salt: 0,
+                    revoke_authority,
+                },
+            );
+            process(
+                &mut context.runtime,
+                &context.payer,
+                &instrs,
+                &[&context.owner, &vesting, &vault],
+            )
+            .await?
+            .unwrap();
+
+            let account = context.runtime.get_account(&vesting.pubkey()).await?;
+            let vesting =
+                VestingEntity::load(&context.program_id, Box::new(account.unwrap())).unwrap();
+            vestings.push(vesting);
+        }
+
+        let treasury = treasury_wallet.pubkey();
+
+        // not revocable
+        let mut instr = vestings[0].revoke_instruction(treasury);
+        instr.accounts[5].pubkey = authority.pubkey();
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&authority],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        // not the revoke authority
+        let mut instr = vestings[1].revoke_instruction(treasury);
+        instr.accounts[5].pubkey = context.owner.pubkey();
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAuthority));
+
+        let instr = vestings[1].revoke_instruction(treasury);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&authority],
+        )
+        .await?;
+        result.unwrap();
+
+        let key = *vestings[1].account().key();
+        let account = context.runtime.get_account(&key).await?.unwrap();
+        let vesting = VestingEntity::load(&context.program_id, Box::new(account)).unwrap();
+        let vested = vesting.total_amount.value();
+        let revoked = balance(&mut context, &treasury).await?;
+
+        assert!(!vesting.is_revocable());
+        assert!(vested > 0 && revoked > 0);
+        assert_eq!(vested + revoked, LOCKED_AMOUNT);
+        assert_eq!(balance(&mut context, &vesting.vault).await?, vested);
+
+        // already revoked
+        let instr = vesting.revoke_instruction(treasury);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&authority],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        // vested part stays with the owner
+        let owner_wallet = context.owner_wallet.pubkey();
+        let instr = vesting.claim_instruction(owner_wallet);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        result.unwrap();
+
+        assert_eq!(
+            balance(&mut context, &owner_wallet).await?,
+            INITIAL_BALANCE - 2 * LOCKED_AMOUNT + vested
+        );
+        assert_eq!(balance(&mut context, &vesting.vault).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn create_batch_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let unlock_date = now(&mut context).await? + LOCK_DURATION;
+
+        let recipients = (1..=3)
+            .map(|i| Recipient {
+                owner: Pubkey::new_unique(),
+                amount: i * 100,
+                unlock_date,
+            })
+            .collect::<Vec<_>>();
+
+        let mut lockers = vec![];
+        for _ in recipients.iter() {
+            let locker = Keypair::new();
+            let vault = Keypair::new();
+
+            let instrs = create_batch_accounts_instructions(
+                &context.program_id,
+                &context.payer.pubkey(),
+                &context.mint.pubkey(),
+                &[(locker.pubkey(), vault.pubkey())],
+            );
+            process(
+                &mut context.runtime,
+                &context.payer,
+                &instrs,
+                &[&locker, &vault],
+            )
+            .await?
+            .unwrap();
+
+            lockers.push((locker.pubkey(), vault.pubkey()));
+        }
+
+        let program_id = context.program_id;
+        let owner = context.owner.pubkey();
+        let owner_wallet = context.owner_wallet.pubkey();
+        let batch = |recipients: &[Recipient], lockers: &[(Pubkey, Pubkey)]| {
+            create_lock_batch_instruction(&program_id, &owner_wallet, &owner, recipients, lockers)
+        };
+
+        // every entry needs a locker and a vault
+        let mut instr = batch(&recipients, &lockers);
+        instr.accounts.pop();
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidData));
+
+        // not enough funds
+        let mut expensive = recipients.clone();
+        expensive[0].amount = INITIAL_BALANCE;
+        let instr = batch(&expensive, &lockers);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        let instr = batch(&recipients, &lockers);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        result.unwrap();
+
+        for (recipient, (locker_key, vault)) in recipients.iter().zip(lockers.iter()) {
+            let locker = load_locker(&mut context, locker_key).await?;
+
+            assert_eq!(locker.owner, recipient.owner);
+            assert_eq!(locker.vault, *vault);
+            assert_eq!(locker.release_date, unlock_date.into());
+            assert_eq!(balance(&mut context, vault).await?, recipient.amount);
+        }
+        assert_eq!(
+            balance(&mut context, &owner_wallet).await?,
+            INITIAL_BALANCE - 600
+        );
+
+        // lockers are already initialized
+        let instr = batch(&recipients, &lockers);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidData));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn merge_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let unlock_date = now(&mut context).await? + LOCK_DURATION;
+
+        let (target_key, result) = create_lock(&mut context, unlock_date, LOCKED_AMOUNT).await?;
+        result.unwrap();
+        let (source_key, result) =
+            create_lock(&mut context, unlock_date + 10, LOCKED_AMOUNT).await?;
+        result.unwrap();
+
+        let target = load_locker(&mut context, &target_key).await?;
+        let source = load_locker(&mut context, &source_key).await?;
+
+        // can't merge locker into itself
+        let instr = target.merge_instruction(&target);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAccount));
+
+        let owner_lamports = context
+            .runtime
+            .get_account(&context.owner.pubkey())
+            .await?
+            .unwrap()
+            .lamports;
+        let source_lamports = context
+            .runtime
+            .get_account(&source_key)
+            .await?
+            .unwrap()
+            .lamports;
+        let vault_lamports = context
+            .runtime
+            .get_account(&source.vault)
+            .await?
+            .unwrap()
+            .lamports;
+
+        let instr = target.merge_instruction(&source);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        result.unwrap();
+
+        let target = load_locker(&mut context, &target_key).await?;
+        assert_eq!(target.release_date, (unlock_date + 10).into());
+        assert_eq!(
+            balance(&mut context, &target.vault).await?,
+            2 * LOCKED_AMOUNT
+        );
+
+        // source locker and vault are closed, rent goes to the owner
+        assert!(context.runtime.get_account(&source_key).await?.is_none());
+        assert!(context.runtime.get_account(&source.vault).await?.is_none());
+        let owner = context
+            .runtime
+            .get_account(&context.owner.pubkey())
+            .await?
+            .unwrap();
+        assert_eq!(
+            owner.lamports,
+            owner_lamports + source_lamports + vault_lamports
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn relock_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let unlock_date = now(&mut context).await? + LOCK_DURATION;
+
+        let (locker_key, result) = create_lock(&mut context, unlock_date, LOCKED_AMOUNT).await?;
+        result.unwrap();
+
+        let locker = load_locker(&mut context, &locker_key).await?;
+        let stranger = Keypair::new();
+
+        // not the owner
+        let mut instr = locker.relock_instruction((unlock_date + 10).into());
+        instr.accounts[1].pubkey = stranger.pubkey();
+        let result = process(&mut context.runtime, &context.payer, &[instr], &[&stranger]).await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAuthority));
+
+        // release date can't be moved back
+        let instr = locker.relock_instruction((unlock_date - 10).into());
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidData));
+
+        let instr = locker.relock_instruction((unlock_date + LOCK_DURATION).into());
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        result.unwrap();
+
+        let locker = load_locker(&mut context, &locker_key).await?;
+        assert_eq!(locker.release_date, (unlock_date + LOCK_DURATION).into());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalid_accounts_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let unlock_date = now(&mut context).await? + LOCK_DURATION;
+
+        let (locker_key, result) = create_lock(&mut context, unlock_date, LOCKED_AMOUNT).await?;
+        result.unwrap();
+        let (other_key, result) = create_lock(&mut context, unlock_date, LOCKED_AMOUNT).await?;
+        result.unwrap();
+
+        let locker = load_locker(&mut context, &locker_key).await?;
+        let other = load_locker(&mut context, &other_key).await?;
+        let owner = context.owner.pubkey();
+        let owner_wallet = context.owner_wallet.pubkey();
+
+        // vault of another locker
+        let mut instr = locker.increment_instruction(owner_wallet, owner, 100.into());
+        instr.accounts[2].pubkey = other.vault;
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAccount));
+
+        // not a token wallet
+        let mut instr = locker.increment_instruction(owner_wallet, owner, 100.into());
+        instr.accounts[2].pubkey = owner;
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(
+            result,
+            custom_error(0, Error::SplReadError(SplReadError::InvalidOwner))
+        );
+
+        // source wallet is not owned by the authority
+        let stranger = Keypair::new();
+        let instr = locker.increment_instruction(owner_wallet, stranger.pubkey(), 100.into());
+        let result = process(&mut context.runtime, &context.payer, &[instr], &[&stranger]).await?;
+        assert_eq!(
+            result,
+            custom_error(0, Error::TokenError(TokenError::OwnerMismatch))
+        );
+        assert_eq!(
+            Error::from_transaction_error(&result.unwrap_err()),
+            Some(Error::TokenError(TokenError::OwnerMismatch))
+        );
+
+        let vault = locker.vault;
+        assert_eq!(balance(&mut context, &vault).await?, LOCKED_AMOUNT);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalid_entity_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let now = now(&mut context).await?;
+
+        let (locker_key, result) =
+            create_lock(&mut context, now + LOCK_DURATION, LOCKED_AMOUNT).await?;
+        result.unwrap();
+        let locker = load_locker(&mut context, &locker_key).await?;
+
+        let vesting = Keypair::new();
+        let vault = Keypair::new();
+        let instrs = VestingEntity::<Box<Account>>::create_instruction(
+            context.program_id,
+            vesting.pubkey(),
+            vault.pubkey(),
+            context.owner.pubkey(),
+            context.owner_wallet.pubkey(),
+            context.mint.pubkey(),
+            context.owner.pubkey(),
+            CreateVestingArgs {
+                amount: LOCKED_AMOUNT.into(),
+                start_date: now.into(),
+                cliff_date: now.into(),
+                end_date: (now + 100).into(),
+                period: None,
+                program_authority_salt: 0,
+                revoke_authority: None,
+            },
+        );
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&context.owner, &vesting, &vault],
+        )
+        .await?
+        .unwrap();
+
+        // vesting passed as a locker
+        let mut instr = locker.relock_instruction((now + 2 * LOCK_DURATION).into());
+        instr.accounts[0].pubkey = vesting.pubkey();
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidKind));
+
+        // account of another program passed as a locker
+        let mut instr = locker.relock_instruction((now + 2 * LOCK_DURATION).into());
+        instr.accounts[0].pubkey = locker.vault;
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidOwner));
+
+        let locker = load_locker(&mut context, &locker_key).await?;
+        assert_eq!(locker.release_date, (now + LOCK_DURATION).into());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn receipt_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let unlock_date = now(&mut context).await? + LOCK_DURATION;
+
+        let locker = Keypair::new();
+        let vault = Keypair::new();
+        let receipt_mint = Keypair::new();
+        let receipt_wallet = Keypair::new();
+
+        let instrs = TokenLockEntity::<Box<Account>>::create_with_receipt_instruction(
+            context.program_id,
+            locker.pubkey(),
+            vault.pubkey(),
+            receipt_mint.pubkey(),
+            receipt_wallet.pubkey(),
+            context.owner.pubkey(),
+            context.owner_wallet.pubkey(),
+            context.mint.pubkey(),
+            context.owner.pubkey(),
+            unlock_date.into(),
+            LOCKED_AMOUNT.into(),
+            None,
+        );
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[
+                &context.owner,
+                &locker,
+                &vault,
+                &receipt_mint,
+                &receipt_wallet,
+            ],
+        )
+        .await?
+        .unwrap();
+
+        let entity = load_locker(&mut context, &locker.pubkey()).await?;
+        assert!(entity.has_receipt());
+        assert_eq!(entity.receipt_mint, receipt_mint.pubkey());
+        assert_eq!(supply(&mut context, &receipt_mint.pubkey()).await?, 1);
+        assert_eq!(balance(&mut context, &receipt_wallet.pubkey()).await?, 1);
+
+        // hand the receipt over to another wallet
+        let holder = Keypair::new();
+        let holder_receipt_wallet = Keypair::new();
+        let holder_wallet = Keypair::new();
+
+        let mut instrs = create_wallet(
+            &context.payer.pubkey(),
+            &holder_receipt_wallet.pubkey(),
+            &receipt_mint.pubkey(),
+            &holder.pubkey(),
+        )
+        .to_vec();
+        instrs.extend(create_wallet(
+            &context.payer.pubkey(),
+            &holder_wallet.pubkey(),
+            &context.mint.pubkey(),
+            &holder.pubkey(),
+        ));
+        instrs.push(Instruction {
+            program_id: *spl::ID,
+            accounts: vec![
+                AccountMeta::new(receipt_wallet.pubkey(), false),
+                AccountMeta::new(holder_receipt_wallet.pubkey(), false),
+                AccountMeta::new_readonly(context.owner.pubkey(), true),
+            ],
+            data: TokenInstruction::Transfer { amount: 1 }.pack_vec(),
+        });
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&holder_receipt_wallet, &holder_wallet, &context.owner],
+        )
+        .await?
+        .unwrap();
+
+        // the former owner can't act without the receipt
+        let instr = entity.relock_instruction((unlock_date + LOCK_DURATION).into());
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAccount));
+
+        let instr = entity.relock_with_receipt_instruction(
+            context.owner.pubkey(),
+            receipt_wallet.pubkey(),
+            (unlock_date + LOCK_DURATION).into(),
+        );
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAuthority));
+
+        // the holder can
+        let instr = entity.relock_with_receipt_instruction(
+            holder.pubkey(),
+            holder_receipt_wallet.pubkey(),
+            (unlock_date + LOCK_DURATION).into(),
+        );
+        process(&mut context.runtime, &context.payer, &[instr], &[&holder])
+            .await?
+            .unwrap();
+
+        let entity = load_locker(&mut context, &locker.pubkey()).await?;
+        assert_eq!(entity.release_date, (unlock_date + LOCK_DURATION).into());
+
+        warp_past(&mut context, unlock_date + LOCK_DURATION).await?;
+
+        let instr = entity.withdraw_with_receipt_instruction(
+            context.owner.pubkey(),
+            receipt_wallet.pubkey(),
+            context.owner_wallet.pubkey(),
+            LOCKED_AMOUNT.into(),
+        );
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.owner],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAuthority));
+
+        let instr = entity.withdraw_with_receipt_instruction(
+            holder.pubkey(),
+            holder_receipt_wallet.pubkey(),
+            holder_wallet.pubkey(),
+            LOCKED_AMOUNT.into(),
+        );
+        process(&mut context.runtime, &context.payer, &[instr], &[&holder])
+            .await?
+            .unwrap();
+
+        // emptying the vault burns the receipt
+        assert_eq!(
+            balance(&mut context, &holder_wallet.pubkey()).await?,
+            LOCKED_AMOUNT
+        );
+        assert_eq!(balance(&mut context, &entity.vault).await?, 0);
+        assert_eq!(
+            balance(&mut context, &holder_receipt_wallet.pubkey()).await?,
+            0
+        );
+        assert_eq!(supply(&mut context, &receipt_mint.pubkey()).await?, 0);
 
         Ok(())
     }
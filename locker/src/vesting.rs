@@ -0,0 +1,721 @@
+use std::mem::size_of;
+
+use az::CheckedAs;
+use solana_api_types::{program::ProgramError, system, AccountMeta, Instruction, Pubkey};
+
+#[cfg(feature = "onchain")]
+use solar::account::onchain::Account;
+
+use solar::{
+    account::AccountFields,
+    input::AccountSource,
+    math::{Checked, ToF64},
+    prelude::AccountBackend,
+    qlog,
+    reinterpret::as_bytes,
+    spl::{self, TokenProgram, WalletAccount},
+    time::{SolDuration, SolTimestamp},
+    util::{is_zeroed, pubkey_eq, timestamp_now, ResultExt},
+};
+
+use crate::{
+    data::{AccountType, Entity, EntityAllocator, EntityKind, HEADER_RESERVED},
+    error::Error,
+    find_program_authority, impl_entity_simple_deref, Method, TokenAmount,
+};
+
+pub type VestingEntity<B> = Entity<B, Vesting>;
+
+#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode)]
+pub struct CreateVestingArgs {
+    pub amount: TokenAmount,
+    pub start_date: SolTimestamp,
+    pub cliff_date: SolTimestamp,
+    pub end_date: SolTimestamp,
+    /// Unlock interval, tokens vest continuously if not set.
+    pub period: Option<SolDuration>,
+    pub program_authority_salt: u64,
+    /// Key allowed to take back the unvested part, the grant is irrevocable if not set.
+    pub revoke_authority: Option<Pubkey>,
+}
+
+#[repr(C)]
+pub struct VestingState {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub program_authority: Pubkey,
+    pub program_authority_salt: u64,
+    /// Zeroed for grants that can't be revoked.
+    pub revoke_authority: Pubkey,
+
+    pub start_date: SolTimestamp,
+    pub cliff_date: SolTimestamp,
+    pub end_date: SolTimestamp,
+    /// Zero period means continuous (linear) vesting.
+    pub period: SolDuration,
+
+    pub total_amount: TokenAmount,
+    pub claimed_amount: TokenAmount,
+
+    pub allocator: EntityAllocator,
+}
+
+impl VestingState {
+    /// Total amount vested by `now`, including already claimed tokens.
+    pub fn vested_amount(&self, now: Checked<i64>) -> TokenAmount {
+        let start_date = i64::from(self.start_date);
+        let cliff_date = i64::from(self.cliff_date);
+        let end_date = i64::from(self.end_date);
+
+        if now.value() < cliff_date {
+            return 0.into();
+        }
+
+        if now.value() >= end_date {
+            return self.total_amount;
+        }
+
+        let mut elapsed = now - start_date;
+        let period = i64::from(self.period);
+        if period > 0 {
+            elapsed -= elapsed.value() % period;
+        }
+
+        let duration = Checked::from(end_date) - start_date;
+        let share = elapsed.to_u64f64() / duration.to_u64f64();
+        let vested = share * self.total_amount.to_u64f64();
+
+        vested.checked_as::<TokenAmount>().bpf_unwrap()
+    }
+
+    /// Amount that can be claimed at `now`.
+    pub fn claimable(&self, now: Checked<i64>) -> TokenAmount {
+        let vested = self.vested_amount(now);
+
+        if vested > self.claimed_amount {
+            vested - self.claimed_amount
+        } else {
+            0.into()
+        }
+    }
+
+    #[inline]
+    pub fn is_revocable(&self) -> bool {
+        !is_zeroed(self.revoke_authority.as_ref())
+    }
+
+    /// Stops vesting at `now` and returns the unvested amount taken out of the grant.
+    ///
+    /// Schedule ends at `now`, so that everything vested by then stays claimable.
+    pub fn revoke(&mut self, now: Checked<i64>) -> TokenAmount {
+        let vested = self.vested_amount(now);
+        let unvested = self.total_amount - vested;
+
+        let now = SolTimestamp::from(now.value());
+        self.start_date = self.start_date.min(now);
+        self.cliff_date = self.cliff_date.min(now);
+        self.end_date = self.end_date.min(now);
+
+        self.total_amount = vested;
+        self.revoke_authority = Pubkey::default();
+
+        unvested
+    }
+}
+
+#[derive(Debug)]
+pub struct Vesting;
+
+impl AccountType for Vesting {
+    const KIND: EntityKind = EntityKind::Vesting;
+
+    fn is_valid_size(size: usize) -> bool {
+        size == size_of::<VestingState>()
+    }
+
+    fn default_size() -> usize {
+        size_of::<VestingState>() + HEADER_RESERVED
+    }
+}
+
+impl_entity_simple_deref!(Vesting, VestingState);
+
+#[derive(Debug)]
+pub struct CreateVestingArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+
+    pub vesting: B, //(empty, uninitialized)
+    pub source_spl_token_wallet: WalletAccount<B>,
+    pub source_authority: B,                      //(signed)
+    pub spl_token_wallet_vault: WalletAccount<B>, //(authority = program authority)
+    pub program_authority: B,
+    pub owner_authority: B, //claim authority
+}
+
+impl<B: AccountBackend> CreateVestingArgsAccounts<B> {
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        parse_accounts! {
+            &token_program = TokenProgram::load(this)?,
+
+            &mut vesting,
+            &mut source_spl_token_wallet = WalletAccount::any(this)?,
+            &source_authority,
+            &mut spl_token_wallet_vault = WalletAccount::any(this)?,
+            &program_authority,
+            &owner_authority,
+        }
+
+        Ok(Self {
+            token_program,
+
+            vesting,
+            source_spl_token_wallet,
+            source_authority,
+            spl_token_wallet_vault,
+            program_authority,
+            owner_authority,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct RevokeVestingArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+
+    pub vesting: Entity<B, Vesting>,
+    pub spl_token_wallet_vault: WalletAccount<B>,
+    pub treasury_spl_token_wallet: WalletAccount<B>,
+    pub program_authority: B,
+    pub revoke_authority: B,
+}
+
+impl<B: AccountBackend> RevokeVestingArgsAccounts<B> {
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts! {
+            &token_program = TokenProgram::load(this)?,
+
+            &mut vesting = <Entity<B, Vesting>>::load(&program_id, this)?,
+            &mut spl_token_wallet_vault = vesting.vault(this)?,
+            &mut treasury_spl_token_wallet = vesting.wallet(this)?,
+            &program_authority = vesting.program_authority(this)?,
+            &revoke_authority,
+        }
+
+        Ok(Self {
+            token_program,
+
+            vesting,
+            spl_token_wallet_vault,
+            treasury_spl_token_wallet,
+            program_authority,
+            revoke_authority,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct ClaimArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+
+    pub vesting: Entity<B, Vesting>,
+    pub spl_token_wallet_vault: WalletAccount<B>,
+    pub destination_spl_token_wallet: WalletAccount<B>,
+    pub program_authority: B,
+    pub owner_authority: B,
+}
+
+impl<B: AccountBackend> ClaimArgsAccounts<B> {
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts! {
+            &token_program = TokenProgram::load(this)?,
+
+            &mut vesting = <Entity<B, Vesting>>::load(&program_id, this)?,
+            &mut spl_token_wallet_vault = vesting.vault(this)?,
+            &mut destination_spl_token_wallet = vesting.wallet(this)?,
+            &program_authority = vesting.program_authority(this)?,
+            &owner_authority,
+        }
+
+        Ok(Self {
+            token_program,
+
+            vesting,
+            spl_token_wallet_vault,
+            destination_spl_token_wallet,
+            program_authority,
+            owner_authority,
+        })
+    }
+}
+
+impl<B: AccountBackend> Entity<B, Vesting> {
+    /// Creates a vesting schedule and moves the whole allocation into the vault.
+    ///
+    /// Account inputs:
+    /// SPL Token Program
+    /// Vesting (empty, uninitialized)
+    /// SPL Token Wallet source
+    /// Source Authority (signed)
+    /// SPL Token Wallet vault (authority = program authority)
+    /// Program Authority
+    /// Owner (claim authority)
+    #[cfg(feature = "onchain")]
+    pub fn create<S>(mut input: S, args: CreateVestingArgs) -> Result<(), ProgramError>
+    where
+        S: AccountSource<B>,
+        B: AccountBackend<Impl = Account>,
+    {
+        let CreateVestingArgsAccounts {
+            token_program,
+
+            vesting,
+            mut source_spl_token_wallet,
+            source_authority,
+            mut spl_token_wallet_vault,
+            program_authority,
+            owner_authority,
+        } = CreateVestingArgsAccounts::from_program_input(&mut input)?;
+
+        let mut entity = Self::raw_any(input.program_id(), vesting)?;
+
+        if entity.header().kind != EntityKind::None || !is_zeroed(entity.account().data()) {
+            qlog!("vesting is already initialized");
+            return Err(Error::InvalidData.into());
+        }
+
+        let expected_program_authority = Pubkey::create_program_address(
+            &[
+                entity.account().key().as_ref(),
+                &args.program_authority_salt.to_le_bytes(),
+            ],
+            input.program_id(),
+        )
+        .bpf_expect("couldn't derive program authority");
+
+        if !pubkey_eq(program_authority.key(), &expected_program_authority) {
+            qlog!("provided program authority does not match expected authority");
+            return Err(Error::InvalidAuthority.into());
+        }
+
+        if !pubkey_eq(
+            spl_token_wallet_vault.authority(),
+            &expected_program_authority,
+        ) {
+            qlog!("spl token wallet vault authority does not match program authority");
+            return Err(Error::InvalidAuthority.into());
+        }
+
+        if !pubkey_eq(
+            spl_token_wallet_vault.mint(),
+            source_spl_token_wallet.mint(),
+        ) {
+            qlog!("spl token wallet vault mint does not match source wallet mint");
+            return Err(Error::InvalidMint.into());
+        }
+
+        if args.start_date > args.cliff_date || args.cliff_date > args.end_date {
+            qlog!("vesting dates should satisfy start <= cliff <= end");
+            return Err(Error::InvalidData.into());
+        }
+
+        let duration = i64::from(args.end_date) - i64::from(args.start_date);
+
+        if duration <= 0 {
+            qlog!("vesting end date should be later than the start date");
+            return Err(Error::InvalidData.into());
+        }
+
+        let period = args.period.unwrap_or_default();
+
+        if i64::from(period) < 0 || i64::from(period) > duration {
+            qlog!("vesting period should fit into the vesting duration");
+            return Err(Error::InvalidData.into());
+        }
+
+        if args.amount == 0.into() || source_spl_token_wallet.amount() < args.amount {
+            qlog!("not enough funds in wallet");
+            return Err(Error::Validation.into());
+        }
+
+        entity.owner = *owner_authority.key();
+        entity.mint = *source_spl_token_wallet.mint();
+        entity.vault = *spl_token_wallet_vault.key();
+        entity.program_authority = *program_authority.key();
+        entity.program_authority_salt = args.program_authority_salt;
+        entity.start_date = args.start_date;
+        entity.cliff_date = args.cliff_date;
+        entity.end_date = args.end_date;
+        entity.period = period;
+        entity.total_amount = args.amount;
+        entity.claimed_amount = 0.into();
+        entity.revoke_authority = args.revoke_authority.unwrap_or_default();
+
+        let id = entity.allocator.allocate_id();
+        let header = entity.header_mut();
+        header.id = id;
+        header.kind = EntityKind::Vesting;
+
+        let amount_before = spl_token_wallet_vault.amount();
+        token_program
+            .transfer(
+                &mut source_spl_token_wallet,
+                &mut spl_token_wallet_vault,
+                args.amount.value(),
+                &source_authority,
+                &[],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("transfer failed");
+        let amount_after = spl_token_wallet_vault.amount();
+
+        assert!(amount_after - amount_before == args.amount);
+
+        Ok(())
+    }
+
+    /// Releases the vested but not yet claimed portion to the owner.
+    ///
+    /// Input accounts:
+    /// SPL Token Program
+    /// Vesting
+    /// SPL Token Wallet vault
+    /// SPL Token Wallet destination
+    /// Program Authority
+    /// Owner (signed)
+    #[cfg(feature = "onchain")]
+    pub fn claim<S>(mut input: S) -> Result<(), ProgramError>
+    where
+        S: AccountSource<B>,
+        B: AccountBackend<Impl = Account>,
+    {
+        let ClaimArgsAccounts {
+            token_program,
+
+            mut vesting,
+            mut spl_token_wallet_vault,
+            mut destination_spl_token_wallet,
+            program_authority,
+            owner_authority,
+        } = ClaimArgsAccounts::from_program_input(&mut input)?;
+
+        if !pubkey_eq(vesting.owner, owner_authority.key()) {
+            qlog!("provided owner authority does not match expected authority");
+            return Err(Error::InvalidAuthority.into());
+        }
+
+        if !owner_authority.is_signer() {
+            qlog!("the owner is expected to sign");
+            return Err(Error::Validation.into());
+        }
+
+        let now = timestamp_now();
+        let amount = vesting.claimable(now);
+
+        if amount == 0.into() {
+            qlog!("nothing to claim yet");
+            return Err(Error::Validation.into());
+        }
+
+        let seeds = vesting.authority_seeds();
+        let amount_before = spl_token_wallet_vault.amount();
+        token_program
+            .transfer(
+                &mut spl_token_wallet_vault,
+                &mut destination_spl_token_wallet,
+                amount.value(),
+                &program_authority,
+                &[&seeds],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("transfer failed");
+        let amount_after = spl_token_wallet_vault.amount();
+
+        assert!(amount_before - amount_after == amount);
+
+        vesting.claimed_amount += amount;
+
+        Ok(())
+    }
+
+    /// Takes back the part of the grant that has not vested yet.
+    ///
+    /// Unvested tokens are returned to the treasury, while the vested ones stay claimable
+    /// by the owner. Schedule stops at the revocation, so nothing vests afterwards.
+    ///
+    /// Input accounts:
+    /// SPL Token Program
+    /// Vesting
+    /// SPL Token Wallet vault
+    /// SPL Token Wallet treasury
+    /// Program Authority
+    /// Revoke Authority (signed)
+    #[cfg(feature = "onchain")]
+    pub fn revoke<S>(mut input: S) -> Result<(), ProgramError>
+    where
+        S: AccountSource<B>,
+        B: AccountBackend<Impl = Account>,
+    {
+        let RevokeVestingArgsAccounts {
+            token_program,
+
+            mut vesting,
+            mut spl_token_wallet_vault,
+            mut treasury_spl_token_wallet,
+            program_authority,
+            revoke_authority,
+        } = RevokeVestingArgsAccounts::from_program_input(&mut input)?;
+
+        if !vesting.is_revocable() {
+            qlog!("vesting is not revocable");
+            return Err(Error::Validation.into());
+        }
+
+        if !pubkey_eq(vesting.revoke_authority, revoke_authority.key()) {
+            qlog!("provided revoke authority does not match expected authority");
+            return Err(Error::InvalidAuthority.into());
+        }
+
+        if !revoke_authority.is_signer() {
+            qlog!("the revoke authority is expected to sign");
+            return Err(Error::Validation.into());
+        }
+
+        let now = timestamp_now();
+
+        if vesting.vested_amount(now) == vesting.total_amount {
+            qlog!("fully vested funds can't be revoked");
+            return Err(Error::Validation.into());
+        }
+
+        let amount = vesting.revoke(now);
+
+        let seeds = vesting.authority_seeds();
+        let amount_before = spl_token_wallet_vault.amount();
+        token_program
+            .transfer(
+                &mut spl_token_wallet_vault,
+                &mut treasury_spl_token_wallet,
+                amount.value(),
+                &program_authority,
+                &[&seeds],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("transfer failed");
+        let amount_after = spl_token_wallet_vault.amount();
+
+        assert!(amount_before - amount_after == amount);
+
+        Ok(())
+    }
+
+    /// Returns 4 instructions:
+    /// SystemProgram::CreateAccount (vault)
+    /// SplToken::Initialize (vault)
+    /// SystemProgram::Create (vesting)
+    /// Locker::CreateVesting (vesting)
+    ///
+    /// Source authority pays for the new accounts, vesting and vault are expected to sign.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_instruction(
+        program_id: Pubkey,
+        vesting: Pubkey,
+        vault: Pubkey,
+        owner: Pubkey,
+        source_wallet: Pubkey,
+        source_mint: Pubkey,
+        source_authority: Pubkey,
+        mut args: CreateVestingArgs,
+    ) -> [Instruction; 4] {
+        let (program_authority, program_authority_salt) =
+            find_program_authority(&program_id, &vesting);
+        args.program_authority_salt = program_authority_salt;
+
+        let [create_vault, initialize_vault] =
+            spl::create_wallet(&source_authority, &vault, &source_mint, &program_authority);
+
+        [
+            create_vault,
+            initialize_vault,
+            system::create_account(
+                &source_authority,
+                &vesting,
+                Vesting::default_lamports(),
+                Vesting::default_size() as u64,
+                &program_id,
+            ),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new_readonly(*spl::ID, false),
+                    AccountMeta::new(vesting, false),
+                    AccountMeta::new(source_wallet, false),
+                    AccountMeta::new_readonly(source_authority, true),
+                    AccountMeta::new(vault, false),
+                    AccountMeta::new_readonly(program_authority, false),
+                    AccountMeta::new_readonly(owner, false),
+                ],
+                data: Method::CreateVesting(args).encode(),
+            },
+        ]
+    }
+
+    pub fn claim_instruction(&self, destination_wallet: Pubkey) -> Instruction {
+        Instruction {
+            program_id: *self.account().owner(),
+            accounts: vec![
+                AccountMeta::new_readonly(*spl::ID, false),
+                AccountMeta::new(*self.account().key(), false),
+                AccountMeta::new(self.vault, false),
+                AccountMeta::new(destination_wallet, false),
+                AccountMeta::new_readonly(self.program_authority, false),
+                AccountMeta::new_readonly(self.owner, true),
+            ],
+            data: Method::Claim.encode(),
+        }
+    }
+
+    pub fn revoke_instruction(&self, treasury_wallet: Pubkey) -> Instruction {
+        Instruction {
+            program_id: *self.account().owner(),
+            accounts: vec![
+                AccountMeta::new_readonly(*spl::ID, false),
+                AccountMeta::new(*self.account().key(), false),
+                AccountMeta::new(self.vault, false),
+                AccountMeta::new(treasury_wallet, false),
+                AccountMeta::new_readonly(self.program_authority, false),
+                AccountMeta::new_readonly(self.revoke_authority, true),
+            ],
+            data: Method::RevokeVesting.encode(),
+        }
+    }
+
+    pub fn load(program_id: &Pubkey, account: B) -> Result<Self, Error> {
+        Self::raw_initialized(program_id, account)
+    }
+
+    #[inline]
+    pub fn authority_seeds(&self) -> [&[u8]; 2] {
+        [
+            self.account().key().as_ref(),
+            as_bytes(&self.program_authority_salt),
+        ]
+    }
+
+    #[inline]
+    pub fn program_authority(&self, account: B) -> Result<B, Error> {
+        if !pubkey_eq(&self.program_authority, account.key()) {
+            return Err(Error::InvalidAuthority);
+        }
+
+        Ok(account)
+    }
+
+    #[inline]
+    pub fn vault(&self, account: B) -> Result<WalletAccount<B>, Error> {
+        let wallet = WalletAccount::any(account)?;
+
+        if !pubkey_eq(&self.vault, wallet.key()) {
+            return Err(Error::InvalidAccount);
+        }
+
+        Ok(wallet)
+    }
+
+    #[inline]
+    pub fn wallet(&self, account: B) -> Result<WalletAccount<B>, Error> {
+        let wallet = WalletAccount::any(account)?;
+
+        if !pubkey_eq(&self.mint, wallet.mint()) {
+            return Err(Error::InvalidMint);
+        }
+
+        Ok(wallet)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use solana_api_types::Pubkey;
+
+    use crate::data::EntityAllocator;
+
+    use super::VestingState;
+
+    fn schedule(period: i64) -> VestingState {
+        VestingState {
+            owner: Pubkey::default(),
+            mint: Pubkey::default(),
+            vault: Pubkey::default(),
+            program_authority: Pubkey::default(),
+            program_authority_salt: 0,
+            revoke_authority: Pubkey::new_unique(),
+            start_date: 1000.into(),
+            cliff_date: 1250.into(),
+            end_date: 2000.into(),
+            period: period.into(),
+            total_amount: 1_000_000.into(),
+            claimed_amount: 0.into(),
+            allocator: EntityAllocator::default(),
+        }
+    }
+
+    #[test]
+    fn linear_vesting() {
+        let mut vesting = schedule(0);
+
+        assert_eq!(vesting.claimable(999.into()), 0.into());
+        assert_eq!(vesting.claimable(1249.into()), 0.into());
+        assert_eq!(vesting.claimable(1250.into()), 250_000.into());
+        assert_eq!(vesting.claimable(1500.into()), 500_000.into());
+        assert_eq!(vesting.claimable(2000.into()), 1_000_000.into());
+        assert_eq!(vesting.claimable(i64::MAX.into()), 1_000_000.into());
+
+        vesting.claimed_amount = 500_000.into();
+        assert_eq!(vesting.claimable(1250.into()), 0.into());
+        assert_eq!(vesting.claimable(1750.into()), 250_000.into());
+    }
+
+    #[test]
+    fn periodic_vesting() {
+        let vesting = schedule(250);
+
+        assert_eq!(vesting.claimable(1249.into()), 0.into());
+        assert_eq!(vesting.claimable(1250.into()), 250_000.into());
+        assert_eq!(vesting.claimable(1499.into()), 250_000.into());
+        assert_eq!(vesting.claimable(1500.into()), 500_000.into());
+        assert_eq!(vesting.claimable(1999.into()), 750_000.into());
+        assert_eq!(vesting.claimable(2000.into()), 1_000_000.into());
+    }
+
+    #[test]
+    fn revoked_vesting() {
+        let mut vesting = schedule(250);
+        vesting.claimed_amount = 250_000.into();
+
+        assert!(vesting.is_revocable());
+        assert_eq!(vesting.revoke(1600.into()), 500_000.into());
+        assert!(!vesting.is_revocable());
+
+        assert_eq!(vesting.total_amount, 500_000.into());
+        assert_eq!(vesting.claimable(1600.into()), 250_000.into());
+        assert_eq!(vesting.claimable(2000.into()), 250_000.into());
+
+        // nothing has vested before the cliff
+        let mut vesting = schedule(0);
+        assert_eq!(vesting.revoke(1100.into()), 1_000_000.into());
+        assert_eq!(vesting.claimable(i64::MAX.into()), 0.into());
+
+        // nothing is left to revoke once fully vested
+        let mut vesting = schedule(0);
+        assert_eq!(vesting.revoke(2500.into()), 0.into());
+        assert_eq!(vesting.claimable(2500.into()), 1_000_000.into());
+    }
+}
@@ -0,0 +1,76 @@
+//! Merkle tree of `(staker, max_allocation)` pairs restricting who can stake into a pool.
+//!
+//! Leaves and inner nodes are hashed with distinct prefixes, and children of a node are
+//! sorted before hashing, so that proofs only need sibling hashes without their positions.
+
+use solana_api_types::{hash::hashv, Pubkey};
+
+use crate::TokenAmount;
+
+pub type Node = [u8; 32];
+
+const LEAF_PREFIX: &[u8] = &[0];
+const NODE_PREFIX: &[u8] = &[1];
+
+pub fn leaf(staker: &Pubkey, max_allocation: TokenAmount) -> Node {
+    hashv(&[
+        LEAF_PREFIX,
+        staker.as_ref(),
+        &max_allocation.value().to_le_bytes(),
+    ])
+    .to_bytes()
+}
+
+pub fn parent(left: &Node, right: &Node) -> Node {
+    let (left, right) = if left <= right {
+        (left, right)
+    } else {
+        (right, left)
+    };
+
+    hashv(&[NODE_PREFIX, left, right]).to_bytes()
+}
+
+pub fn verify(root: &Node, leaf: Node, proof: &[Node]) -> bool {
+    let node = proof
+        .iter()
+        .fold(leaf, |node, sibling| parent(&node, sibling));
+
+    &node == root
+}
+
+#[cfg(test)]
+mod test {
+    use solana_api_types::Pubkey;
+
+    use super::{leaf, parent, verify};
+
+    #[test]
+    fn verify_proofs() {
+        let stakers = [
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        ];
+        let leaves = [
+            leaf(&stakers[0], 100.into()),
+            leaf(&stakers[1], 200.into()),
+            leaf(&stakers[2], 300.into()),
+        ];
+
+        let left = parent(&leaves[0], &leaves[1]);
+        let root = parent(&left, &leaves[2]);
+
+        assert!(verify(&root, leaves[0], &[leaves[1], leaves[2]]));
+        assert!(verify(&root, leaves[1], &[leaves[0], leaves[2]]));
+        assert!(verify(&root, leaves[2], &[left]));
+
+        assert!(!verify(
+            &root,
+            leaf(&stakers[0], 101.into()),
+            &[leaves[1], leaves[2]]
+        ));
+        assert!(!verify(&root, leaves[2], &[leaves[0]]));
+        assert!(!verify(&root, leaves[0], &[]));
+    }
+}
@@ -3,21 +3,71 @@ use std::{
     ops::{Deref, DerefMut},
 };
 
+#[cfg(feature = "onchain")]
+use az::CheckedAs;
+use fixed::types::U64F64;
 use solana_api_types::Pubkey;
+#[cfg(feature = "onchain")]
+use solar::{
+    account::onchain::Account,
+    input::AccountSource,
+    math::ToF64,
+    qlog,
+    spl::TokenProgram,
+    util::{is_zeroed, timestamp_now, ResultExt},
+};
 use solar::{
     account::{AccountFields, AccountFieldsMut},
+    math::Checked,
     prelude::AccountBackend,
-    reinterpret::{reinterpret_mut_unchecked, reinterpret_unchecked},
+    reinterpret::{
+        as_bytes, reinterpret_mut_unchecked, reinterpret_slice_mut_unchecked,
+        reinterpret_slice_unchecked, reinterpret_unchecked,
+    },
     spl::{MintAccount, WalletAccount},
+    util::pubkey_eq,
 };
+#[cfg(feature = "onchain")]
+use solar_macros::parse_accounts;
 
 use crate::{
-    data::{AccountType, Entity, EntityAllocator, EntityId, EntityKind},
+    data::{AccountType, Entity, EntityAllocator, EntityId, EntityKind, HEADER_RESERVED},
     error::Error,
+    TokenAmount, TokenAmountF64,
 };
 
+pub type FarmEntity<B> = Entity<B, Farm>;
+pub type RequestQueueEntity<B> = Entity<B, RequestQueue>;
+pub type StakerRegistryEntity<B> = Entity<B, StakerRegistry>;
+
+#[derive(Debug, PartialEq, Eq, Clone, parity_scale_codec::Encode, parity_scale_codec::Decode)]
+pub enum Method {
+    CreateFarm(InitializeArgs),
+    Stake { amount: TokenAmount },
+    Unstake { amount: TokenAmount },
+    Withdraw,
+    ClaimReward,
+    AddReward { amount: TokenAmount },
+    Crank { limit: u64 },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, parity_scale_codec::Encode, parity_scale_codec::Decode)]
+pub struct InitializeArgs {
+    pub program_authority_salt: u64,
+    pub epoch_duration: Checked<i64>,
+    pub reward_per_epoch: TokenAmount,
+    /// Smallest amount a stake or unstake request may move, unless it removes the whole
+    /// stake, so that the queue and the registry can't be filled with dust.
+    pub min_stake: TokenAmount,
+}
+
+/// Number of requests a queue created with [`RequestQueue::default_size`] can hold.
+pub const REQUEST_QUEUE_DEFAULT_CAPACITY: usize = 256;
+/// Number of stakers a registry created with [`StakerRegistry::default_size`] can hold.
+pub const STAKER_REGISTRY_DEFAULT_CAPACITY: usize = 256;
+
 #[repr(C)]
-pub struct StakePoolState {
+pub struct FarmState {
     pub administrator_authority: Pubkey,
     pub program_authority: Pubkey,
     pub stake_mint: Pubkey,
@@ -25,55 +75,158 @@ pub struct StakePoolState {
     pub inactive_stake_vault: Pubkey,
     pub reward_mint: Pubkey,
     pub reward_vault: Pubkey,
+    pub request_queue: Pubkey,
+    pub staker_registry: Pubkey,
 
     pub allocator: EntityAllocator,
-    pub active_stake: u64,
-    pub inactive_stake: u64,
+    /// Stake earning reward in the current epoch, held in the active vault.
+    pub active_stake: TokenAmount,
+    /// Stake waiting to be activated or withdrawn, held in the inactive vault.
+    pub inactive_stake: TokenAmount,
     pub program_authority_salt: u64,
-    pub program_authority_nonce: u8,
+
+    pub epoch: u64,
+    pub epoch_duration: Checked<i64>,
+    pub epoch_started_at: Checked<i64>,
+
+    pub reward_per_epoch: TokenAmount,
+    pub deposited_reward_amount: TokenAmount,
+    pub distributed_reward_amount: TokenAmount,
+    /// Reward accrued by a single active token since the farm was created.
+    pub reward_per_share: TokenAmountF64,
+
+    /// See [`InitializeArgs::min_stake`].
+    pub min_stake: TokenAmount,
 }
 
-pub const STAKE_POOL_STATE_RESERVED: usize = 512;
-const_assert!(size_of::<StakePoolState>() <= STAKE_POOL_STATE_RESERVED);
+pub const FARM_STATE_RESERVED: usize = 512;
+const_assert!(size_of::<FarmState>() <= FARM_STATE_RESERVED);
 
+/// Stake change of a staker, identified by their index in the [`StakerRegistry`].
 #[repr(C)]
+#[derive(Debug, Clone, Copy)]
 pub struct Request {
-    pub slot: u64,
-    pub kind: RequestKind,
+    /// Epoch in which the request was made, it is applied once that epoch ends.
+    pub epoch: u64,
+    pub staker: u64,
+    pub amount: TokenAmount,
+    /// [`RequestKind`] stored as a plain byte, as requests are read straight from account data.
+    pub kind: u8,
+    _reserved: [u8; 7],
 }
 
-#[repr(C)]
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RequestKind {
-    AddStake { staker: Pubkey, amount: u64 },
-    RemoveStake { staker: Pubkey, amount: u64 },
+    AddStake = 0x00,
+    RemoveStake = 0x01,
+}
+
+#[repr(C)]
+pub struct RequestQueueState {
+    pub head: u64,
+    pub len: u64,
 }
 
 #[repr(C)]
 pub struct Staker {
     pub authority: Pubkey,
-    pub active_stake: u64,
-    pub inactive_stake: u64,
-    pub unclaimed_reward: u64,
+    pub active_stake: TokenAmount,
+    /// Stake that was deactivated and can be withdrawn.
+    pub inactive_stake: TokenAmount,
+    /// Part of `active_stake * reward_per_share` that was already accounted for.
+    pub reward_debt: TokenAmountF64,
+    pub unclaimed_reward: TokenAmountF64,
+    /// Number of queued requests referring to the staker, whose slot can't be given back
+    /// until all of them are applied.
+    pub pending_requests: u64,
+}
+
+#[repr(C)]
+pub struct StakerRegistryState {
+    /// Number of slots ever taken, including the ones given back since.
+    pub len: u64,
+    _reserved: u64,
 }
 
+#[derive(Debug)]
 pub struct Farm;
+#[derive(Debug)]
 pub struct RequestQueue;
+#[derive(Debug)]
 pub struct StakerRegistry;
 
 impl AccountType for Farm {
     const KIND: EntityKind = EntityKind::Root;
 
     fn is_valid_size(size: usize) -> bool {
-        size >= STAKE_POOL_STATE_RESERVED
+        size >= FARM_STATE_RESERVED
     }
 
     fn default_size() -> usize {
-        todo!()
+        FARM_STATE_RESERVED + HEADER_RESERVED
+    }
+}
+
+impl AccountType for RequestQueue {
+    const KIND: EntityKind = EntityKind::RequestQueue;
+
+    fn is_valid_size(size: usize) -> bool {
+        size > size_of::<RequestQueueState>()
+            && (size - size_of::<RequestQueueState>()) % size_of::<Request>() == 0
+    }
+
+    fn default_size() -> usize {
+        size_of::<RequestQueueState>()
+            + size_of::<Request>() * REQUEST_QUEUE_DEFAULT_CAPACITY
+            + HEADER_RESERVED
+    }
+}
+
+impl AccountType for StakerRegistry {
+    const KIND: EntityKind = EntityKind::StakerRegistry;
+
+    fn is_valid_size(size: usize) -> bool {
+        size > size_of::<StakerRegistryState>()
+            && (size - size_of::<StakerRegistryState>()) % size_of::<Staker>() == 0
+    }
+
+    fn default_size() -> usize {
+        size_of::<StakerRegistryState>()
+            + size_of::<Staker>() * STAKER_REGISTRY_DEFAULT_CAPACITY
+            + HEADER_RESERVED
+    }
+}
+
+impl RequestKind {
+    pub fn from_u8(kind: u8) -> Option<Self> {
+        match kind {
+            0x00 => Some(RequestKind::AddStake),
+            0x01 => Some(RequestKind::RemoveStake),
+            _ => None,
+        }
+    }
+}
+
+impl Request {
+    pub fn new(epoch: u64, kind: RequestKind, staker: u64, amount: TokenAmount) -> Self {
+        Self {
+            epoch,
+            staker,
+            amount,
+            kind: kind as u8,
+            _reserved: [0; 7],
+        }
+    }
+
+    /// Kind of the request, `None` if the stored byte does not match any of them.
+    pub fn kind(&self) -> Option<RequestKind> {
+        RequestKind::from_u8(self.kind)
     }
 }
 
 impl<B: AccountBackend> Deref for Entity<B, Farm> {
-    type Target = StakePoolState;
+    type Target = FarmState;
 
     fn deref(&self) -> &Self::Target {
         unsafe { reinterpret_unchecked(self.body()) }
@@ -89,6 +242,274 @@ where
     }
 }
 
+#[derive(Debug)]
+pub struct InitializeArgsAccounts<B: AccountBackend> {
+    pub administrator_authority: B,
+    pub program_authority: B,
+    pub farm: B,
+    pub request_queue: B,
+    pub staker_registry: B,
+    pub stake_mint: MintAccount<B>,
+    pub active_stake_vault: WalletAccount<B>,
+    pub inactive_stake_vault: WalletAccount<B>,
+    pub reward_mint: MintAccount<B>,
+    pub reward_vault: WalletAccount<B>,
+}
+
+#[derive(Debug)]
+pub struct StakeArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+    pub farm: Entity<B, Farm>,
+    pub request_queue: Entity<B, RequestQueue>,
+    pub staker_registry: Entity<B, StakerRegistry>,
+    pub staker: B,
+    pub inactive_stake_vault: WalletAccount<B>,
+    pub source_authority: B,
+    pub source_wallet: WalletAccount<B>,
+}
+
+#[derive(Debug)]
+pub struct UnStakeArgsAccounts<B: AccountBackend> {
+    pub farm: Entity<B, Farm>,
+    pub request_queue: Entity<B, RequestQueue>,
+    pub staker_registry: Entity<B, StakerRegistry>,
+    pub staker: B,
+}
+
+#[derive(Debug)]
+pub struct WithdrawArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+    pub farm: Entity<B, Farm>,
+    pub staker_registry: Entity<B, StakerRegistry>,
+    pub staker: B,
+    pub program_authority: B,
+    pub inactive_stake_vault: WalletAccount<B>,
+    pub target_wallet: WalletAccount<B>,
+}
+
+#[derive(Debug)]
+pub struct ClaimRewardArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+    pub farm: Entity<B, Farm>,
+    pub staker_registry: Entity<B, StakerRegistry>,
+    pub staker: B,
+    pub program_authority: B,
+    pub reward_vault: WalletAccount<B>,
+    pub target_wallet: WalletAccount<B>,
+}
+
+#[derive(Debug)]
+pub struct AddRewardArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+    pub farm: Entity<B, Farm>,
+    pub reward_vault: WalletAccount<B>,
+    pub source_authority: B,
+    pub source_wallet: WalletAccount<B>,
+}
+
+#[derive(Debug)]
+pub struct CrankArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+    pub farm: Entity<B, Farm>,
+    pub request_queue: Entity<B, RequestQueue>,
+    pub staker_registry: Entity<B, StakerRegistry>,
+    pub program_authority: B,
+    pub active_stake_vault: WalletAccount<B>,
+    pub inactive_stake_vault: WalletAccount<B>,
+}
+
+impl<B: AccountBackend> InitializeArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        parse_accounts! {
+            &administrator_authority,
+            &program_authority,
+            &mut farm,
+            &mut request_queue,
+            &mut staker_registry,
+            &stake_mint = MintAccount::any(this)?,
+            &active_stake_vault = stake_mint.wallet(this)?,
+            &inactive_stake_vault = stake_mint.wallet(this)?,
+            &reward_mint = MintAccount::any(this)?,
+            &reward_vault = reward_mint.wallet(this)?
+        }
+
+        Ok(Self {
+            administrator_authority,
+            program_authority,
+            farm,
+            request_queue,
+            staker_registry,
+            stake_mint,
+            active_stake_vault,
+            inactive_stake_vault,
+            reward_mint,
+            reward_vault,
+        })
+    }
+}
+
+impl<B: AccountBackend> StakeArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts!(
+            &token_program = TokenProgram::load(this)?,
+            &mut farm = <Entity<B, Farm>>::load(&program_id, this)?,
+            &mut request_queue = farm.load_request_queue(this)?,
+            &mut staker_registry = farm.load_staker_registry(this)?,
+            &staker,
+            &mut inactive_stake_vault = farm.load_inactive_stake_vault(this)?,
+            &source_authority,
+            &mut source_wallet = farm.load_stake_wallet(this)?
+        );
+
+        Ok(Self {
+            token_program,
+            farm,
+            request_queue,
+            staker_registry,
+            staker,
+            inactive_stake_vault,
+            source_authority,
+            source_wallet,
+        })
+    }
+}
+
+impl<B: AccountBackend> UnStakeArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts!(
+            &farm = <Entity<B, Farm>>::load(&program_id, this)?,
+            &mut request_queue = farm.load_request_queue(this)?,
+            &mut staker_registry = farm.load_staker_registry(this)?,
+            &staker
+        );
+
+        Ok(Self {
+            farm,
+            request_queue,
+            staker_registry,
+            staker,
+        })
+    }
+}
+
+impl<B: AccountBackend> WithdrawArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts!(
+            &token_program = TokenProgram::load(this)?,
+            &mut farm = <Entity<B, Farm>>::load(&program_id, this)?,
+            &mut staker_registry = farm.load_staker_registry(this)?,
+            &staker,
+            &program_authority,
+            &mut inactive_stake_vault = farm.load_inactive_stake_vault(this)?,
+            &mut target_wallet = farm.load_stake_wallet(this)?
+        );
+
+        Ok(Self {
+            token_program,
+            farm,
+            staker_registry,
+            staker,
+            program_authority,
+            inactive_stake_vault,
+            target_wallet,
+        })
+    }
+}
+
+impl<B: AccountBackend> ClaimRewardArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts!(
+            &token_program = TokenProgram::load(this)?,
+            &mut farm = <Entity<B, Farm>>::load(&program_id, this)?,
+            &mut staker_registry = farm.load_staker_registry(this)?,
+            &staker,
+            &program_authority,
+            &mut reward_vault = farm.load_reward_vault(this)?,
+            &mut target_wallet = farm.load_reward_wallet(this)?
+        );
+
+        Ok(Self {
+            token_program,
+            farm,
+            staker_registry,
+            staker,
+            program_authority,
+            reward_vault,
+            target_wallet,
+        })
+    }
+}
+
+impl<B: AccountBackend> AddRewardArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts!(
+            &token_program = TokenProgram::load(this)?,
+            &mut farm = <Entity<B, Farm>>::load(&program_id, this)?,
+            &mut reward_vault = farm.load_reward_vault(this)?,
+            &source_authority,
+            &mut source_wallet = farm.load_reward_wallet(this)?
+        );
+
+        Ok(Self {
+            token_program,
+            farm,
+            reward_vault,
+            source_authority,
+            source_wallet,
+        })
+    }
+}
+
+impl<B: AccountBackend> CrankArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts!(
+            &token_program = TokenProgram::load(this)?,
+            &mut farm = <Entity<B, Farm>>::load(&program_id, this)?,
+            &mut request_queue = farm.load_request_queue(this)?,
+            &mut staker_registry = farm.load_staker_registry(this)?,
+            &program_authority,
+            &mut active_stake_vault = farm.load_active_stake_vault(this)?,
+            &mut inactive_stake_vault = farm.load_inactive_stake_vault(this)?
+        );
+
+        Ok(Self {
+            token_program,
+            farm,
+            request_queue,
+            staker_registry,
+            program_authority,
+            active_stake_vault,
+            inactive_stake_vault,
+        })
+    }
+}
+
 impl<B: AccountBackend> Entity<B, Farm> {
     pub fn load(program_id: &Pubkey, account: B) -> Result<Self, Error> {
         let farm = Self::raw_initialized(program_id, account)?;
@@ -105,6 +526,60 @@ impl<B: AccountBackend> Entity<B, Farm> {
         Ok(farm)
     }
 
+    pub fn load_request_queue(&self, account: B) -> Result<Entity<B, RequestQueue>, Error> {
+        let queue = Entity::<B, RequestQueue>::raw_initialized(self.account().owner(), account)?;
+
+        if !pubkey_eq(queue.account().key(), &self.request_queue) || !queue.is_child(self) {
+            return Err(Error::InvalidParent);
+        }
+
+        Ok(queue)
+    }
+
+    pub fn load_staker_registry(&self, account: B) -> Result<Entity<B, StakerRegistry>, Error> {
+        let registry =
+            Entity::<B, StakerRegistry>::raw_initialized(self.account().owner(), account)?;
+
+        if !pubkey_eq(registry.account().key(), &self.staker_registry) || !registry.is_child(self) {
+            return Err(Error::InvalidParent);
+        }
+
+        Ok(registry)
+    }
+
+    #[inline]
+    pub fn authority_seeds(&self) -> [&[u8]; 3] {
+        [
+            self.account().key().as_ref(),
+            self.administrator_authority.as_ref(),
+            as_bytes(&self.program_authority_salt),
+        ]
+    }
+
+    /// Whether the current epoch has ended and the crank is allowed to start the next one.
+    #[inline]
+    pub fn is_epoch_over(&self, now: Checked<i64>) -> bool {
+        now >= self.epoch_started_at + self.epoch_duration
+    }
+
+    /// Epoch `now` falls into, including epochs that have ended but were not started
+    /// by the crank yet, so that requests made meanwhile wait for the epoch they were made in.
+    #[inline]
+    pub fn current_epoch(&self, now: Checked<i64>) -> u64 {
+        if !self.is_epoch_over(now) {
+            return self.epoch;
+        }
+
+        let elapsed = (now - self.epoch_started_at) / self.epoch_duration;
+        self.epoch + elapsed.value() as u64
+    }
+
+    /// Deposited reward that was not distributed to stakers yet.
+    #[inline]
+    pub fn undistributed_reward(&self) -> TokenAmount {
+        self.deposited_reward_amount - self.distributed_reward_amount
+    }
+
     fn wallet<T: AccountBackend>(&self, account: T) -> Result<WalletAccount<T>, Error> {
         let wallet = WalletAccount::any(account)?;
         if wallet.authority() != &self.program_authority {
@@ -126,18 +601,66 @@ impl<B: AccountBackend> Entity<B, Farm> {
         Ok(wallet)
     }
 
+    pub fn load_active_stake_vault<T: AccountBackend>(
+        &self,
+        account: T,
+    ) -> Result<WalletAccount<T>, Error> {
+        let wallet = self.load_stake_vault(account)?;
+        if wallet.key() != &self.active_stake_vault {
+            return Err(Error::InvalidAccount);
+        }
+
+        Ok(wallet)
+    }
+
+    pub fn load_inactive_stake_vault<T: AccountBackend>(
+        &self,
+        account: T,
+    ) -> Result<WalletAccount<T>, Error> {
+        let wallet = self.load_stake_vault(account)?;
+        if wallet.key() != &self.inactive_stake_vault {
+            return Err(Error::InvalidAccount);
+        }
+
+        Ok(wallet)
+    }
+
     pub fn load_reward_vault<T: AccountBackend>(
         &self,
         account: T,
     ) -> Result<WalletAccount<T>, Error> {
         let wallet = self.wallet(account)?;
-        if wallet.mint() != &self.reward_mint {
+        if wallet.mint() != &self.reward_mint || wallet.key() != &self.reward_vault {
             return Err(Error::Validation);
         }
 
         Ok(wallet)
     }
 
+    pub fn load_stake_wallet<T: AccountBackend>(
+        &self,
+        account: T,
+    ) -> Result<WalletAccount<T>, Error> {
+        let wallet = WalletAccount::any(account)?;
+        if wallet.mint() != &self.stake_mint {
+            return Err(Error::InvalidMint);
+        }
+
+        Ok(wallet)
+    }
+
+    pub fn load_reward_wallet<T: AccountBackend>(
+        &self,
+        account: T,
+    ) -> Result<WalletAccount<T>, Error> {
+        let wallet = WalletAccount::any(account)?;
+        if wallet.mint() != &self.reward_mint {
+            return Err(Error::InvalidMint);
+        }
+
+        Ok(wallet)
+    }
+
     pub fn load_stake_mint<T: AccountBackend>(&self, account: T) -> Result<MintAccount<T>, Error> {
         let mint = MintAccount::any(account)?;
         if mint.key() != &self.stake_mint {
@@ -161,16 +684,846 @@ impl<B: AccountBackend> Entity<B, Farm>
 where
     B::Impl: AccountFieldsMut,
 {
-    pub fn initialize(program_id: &Pubkey, destination: B) -> Result<Self, Error> {
-        let mut farm = Self::raw_initialized(program_id, destination)?;
+    /// Distributes reward of the ended epoch between active stakers and starts the next one.
+    #[cfg(feature = "onchain")]
+    fn advance_epoch(&mut self) {
+        if self.active_stake != 0.into() {
+            let reward = self.reward_per_epoch.min(self.undistributed_reward());
+
+            // truncating division rounds the accumulator down, toward the farm
+            self.reward_per_share += reward.to_u64f64() / self.active_stake.to_u64f64();
+            self.distributed_reward_amount += reward;
+        }
+
+        self.epoch += 1;
+        self.epoch_started_at += self.epoch_duration;
+    }
+
+    /// Applies a single request to the registry, returning amounts that have to be
+    /// moved to the active and to the inactive vault respectively.
+    #[cfg(feature = "onchain")]
+    fn apply_request(
+        &mut self,
+        registry: &mut Entity<B, StakerRegistry>,
+        request: &Request,
+    ) -> Result<(TokenAmount, TokenAmount), Error> {
+        let reward_per_share = self.reward_per_share;
+        let amount = request.amount;
+
+        match request.kind() {
+            Some(RequestKind::AddStake) => {
+                let staker = registry.get_mut(request.staker)?;
+                staker.pending_requests -= 1;
+                let active_stake = staker.active_stake + amount;
+                staker.set_active_stake(active_stake, reward_per_share);
+
+                self.active_stake += amount;
+                self.inactive_stake -= amount;
+
+                Ok((amount, 0.into()))
+            }
+            Some(RequestKind::RemoveStake) => {
+                let staker = registry.get_mut(request.staker)?;
+                staker.pending_requests -= 1;
+                // removals never exceed the active stake when requested, clamped just in case
+                let amount = amount.min(staker.active_stake);
+                let active_stake = staker.active_stake - amount;
+                staker.set_active_stake(active_stake, reward_per_share);
+                staker.inactive_stake += amount;
+
+                self.active_stake -= amount;
+                self.inactive_stake += amount;
+
+                Ok((0.into(), amount))
+            }
+            None => {
+                qlog!("unknown request kind");
+                Err(Error::InvalidData)
+            }
+        }
+    }
+
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn initialize<T>(input: &mut T, args: InitializeArgs) -> Result<(), Error>
+    where
+        T: AccountSource<B>,
+    {
+        let InitializeArgsAccounts {
+            administrator_authority,
+            program_authority,
+            farm,
+            request_queue,
+            staker_registry,
+            stake_mint,
+            active_stake_vault,
+            inactive_stake_vault,
+            reward_mint,
+            reward_vault,
+        } = InitializeArgsAccounts::from_program_input(input)?;
+
+        let mut farm = Self::raw_any(input.program_id(), farm)?;
+        let mut request_queue =
+            Entity::<B, RequestQueue>::raw_any(input.program_id(), request_queue)?;
+        let mut staker_registry =
+            Entity::<B, StakerRegistry>::raw_any(input.program_id(), staker_registry)?;
+
+        if !is_zeroed(farm.account().data())
+            || !is_zeroed(request_queue.account().data())
+            || !is_zeroed(staker_registry.account().data())
+        {
+            qlog!("farm accounts are expected to be uninitialized");
+            return Err(Error::InvalidData);
+        }
+
+        let expected_program_authority = Pubkey::create_program_address(
+            &[
+                farm.account().key().as_ref(),
+                administrator_authority.key().as_ref(),
+                &args.program_authority_salt.to_le_bytes(),
+            ],
+            input.program_id(),
+        )
+        .bpf_expect("couldn't derive program authority");
+
+        if !pubkey_eq(program_authority.key(), &expected_program_authority) {
+            qlog!("provided program authority does not match expected authority");
+            return Err(Error::InvalidAuthority);
+        }
+
+        for vault in [&active_stake_vault, &inactive_stake_vault, &reward_vault].iter() {
+            if !pubkey_eq(vault.authority(), &expected_program_authority) {
+                qlog!("vault authority does not match program authority");
+                return Err(Error::InvalidAuthority);
+            }
+        }
 
-        let key = *farm.account().key();
+        if pubkey_eq(active_stake_vault.key(), inactive_stake_vault.key())
+            || pubkey_eq(reward_vault.key(), active_stake_vault.key())
+            || pubkey_eq(reward_vault.key(), inactive_stake_vault.key())
+        {
+            qlog!("farm vaults should be distinct");
+            return Err(Error::InvalidAccount);
+        }
+
+        if args.epoch_duration <= 0.into() {
+            qlog!("epoch_duration should be positive");
+            return Err(Error::InvalidData);
+        }
+
+        let farm_key = *farm.account().key();
+
+        farm.administrator_authority = *administrator_authority.key();
+        farm.program_authority = *program_authority.key();
+        farm.program_authority_salt = args.program_authority_salt;
+        farm.stake_mint = *stake_mint.key();
+        farm.active_stake_vault = *active_stake_vault.key();
+        farm.inactive_stake_vault = *inactive_stake_vault.key();
+        farm.reward_mint = *reward_mint.key();
+        farm.reward_vault = *reward_vault.key();
+        farm.request_queue = *request_queue.account().key();
+        farm.staker_registry = *staker_registry.account().key();
+
+        farm.epoch = 0;
+        farm.epoch_duration = args.epoch_duration;
+        farm.epoch_started_at = timestamp_now();
+        farm.reward_per_epoch = args.reward_per_epoch;
+        farm.min_stake = args.min_stake;
+
+        let farm_id = farm.allocator.allocate_id();
         let header = farm.header_mut();
         header.kind = EntityKind::Root;
-        header.root = key;
-        header.parent_id = EntityId::new(0);
-        header.id = EntityId::new(0);
+        header.version = Farm::VERSION;
+        header.root = farm_key;
+        header.parent_id = farm_id;
+        header.id = farm_id;
 
-        Ok(farm)
+        let id = farm.allocator.allocate_id();
+        let header = request_queue.header_mut();
+        header.kind = EntityKind::RequestQueue;
+        header.version = RequestQueue::VERSION;
+        header.root = farm_key;
+        header.parent_id = farm_id;
+        header.id = id;
+
+        let id = farm.allocator.allocate_id();
+        let header = staker_registry.header_mut();
+        header.kind = EntityKind::StakerRegistry;
+        header.version = StakerRegistry::VERSION;
+        header.root = farm_key;
+        header.parent_id = farm_id;
+        header.id = id;
+
+        Ok(())
+    }
+
+    /// Moves funds into the inactive vault and enqueues them for activation
+    /// at the end of the current epoch.
+    ///
+    /// Stake added during the same epoch is merged into a single request.
+    ///
+    /// Input accounts:
+    /// SPL Token Program
+    /// Farm
+    /// Request Queue
+    /// Staker Registry
+    /// Staker (signed)
+    /// SPL Token Wallet inactive stake vault
+    /// Source Authority (signed)
+    /// SPL Token Wallet source
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn add_stake<T>(input: &mut T, amount: TokenAmount) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let StakeArgsAccounts {
+            token_program,
+            mut farm,
+            mut request_queue,
+            mut staker_registry,
+            staker,
+            mut inactive_stake_vault,
+            source_authority,
+            mut source_wallet,
+        } = StakeArgsAccounts::from_program_input(input)?;
+
+        if !staker.is_signer() {
+            qlog!("the staker is expected to sign");
+            return Err(Error::Validation);
+        }
+
+        if amount == 0.into() || source_wallet.amount() < amount {
+            qlog!("not enough funds in wallet");
+            return Err(Error::Validation);
+        }
+
+        if amount < farm.min_stake {
+            qlog!("stake is below the minimum");
+            return Err(Error::Validation);
+        }
+
+        let index = staker_registry
+            .find_or_register(staker.key())
+            .map_err(|error| {
+                qlog!("staker registry is full");
+                error
+            })?;
+        let epoch = farm.current_epoch(timestamp_now());
+        let is_enqueued = request_queue
+            .merge_or_push_back(Request::new(epoch, RequestKind::AddStake, index, amount))
+            .map_err(|error| {
+                qlog!("request queue is full");
+                error
+            })?;
+
+        if is_enqueued {
+            staker_registry.get_mut(index)?.pending_requests += 1;
+        }
+
+        let amount_before = inactive_stake_vault.amount();
+        token_program
+            .transfer(
+                &mut source_wallet,
+                &mut inactive_stake_vault,
+                amount.value(),
+                &source_authority,
+                &[],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("transfer failed");
+        let amount_after = inactive_stake_vault.amount();
+
+        assert!(amount_after - amount_before == amount);
+
+        farm.inactive_stake += amount;
+
+        Ok(())
+    }
+
+    /// Enqueues active stake for deactivation at the end of the current epoch.
+    ///
+    /// Removals requested during the same epoch are merged into a single request,
+    /// and all pending removals together can't exceed the active stake.
+    ///
+    /// Input accounts:
+    /// Farm
+    /// Request Queue
+    /// Staker Registry
+    /// Staker (signed)
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn remove_stake<T>(input: &mut T, amount: TokenAmount) -> Result<(), Error>
+    where
+        T: AccountSource<B>,
+    {
+        let UnStakeArgsAccounts {
+            farm,
+            mut request_queue,
+            mut staker_registry,
+            staker,
+        } = UnStakeArgsAccounts::from_program_input(input)?;
+
+        if !staker.is_signer() {
+            qlog!("the staker is expected to sign");
+            return Err(Error::Validation);
+        }
+
+        let index = staker_registry.find(staker.key()).ok_or_else(|| {
+            qlog!("staker is not registered");
+            Error::Validation
+        })?;
+
+        let active_stake = staker_registry.get(index)?.active_stake;
+        let pending = request_queue.pending_amount(index, RequestKind::RemoveStake);
+
+        if amount == 0.into() || pending + amount > active_stake {
+            qlog!("not enough active stake");
+            return Err(Error::Validation);
+        }
+
+        if amount < farm.min_stake && pending + amount != active_stake {
+            qlog!("unstake is below the minimum");
+            return Err(Error::Validation);
+        }
+
+        let epoch = farm.current_epoch(timestamp_now());
+        let is_enqueued = request_queue
+            .merge_or_push_back(Request::new(epoch, RequestKind::RemoveStake, index, amount))
+            .map_err(|error| {
+                qlog!("request queue is full");
+                error
+            })?;
+
+        if is_enqueued {
+            staker_registry.get_mut(index)?.pending_requests += 1;
+        }
+
+        Ok(())
+    }
+
+    /// Withdraws all of the staker's deactivated stake, giving the registry slot back
+    /// if nothing is left to the staker.
+    ///
+    /// Input accounts:
+    /// SPL Token Program
+    /// Farm
+    /// Staker Registry
+    /// Staker (signed)
+    /// Program Authority
+    /// SPL Token Wallet inactive stake vault
+    /// SPL Token Wallet target
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn withdraw<T>(input: &mut T) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let WithdrawArgsAccounts {
+            token_program,
+            mut farm,
+            mut staker_registry,
+            staker,
+            program_authority,
+            mut inactive_stake_vault,
+            mut target_wallet,
+        } = WithdrawArgsAccounts::from_program_input(input)?;
+
+        if !staker.is_signer() {
+            qlog!("the staker is expected to sign");
+            return Err(Error::Validation);
+        }
+
+        let index = staker_registry.find(staker.key()).ok_or_else(|| {
+            qlog!("staker is not registered");
+            Error::Validation
+        })?;
+        let entry = staker_registry.get_mut(index)?;
+        let amount = entry.inactive_stake;
+
+        if amount == 0.into() {
+            qlog!("nothing to withdraw");
+            return Err(Error::Validation);
+        }
+
+        let seeds = farm.authority_seeds();
+        let amount_before = inactive_stake_vault.amount();
+        token_program
+            .transfer(
+                &mut inactive_stake_vault,
+                &mut target_wallet,
+                amount.value(),
+                &program_authority,
+                &[&seeds],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("transfer failed");
+        let amount_after = inactive_stake_vault.amount();
+
+        assert!(amount_before - amount_after == amount);
+
+        entry.inactive_stake = 0.into();
+        farm.inactive_stake -= amount;
+
+        staker_registry.release_if_empty(index)?;
+
+        Ok(())
+    }
+
+    /// Gives the registry slot back if nothing is left to the staker.
+    ///
+    /// Input accounts:
+    /// SPL Token Program
+    /// Farm
+    /// Staker Registry
+    /// Staker (signed)
+    /// Program Authority
+    /// SPL Token Wallet reward vault
+    /// SPL Token Wallet target
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn claim_reward<T>(input: &mut T) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let ClaimRewardArgsAccounts {
+            token_program,
+            farm,
+            mut staker_registry,
+            staker,
+            program_authority,
+            mut reward_vault,
+            mut target_wallet,
+        } = ClaimRewardArgsAccounts::from_program_input(input)?;
+
+        if !staker.is_signer() {
+            qlog!("the staker is expected to sign");
+            return Err(Error::Validation);
+        }
+
+        let index = staker_registry.find(staker.key()).ok_or_else(|| {
+            qlog!("staker is not registered");
+            Error::Validation
+        })?;
+        let entry = staker_registry.get_mut(index)?;
+        entry.set_active_stake(entry.active_stake, farm.reward_per_share);
+
+        // fractional part stays with the staker until it adds up to a whole token
+        let reward = entry
+            .unclaimed_reward
+            .value()
+            .floor()
+            .checked_as::<u64>()
+            .bpf_unwrap();
+        let reward = TokenAmount::from(reward);
+
+        if reward == 0.into() {
+            qlog!("nothing to claim");
+            return Err(Error::Validation);
+        }
+
+        let seeds = farm.authority_seeds();
+        let amount_before = reward_vault.amount();
+        token_program
+            .transfer(
+                &mut reward_vault,
+                &mut target_wallet,
+                reward.value(),
+                &program_authority,
+                &[&seeds],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("transfer failed");
+        let amount_after = reward_vault.amount();
+
+        assert!(amount_before - amount_after == reward);
+
+        entry.unclaimed_reward -= reward.to_u64f64();
+
+        staker_registry.release_if_empty(index)?;
+
+        Ok(())
+    }
+
+    /// Input accounts:
+    /// SPL Token Program
+    /// Farm
+    /// SPL Token Wallet reward vault
+    /// Source Authority (signed)
+    /// SPL Token Wallet source
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn add_reward<T>(input: &mut T, amount: TokenAmount) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let AddRewardArgsAccounts {
+            token_program,
+            mut farm,
+            mut reward_vault,
+            source_authority,
+            mut source_wallet,
+        } = AddRewardArgsAccounts::from_program_input(input)?;
+
+        let transfer_amount = amount.min(source_wallet.amount());
+
+        if transfer_amount == 0.into() {
+            qlog!("no reward to deposit");
+            return Err(Error::Validation);
+        }
+
+        let amount_before = reward_vault.amount();
+        token_program
+            .transfer(
+                &mut source_wallet,
+                &mut reward_vault,
+                transfer_amount.value(),
+                &source_authority,
+                &[],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("transfer failed");
+        let amount_after = reward_vault.amount();
+        assert!(amount_after - amount_before == transfer_amount);
+
+        farm.deposited_reward_amount += transfer_amount;
+
+        Ok(())
+    }
+
+    /// Applies queued requests of ended epochs and starts new epochs once they are due.
+    ///
+    /// Anyone can call this method, at most `limit` requests and epoch changes are
+    /// processed per call, so the crank has to be repeated for long queues.
+    ///
+    /// Input accounts:
+    /// SPL Token Program
+    /// Farm
+    /// Request Queue
+    /// Staker Registry
+    /// Program Authority
+    /// SPL Token Wallet active stake vault
+    /// SPL Token Wallet inactive stake vault
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn crank<T>(input: &mut T, limit: u64) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let CrankArgsAccounts {
+            token_program,
+            mut farm,
+            mut request_queue,
+            mut staker_registry,
+            program_authority,
+            mut active_stake_vault,
+            mut inactive_stake_vault,
+        } = CrankArgsAccounts::from_program_input(input)?;
+
+        let now = timestamp_now();
+
+        let mut steps = 0;
+        let mut activated: TokenAmount = 0.into();
+        let mut deactivated: TokenAmount = 0.into();
+
+        while steps < limit {
+            let is_ready = match request_queue.front() {
+                Some(request) => request.epoch < farm.epoch,
+                None => false,
+            };
+
+            if is_ready {
+                let request = request_queue.pop_front().bpf_unwrap();
+                let (to_active, to_inactive) =
+                    farm.apply_request(&mut staker_registry, &request)?;
+                activated += to_active;
+                deactivated += to_inactive;
+            } else if farm.is_epoch_over(now) {
+                // requests of the previous epoch are all applied at this point,
+                // so the reward is distributed against the settled active stake
+                farm.advance_epoch();
+            } else {
+                break;
+            }
+
+            steps += 1;
+        }
+
+        if steps == 0 {
+            qlog!("nothing to crank");
+            return Err(Error::Validation);
+        }
+
+        let seeds = farm.authority_seeds();
+
+        if activated > deactivated {
+            let amount = activated - deactivated;
+            token_program
+                .transfer(
+                    &mut inactive_stake_vault,
+                    &mut active_stake_vault,
+                    amount.value(),
+                    &program_authority,
+                    &[&seeds],
+                )
+                .bpf_expect("call failed")
+                .bpf_expect("transfer failed");
+        } else if deactivated > activated {
+            let amount = deactivated - activated;
+            token_program
+                .transfer(
+                    &mut active_stake_vault,
+                    &mut inactive_stake_vault,
+                    amount.value(),
+                    &program_authority,
+                    &[&seeds],
+                )
+                .bpf_expect("call failed")
+                .bpf_expect("transfer failed");
+        }
+
+        Ok(())
+    }
+}
+
+impl<B: AccountBackend> Entity<B, RequestQueue> {
+    fn state(&self) -> &RequestQueueState {
+        unsafe { reinterpret_unchecked(self.body()) }
+    }
+
+    fn requests(&self) -> &[Request] {
+        let data = &self.body()[size_of::<RequestQueueState>()..];
+        unsafe { reinterpret_slice_unchecked(data) }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.requests().len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.state().len as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pending requests, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &Request> {
+        let head = self.state().head as usize;
+        let requests = self.requests();
+
+        (0..self.len()).map(move |i| &requests[(head + i) % requests.len()])
+    }
+
+    pub fn front(&self) -> Option<&Request> {
+        self.iter().next()
+    }
+
+    /// Total amount of pending requests of `kind` made by the staker at `staker` index.
+    pub fn pending_amount(&self, staker: u64, kind: RequestKind) -> TokenAmount {
+        self.iter()
+            .filter(|request| request.staker == staker && request.kind() == Some(kind))
+            .fold(0.into(), |amount, request| amount + request.amount)
+    }
+}
+
+impl<B: AccountBackend> Entity<B, RequestQueue>
+where
+    B::Impl: AccountFieldsMut,
+{
+    fn split_mut(&mut self) -> (&mut RequestQueueState, &mut [Request]) {
+        let (state, requests) = self.body_mut().split_at_mut(size_of::<RequestQueueState>());
+
+        unsafe {
+            (
+                reinterpret_mut_unchecked(state),
+                reinterpret_slice_mut_unchecked(requests),
+            )
+        }
+    }
+
+    pub fn push_back(&mut self, request: Request) -> Result<(), Error> {
+        let (state, requests) = self.split_mut();
+
+        if state.len as usize == requests.len() {
+            return Err(Error::Validation);
+        }
+
+        let index = (state.head + state.len) as usize % requests.len();
+        requests[index] = request;
+        state.len += 1;
+
+        Ok(())
+    }
+
+    /// Adds `request` to a pending request of the same staker, kind and epoch,
+    /// enqueueing it only if there is none. Returns whether the request was enqueued.
+    pub fn merge_or_push_back(&mut self, request: Request) -> Result<bool, Error> {
+        let (state, requests) = self.split_mut();
+        let capacity = requests.len();
+
+        for i in 0..state.len as usize {
+            let pending = &mut requests[(state.head as usize + i) % capacity];
+
+            if pending.staker == request.staker
+                && pending.kind == request.kind
+                && pending.epoch == request.epoch
+            {
+                pending.amount += request.amount;
+                return Ok(false);
+            }
+        }
+
+        self.push_back(request)?;
+
+        Ok(true)
+    }
+
+    pub fn pop_front(&mut self) -> Option<Request> {
+        let (state, requests) = self.split_mut();
+
+        if state.len == 0 {
+            return None;
+        }
+
+        let request = requests[state.head as usize];
+        state.head = (state.head + 1) % requests.len() as u64;
+        state.len -= 1;
+
+        Some(request)
+    }
+}
+
+impl<B: AccountBackend> Entity<B, StakerRegistry> {
+    fn state(&self) -> &StakerRegistryState {
+        unsafe { reinterpret_unchecked(self.body()) }
+    }
+
+    /// Registered stakers, in order of registration.
+    pub fn stakers(&self) -> &[Staker] {
+        let data = &self.body()[size_of::<StakerRegistryState>()..];
+        let stakers: &[Staker] = unsafe { reinterpret_slice_unchecked(data) };
+
+        &stakers[..self.state().len as usize]
+    }
+
+    pub fn capacity(&self) -> usize {
+        (self.body().len() - size_of::<StakerRegistryState>()) / size_of::<Staker>()
+    }
+
+    pub fn find(&self, authority: &Pubkey) -> Option<u64> {
+        self.stakers()
+            .iter()
+            .position(|staker| pubkey_eq(&staker.authority, authority))
+            .map(|index| index as u64)
+    }
+
+    pub fn get(&self, index: u64) -> Result<&Staker, Error> {
+        self.stakers().get(index as usize).ok_or(Error::InvalidData)
+    }
+}
+
+impl<B: AccountBackend> Entity<B, StakerRegistry>
+where
+    B::Impl: AccountFieldsMut,
+{
+    fn split_mut(&mut self) -> (&mut StakerRegistryState, &mut [Staker]) {
+        let (state, stakers) = self
+            .body_mut()
+            .split_at_mut(size_of::<StakerRegistryState>());
+
+        unsafe {
+            (
+                reinterpret_mut_unchecked(state),
+                reinterpret_slice_mut_unchecked(stakers),
+            )
+        }
+    }
+
+    pub fn get_mut(&mut self, index: u64) -> Result<&mut Staker, Error> {
+        let (state, stakers) = self.split_mut();
+
+        if index >= state.len {
+            return Err(Error::InvalidData);
+        }
+
+        Ok(&mut stakers[index as usize])
+    }
+
+    /// Returns index of the staker, registering them first if needed.
+    ///
+    /// Slots given back by stakers who left are taken before new ones. A slot is only given
+    /// back once no request refers to it, so the index stays valid while requests are queued.
+    pub fn find_or_register(&mut self, authority: &Pubkey) -> Result<u64, Error> {
+        if let Some(index) = self.find(authority) {
+            return Ok(index);
+        }
+
+        let (state, stakers) = self.split_mut();
+
+        let free = stakers[..state.len as usize]
+            .iter()
+            .position(|staker| staker.is_free());
+
+        let index = match free {
+            Some(index) => index as u64,
+            None if state.len as usize == stakers.len() => return Err(Error::Validation),
+            None => {
+                state.len += 1;
+                state.len - 1
+            }
+        };
+        stakers[index as usize].authority = *authority;
+
+        Ok(index)
+    }
+
+    /// Gives the slot of the staker back, if they hold no stake, reward or pending request.
+    /// Reward below a single token is left to the farm.
+    pub fn release_if_empty(&mut self, index: u64) -> Result<bool, Error> {
+        let staker = self.get_mut(index)?;
+
+        if !staker.is_empty() {
+            return Ok(false);
+        }
+
+        staker.authority = Pubkey::default();
+        staker.reward_debt = U64F64::from_bits(0).into();
+        staker.unclaimed_reward = U64F64::from_bits(0).into();
+
+        Ok(true)
+    }
+}
+
+impl Staker {
+    /// Whether the slot was given back and can be taken by another staker.
+    #[inline]
+    pub fn is_free(&self) -> bool {
+        self.authority == Pubkey::default()
+    }
+
+    /// Whether the staker holds no stake, no pending request and less than a token of reward.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.active_stake == 0.into()
+            && self.inactive_stake == 0.into()
+            && self.pending_requests == 0
+            && self.unclaimed_reward.value() < U64F64::from_num(1)
+    }
+
+    /// Updates active stake, moving reward accrued so far into `unclaimed_reward`.
+    #[cfg(feature = "onchain")]
+    pub fn set_active_stake(&mut self, amount: TokenAmount, reward_per_share: TokenAmountF64) {
+        let accrued_reward = self.active_stake.to_u64f64() * reward_per_share;
+        self.unclaimed_reward += accrued_reward - self.reward_debt;
+
+        self.active_stake = amount;
+        self.reward_debt = amount.to_u64f64() * reward_per_share;
     }
 }
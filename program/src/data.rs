@@ -1,4 +1,4 @@
-use std::marker::PhantomData;
+use std::{cmp::Ordering, marker::PhantomData};
 
 use solana_api_types::{sysvar::rent::Rent, sysvar::Sysvar, Pubkey};
 use solar::{
@@ -41,10 +41,20 @@ macro_rules! impl_entity_simple_deref {
 
 pub trait AccountType {
     const KIND: EntityKind;
+    /// Layout version written to the header of newly initialized entities.
+    const VERSION: u8 = 1;
 
+    /// Whether `size` matches the body of the current layout.
     fn is_valid_size(size: usize) -> bool;
     fn default_size() -> usize;
 
+    /// Whether body of `size` bytes written with an older layout `version` can be read
+    /// as the current one, otherwise the entity has to be migrated first.
+    fn is_compatible(version: u8, size: usize) -> bool {
+        let _ = (version, size);
+        false
+    }
+
     fn default_lamports() -> u64 {
         minimum_balance(Self::default_size() as u64)
     }
@@ -85,6 +95,15 @@ pub struct EntityHeader {
     pub id: EntityId,
     pub parent_id: EntityId,
     pub kind: EntityKind,
+    /// Layout version of the body, zero for entities initialized before layouts were versioned.
+    pub version: u8,
+}
+
+impl EntityHeader {
+    /// Layout version of the body, unversioned entities use the first one.
+    pub fn version(&self) -> u8 {
+        self.version.max(1)
+    }
 }
 
 #[derive(Debug)]
@@ -102,10 +121,8 @@ where
     B: AccountBackend,
     T: AccountType,
 {
-    pub(crate) fn raw_any(program_id: &Pubkey, account: B) -> Result<Self, Error> {
-        let size = account.data().len();
-
-        if size < HEADER_RESERVED || !T::is_valid_size(size - HEADER_RESERVED) {
+    fn raw(program_id: &Pubkey, account: B) -> Result<Self, Error> {
+        if account.data().len() < HEADER_RESERVED {
             return Err(Error::InvalidData);
         }
 
@@ -132,6 +149,16 @@ where
         Ok(entity)
     }
 
+    pub(crate) fn raw_any(program_id: &Pubkey, account: B) -> Result<Self, Error> {
+        let size = account.data().len();
+
+        if size < HEADER_RESERVED || !T::is_valid_size(size - HEADER_RESERVED) {
+            return Err(Error::InvalidData);
+        }
+
+        Self::raw(program_id, account)
+    }
+
     /// Raw constructor for protocol entities.
     ///
     /// # Safety
@@ -142,8 +169,35 @@ where
     /// *However*, consumers of this struct can rely on all instances of [`Entity`] to uphold
     /// invariants required by `T`, so callers are required to ensure that this account is actually
     /// an instance of account type `T` before returning it elsewhere.
+    ///
+    /// Entities written with an older layout are only accepted if it is compatible
+    /// with the current one, see [`AccountType::is_compatible`].
     pub(crate) fn raw_initialized(program_id: &Pubkey, account: B) -> Result<Self, Error> {
-        let entity = Self::raw_any(program_id, account)?;
+        let entity = Self::raw_versioned(program_id, account)?;
+        entity.check_layout()?;
+
+        Ok(entity)
+    }
+
+    /// Checks that the body can be read as the current layout of `T`.
+    pub(crate) fn check_layout(&self) -> Result<(), Error> {
+        let size = self.body().len();
+        let version = self.header().version();
+
+        match version.cmp(&T::VERSION) {
+            Ordering::Equal if T::is_valid_size(size) => Ok(()),
+            Ordering::Equal => Err(Error::InvalidData),
+            Ordering::Less if T::is_compatible(version, size) => Ok(()),
+            _ => Err(Error::InvalidVersion),
+        }
+    }
+
+    /// Same as [`Entity::raw_initialized`], but accepts entities with any layout version.
+    ///
+    /// Body is not validated against the layout, so it must not be dereferenced before
+    /// the caller checks the version and size of it.
+    pub(crate) fn raw_versioned(program_id: &Pubkey, account: B) -> Result<Self, Error> {
+        let entity = Self::raw(program_id, account)?;
 
         if entity.header().kind != T::KIND {
             return Err(Error::InvalidKind);
@@ -217,7 +271,7 @@ where
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 #[repr(C)]
 pub struct EntityAllocator {
     counter: u64,
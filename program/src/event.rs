@@ -0,0 +1,61 @@
+//! Events emitted by the stake pool program through [`solar::log::emit`].
+//!
+//! Indexers can extract them from transaction logs with
+//! [`solana_api_types::event::parse_events`].
+
+use solana_api_types::Pubkey;
+
+use crate::TokenAmount;
+
+#[derive(Debug, PartialEq, Eq, Clone, parity_scale_codec::Encode, parity_scale_codec::Decode)]
+pub enum Event {
+    PoolCreated {
+        pool: Pubkey,
+        administrator: Pubkey,
+        stake_mint: Pubkey,
+        reward_mint: Pubkey,
+    },
+    Staked {
+        pool: Pubkey,
+        staker: Pubkey,
+        amount: TokenAmount,
+    },
+    /// Stake returned to the staker, `amount` includes the withheld `penalty`.
+    Unstaked {
+        pool: Pubkey,
+        staker: Pubkey,
+        amount: TokenAmount,
+        penalty: TokenAmount,
+    },
+    /// Reward paid to the staker, `fee` is paid to the fee wallet on top of `amount`.
+    RewardClaimed {
+        pool: Pubkey,
+        staker: Pubkey,
+        amount: TokenAmount,
+        fee: TokenAmount,
+    },
+    RewardAdded {
+        pool: Pubkey,
+        amount: TokenAmount,
+    },
+    TicketTransferred {
+        pool: Pubkey,
+        ticket: Pubkey,
+        staker: Pubkey,
+        new_staker: Pubkey,
+    },
+    PoolRolledOver {
+        pool: Pubkey,
+        successor: Pubkey,
+    },
+    /// Stake moved into the successor pool, `reward` is staked along with `principal`
+    /// and `fee` is paid to the fee wallet on top of it.
+    TicketRolledOver {
+        pool: Pubkey,
+        successor: Pubkey,
+        staker: Pubkey,
+        principal: TokenAmount,
+        reward: TokenAmount,
+        fee: TokenAmount,
+    },
+}
@@ -1,6 +1,8 @@
 #![allow(stable_features)]
 #![feature(min_const_generics)]
 
+#[cfg(feature = "onchain")]
+use complex::FarmEntity;
 use fixed::types::U64F64;
 #[cfg(feature = "onchain")]
 use simple_stake::StakePoolEntity;
@@ -17,9 +19,12 @@ extern crate static_assertions;
 #[macro_use]
 extern crate strum;
 
+#[cfg(any(feature = "onchain", test))]
+pub mod allowlist;
 pub mod complex;
 pub mod data;
 pub mod error;
+pub mod event;
 pub mod simple_stake;
 
 pub type TokenAmount = Checked<u64>;
@@ -28,10 +33,10 @@ pub type TokenAmountF64 = Checked<U64F64>;
 #[derive(Debug, PartialEq, Eq, Clone, parity_scale_codec::Encode, parity_scale_codec::Decode)]
 pub enum Method {
     Simple(simple_stake::Method),
+    Farm(complex::Method),
 }
 
 #[cfg(feature = "onchain")]
-#[allow(unused)]
 pub fn main(mut input: BpfProgramInput) -> ProgramResult {
     use solar::qlog;
 
@@ -42,10 +47,16 @@ pub fn main(mut input: BpfProgramInput) -> ProgramResult {
 
     let result = match method {
         Method::Simple(method) => match method {
-            simple_stake::Method::CreatePool(args) => StakePoolEntity::initialize(&mut input, args),
-            simple_stake::Method::Stake { amount } => {
-                StakePoolEntity::add_stake(&mut input, amount)
+            simple_stake::Method::CreatePool(args) => {
+                StakePoolEntity::initialize(&mut input, args, false)
+            }
+            simple_stake::Method::CreatePoolWithReward(args) => {
+                StakePoolEntity::initialize(&mut input, args, true)
             }
+            simple_stake::Method::Stake {
+                amount,
+                allowlist_proof,
+            } => StakePoolEntity::add_stake(&mut input, amount, allowlist_proof),
             simple_stake::Method::Unstake { amount } => {
                 StakePoolEntity::remove_stake(&mut input, amount)
             }
@@ -53,14 +64,50 @@ pub fn main(mut input: BpfProgramInput) -> ProgramResult {
             simple_stake::Method::AddReward { amount } => {
                 StakePoolEntity::add_reward(&mut input, amount)
             }
+            simple_stake::Method::ClosePool => StakePoolEntity::close(&mut input),
+            simple_stake::Method::UpdatePool(args) => StakePoolEntity::update(&mut input, args),
+            simple_stake::Method::ProposeAdmin { administrator } => {
+                StakePoolEntity::propose_administrator(&mut input, administrator)
+            }
+            simple_stake::Method::AcceptAdmin => StakePoolEntity::accept_administrator(&mut input),
+            simple_stake::Method::EmergencyUnstake { amount } => {
+                StakePoolEntity::emergency_unstake(&mut input, amount)
+            }
+            simple_stake::Method::Migrate {
+                program_authority_salt,
+            } => StakePoolEntity::migrate(&mut input, program_authority_salt),
+            simple_stake::Method::TransferTicket { allowlist_proof } => {
+                StakePoolEntity::transfer_ticket(&mut input, allowlist_proof)
+            }
+            simple_stake::Method::SetTicketDelegate { delegate } => {
+                StakePoolEntity::set_ticket_delegate(&mut input, delegate)
+            }
+            simple_stake::Method::RolloverPool {
+                program_authority_salt,
+            } => StakePoolEntity::rollover(&mut input, program_authority_salt),
+            simple_stake::Method::SetTicketRollover { rollover } => {
+                StakePoolEntity::set_ticket_rollover(&mut input, rollover)
+            }
+            simple_stake::Method::RolloverTicket { allowlist_proof } => {
+                StakePoolEntity::rollover_ticket(&mut input, allowlist_proof)
+            }
+            simple_stake::Method::SetFeeWallet => StakePoolEntity::set_fee_wallet(&mut input),
+        },
+        Method::Farm(method) => match method {
+            complex::Method::CreateFarm(args) => FarmEntity::initialize(&mut input, args),
+            complex::Method::Stake { amount } => FarmEntity::add_stake(&mut input, amount),
+            complex::Method::Unstake { amount } => FarmEntity::remove_stake(&mut input, amount),
+            complex::Method::Withdraw => FarmEntity::withdraw(&mut input),
+            complex::Method::ClaimReward => FarmEntity::claim_reward(&mut input),
+            complex::Method::AddReward { amount } => FarmEntity::add_reward(&mut input, amount),
+            complex::Method::Crank { limit } => FarmEntity::crank(&mut input, limit),
         },
     };
 
-    if result.is_err() {
-        dbg!(result);
-    }
-
-    Ok(())
+    result.map_err(|error| {
+        qlog!("instruction failed: ", error);
+        error.into()
+    })
 }
 
 pub struct Program;
@@ -77,27 +124,68 @@ impl Entrypoint for Program {
 mod test {
     use std::mem::size_of;
 
+    use fixed::types::U64F64;
     use parity_scale_codec::Encode;
-    use solana_program_test::builtin_process_instruction;
     use solar::{
         input::wrapped_entrypoint,
-        spl::{create_mint, create_wallet, mint_to, Mint, Wallet},
+        spl::{create_mint, create_wallet, mint_to, Mint, MintAccount, Wallet, WalletAccount},
         util::minimum_balance,
     };
 
     use solana_api_types::{
-        program_test::ProgramTest, system::create_account, AccountMeta, Instruction, Keypair,
-        Pubkey, Signer, Transaction,
+        event::parse_log_messages,
+        program_test::{builtin_process_instruction, program_logs, ProgramTest, Runtime},
+        system::{create_account, transfer},
+        Account, AccountMeta, Instruction, InstructionError, Keypair, Pubkey, Signer, Transaction,
+        TransactionError,
     };
 
     use crate::{
-        data::AccountType,
-        simple_stake::{self, InitializeArgs, StakePool, StakePoolEntity, StakerTicket},
+        allowlist,
+        complex::{
+            self, Farm, FarmEntity, Request, RequestQueue, RequestQueueState, StakerRegistry,
+        },
+        data::{AccountType, EntityAllocator, EntityHeader, EntityKind, HEADER_RESERVED},
+        error::Error,
+        event::Event,
+        simple_stake::{
+            self, AllowlistProof, InitializeArgs, RewardMode, StakePool, StakePoolEntity,
+            StakePoolStateV1, StakerTicket, StakerTicketEntity, UpdatePoolArgs,
+        },
         Method,
     };
 
-    #[tokio::test]
-    async fn create_test() -> anyhow::Result<()> {
+    const INITIAL_BALANCE: u64 = 1_000_000;
+    const TOPUP_DURATION: i64 = 200;
+    const LOCKUP_DURATION: i64 = 1_000;
+
+    struct TestContext {
+        runtime: Runtime,
+        payer: Keypair,
+        program_id: Pubkey,
+        mint: Keypair,
+        administrator: Keypair,
+        administrator_wallet: Keypair,
+    }
+
+    /// Accounts of a pool created with [`create_pool`].
+    struct PoolKeys {
+        pool: Pubkey,
+        program_authority: Pubkey,
+        stake_vault: Pubkey,
+        /// Mint of the pool shares, if the pool is liquid.
+        share_mint: Option<Pubkey>,
+    }
+
+    async fn setup() -> anyhow::Result<TestContext> {
+        setup_with_accounts(|_, _, _| vec![]).await
+    }
+
+    /// Same as [`setup`], with `accounts` added to the genesis, given the program id,
+    /// the mint and the administrator.
+    async fn setup_with_accounts(
+        accounts: impl FnOnce(&Pubkey, &Pubkey, &Pubkey) -> Vec<Account>,
+    ) -> anyhow::Result<TestContext> {
         let mut program_test = ProgramTest::default();
         let program_id = Pubkey::new_unique();
 
@@ -109,368 +197,992 @@ mod test {
             }),
         );
 
-        let pool_key = Keypair::new();
-        let pool_administrator_key = Keypair::new();
-
-        let mut salt: u64 = 0;
-        let pool_program_authority = loop {
-            let pool_program_authority = Pubkey::create_program_address(
-                &[
-                    pool_key.pubkey().as_ref(),
-                    pool_administrator_key.pubkey().as_ref(),
-                    &salt.to_le_bytes(),
-                ],
-                &program_id,
-            );
-
-            match pool_program_authority {
-                Some(s) => break s,
-                None => {
-                    salt += 1;
-                }
-            }
-        };
-
-        let (mut client, payer, hash) = program_test.start().await;
+        let mint = Keypair::new();
+        let administrator = Keypair::new();
+        let administrator_wallet = Keypair::new();
 
-        let stake_mint_key = Keypair::new();
-        let stake_vault_key = Keypair::new();
-        let aux_wallet_key = Keypair::new();
+        for account in accounts(&program_id, &mint.pubkey(), &administrator.pubkey()) {
+            program_test.add_account(account);
+        }
 
-        let staker_key = Keypair::new();
-        let staker_ticket_key = Keypair::new();
+        let (mut runtime, payer, _) = program_test.start_with_context().await;
 
-        let mut instrs = vec![];
+        let mut instrs = vec![transfer(
+            &payer.pubkey(),
+            &administrator.pubkey(),
+            1_000_000_000,
+        )];
         instrs.extend(create_mint(
             &payer.pubkey(),
-            &stake_mint_key.pubkey(),
-            &pool_administrator_key.pubkey(),
+            &mint.pubkey(),
+            &administrator.pubkey(),
             6,
         ));
         instrs.extend(create_wallet(
             &payer.pubkey(),
-            &stake_vault_key.pubkey(),
-            &stake_mint_key.pubkey(),
-            &pool_program_authority,
-        ));
-        instrs.extend(create_wallet(
-            &payer.pubkey(),
-            &aux_wallet_key.pubkey(),
-            &stake_mint_key.pubkey(),
-            &pool_administrator_key.pubkey(),
+            &administrator_wallet.pubkey(),
+            &mint.pubkey(),
+            &administrator.pubkey(),
         ));
         instrs.push(mint_to(
-            &stake_mint_key.pubkey(),
-            &aux_wallet_key.pubkey(),
-            &pool_administrator_key.pubkey(),
-            1_000_000,
+            &mint.pubkey(),
+            &administrator_wallet.pubkey(),
+            &administrator.pubkey(),
+            INITIAL_BALANCE,
         ));
-        instrs.push(create_account(
-            &payer.pubkey(),
-            &pool_key.pubkey(),
-            minimum_balance(StakePool::default_size() as u64),
-            StakePool::default_size() as u64,
-            &program_id,
+
+        process(
+            &mut runtime,
+            &payer,
+            &instrs,
+            &[&mint, &administrator_wallet, &administrator],
+        )
+        .await?
+        .unwrap();
+
+        Ok(TestContext {
+            runtime,
+            payer,
+            program_id,
+            mint,
+            administrator,
+            administrator_wallet,
+        })
+    }
+
+    async fn process(
+        runtime: &mut Runtime,
+        payer: &Keypair,
+        instrs: &[Instruction],
+        signers: &[&Keypair],
+    ) -> anyhow::Result<Result<(), TransactionError>> {
+        let hash = runtime.get_recent_blockhash().await?;
+
+        let mut keypairs: Vec<&dyn Signer> = vec![payer];
+        keypairs.extend(signers.iter().map(|keypair| *keypair as &dyn Signer));
+
+        let trx =
+            Transaction::new_signed_with_payer(instrs, Some(&payer.pubkey()), &keypairs, hash);
+        runtime.try_process_transaction(trx).await
+    }
+
+    fn find_program_authority(program_id: &Pubkey, pool: &Pubkey) -> (Pubkey, u64) {
+        let mut salt: u64 = 0;
+        loop {
+            let program_authority =
+                Pubkey::create_program_address(&[pool.as_ref(), &salt.to_le_bytes()], program_id);
+
+            match program_authority {
+                Some(program_authority) => break (program_authority, salt),
+                None => salt += 1,
+            }
+        }
+    }
+
+    fn pool_args() -> InitializeArgs {
+        InitializeArgs {
+            program_authority_salt: 0,
+            lockup_duration: LOCKUP_DURATION.into(),
+            topup_duration: TOPUP_DURATION.into(),
+            reward_amount: 1_000.into(),
+            target_amount: 10_000.into(),
+            reward_mode: RewardMode::LumpSum,
+            early_exit_penalty_bps: 0,
+            early_exit_treasury: None,
+            min_stake: 0.into(),
+            max_stake_per_ticket: 0.into(),
+            allowlist_root: None,
+            fee_bps: 0,
+            fee_wallet: None,
+            liquid: false,
+        }
+    }
+
+    /// Creates a pool in the context mint administered by the context administrator.
+    async fn create_pool(
+        context: &mut TestContext,
+        mut args: InitializeArgs,
+    ) -> anyhow::Result<PoolKeys> {
+        let program_id = context.program_id;
+        let payer = context.payer.pubkey();
+        let pool = Keypair::new();
+        let stake_vault = Keypair::new();
+        let share_mint = Keypair::new();
+
+        let (program_authority, salt) = find_program_authority(&program_id, &pool.pubkey());
+        args.program_authority_salt = salt;
+
+        let mut instrs = vec![];
+        instrs.extend(create_wallet(
+            &payer,
+            &stake_vault.pubkey(),
+            &context.mint.pubkey(),
+            &program_authority,
         ));
+        if args.liquid {
+            instrs.extend(create_mint(
+                &payer,
+                &share_mint.pubkey(),
+                &program_authority,
+                6,
+            ));
+        }
         instrs.push(create_account(
-            &payer.pubkey(),
-            &staker_ticket_key.pubkey(),
-            minimum_balance(StakerTicket::default_size() as u64),
-            StakerTicket::default_size() as u64,
+            &payer,
+            &pool.pubkey(),
+            StakePool::default_lamports(),
+            StakePool::default_size() as u64,
             &program_id,
         ));
 
+        let mut accounts = vec![
+            AccountMeta::new_readonly(context.administrator.pubkey(), false),
+            AccountMeta::new_readonly(program_authority, false),
+            AccountMeta::new(pool.pubkey(), false),
+            AccountMeta::new_readonly(context.mint.pubkey(), false),
+            AccountMeta::new_readonly(stake_vault.pubkey(), false),
+        ];
+        if args.liquid {
+            accounts.push(AccountMeta::new_readonly(share_mint.pubkey(), false));
+        }
+
+        let liquid = args.liquid;
         instrs.push(Instruction {
             program_id,
-            accounts: vec![
-                AccountMeta::new_readonly(pool_administrator_key.pubkey(), false),
-                AccountMeta::new_readonly(pool_program_authority, false),
-                AccountMeta::new(pool_key.pubkey(), false),
-                AccountMeta::new_readonly(stake_mint_key.pubkey(), false),
-                AccountMeta::new_readonly(stake_vault_key.pubkey(), false),
-            ],
-            data: Method::Simple(simple_stake::Method::CreatePool(InitializeArgs {
-                program_authority_salt: salt,
-                lockup_duration: 1000.into(),
-                topup_duration: 200.into(),
-                reward_amount: 1000.into(),
-                target_amount: 10000.into(),
-            }))
-            .encode(),
+            accounts,
+            data: Method::Simple(simple_stake::Method::CreatePool(args)).encode(),
         });
 
+        let mut signers = vec![&pool, &stake_vault];
+        if liquid {
+            signers.push(&share_mint);
+        }
+
+        process(&mut context.runtime, &context.payer, &instrs, &signers)
+            .await?
+            .unwrap();
+
+        Ok(PoolKeys {
+            pool: pool.pubkey(),
+            program_authority,
+            stake_vault: stake_vault.pubkey(),
+            share_mint: if liquid {
+                Some(share_mint.pubkey())
+            } else {
+                None
+            },
+        })
+    }
+
+    /// Opens the successor of an expired pool created with [`create_pool`].
+    async fn create_successor(
+        context: &mut TestContext,
+        pool: &PoolKeys,
+    ) -> anyhow::Result<PoolKeys> {
+        let program_id = context.program_id;
+        let payer = context.payer.pubkey();
+        let successor = Keypair::new();
+        let stake_vault = Keypair::new();
+
+        let (program_authority, salt) = find_program_authority(&program_id, &successor.pubkey());
+
+        let mut instrs = create_wallet(
+            &payer,
+            &stake_vault.pubkey(),
+            &context.mint.pubkey(),
+            &program_authority,
+        )
+        .to_vec();
+        instrs.push(create_account(
+            &payer,
+            &successor.pubkey(),
+            StakePool::default_lamports(),
+            StakePool::default_size() as u64,
+            &program_id,
+        ));
         instrs.push(Instruction {
             program_id,
             accounts: vec![
-                AccountMeta::new_readonly(*solar::spl::ID, false),
-                AccountMeta::new(pool_key.pubkey(), false),
-                AccountMeta::new_readonly(staker_key.pubkey(), false),
-                AccountMeta::new(staker_ticket_key.pubkey(), false),
-                AccountMeta::new(stake_vault_key.pubkey(), false),
-                AccountMeta::new_readonly(pool_administrator_key.pubkey(), true),
-                AccountMeta::new(aux_wallet_key.pubkey(), false),
+                AccountMeta::new(pool.pool, false),
+                AccountMeta::new_readonly(context.administrator.pubkey(), true),
+                AccountMeta::new(successor.pubkey(), false),
+                AccountMeta::new_readonly(program_authority, false),
+                AccountMeta::new_readonly(stake_vault.pubkey(), false),
             ],
-            data: Method::Simple(simple_stake::Method::Stake {
-                amount: 10000.into(),
+            data: Method::Simple(simple_stake::Method::RolloverPool {
+                program_authority_salt: salt,
             })
             .encode(),
         });
 
-        let trx = Transaction::new_signed_with_payer(
+        process(
+            &mut context.runtime,
+            &context.payer,
             &instrs,
-            Some(&payer.pubkey()),
-            &vec![
-                payer,
-                stake_mint_key,
-                stake_vault_key,
-                pool_key.clone(),
-                aux_wallet_key,
-                pool_administrator_key,
-                staker_ticket_key.clone(),
-            ],
-            hash,
-        );
+            &[&successor, &stake_vault, &context.administrator],
+        )
+        .await?
+        .unwrap();
 
-        let result = client.process_transaction(trx).await;
-        println!("{:?}", result);
+        Ok(PoolKeys {
+            pool: successor.pubkey(),
+            program_authority,
+            stake_vault: stake_vault.pubkey(),
+            share_mint: None,
+        })
+    }
 
-        let stake_pool = client
-            .get_account(&pool_key.pubkey())
-            .await
-            .unwrap()
-            .unwrap();
+    /// Creates a staker able to pay for its ticket, holding `amount` in a wallet of the context mint.
+    async fn create_staker(
+        context: &mut TestContext,
+        amount: u64,
+    ) -> anyhow::Result<(Keypair, Pubkey)> {
+        let staker = Keypair::new();
+        let wallet = Keypair::new();
 
-        let staker_ticket = client
-            .get_account(&staker_ticket_key.pubkey())
-            .await
+        let mut instrs = vec![transfer(
+            &context.payer.pubkey(),
+            &staker.pubkey(),
+            1_000_000_000,
+        )];
+        instrs.extend(create_wallet(
+            &context.payer.pubkey(),
+            &wallet.pubkey(),
+            &context.mint.pubkey(),
+            &staker.pubkey(),
+        ));
+        instrs.push(mint_to(
+            &context.mint.pubkey(),
+            &wallet.pubkey(),
+            &context.administrator.pubkey(),
+            amount,
+        ));
+
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&wallet, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        Ok((staker, wallet.pubkey()))
+    }
+
+    fn ticket_address(program_id: &Pubkey, pool: &Pubkey, staker: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&StakerTicket::seeds(pool, staker), program_id)
             .unwrap()
-            .unwrap();
+            .0
+    }
 
-        let stake_pool = StakePoolEntity::load(&program_id, Box::new(stake_pool)).unwrap();
-        let staker_ticket = stake_pool.load_ticket(Box::new(staker_ticket)).unwrap();
+    /// Account of an entity written before layouts were versioned, both v1 pools
+    /// and tickets are sized after [`StakePoolStateV1`].
+    fn v1_entity_account(
+        program_id: &Pubkey,
+        pubkey: Pubkey,
+        header: EntityHeader,
+        body: &[u8],
+    ) -> Account {
+        let mut data = vec![0; HEADER_RESERVED + size_of::<StakePoolStateV1>()];
+        unsafe { std::ptr::write_unaligned(data.as_mut_ptr().cast(), header) };
+        data[HEADER_RESERVED..HEADER_RESERVED + body.len()].copy_from_slice(body);
 
-        assert!(stake_pool.stake_acquired_amount == 10000.into());
-        assert!(stake_pool.stake_target_amount == 10000.into());
-        assert!(staker_ticket.staked_amount == 10000.into());
-        Ok(())
+        Account {
+            lamports: minimum_balance(data.len() as u64),
+            data,
+            owner: *program_id,
+            executable: false,
+            rent_epoch: 0,
+            pubkey,
+        }
     }
 
-    #[tokio::test]
-    async fn stake_test() -> anyhow::Result<()> {
-        let mut program_test = ProgramTest::default();
-        let program_id = Pubkey::new_unique();
+    /// Stake paid from `wallet` by the staker, who also pays for the ticket.
+    fn stake_instruction(
+        program_id: &Pubkey,
+        pool: &PoolKeys,
+        staker: &Pubkey,
+        wallet: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new_readonly(*solana_api_types::system::ID, false),
+                AccountMeta::new(pool.pool, false),
+                AccountMeta::new_readonly(*staker, false),
+                AccountMeta::new(ticket_address(program_id, &pool.pool, staker), false),
+                AccountMeta::new(pool.stake_vault, false),
+                AccountMeta::new(*staker, true),
+                AccountMeta::new(*wallet, false),
+            ],
+            data: Method::Simple(simple_stake::Method::Stake {
+                amount: amount.into(),
+                allowlist_proof: None,
+            })
+            .encode(),
+        }
+    }
 
-        program_test.add_program(
-            "x5margin",
-            program_id,
-            Some(|a, b, c| {
-                builtin_process_instruction(wrapped_entrypoint::<super::Program>, a, b, c)
-            }),
+    /// Same as [`stake_instruction`], carrying the allowlist proof of the staker.
+    fn allowlisted_stake_instruction(
+        program_id: &Pubkey,
+        pool: &PoolKeys,
+        staker: &Pubkey,
+        wallet: &Pubkey,
+        amount: u64,
+        allowlist_proof: AllowlistProof,
+    ) -> Instruction {
+        let mut instr = stake_instruction(program_id, pool, staker, wallet, amount);
+        instr.data = Method::Simple(simple_stake::Method::Stake {
+            amount: amount.into(),
+            allowlist_proof: Some(allowlist_proof),
+        })
+        .encode();
+
+        instr
+    }
+
+    /// Stake into a liquid pool, minting shares into `share_wallet`.
+    fn liquid_stake_instruction(
+        program_id: &Pubkey,
+        pool: &PoolKeys,
+        staker: &Pubkey,
+        wallet: &Pubkey,
+        share_wallet: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new_readonly(*solana_api_types::system::ID, false),
+                AccountMeta::new(pool.pool, false),
+                AccountMeta::new_readonly(*staker, false),
+                AccountMeta::new(pool.share_mint.unwrap(), false),
+                AccountMeta::new(*share_wallet, false),
+                AccountMeta::new_readonly(pool.program_authority, false),
+                AccountMeta::new(pool.stake_vault, false),
+                AccountMeta::new(*staker, true),
+                AccountMeta::new(*wallet, false),
+            ],
+            data: Method::Simple(simple_stake::Method::Stake {
+                amount: amount.into(),
+                allowlist_proof: None,
+            })
+            .encode(),
+        }
+    }
+
+    /// Replaces the ticket of an unstake or claim with the shares of a liquid pool.
+    fn with_shares(mut instr: Instruction, pool: &PoolKeys, share_wallet: &Pubkey) -> Instruction {
+        instr.accounts.splice(
+            2..3,
+            vec![
+                AccountMeta::new(pool.share_mint.unwrap(), false),
+                AccountMeta::new(*share_wallet, false),
+            ],
         );
+        instr
+    }
 
-        let pool_key = Keypair::new();
-        let pool_administrator_key = Keypair::new();
+    fn unstake_instruction(
+        program_id: &Pubkey,
+        pool: &PoolKeys,
+        staker: &Pubkey,
+        wallet: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new(pool.pool, false),
+                AccountMeta::new(ticket_address(program_id, &pool.pool, staker), false),
+                AccountMeta::new(*staker, true),
+                AccountMeta::new_readonly(pool.program_authority, false),
+                AccountMeta::new(pool.stake_vault, false),
+                AccountMeta::new(*wallet, false),
+            ],
+            data: Method::Simple(simple_stake::Method::Unstake {
+                amount: amount.into(),
+            })
+            .encode(),
+        }
+    }
 
-        let mut salt: u64 = 0;
-        let pool_program_authority = loop {
-            let pool_program_authority = Pubkey::create_program_address(
-                &[
-                    pool_key.pubkey().as_ref(),
-                    pool_administrator_key.pubkey().as_ref(),
-                    &salt.to_le_bytes(),
-                ],
-                &program_id,
-            );
+    /// Claim signed by the staker into `wallet`, pools charging the fee expect the fee wallet
+    /// to be appended.
+    fn claim_instruction(
+        program_id: &Pubkey,
+        pool: &PoolKeys,
+        staker: &Pubkey,
+        wallet: &Pubkey,
+    ) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new(pool.pool, false),
+                AccountMeta::new(ticket_address(program_id, &pool.pool, staker), false),
+                AccountMeta::new(*staker, true),
+                AccountMeta::new_readonly(pool.program_authority, false),
+                AccountMeta::new(pool.stake_vault, false),
+                AccountMeta::new(*wallet, false),
+            ],
+            data: Method::Simple(simple_stake::Method::ClaimReward).encode(),
+        }
+    }
 
-            match pool_program_authority {
-                Some(s) => break s,
-                None => {
-                    salt += 1;
-                }
-            }
-        };
+    /// Early exit during lockup, pools with a treasury expect the treasury wallet to be appended.
+    fn emergency_unstake_instruction(
+        program_id: &Pubkey,
+        pool: &PoolKeys,
+        staker: &Pubkey,
+        wallet: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new(pool.pool, false),
+                AccountMeta::new(ticket_address(program_id, &pool.pool, staker), false),
+                AccountMeta::new(*staker, true),
+                AccountMeta::new_readonly(pool.program_authority, false),
+                AccountMeta::new(pool.stake_vault, false),
+                AccountMeta::new(*wallet, false),
+            ],
+            data: Method::Simple(simple_stake::Method::EmergencyUnstake {
+                amount: amount.into(),
+            })
+            .encode(),
+        }
+    }
 
-        let (mut client, payer, hash) = program_test.start().await;
+    /// Transfer of the staker's ticket into the one of `new_staker`, paid by `payer`.
+    fn transfer_ticket_instruction(
+        program_id: &Pubkey,
+        pool: &PoolKeys,
+        staker: &Pubkey,
+        new_staker: &Pubkey,
+        payer: &Pubkey,
+    ) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solana_api_types::system::ID, false),
+                AccountMeta::new(pool.pool, false),
+                AccountMeta::new(ticket_address(program_id, &pool.pool, staker), false),
+                AccountMeta::new(*staker, true),
+                AccountMeta::new_readonly(*new_staker, true),
+                AccountMeta::new(ticket_address(program_id, &pool.pool, new_staker), false),
+                AccountMeta::new(*payer, true),
+            ],
+            data: Method::Simple(simple_stake::Method::TransferTicket {
+                allowlist_proof: None,
+            })
+            .encode(),
+        }
+    }
 
-        let stake_mint_key = Keypair::new();
-        let stake_vault_key = Keypair::new();
+    fn set_delegate_instruction(
+        program_id: &Pubkey,
+        pool: &PoolKeys,
+        staker: &Pubkey,
+        delegate: Option<Pubkey>,
+    ) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(pool.pool, false),
+                AccountMeta::new(ticket_address(program_id, &pool.pool, staker), false),
+                AccountMeta::new_readonly(*staker, true),
+            ],
+            data: Method::Simple(simple_stake::Method::SetTicketDelegate { delegate }).encode(),
+        }
+    }
 
-        let amount: super::TokenAmount = solar::math::Checked::from(100);
+    fn set_rollover_instruction(
+        program_id: &Pubkey,
+        pool: &PoolKeys,
+        staker: &Pubkey,
+    ) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(pool.pool, false),
+                AccountMeta::new(ticket_address(program_id, &pool.pool, staker), false),
+                AccountMeta::new_readonly(*staker, true),
+            ],
+            data: Method::Simple(simple_stake::Method::SetTicketRollover { rollover: true })
+                .encode(),
+        }
+    }
 
-        let instrs = vec![
-            create_account(
-                &payer.pubkey(),
-                &stake_mint_key.pubkey(),
-                minimum_balance(size_of::<Mint>() as u64),
-                size_of::<Mint>() as u64,
-                &solar::spl::ID,
-            ),
-            create_account(
-                &payer.pubkey(),
-                &stake_vault_key.pubkey(),
-                minimum_balance(size_of::<Wallet>() as u64),
-                size_of::<Wallet>() as u64,
-                &solar::spl::ID,
-            ),
-            create_account(
-                &payer.pubkey(),
-                &pool_key.pubkey(),
-                StakePool::default_lamports(),
-                StakePool::default_size() as u64,
-                &program_id,
-            ),
-            spl_token::instruction::initialize_mint(
-                &solar::spl::ID.compat(),
-                &stake_mint_key.pubkey().compat(),
-                &pool_administrator_key.pubkey().compat(),
-                None,
-                6,
-            )
+    /// Moves the stake of the staker's ticket into `successor_ticket`, paid by `payer`.
+    fn rollover_ticket_instruction(
+        program_id: &Pubkey,
+        pool: &PoolKeys,
+        successor: &PoolKeys,
+        staker: &Pubkey,
+        successor_ticket: &Pubkey,
+        payer: &Pubkey,
+    ) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new_readonly(*solana_api_types::system::ID, false),
+                AccountMeta::new(pool.pool, false),
+                AccountMeta::new(ticket_address(program_id, &pool.pool, staker), false),
+                AccountMeta::new(*staker, false),
+                AccountMeta::new_readonly(pool.program_authority, false),
+                AccountMeta::new(pool.stake_vault, false),
+                AccountMeta::new(successor.pool, false),
+                AccountMeta::new(*successor_ticket, false),
+                AccountMeta::new(successor.stake_vault, false),
+                AccountMeta::new(*payer, true),
+            ],
+            data: Method::Simple(simple_stake::Method::RolloverTicket {
+                allowlist_proof: None,
+            })
+            .encode(),
+        }
+    }
+
+    /// Claim sent by the delegate of the staker's ticket.
+    fn delegate_claim_instruction(
+        program_id: &Pubkey,
+        pool: &PoolKeys,
+        staker: &Pubkey,
+        wallet: &Pubkey,
+        delegate: &Pubkey,
+    ) -> Instruction {
+        let mut instr = claim_instruction(program_id, pool, staker, wallet);
+        instr.accounts[3] = AccountMeta::new(*staker, false);
+        instr
+            .accounts
+            .push(AccountMeta::new_readonly(*delegate, true));
+        instr
+    }
+
+    /// Reward deposited by the context administrator from their wallet.
+    fn add_reward_instruction(context: &TestContext, pool: &PoolKeys, amount: u64) -> Instruction {
+        Instruction {
+            program_id: context.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new(pool.pool, false),
+                AccountMeta::new(pool.stake_vault, false),
+                AccountMeta::new_readonly(context.administrator.pubkey(), true),
+                AccountMeta::new(context.administrator_wallet.pubkey(), false),
+            ],
+            data: Method::Simple(simple_stake::Method::AddReward {
+                amount: amount.into(),
+            })
+            .encode(),
+        }
+    }
+
+    fn close_instruction(
+        program_id: &Pubkey,
+        pool: &PoolKeys,
+        administrator: &Pubkey,
+        target_wallet: &Pubkey,
+    ) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new(pool.pool, false),
+                AccountMeta::new(*administrator, true),
+                AccountMeta::new_readonly(pool.program_authority, false),
+                AccountMeta::new(pool.stake_vault, false),
+                AccountMeta::new(*target_wallet, false),
+            ],
+            data: Method::Simple(simple_stake::Method::ClosePool).encode(),
+        }
+    }
+
+    async fn load_pool(
+        context: &mut TestContext,
+        pool: &Pubkey,
+    ) -> anyhow::Result<StakePoolEntity<Box<Account>>> {
+        let account = context.runtime.get_account(pool).await?.unwrap();
+
+        Ok(StakePoolEntity::load(&context.program_id, Box::new(account)).unwrap())
+    }
+
+    async fn load_ticket(
+        context: &mut TestContext,
+        pool: &Pubkey,
+        staker: &Pubkey,
+    ) -> anyhow::Result<StakerTicketEntity<Box<Account>>> {
+        let ticket = ticket_address(&context.program_id, pool, staker);
+        let pool = load_pool(context, pool).await?;
+        let account = context.runtime.get_account(&ticket).await?.unwrap();
+
+        Ok(pool.load_ticket(Box::new(account)).unwrap())
+    }
+
+    async fn supply(context: &mut TestContext, mint: &Pubkey) -> anyhow::Result<u64> {
+        let account = context.runtime.get_account(mint).await?.unwrap();
+
+        Ok(MintAccount::any(Box::new(account))
             .unwrap()
-            .into(),
-            spl_token::instruction::initialize_account(
-                &solar::spl::ID.compat(),
-                &stake_vault_key.pubkey().compat(),
-                &stake_mint_key.pubkey().compat(),
-                &pool_program_authority.compat(),
-            )
+            .supply()
+            .value())
+    }
+
+    async fn balance(context: &mut TestContext, wallet: &Pubkey) -> anyhow::Result<u64> {
+        let account = context.runtime.get_account(wallet).await?.unwrap();
+
+        Ok(WalletAccount::any(Box::new(account))
             .unwrap()
-            .into(),
-            Instruction {
-                program_id,
-                accounts: vec![
-                    AccountMeta::new_readonly(pool_administrator_key.pubkey(), false),
-                    AccountMeta::new_readonly(pool_program_authority, false),
-                    AccountMeta::new(pool_key.pubkey(), false),
-                    AccountMeta::new_readonly(stake_mint_key.pubkey(), false),
-                    AccountMeta::new_readonly(stake_vault_key.pubkey(), false),
-                ],
-                data: Method::Simple(simple_stake::Method::Stake { amount: amount }).encode(),
-            },
-        ];
+            .amount()
+            .value())
+    }
 
-        let trx = Transaction::new_signed_with_payer(
-            &instrs,
-            Some(&payer.pubkey()),
-            &vec![payer, stake_mint_key, stake_vault_key, pool_key],
-            hash,
-        );
+    async fn now(context: &mut TestContext) -> anyhow::Result<i64> {
+        Ok(context.runtime.get_clock().await?.unix_timestamp)
+    }
 
-        let result = client.process_transaction(trx).await;
-        println!("{:?}", result);
+    /// Moves the clock past `date`.
+    async fn warp_past(context: &mut TestContext, date: i64) -> anyhow::Result<()> {
+        while now(context).await? <= date {
+            let clock = context.runtime.get_clock().await?;
+            context.runtime.warp_to_slot(clock.slot + 1_000)?;
+        }
 
         Ok(())
     }
 
-    #[tokio::test]
-    async fn unstake_test() -> anyhow::Result<()> {
-        let mut program_test = ProgramTest::default();
-        let program_id = Pubkey::new_unique();
+    /// Moves to the next slot, so that a retried transaction is signed with a new blockhash.
+    async fn next_slot(context: &mut TestContext) -> anyhow::Result<()> {
+        let clock = context.runtime.get_clock().await?;
+        context.runtime.warp_to_slot(clock.slot + 1)
+    }
 
-        program_test.add_program(
-            "x5margin",
-            program_id,
-            Some(|a, b, c| {
-                builtin_process_instruction(wrapped_entrypoint::<super::Program>, a, b, c)
-            }),
-        );
+    /// Moves the clock forward by `slots` slots.
+    async fn warp_slots(context: &mut TestContext, slots: u64) -> anyhow::Result<()> {
+        let clock = context.runtime.get_clock().await?;
+        context.runtime.warp_to_slot(clock.slot + slots)
+    }
 
-        let pool_key = Keypair::new();
-        let pool_administrator_key = Keypair::new();
+    /// Moves the clock past the end of the pool topup.
+    async fn warp_past_topup(context: &mut TestContext, pool: &PoolKeys) -> anyhow::Result<()> {
+        let pool = load_pool(context, &pool.pool).await?;
+        warp_past(context, (pool.genesis() + pool.topup_duration()).value()).await
+    }
+
+    /// Moves the clock past the end of the pool lockup.
+    async fn warp_past_expiration(
+        context: &mut TestContext,
+        pool: &PoolKeys,
+    ) -> anyhow::Result<()> {
+        let pool = load_pool(context, &pool.pool).await?;
+        warp_past(context, (pool.genesis() + pool.lockup_duration()).value()).await
+    }
+
+    const EPOCH_DURATION: i64 = 1_000;
+    const FARM_MIN_STAKE: u64 = 100;
+
+    /// Accounts of a farm created with [`create_farm`].
+    struct FarmKeys {
+        farm: Pubkey,
+        program_authority: Pubkey,
+        request_queue: Pubkey,
+        staker_registry: Pubkey,
+        active_vault: Pubkey,
+        inactive_vault: Pubkey,
+        reward_vault: Pubkey,
+    }
+
+    /// Creates a farm staking and paying out the context mint, with room for `queue_capacity`
+    /// pending requests.
+    async fn create_farm(
+        context: &mut TestContext,
+        reward_per_epoch: u64,
+        queue_capacity: usize,
+    ) -> anyhow::Result<FarmKeys> {
+        let program_id = context.program_id;
+        let payer = context.payer.pubkey();
+        let administrator = context.administrator.pubkey();
+        let mint = context.mint.pubkey();
+
+        let farm = Keypair::new();
+        let request_queue = Keypair::new();
+        let staker_registry = Keypair::new();
+        let active_vault = Keypair::new();
+        let inactive_vault = Keypair::new();
+        let reward_vault = Keypair::new();
 
         let mut salt: u64 = 0;
-        let pool_program_authority = loop {
-            let pool_program_authority = Pubkey::create_program_address(
+        let program_authority = loop {
+            let program_authority = Pubkey::create_program_address(
                 &[
-                    pool_key.pubkey().as_ref(),
-                    pool_administrator_key.pubkey().as_ref(),
+                    farm.pubkey().as_ref(),
+                    administrator.as_ref(),
                     &salt.to_le_bytes(),
                 ],
                 &program_id,
             );
 
-            match pool_program_authority {
-                Some(s) => break s,
-                None => {
-                    salt += 1;
-                }
+            match program_authority {
+                Some(program_authority) => break program_authority,
+                None => salt += 1,
             }
         };
 
-        let (mut client, payer, hash) = program_test.start().await;
+        let queue_size = size_of::<RequestQueueState>()
+            + size_of::<Request>() * queue_capacity
+            + HEADER_RESERVED;
 
-        let stake_mint_key = Keypair::new();
-        let stake_vault_key = Keypair::new();
+        let mut instrs = vec![];
+        for vault in [&active_vault, &inactive_vault, &reward_vault].iter() {
+            instrs.extend(create_wallet(
+                &payer,
+                &vault.pubkey(),
+                &mint,
+                &program_authority,
+            ));
+        }
+        instrs.push(create_account(
+            &payer,
+            &farm.pubkey(),
+            Farm::default_lamports(),
+            Farm::default_size() as u64,
+            &program_id,
+        ));
+        instrs.push(create_account(
+            &payer,
+            &request_queue.pubkey(),
+            minimum_balance(queue_size as u64),
+            queue_size as u64,
+            &program_id,
+        ));
+        instrs.push(create_account(
+            &payer,
+            &staker_registry.pubkey(),
+            StakerRegistry::default_lamports(),
+            StakerRegistry::default_size() as u64,
+            &program_id,
+        ));
+        instrs.push(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(administrator, false),
+                AccountMeta::new_readonly(program_authority, false),
+                AccountMeta::new(farm.pubkey(), false),
+                AccountMeta::new(request_queue.pubkey(), false),
+                AccountMeta::new(staker_registry.pubkey(), false),
+                AccountMeta::new_readonly(mint, false),
+                AccountMeta::new_readonly(active_vault.pubkey(), false),
+                AccountMeta::new_readonly(inactive_vault.pubkey(), false),
+                AccountMeta::new_readonly(mint, false),
+                AccountMeta::new_readonly(reward_vault.pubkey(), false),
+            ],
+            data: Method::Farm(complex::Method::CreateFarm(complex::InitializeArgs {
+                program_authority_salt: salt,
+                epoch_duration: EPOCH_DURATION.into(),
+                reward_per_epoch: reward_per_epoch.into(),
+                min_stake: FARM_MIN_STAKE.into(),
+            }))
+            .encode(),
+        });
 
-        let amount: super::TokenAmount = solar::math::Checked::from(100);
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[
+                &farm,
+                &request_queue,
+                &staker_registry,
+                &active_vault,
+                &inactive_vault,
+                &reward_vault,
+            ],
+        )
+        .await?
+        .unwrap();
 
-        let instrs = vec![
-            create_account(
-                &payer.pubkey(),
-                &stake_mint_key.pubkey(),
-                minimum_balance(size_of::<Mint>() as u64),
-                size_of::<Mint>() as u64,
-                &solar::spl::ID,
-            ),
-            create_account(
-                &payer.pubkey(),
-                &stake_vault_key.pubkey(),
-                minimum_balance(size_of::<Wallet>() as u64),
-                size_of::<Wallet>() as u64,
-                &solar::spl::ID,
-            ),
-            create_account(
-                &payer.pubkey(),
-                &pool_key.pubkey(),
-                StakePool::default_lamports(),
-                StakePool::default_size() as u64,
-                &program_id,
-            ),
-            spl_token::instruction::initialize_mint(
-                &solar::spl::ID.compat(),
-                &stake_mint_key.pubkey().compat(),
-                &pool_administrator_key.pubkey().compat(),
-                None,
-                6,
-            )
-            .unwrap()
-            .into(),
-            spl_token::instruction::initialize_account(
-                &solar::spl::ID.compat(),
-                &stake_vault_key.pubkey().compat(),
-                &stake_mint_key.pubkey().compat(),
-                &pool_program_authority.compat(),
-            )
-            .unwrap()
-            .into(),
-            Instruction {
-                program_id,
-                accounts: vec![
-                    AccountMeta::new_readonly(pool_administrator_key.pubkey(), false),
-                    AccountMeta::new_readonly(pool_program_authority, false),
-                    AccountMeta::new(pool_key.pubkey(), false),
-                    AccountMeta::new_readonly(stake_mint_key.pubkey(), false),
-                    AccountMeta::new_readonly(stake_vault_key.pubkey(), false),
-                ],
-                data: Method::Simple(simple_stake::Method::Unstake { amount: amount }).encode(),
-            },
-        ];
+        Ok(FarmKeys {
+            farm: farm.pubkey(),
+            program_authority,
+            request_queue: request_queue.pubkey(),
+            staker_registry: staker_registry.pubkey(),
+            active_vault: active_vault.pubkey(),
+            inactive_vault: inactive_vault.pubkey(),
+            reward_vault: reward_vault.pubkey(),
+        })
+    }
 
-        let trx = Transaction::new_signed_with_payer(
-            &instrs,
-            Some(&payer.pubkey()),
-            &vec![payer, stake_mint_key, stake_vault_key, pool_key],
-            hash,
-        );
+    fn farm_stake_instruction(
+        program_id: &Pubkey,
+        farm: &FarmKeys,
+        staker: &Pubkey,
+        wallet: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new(farm.farm, false),
+                AccountMeta::new(farm.request_queue, false),
+                AccountMeta::new(farm.staker_registry, false),
+                AccountMeta::new_readonly(*staker, true),
+                AccountMeta::new(farm.inactive_vault, false),
+                AccountMeta::new_readonly(*staker, true),
+                AccountMeta::new(*wallet, false),
+            ],
+            data: Method::Farm(complex::Method::Stake {
+                amount: amount.into(),
+            })
+            .encode(),
+        }
+    }
 
-        let result = client.process_transaction(trx).await;
-        println!("{:?}", result);
+    fn farm_unstake_instruction(
+        program_id: &Pubkey,
+        farm: &FarmKeys,
+        staker: &Pubkey,
+        amount: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(farm.farm, false),
+                AccountMeta::new(farm.request_queue, false),
+                AccountMeta::new(farm.staker_registry, false),
+                AccountMeta::new_readonly(*staker, true),
+            ],
+            data: Method::Farm(complex::Method::Unstake {
+                amount: amount.into(),
+            })
+            .encode(),
+        }
+    }
+
+    fn farm_withdraw_instruction(
+        program_id: &Pubkey,
+        farm: &FarmKeys,
+        staker: &Pubkey,
+        wallet: &Pubkey,
+    ) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new(farm.farm, false),
+                AccountMeta::new(farm.staker_registry, false),
+                AccountMeta::new_readonly(*staker, true),
+                AccountMeta::new_readonly(farm.program_authority, false),
+                AccountMeta::new(farm.inactive_vault, false),
+                AccountMeta::new(*wallet, false),
+            ],
+            data: Method::Farm(complex::Method::Withdraw).encode(),
+        }
+    }
+
+    fn farm_claim_instruction(
+        program_id: &Pubkey,
+        farm: &FarmKeys,
+        staker: &Pubkey,
+        wallet: &Pubkey,
+    ) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new(farm.farm, false),
+                AccountMeta::new(farm.staker_registry, false),
+                AccountMeta::new_readonly(*staker, true),
+                AccountMeta::new_readonly(farm.program_authority, false),
+                AccountMeta::new(farm.reward_vault, false),
+                AccountMeta::new(*wallet, false),
+            ],
+            data: Method::Farm(complex::Method::ClaimReward).encode(),
+        }
+    }
+
+    /// Reward deposited by the context administrator from their wallet.
+    fn farm_add_reward_instruction(
+        context: &TestContext,
+        farm: &FarmKeys,
+        amount: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: context.program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new(farm.farm, false),
+                AccountMeta::new(farm.reward_vault, false),
+                AccountMeta::new_readonly(context.administrator.pubkey(), true),
+                AccountMeta::new(context.administrator_wallet.pubkey(), false),
+            ],
+            data: Method::Farm(complex::Method::AddReward {
+                amount: amount.into(),
+            })
+            .encode(),
+        }
+    }
+
+    fn crank_instruction(program_id: &Pubkey, farm: &FarmKeys) -> Instruction {
+        Instruction {
+            program_id: *program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new(farm.farm, false),
+                AccountMeta::new(farm.request_queue, false),
+                AccountMeta::new(farm.staker_registry, false),
+                AccountMeta::new_readonly(farm.program_authority, false),
+                AccountMeta::new(farm.active_vault, false),
+                AccountMeta::new(farm.inactive_vault, false),
+            ],
+            data: Method::Farm(complex::Method::Crank { limit: 16 }).encode(),
+        }
+    }
+
+    async fn load_farm(
+        context: &mut TestContext,
+        farm: &Pubkey,
+    ) -> anyhow::Result<FarmEntity<Box<Account>>> {
+        let account = context.runtime.get_account(farm).await?.unwrap();
+
+        Ok(FarmEntity::load(&context.program_id, Box::new(account)).unwrap())
+    }
+
+    /// Moves the clock past the end of the current farm epoch and runs the crank.
+    async fn next_epoch(context: &mut TestContext, farm: &FarmKeys) -> anyhow::Result<()> {
+        let state = load_farm(context, &farm.farm).await?;
+        warp_past(
+            context,
+            (state.epoch_started_at + state.epoch_duration).value(),
+        )
+        .await?;
+
+        let crank = crank_instruction(&context.program_id, farm);
+        process(&mut context.runtime, &context.payer, &[crank], &[])
+            .await?
+            .unwrap();
 
         Ok(())
     }
 
+    fn custom_error(index: u8, error: Error) -> Result<(), TransactionError> {
+        Err(TransactionError::InstructionError(
+            index,
+            InstructionError::Custom(error.code()),
+        ))
+    }
+
     #[tokio::test]
-    async fn claim_reward_test() -> anyhow::Result<()> {
+    async fn create_test() -> anyhow::Result<()> {
         let mut program_test = ProgramTest::default();
         let program_id = Pubkey::new_unique();
 
@@ -488,11 +1200,7 @@ mod test {
         let mut salt: u64 = 0;
         let pool_program_authority = loop {
             let pool_program_authority = Pubkey::create_program_address(
-                &[
-                    pool_key.pubkey().as_ref(),
-                    pool_administrator_key.pubkey().as_ref(),
-                    &salt.to_le_bytes(),
-                ],
+                &[pool_key.pubkey().as_ref(), &salt.to_le_bytes()],
                 &program_id,
             );
 
@@ -508,19 +1216,189 @@ mod test {
 
         let stake_mint_key = Keypair::new();
         let stake_vault_key = Keypair::new();
+        let aux_wallet_key = Keypair::new();
 
-        let instrs = vec![
-            create_account(
-                &payer.pubkey(),
-                &stake_mint_key.pubkey(),
-                minimum_balance(size_of::<Mint>() as u64),
-                size_of::<Mint>() as u64,
-                &solar::spl::ID,
-            ),
-            create_account(
-                &payer.pubkey(),
-                &stake_vault_key.pubkey(),
-                minimum_balance(size_of::<Wallet>() as u64),
+        let staker_key = Keypair::new();
+        let (staker_ticket_key, _) = Pubkey::find_program_address(
+            &StakerTicket::seeds(&pool_key.pubkey(), &staker_key.pubkey()),
+            &program_id,
+        )
+        .unwrap();
+
+        let mut instrs = vec![];
+        // source authority pays for the staker ticket
+        instrs.push(transfer(
+            &payer.pubkey(),
+            &pool_administrator_key.pubkey(),
+            minimum_balance(StakerTicket::default_size() as u64),
+        ));
+        instrs.extend(create_mint(
+            &payer.pubkey(),
+            &stake_mint_key.pubkey(),
+            &pool_administrator_key.pubkey(),
+            6,
+        ));
+        instrs.extend(create_wallet(
+            &payer.pubkey(),
+            &stake_vault_key.pubkey(),
+            &stake_mint_key.pubkey(),
+            &pool_program_authority,
+        ));
+        instrs.extend(create_wallet(
+            &payer.pubkey(),
+            &aux_wallet_key.pubkey(),
+            &stake_mint_key.pubkey(),
+            &pool_administrator_key.pubkey(),
+        ));
+        instrs.push(mint_to(
+            &stake_mint_key.pubkey(),
+            &aux_wallet_key.pubkey(),
+            &pool_administrator_key.pubkey(),
+            1_000_000,
+        ));
+        instrs.push(create_account(
+            &payer.pubkey(),
+            &pool_key.pubkey(),
+            minimum_balance(StakePool::default_size() as u64),
+            StakePool::default_size() as u64,
+            &program_id,
+        ));
+
+        instrs.push(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(pool_administrator_key.pubkey(), false),
+                AccountMeta::new_readonly(pool_program_authority, false),
+                AccountMeta::new(pool_key.pubkey(), false),
+                AccountMeta::new_readonly(stake_mint_key.pubkey(), false),
+                AccountMeta::new_readonly(stake_vault_key.pubkey(), false),
+            ],
+            data: Method::Simple(simple_stake::Method::CreatePool(InitializeArgs {
+                program_authority_salt: salt,
+                lockup_duration: 1000.into(),
+                topup_duration: 200.into(),
+                reward_amount: 1000.into(),
+                target_amount: 10000.into(),
+                reward_mode: RewardMode::LumpSum,
+                early_exit_penalty_bps: 0,
+                early_exit_treasury: None,
+                min_stake: 0.into(),
+                max_stake_per_ticket: 0.into(),
+                allowlist_root: None,
+                fee_bps: 0,
+                fee_wallet: None,
+                liquid: false,
+            }))
+            .encode(),
+        });
+
+        instrs.push(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new_readonly(*solana_api_types::system::ID, false),
+                AccountMeta::new(pool_key.pubkey(), false),
+                AccountMeta::new_readonly(staker_key.pubkey(), false),
+                AccountMeta::new(staker_ticket_key, false),
+                AccountMeta::new(stake_vault_key.pubkey(), false),
+                AccountMeta::new(pool_administrator_key.pubkey(), true),
+                AccountMeta::new(aux_wallet_key.pubkey(), false),
+            ],
+            data: Method::Simple(simple_stake::Method::Stake {
+                amount: 10000.into(),
+                allowlist_proof: None,
+            })
+            .encode(),
+        });
+
+        let trx = Transaction::new_signed_with_payer(
+            &instrs,
+            Some(&payer.pubkey()),
+            &vec![
+                payer,
+                stake_mint_key,
+                stake_vault_key,
+                pool_key.clone(),
+                aux_wallet_key,
+                pool_administrator_key,
+            ],
+            hash,
+        );
+
+        let result = client.process_transaction(trx).await;
+        println!("{:?}", result);
+
+        let stake_pool = client
+            .get_account(&pool_key.pubkey())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let staker_ticket = client
+            .get_account(&staker_ticket_key)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let stake_pool = StakePoolEntity::load(&program_id, Box::new(stake_pool)).unwrap();
+        let staker_ticket = stake_pool.load_ticket(Box::new(staker_ticket)).unwrap();
+
+        assert!(stake_pool.stake_acquired_amount == 10000.into());
+        assert!(stake_pool.stake_target_amount == 10000.into());
+        assert!(staker_ticket.staked_amount == 10000.into());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stake_test() -> anyhow::Result<()> {
+        let mut program_test = ProgramTest::default();
+        let program_id = Pubkey::new_unique();
+
+        program_test.add_program(
+            "x5margin",
+            program_id,
+            Some(|a, b, c| {
+                builtin_process_instruction(wrapped_entrypoint::<super::Program>, a, b, c)
+            }),
+        );
+
+        let pool_key = Keypair::new();
+        let pool_administrator_key = Keypair::new();
+
+        let mut salt: u64 = 0;
+        let pool_program_authority = loop {
+            let pool_program_authority = Pubkey::create_program_address(
+                &[pool_key.pubkey().as_ref(), &salt.to_le_bytes()],
+                &program_id,
+            );
+
+            match pool_program_authority {
+                Some(s) => break s,
+                None => {
+                    salt += 1;
+                }
+            }
+        };
+
+        let (mut client, payer, hash) = program_test.start().await;
+
+        let stake_mint_key = Keypair::new();
+        let stake_vault_key = Keypair::new();
+
+        let amount: super::TokenAmount = solar::math::Checked::from(100);
+
+        let instrs = vec![
+            create_account(
+                &payer.pubkey(),
+                &stake_mint_key.pubkey(),
+                minimum_balance(size_of::<Mint>() as u64),
+                size_of::<Mint>() as u64,
+                &solar::spl::ID,
+            ),
+            create_account(
+                &payer.pubkey(),
+                &stake_vault_key.pubkey(),
+                minimum_balance(size_of::<Wallet>() as u64),
                 size_of::<Wallet>() as u64,
                 &solar::spl::ID,
             ),
@@ -557,7 +1435,11 @@ mod test {
                     AccountMeta::new_readonly(stake_mint_key.pubkey(), false),
                     AccountMeta::new_readonly(stake_vault_key.pubkey(), false),
                 ],
-                data: Method::Simple(simple_stake::Method::ClaimReward).encode(),
+                data: Method::Simple(simple_stake::Method::Stake {
+                    amount: amount,
+                    allowlist_proof: None,
+                })
+                .encode(),
             },
         ];
 
@@ -575,7 +1457,7 @@ mod test {
     }
 
     #[tokio::test]
-    async fn add_reward_test() -> anyhow::Result<()> {
+    async fn unstake_test() -> anyhow::Result<()> {
         let mut program_test = ProgramTest::default();
         let program_id = Pubkey::new_unique();
 
@@ -593,11 +1475,7 @@ mod test {
         let mut salt: u64 = 0;
         let pool_program_authority = loop {
             let pool_program_authority = Pubkey::create_program_address(
-                &[
-                    pool_key.pubkey().as_ref(),
-                    pool_administrator_key.pubkey().as_ref(),
-                    &salt.to_le_bytes(),
-                ],
+                &[pool_key.pubkey().as_ref(), &salt.to_le_bytes()],
                 &program_id,
             );
 
@@ -664,7 +1542,7 @@ mod test {
                     AccountMeta::new_readonly(stake_mint_key.pubkey(), false),
                     AccountMeta::new_readonly(stake_vault_key.pubkey(), false),
                 ],
-                data: Method::Simple(simple_stake::Method::AddReward { amount: amount }).encode(),
+                data: Method::Simple(simple_stake::Method::Unstake { amount: amount }).encode(),
             },
         ];
 
@@ -680,4 +1558,2728 @@ mod test {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn claim_reward_test() -> anyhow::Result<()> {
+        let mut program_test = ProgramTest::default();
+        let program_id = Pubkey::new_unique();
+
+        program_test.add_program(
+            "x5margin",
+            program_id,
+            Some(|a, b, c| {
+                builtin_process_instruction(wrapped_entrypoint::<super::Program>, a, b, c)
+            }),
+        );
+
+        let pool_key = Keypair::new();
+        let pool_administrator_key = Keypair::new();
+
+        let mut salt: u64 = 0;
+        let pool_program_authority = loop {
+            let pool_program_authority = Pubkey::create_program_address(
+                &[pool_key.pubkey().as_ref(), &salt.to_le_bytes()],
+                &program_id,
+            );
+
+            match pool_program_authority {
+                Some(s) => break s,
+                None => {
+                    salt += 1;
+                }
+            }
+        };
+
+        let (mut client, payer, hash) = program_test.start().await;
+
+        let stake_mint_key = Keypair::new();
+        let stake_vault_key = Keypair::new();
+
+        let instrs = vec![
+            create_account(
+                &payer.pubkey(),
+                &stake_mint_key.pubkey(),
+                minimum_balance(size_of::<Mint>() as u64),
+                size_of::<Mint>() as u64,
+                &solar::spl::ID,
+            ),
+            create_account(
+                &payer.pubkey(),
+                &stake_vault_key.pubkey(),
+                minimum_balance(size_of::<Wallet>() as u64),
+                size_of::<Wallet>() as u64,
+                &solar::spl::ID,
+            ),
+            create_account(
+                &payer.pubkey(),
+                &pool_key.pubkey(),
+                StakePool::default_lamports(),
+                StakePool::default_size() as u64,
+                &program_id,
+            ),
+            spl_token::instruction::initialize_mint(
+                &solar::spl::ID.compat(),
+                &stake_mint_key.pubkey().compat(),
+                &pool_administrator_key.pubkey().compat(),
+                None,
+                6,
+            )
+            .unwrap()
+            .into(),
+            spl_token::instruction::initialize_account(
+                &solar::spl::ID.compat(),
+                &stake_vault_key.pubkey().compat(),
+                &stake_mint_key.pubkey().compat(),
+                &pool_program_authority.compat(),
+            )
+            .unwrap()
+            .into(),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new_readonly(pool_administrator_key.pubkey(), false),
+                    AccountMeta::new_readonly(pool_program_authority, false),
+                    AccountMeta::new(pool_key.pubkey(), false),
+                    AccountMeta::new_readonly(stake_mint_key.pubkey(), false),
+                    AccountMeta::new_readonly(stake_vault_key.pubkey(), false),
+                ],
+                data: Method::Simple(simple_stake::Method::ClaimReward).encode(),
+            },
+        ];
+
+        let trx = Transaction::new_signed_with_payer(
+            &instrs,
+            Some(&payer.pubkey()),
+            &vec![payer, stake_mint_key, stake_vault_key, pool_key],
+            hash,
+        );
+
+        let result = client.process_transaction(trx).await;
+        println!("{:?}", result);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn add_reward_test() -> anyhow::Result<()> {
+        let mut program_test = ProgramTest::default();
+        let program_id = Pubkey::new_unique();
+
+        program_test.add_program(
+            "x5margin",
+            program_id,
+            Some(|a, b, c| {
+                builtin_process_instruction(wrapped_entrypoint::<super::Program>, a, b, c)
+            }),
+        );
+
+        let pool_key = Keypair::new();
+        let pool_administrator_key = Keypair::new();
+
+        let mut salt: u64 = 0;
+        let pool_program_authority = loop {
+            let pool_program_authority = Pubkey::create_program_address(
+                &[pool_key.pubkey().as_ref(), &salt.to_le_bytes()],
+                &program_id,
+            );
+
+            match pool_program_authority {
+                Some(s) => break s,
+                None => {
+                    salt += 1;
+                }
+            }
+        };
+
+        let (mut client, payer, hash) = program_test.start().await;
+
+        let stake_mint_key = Keypair::new();
+        let stake_vault_key = Keypair::new();
+
+        let amount: super::TokenAmount = solar::math::Checked::from(100);
+
+        let instrs = vec![
+            create_account(
+                &payer.pubkey(),
+                &stake_mint_key.pubkey(),
+                minimum_balance(size_of::<Mint>() as u64),
+                size_of::<Mint>() as u64,
+                &solar::spl::ID,
+            ),
+            create_account(
+                &payer.pubkey(),
+                &stake_vault_key.pubkey(),
+                minimum_balance(size_of::<Wallet>() as u64),
+                size_of::<Wallet>() as u64,
+                &solar::spl::ID,
+            ),
+            create_account(
+                &payer.pubkey(),
+                &pool_key.pubkey(),
+                StakePool::default_lamports(),
+                StakePool::default_size() as u64,
+                &program_id,
+            ),
+            spl_token::instruction::initialize_mint(
+                &solar::spl::ID.compat(),
+                &stake_mint_key.pubkey().compat(),
+                &pool_administrator_key.pubkey().compat(),
+                None,
+                6,
+            )
+            .unwrap()
+            .into(),
+            spl_token::instruction::initialize_account(
+                &solar::spl::ID.compat(),
+                &stake_vault_key.pubkey().compat(),
+                &stake_mint_key.pubkey().compat(),
+                &pool_program_authority.compat(),
+            )
+            .unwrap()
+            .into(),
+            Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new_readonly(pool_administrator_key.pubkey(), false),
+                    AccountMeta::new_readonly(pool_program_authority, false),
+                    AccountMeta::new(pool_key.pubkey(), false),
+                    AccountMeta::new_readonly(stake_mint_key.pubkey(), false),
+                    AccountMeta::new_readonly(stake_vault_key.pubkey(), false),
+                ],
+                data: Method::Simple(simple_stake::Method::AddReward { amount: amount }).encode(),
+            },
+        ];
+
+        let trx = Transaction::new_signed_with_payer(
+            &instrs,
+            Some(&payer.pubkey()),
+            &vec![payer, stake_mint_key, stake_vault_key, pool_key],
+            hash,
+        );
+
+        let result = client.process_transaction(trx).await;
+        println!("{:?}", result);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rotate_admin_test() -> anyhow::Result<()> {
+        let mut program_test = ProgramTest::default();
+        let program_id = Pubkey::new_unique();
+
+        program_test.add_program(
+            "x5margin",
+            program_id,
+            Some(|a, b, c| {
+                builtin_process_instruction(wrapped_entrypoint::<super::Program>, a, b, c)
+            }),
+        );
+
+        let pool_key = Keypair::new();
+        let pool_administrator_key = Keypair::new();
+        let new_administrator_key = Keypair::new();
+
+        let mut salt: u64 = 0;
+        let pool_program_authority = loop {
+            let pool_program_authority = Pubkey::create_program_address(
+                &[pool_key.pubkey().as_ref(), &salt.to_le_bytes()],
+                &program_id,
+            );
+
+            match pool_program_authority {
+                Some(s) => break s,
+                None => {
+                    salt += 1;
+                }
+            }
+        };
+
+        let (mut runtime, payer, hash) = program_test.start_with_context().await;
+
+        let stake_mint_key = Keypair::new();
+        let stake_vault_key = Keypair::new();
+
+        let mut instrs = vec![];
+        instrs.extend(create_mint(
+            &payer.pubkey(),
+            &stake_mint_key.pubkey(),
+            &pool_administrator_key.pubkey(),
+            6,
+        ));
+        instrs.extend(create_wallet(
+            &payer.pubkey(),
+            &stake_vault_key.pubkey(),
+            &stake_mint_key.pubkey(),
+            &pool_program_authority,
+        ));
+        instrs.push(create_account(
+            &payer.pubkey(),
+            &pool_key.pubkey(),
+            StakePool::default_lamports(),
+            StakePool::default_size() as u64,
+            &program_id,
+        ));
+        instrs.push(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(pool_administrator_key.pubkey(), false),
+                AccountMeta::new_readonly(pool_program_authority, false),
+                AccountMeta::new(pool_key.pubkey(), false),
+                AccountMeta::new_readonly(stake_mint_key.pubkey(), false),
+                AccountMeta::new_readonly(stake_vault_key.pubkey(), false),
+            ],
+            data: Method::Simple(simple_stake::Method::CreatePool(InitializeArgs {
+                program_authority_salt: salt,
+                lockup_duration: 1000.into(),
+                topup_duration: 200.into(),
+                reward_amount: 1000.into(),
+                target_amount: 10000.into(),
+                reward_mode: RewardMode::LumpSum,
+                early_exit_penalty_bps: 0,
+                early_exit_treasury: None,
+                min_stake: 0.into(),
+                max_stake_per_ticket: 0.into(),
+                allowlist_root: None,
+                fee_bps: 0,
+                fee_wallet: None,
+                liquid: false,
+            }))
+            .encode(),
+        });
+        instrs.push(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pool_key.pubkey(), false),
+                AccountMeta::new_readonly(pool_administrator_key.pubkey(), true),
+            ],
+            data: Method::Simple(simple_stake::Method::ProposeAdmin {
+                administrator: new_administrator_key.pubkey(),
+            })
+            .encode(),
+        });
+        instrs.push(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pool_key.pubkey(), false),
+                AccountMeta::new_readonly(new_administrator_key.pubkey(), true),
+            ],
+            data: Method::Simple(simple_stake::Method::AcceptAdmin).encode(),
+        });
+        instrs.push(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pool_key.pubkey(), false),
+                AccountMeta::new_readonly(new_administrator_key.pubkey(), true),
+            ],
+            data: Method::Simple(simple_stake::Method::UpdatePool(UpdatePoolArgs {
+                topup_duration: Some(400.into()),
+                stake_target_amount: Some(20000.into()),
+                reward_amount: None,
+            }))
+            .encode(),
+        });
+
+        let trx = Transaction::new_signed_with_payer(
+            &instrs,
+            Some(&payer.pubkey()),
+            &vec![
+                &payer as &dyn Signer,
+                &stake_mint_key,
+                &stake_vault_key,
+                &pool_key,
+                &pool_administrator_key,
+                &new_administrator_key,
+            ],
+            hash,
+        );
+        runtime.try_process_transaction(trx).await?.unwrap();
+
+        let stake_pool = runtime.get_account(&pool_key.pubkey()).await?.unwrap();
+        let stake_pool = StakePoolEntity::load(&program_id, Box::new(stake_pool)).unwrap();
+
+        assert_eq!(
+            stake_pool.administrator_authority,
+            new_administrator_key.pubkey()
+        );
+        assert_eq!(
+            stake_pool.pending_administrator_authority,
+            Pubkey::default()
+        );
+        assert_eq!(stake_pool.program_authority, pool_program_authority);
+        assert!(stake_pool.topup_duration == 400.into());
+        assert!(stake_pool.stake_target_amount == 20000.into());
+
+        // previous administrator no longer has access to the pool
+        let hash = runtime.get_recent_blockhash().await?;
+        let trx = Transaction::new_signed_with_payer(
+            &[Instruction {
+                program_id,
+                accounts: vec![
+                    AccountMeta::new(pool_key.pubkey(), false),
+                    AccountMeta::new_readonly(pool_administrator_key.pubkey(), true),
+                ],
+                data: Method::Simple(simple_stake::Method::UpdatePool(UpdatePoolArgs {
+                    topup_duration: None,
+                    stake_target_amount: Some(30000.into()),
+                    reward_amount: None,
+                }))
+                .encode(),
+            }],
+            Some(&payer.pubkey()),
+            &vec![&payer as &dyn Signer, &pool_administrator_key],
+            hash,
+        );
+        assert!(runtime.try_process_transaction(trx).await?.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn farm_crank_test() -> anyhow::Result<()> {
+        let mut program_test = ProgramTest::default();
+        let program_id = Pubkey::new_unique();
+
+        program_test.add_program(
+            "x5margin",
+            program_id,
+            Some(|a, b, c| {
+                builtin_process_instruction(wrapped_entrypoint::<super::Program>, a, b, c)
+            }),
+        );
+
+        let farm_key = Keypair::new();
+        let administrator_key = Keypair::new();
+
+        let mut salt: u64 = 0;
+        let program_authority = loop {
+            let program_authority = Pubkey::create_program_address(
+                &[
+                    farm_key.pubkey().as_ref(),
+                    administrator_key.pubkey().as_ref(),
+                    &salt.to_le_bytes(),
+                ],
+                &program_id,
+            );
+
+            match program_authority {
+                Some(s) => break s,
+                None => {
+                    salt += 1;
+                }
+            }
+        };
+
+        let (mut runtime, payer, hash) = program_test.start_with_context().await;
+
+        let mint_key = Keypair::new();
+        let active_vault_key = Keypair::new();
+        let inactive_vault_key = Keypair::new();
+        let reward_vault_key = Keypair::new();
+        let request_queue_key = Keypair::new();
+        let staker_registry_key = Keypair::new();
+        let staker_key = Keypair::new();
+        let staker_wallet_key = Keypair::new();
+
+        let mut instrs = vec![];
+        instrs.extend(create_mint(
+            &payer.pubkey(),
+            &mint_key.pubkey(),
+            &administrator_key.pubkey(),
+            6,
+        ));
+        for vault in [&active_vault_key, &inactive_vault_key, &reward_vault_key].iter() {
+            instrs.extend(create_wallet(
+                &payer.pubkey(),
+                &vault.pubkey(),
+                &mint_key.pubkey(),
+                &program_authority,
+            ));
+        }
+        instrs.extend(create_wallet(
+            &payer.pubkey(),
+            &staker_wallet_key.pubkey(),
+            &mint_key.pubkey(),
+            &staker_key.pubkey(),
+        ));
+        instrs.push(mint_to(
+            &mint_key.pubkey(),
+            &staker_wallet_key.pubkey(),
+            &administrator_key.pubkey(),
+            1_000,
+        ));
+        instrs.push(create_account(
+            &payer.pubkey(),
+            &farm_key.pubkey(),
+            Farm::default_lamports(),
+            Farm::default_size() as u64,
+            &program_id,
+        ));
+        instrs.push(create_account(
+            &payer.pubkey(),
+            &request_queue_key.pubkey(),
+            RequestQueue::default_lamports(),
+            RequestQueue::default_size() as u64,
+            &program_id,
+        ));
+        instrs.push(create_account(
+            &payer.pubkey(),
+            &staker_registry_key.pubkey(),
+            StakerRegistry::default_lamports(),
+            StakerRegistry::default_size() as u64,
+            &program_id,
+        ));
+        instrs.push(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(administrator_key.pubkey(), false),
+                AccountMeta::new_readonly(program_authority, false),
+                AccountMeta::new(farm_key.pubkey(), false),
+                AccountMeta::new(request_queue_key.pubkey(), false),
+                AccountMeta::new(staker_registry_key.pubkey(), false),
+                AccountMeta::new_readonly(mint_key.pubkey(), false),
+                AccountMeta::new_readonly(active_vault_key.pubkey(), false),
+                AccountMeta::new_readonly(inactive_vault_key.pubkey(), false),
+                AccountMeta::new_readonly(mint_key.pubkey(), false),
+                AccountMeta::new_readonly(reward_vault_key.pubkey(), false),
+            ],
+            data: Method::Farm(complex::Method::CreateFarm(complex::InitializeArgs {
+                program_authority_salt: salt,
+                epoch_duration: 10_000.into(),
+                reward_per_epoch: 100.into(),
+                min_stake: 100.into(),
+            }))
+            .encode(),
+        });
+        instrs.push(Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new(farm_key.pubkey(), false),
+                AccountMeta::new(request_queue_key.pubkey(), false),
+                AccountMeta::new(staker_registry_key.pubkey(), false),
+                AccountMeta::new_readonly(staker_key.pubkey(), true),
+                AccountMeta::new(inactive_vault_key.pubkey(), false),
+                AccountMeta::new_readonly(staker_key.pubkey(), true),
+                AccountMeta::new(staker_wallet_key.pubkey(), false),
+            ],
+            data: Method::Farm(complex::Method::Stake {
+                amount: 1_000.into(),
+            })
+            .encode(),
+        });
+
+        let trx = Transaction::new_signed_with_payer(
+            &instrs,
+            Some(&payer.pubkey()),
+            &vec![
+                &payer as &dyn Signer,
+                &mint_key,
+                &active_vault_key,
+                &inactive_vault_key,
+                &reward_vault_key,
+                &staker_wallet_key,
+                &farm_key,
+                &request_queue_key,
+                &staker_registry_key,
+                &administrator_key,
+                &staker_key,
+            ],
+            hash,
+        );
+        runtime.try_process_transaction(trx).await?.unwrap();
+
+        let crank = Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new(farm_key.pubkey(), false),
+                AccountMeta::new(request_queue_key.pubkey(), false),
+                AccountMeta::new(staker_registry_key.pubkey(), false),
+                AccountMeta::new_readonly(program_authority, false),
+                AccountMeta::new(active_vault_key.pubkey(), false),
+                AccountMeta::new(inactive_vault_key.pubkey(), false),
+            ],
+            data: Method::Farm(complex::Method::Crank { limit: 16 }).encode(),
+        };
+
+        // requests are only applied once the epoch they were made in is over
+        let hash = runtime.get_recent_blockhash().await?;
+        let trx = Transaction::new_signed_with_payer(
+            &[crank.clone()],
+            Some(&payer.pubkey()),
+            &vec![&payer as &dyn Signer],
+            hash,
+        );
+        assert!(runtime.try_process_transaction(trx).await?.is_err());
+
+        let farm = runtime.get_account(&farm_key.pubkey()).await?.unwrap();
+        let farm = FarmEntity::load(&program_id, Box::new(farm)).unwrap();
+        let epoch_end = (farm.epoch_started_at + farm.epoch_duration).value();
+
+        let clock = runtime.get_clock().await?;
+        runtime.warp_to_slot(clock.slot + 30_000)?;
+        assert!(runtime.get_clock().await?.unix_timestamp >= epoch_end);
+
+        let hash = runtime.get_recent_blockhash().await?;
+        let trx = Transaction::new_signed_with_payer(
+            &[crank],
+            Some(&payer.pubkey()),
+            &vec![&payer as &dyn Signer],
+            hash,
+        );
+        runtime.try_process_transaction(trx).await?.unwrap();
+
+        let farm = runtime.get_account(&farm_key.pubkey()).await?.unwrap();
+        let farm = FarmEntity::load(&program_id, Box::new(farm)).unwrap();
+        let registry = runtime
+            .get_account(&staker_registry_key.pubkey())
+            .await?
+            .unwrap();
+        let registry = farm.load_staker_registry(Box::new(registry)).unwrap();
+
+        assert!(farm.epoch >= 1);
+        assert!(farm.active_stake == 1_000.into());
+        assert!(farm.inactive_stake == 0.into());
+        assert_eq!(registry.find(&staker_key.pubkey()), Some(0));
+        assert!(registry.get(0).unwrap().active_stake == 1_000.into());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn close_pool_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+        let administrator = context.administrator.pubkey();
+        let administrator_wallet = context.administrator_wallet.pubkey();
+
+        let pool = create_pool(&mut context, pool_args()).await?;
+        let (staker, staker_wallet) = create_staker(&mut context, 10_000).await?;
+
+        // unstaking everything during topup must not make the pool closable
+        let instrs = [
+            stake_instruction(&program_id, &pool, &staker.pubkey(), &staker_wallet, 10_000),
+            unstake_instruction(&program_id, &pool, &staker.pubkey(), &staker_wallet, 10_000),
+        ];
+        process(&mut context.runtime, &context.payer, &instrs, &[&staker])
+            .await?
+            .unwrap();
+        assert!(
+            load_pool(&mut context, &pool.pool)
+                .await?
+                .stake_acquired_amount
+                == 0.into()
+        );
+
+        let close = close_instruction(&program_id, &pool, &administrator, &administrator_wallet);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[close.clone()],
+            &[&context.administrator],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        let instrs = [
+            stake_instruction(&program_id, &pool, &staker.pubkey(), &staker_wallet, 10_000),
+            add_reward_instruction(&context, &pool, 1_000),
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&staker, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        warp_past_expiration(&mut context, &pool).await?;
+
+        let impostor = Keypair::new();
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[close_instruction(
+                &program_id,
+                &pool,
+                &impostor.pubkey(),
+                &administrator_wallet,
+            )],
+            &[&impostor],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAuthority));
+
+        // stake is still there and the grace period has not passed
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[close.clone()],
+            &[&context.administrator],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        let claim = claim_instruction(&program_id, &pool, &staker.pubkey(), &staker_wallet);
+        process(&mut context.runtime, &context.payer, &[claim], &[&staker])
+            .await?
+            .unwrap();
+        assert_eq!(balance(&mut context, &staker_wallet).await?, 11_000);
+
+        next_slot(&mut context).await?;
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &[close],
+            &[&context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        assert!(context.runtime.get_account(&pool.pool).await?.is_none());
+        assert!(context
+            .runtime
+            .get_account(&pool.stake_vault)
+            .await?
+            .is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn sequential_claim_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let pool = create_pool(&mut context, pool_args()).await?;
+        let (first, first_wallet) = create_staker(&mut context, 7_500).await?;
+        let (second, second_wallet) = create_staker(&mut context, 2_500).await?;
+
+        let instrs = [
+            stake_instruction(&program_id, &pool, &first.pubkey(), &first_wallet, 7_500),
+            stake_instruction(&program_id, &pool, &second.pubkey(), &second_wallet, 2_500),
+            add_reward_instruction(&context, &pool, 1_000),
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&first, &second, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        warp_past_expiration(&mut context, &pool).await?;
+
+        let claim = claim_instruction(&program_id, &pool, &first.pubkey(), &first_wallet);
+        process(&mut context.runtime, &context.payer, &[claim], &[&first])
+            .await?
+            .unwrap();
+
+        // the first claim takes its stake and reward share out of the pool
+        let stake_pool = load_pool(&mut context, &pool.pool).await?;
+        assert!(stake_pool.stake_acquired_amount == 2_500.into());
+        assert!(stake_pool.available_reward() == 250.into());
+        assert_eq!(balance(&mut context, &first_wallet).await?, 8_250);
+
+        let claim = claim_instruction(&program_id, &pool, &second.pubkey(), &second_wallet);
+        process(&mut context.runtime, &context.payer, &[claim], &[&second])
+            .await?
+            .unwrap();
+
+        let stake_pool = load_pool(&mut context, &pool.pool).await?;
+        assert!(stake_pool.stake_acquired_amount == 0.into());
+        assert!(stake_pool.available_reward() == 0.into());
+        assert_eq!(balance(&mut context, &second_wallet).await?, 2_750);
+        assert_eq!(balance(&mut context, &pool.stake_vault).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn underfunded_claim_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let pool = create_pool(&mut context, pool_args()).await?;
+        let (first, first_wallet) = create_staker(&mut context, 7_500).await?;
+        let (second, second_wallet) = create_staker(&mut context, 2_500).await?;
+
+        // only 400 out of the 1_000 promised are deposited
+        let instrs = [
+            stake_instruction(&program_id, &pool, &first.pubkey(), &first_wallet, 7_500),
+            stake_instruction(&program_id, &pool, &second.pubkey(), &second_wallet, 2_500),
+            add_reward_instruction(&context, &pool, 400),
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&first, &second, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        warp_past_expiration(&mut context, &pool).await?;
+
+        let claim = claim_instruction(&program_id, &pool, &first.pubkey(), &first_wallet);
+        process(&mut context.runtime, &context.payer, &[claim], &[&first])
+            .await?
+            .unwrap();
+        assert_eq!(balance(&mut context, &first_wallet).await?, 7_800);
+
+        let claim = claim_instruction(&program_id, &pool, &second.pubkey(), &second_wallet);
+        process(&mut context.runtime, &context.payer, &[claim], &[&second])
+            .await?
+            .unwrap();
+        assert_eq!(balance(&mut context, &second_wallet).await?, 2_600);
+
+        // the haircut never reaches into the principal of the last claimer
+        assert_eq!(balance(&mut context, &pool.stake_vault).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unfunded_pool_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let pool = create_pool(&mut context, pool_args()).await?;
+        let (first, first_wallet) = create_staker(&mut context, 3_000).await?;
+        let (second, second_wallet) = create_staker(&mut context, 2_000).await?;
+
+        let instrs = [
+            stake_instruction(&program_id, &pool, &first.pubkey(), &first_wallet, 3_000),
+            stake_instruction(&program_id, &pool, &second.pubkey(), &second_wallet, 2_000),
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&first, &second],
+        )
+        .await?
+        .unwrap();
+
+        // lockup holds even though the reward was not deposited yet
+        warp_past_topup(&mut context, &pool).await?;
+
+        let unstake =
+            unstake_instruction(&program_id, &pool, &first.pubkey(), &first_wallet, 3_000);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[unstake.clone()],
+            &[&first],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        // once the pool expired unfunded, stakers take the principal alone
+        warp_past_expiration(&mut context, &pool).await?;
+
+        process(&mut context.runtime, &context.payer, &[unstake], &[&first])
+            .await?
+            .unwrap();
+        assert_eq!(balance(&mut context, &first_wallet).await?, 3_000);
+
+        let claim = claim_instruction(&program_id, &pool, &second.pubkey(), &second_wallet);
+        process(&mut context.runtime, &context.payer, &[claim], &[&second])
+            .await?
+            .unwrap();
+        assert_eq!(balance(&mut context, &second_wallet).await?, 2_000);
+
+        let stake_pool = load_pool(&mut context, &pool.pool).await?;
+        assert!(stake_pool.stake_acquired_amount == 0.into());
+        assert_eq!(balance(&mut context, &pool.stake_vault).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn separate_reward_mint_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+        let payer = context.payer.pubkey();
+        let administrator = context.administrator.pubkey();
+        let stake_mint = context.mint.pubkey();
+
+        let reward_mint = Keypair::new();
+        let reward_wallet = Keypair::new();
+
+        let mut instrs = create_mint(&payer, &reward_mint.pubkey(), &administrator, 6).to_vec();
+        instrs.extend(create_wallet(
+            &payer,
+            &reward_wallet.pubkey(),
+            &reward_mint.pubkey(),
+            &administrator,
+        ));
+        instrs.push(mint_to(
+            &reward_mint.pubkey(),
+            &reward_wallet.pubkey(),
+            &administrator,
+            INITIAL_BALANCE,
+        ));
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&reward_mint, &reward_wallet, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        let pool = Keypair::new();
+        let stake_vault = Keypair::new();
+        let reward_vault = Keypair::new();
+
+        let (program_authority, salt) = find_program_authority(&program_id, &pool.pubkey());
+
+        let mut instrs = create_wallet(
+            &payer,
+            &stake_vault.pubkey(),
+            &stake_mint,
+            &program_authority,
+        )
+        .to_vec();
+        instrs.extend(create_wallet(
+            &payer,
+            &reward_vault.pubkey(),
+            &reward_mint.pubkey(),
+            &program_authority,
+        ));
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&stake_vault, &reward_vault],
+        )
+        .await?
+        .unwrap();
+
+        let create_instrs = |stake_vault: Pubkey, reward_vault: Pubkey| {
+            let mut args = pool_args();
+            args.program_authority_salt = salt;
+
+            [
+                create_account(
+                    &payer,
+                    &pool.pubkey(),
+                    StakePool::default_lamports(),
+                    StakePool::default_size() as u64,
+                    &program_id,
+                ),
+                Instruction {
+                    program_id,
+                    accounts: vec![
+                        AccountMeta::new_readonly(administrator, false),
+                        AccountMeta::new_readonly(program_authority, false),
+                        AccountMeta::new(pool.pubkey(), false),
+                        AccountMeta::new_readonly(stake_mint, false),
+                        AccountMeta::new_readonly(stake_vault, false),
+                        AccountMeta::new_readonly(reward_mint.pubkey(), false),
+                        AccountMeta::new_readonly(reward_vault, false),
+                    ],
+                    data: Method::Simple(simple_stake::Method::CreatePoolWithReward(args)).encode(),
+                },
+            ]
+        };
+
+        // both vaults have to be held by the program authority
+        let instrs = create_instrs(context.administrator_wallet.pubkey(), reward_vault.pubkey());
+        let result = process(&mut context.runtime, &context.payer, &instrs, &[&pool]).await?;
+        assert_eq!(result, custom_error(1, Error::InvalidAuthority));
+
+        let instrs = create_instrs(stake_vault.pubkey(), reward_wallet.pubkey());
+        let result = process(&mut context.runtime, &context.payer, &instrs, &[&pool]).await?;
+        assert_eq!(result, custom_error(1, Error::InvalidAuthority));
+
+        let instrs = create_instrs(stake_vault.pubkey(), reward_vault.pubkey());
+        process(&mut context.runtime, &context.payer, &instrs, &[&pool])
+            .await?
+            .unwrap();
+
+        let stake_pool = load_pool(&mut context, &pool.pubkey()).await?;
+        assert!(stake_pool.has_reward_vault());
+        assert_eq!(stake_pool.reward_mint, reward_mint.pubkey());
+        assert_eq!(stake_pool.reward_vault, reward_vault.pubkey());
+
+        let pool = PoolKeys {
+            pool: pool.pubkey(),
+            program_authority,
+            stake_vault: stake_vault.pubkey(),
+            share_mint: None,
+        };
+
+        let (staker, wallet) = create_staker(&mut context, 1_000).await?;
+        let staker_reward_wallet = Keypair::new();
+
+        let mut instrs = create_wallet(
+            &payer,
+            &staker_reward_wallet.pubkey(),
+            &reward_mint.pubkey(),
+            &staker.pubkey(),
+        )
+        .to_vec();
+        instrs.push(stake_instruction(
+            &program_id,
+            &pool,
+            &staker.pubkey(),
+            &wallet,
+            1_000,
+        ));
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&staker_reward_wallet, &staker],
+        )
+        .await?
+        .unwrap();
+
+        // reward can't be deposited into the stake vault
+        let instr = add_reward_instruction(&context, &pool, 1_000);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr.clone()],
+            &[&context.administrator],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAccount));
+
+        let mut instr = instr;
+        instr.accounts[2].pubkey = reward_vault.pubkey();
+        instr.accounts[4].pubkey = reward_wallet.pubkey();
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &[instr],
+            &[&context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        assert_eq!(balance(&mut context, &reward_vault.pubkey()).await?, 1_000);
+        assert_eq!(balance(&mut context, &pool.stake_vault).await?, 1_000);
+
+        warp_past_expiration(&mut context, &pool).await?;
+
+        // principal comes from the stake vault, reward from the reward vault
+        let mut claim = claim_instruction(&program_id, &pool, &staker.pubkey(), &wallet);
+        claim
+            .accounts
+            .push(AccountMeta::new(reward_vault.pubkey(), false));
+        claim
+            .accounts
+            .push(AccountMeta::new(staker_reward_wallet.pubkey(), false));
+        process(&mut context.runtime, &context.payer, &[claim], &[&staker])
+            .await?
+            .unwrap();
+
+        assert_eq!(balance(&mut context, &wallet).await?, 1_000);
+        assert_eq!(
+            balance(&mut context, &staker_reward_wallet.pubkey()).await?,
+            1_000
+        );
+        assert_eq!(balance(&mut context, &pool.stake_vault).await?, 0);
+        assert_eq!(balance(&mut context, &reward_vault.pubkey()).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stake_limits_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let mut args = pool_args();
+        args.min_stake = 200.into();
+        args.max_stake_per_ticket = 1_000.into();
+        let pool = create_pool(&mut context, args).await?;
+        let (staker, wallet) = create_staker(&mut context, 2_000).await?;
+
+        let stake =
+            |amount| stake_instruction(&program_id, &pool, &staker.pubkey(), &wallet, amount);
+
+        // first stake below the minimum
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[stake(150)],
+            &[&staker],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &[stake(600)],
+            &[&staker],
+        )
+        .await?
+        .unwrap();
+
+        // ticket would hold more than the maximum
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[stake(500)],
+            &[&staker],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        // once the ticket holds the minimum, smaller stakes can be added up to the maximum
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &[stake(100), stake(300)],
+            &[&staker],
+        )
+        .await?
+        .unwrap();
+
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[stake(1)],
+            &[&staker],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        let ticket = load_ticket(&mut context, &pool.pool, &staker.pubkey()).await?;
+        assert!(ticket.staked_amount == 1_000.into());
+        assert_eq!(balance(&mut context, &wallet).await?, 1_000);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn allowlist_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let (first, first_wallet) = create_staker(&mut context, 1_000).await?;
+        let (second, second_wallet) = create_staker(&mut context, 1_000).await?;
+
+        let leaves = [
+            allowlist::leaf(&first.pubkey(), 500.into()),
+            allowlist::leaf(&second.pubkey(), 1_000.into()),
+            allowlist::leaf(&Pubkey::new_unique(), 1_000.into()),
+        ];
+        let left = allowlist::parent(&leaves[0], &leaves[1]);
+        let root = allowlist::parent(&left, &leaves[2]);
+
+        let mut args = pool_args();
+        args.allowlist_root = Some(root);
+        let pool = create_pool(&mut context, args).await?;
+
+        let first_proof = |max_allocation: u64| AllowlistProof {
+            max_allocation: max_allocation.into(),
+            proof: vec![leaves[1], leaves[2]],
+        };
+
+        // proof is required
+        let stake = stake_instruction(&program_id, &pool, &first.pubkey(), &first_wallet, 500);
+        let result = process(&mut context.runtime, &context.payer, &[stake], &[&first]).await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        // allocation does not match the allowlist
+        let stake = allowlisted_stake_instruction(
+            &program_id,
+            &pool,
+            &first.pubkey(),
+            &first_wallet,
+            500,
+            first_proof(1_000),
+        );
+        let result = process(&mut context.runtime, &context.payer, &[stake], &[&first]).await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        // stake above the allocation
+        let stake = allowlisted_stake_instruction(
+            &program_id,
+            &pool,
+            &first.pubkey(),
+            &first_wallet,
+            600,
+            first_proof(500),
+        );
+        let result = process(&mut context.runtime, &context.payer, &[stake], &[&first]).await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        let instrs = [
+            allowlisted_stake_instruction(
+                &program_id,
+                &pool,
+                &first.pubkey(),
+                &first_wallet,
+                500,
+                first_proof(500),
+            ),
+            allowlisted_stake_instruction(
+                &program_id,
+                &pool,
+                &second.pubkey(),
+                &second_wallet,
+                1_000,
+                AllowlistProof {
+                    max_allocation: 1_000.into(),
+                    proof: vec![leaves[0], leaves[2]],
+                },
+            ),
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&first, &second],
+        )
+        .await?
+        .unwrap();
+
+        let ticket = load_ticket(&mut context, &pool.pool, &first.pubkey()).await?;
+        assert!(ticket.staked_amount == 500.into());
+        let ticket = load_ticket(&mut context, &pool.pool, &second.pubkey()).await?;
+        assert!(ticket.staked_amount == 1_000.into());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn farm_unstake_withdraw_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let farm = create_farm(&mut context, 0, 16).await?;
+        let (staker, wallet) = create_staker(&mut context, 1_000).await?;
+
+        let stake = farm_stake_instruction(&program_id, &farm, &staker.pubkey(), &wallet, 1_000);
+        process(&mut context.runtime, &context.payer, &[stake], &[&staker])
+            .await?
+            .unwrap();
+        next_epoch(&mut context, &farm).await?;
+
+        let unstake = farm_unstake_instruction(&program_id, &farm, &staker.pubkey(), 400);
+        process(&mut context.runtime, &context.payer, &[unstake], &[&staker])
+            .await?
+            .unwrap();
+
+        // stake stays active until the epoch of the request ends
+        let withdraw = farm_withdraw_instruction(&program_id, &farm, &staker.pubkey(), &wallet);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[withdraw.clone()],
+            &[&staker],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        next_epoch(&mut context, &farm).await?;
+
+        let state = load_farm(&mut context, &farm.farm).await?;
+        assert!(state.active_stake == 600.into());
+        assert!(state.inactive_stake == 400.into());
+        assert_eq!(balance(&mut context, &farm.active_vault).await?, 600);
+        assert_eq!(balance(&mut context, &farm.inactive_vault).await?, 400);
+
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &[withdraw],
+            &[&staker],
+        )
+        .await?
+        .unwrap();
+
+        let state = load_farm(&mut context, &farm.farm).await?;
+        let registry = context
+            .runtime
+            .get_account(&farm.staker_registry)
+            .await?
+            .unwrap();
+        let registry = state.load_staker_registry(Box::new(registry)).unwrap();
+
+        assert!(state.inactive_stake == 0.into());
+        assert!(registry.get(0).unwrap().active_stake == 600.into());
+        assert!(registry.get(0).unwrap().inactive_stake == 0.into());
+        assert_eq!(balance(&mut context, &wallet).await?, 400);
+
+        // more than the active stake can't be requested
+        let unstake = farm_unstake_instruction(&program_id, &farm, &staker.pubkey(), 601);
+        let result = process(&mut context.runtime, &context.payer, &[unstake], &[&staker]).await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn farm_late_request_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let farm = create_farm(&mut context, 0, 16).await?;
+        let (staker, wallet) = create_staker(&mut context, 1_000).await?;
+
+        // epoch is over, but the crank has not started the next one yet
+        let state = load_farm(&mut context, &farm.farm).await?;
+        warp_past(
+            &mut context,
+            (state.epoch_started_at + state.epoch_duration).value(),
+        )
+        .await?;
+
+        let stake = farm_stake_instruction(&program_id, &farm, &staker.pubkey(), &wallet, 1_000);
+        process(&mut context.runtime, &context.payer, &[stake], &[&staker])
+            .await?
+            .unwrap();
+
+        let crank = crank_instruction(&program_id, &farm);
+        process(&mut context.runtime, &context.payer, &[crank], &[])
+            .await?
+            .unwrap();
+
+        let state = load_farm(&mut context, &farm.farm).await?;
+        let queue = context
+            .runtime
+            .get_account(&farm.request_queue)
+            .await?
+            .unwrap();
+        let queue = state.load_request_queue(Box::new(queue)).unwrap();
+
+        assert_eq!(state.epoch, 1);
+        assert!(state.active_stake == 0.into());
+        assert!(state.inactive_stake == 1_000.into());
+        assert_eq!(queue.front().unwrap().epoch, 1);
+
+        next_epoch(&mut context, &farm).await?;
+
+        let state = load_farm(&mut context, &farm.farm).await?;
+        assert!(state.active_stake == 1_000.into());
+        assert!(state.inactive_stake == 0.into());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn farm_reward_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let farm = create_farm(&mut context, 250, 16).await?;
+        let (staker, wallet) = create_staker(&mut context, 1_000).await?;
+
+        let instrs = [
+            farm_stake_instruction(&program_id, &farm, &staker.pubkey(), &wallet, 1_000),
+            farm_add_reward_instruction(&context, &farm, 375),
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&staker, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        let state = load_farm(&mut context, &farm.farm).await?;
+        assert!(state.deposited_reward_amount == 375.into());
+        assert_eq!(balance(&mut context, &farm.reward_vault).await?, 375);
+
+        // stake is activated at the end of the first epoch and earns nothing in it
+        next_epoch(&mut context, &farm).await?;
+
+        let claim = farm_claim_instruction(&program_id, &farm, &staker.pubkey(), &wallet);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[claim.clone()],
+            &[&staker],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        next_epoch(&mut context, &farm).await?;
+
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &[claim.clone()],
+            &[&staker],
+        )
+        .await?
+        .unwrap();
+        assert_eq!(balance(&mut context, &wallet).await?, 250);
+
+        // the next epoch distributes only what is left of the deposit
+        next_epoch(&mut context, &farm).await?;
+
+        process(&mut context.runtime, &context.payer, &[claim], &[&staker])
+            .await?
+            .unwrap();
+        assert_eq!(balance(&mut context, &wallet).await?, 375);
+
+        let state = load_farm(&mut context, &farm.farm).await?;
+        assert!(state.distributed_reward_amount == 375.into());
+        assert!(state.undistributed_reward() == 0.into());
+
+        // nothing is deposited from an empty wallet
+        let (empty, empty_wallet) = create_staker(&mut context, 0).await?;
+        let mut add_reward = farm_add_reward_instruction(&context, &farm, 100);
+        add_reward.accounts[3] = AccountMeta::new_readonly(empty.pubkey(), true);
+        add_reward.accounts[4] = AccountMeta::new(empty_wallet, false);
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[add_reward],
+            &[&empty],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn farm_full_queue_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let farm = create_farm(&mut context, 0, 2).await?;
+        let (first, first_wallet) = create_staker(&mut context, 100).await?;
+        let (second, second_wallet) = create_staker(&mut context, 200).await?;
+        let (third, third_wallet) = create_staker(&mut context, 300).await?;
+
+        let instrs = [
+            farm_stake_instruction(&program_id, &farm, &first.pubkey(), &first_wallet, 100),
+            farm_stake_instruction(&program_id, &farm, &second.pubkey(), &second_wallet, 200),
+            farm_stake_instruction(&program_id, &farm, &third.pubkey(), &third_wallet, 300),
+        ];
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&first, &second, &third],
+        )
+        .await?;
+        assert_eq!(result, custom_error(2, Error::Validation));
+
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs[..2],
+            &[&first, &second],
+        )
+        .await?
+        .unwrap();
+
+        // the crank frees the queue once the requests are applied
+        next_epoch(&mut context, &farm).await?;
+
+        let state = load_farm(&mut context, &farm.farm).await?;
+        assert!(state.active_stake == 300.into());
+
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs[2..],
+            &[&third],
+        )
+        .await?
+        .unwrap();
+
+        let state = load_farm(&mut context, &farm.farm).await?;
+        assert!(state.inactive_stake == 300.into());
+        assert_eq!(balance(&mut context, &third_wallet).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn farm_dust_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let farm = create_farm(&mut context, 0, 16).await?;
+        let (staker, wallet) = create_staker(&mut context, 1_000).await?;
+        let (next, next_wallet) = create_staker(&mut context, 1_000).await?;
+
+        let stake = farm_stake_instruction(
+            &program_id,
+            &farm,
+            &staker.pubkey(),
+            &wallet,
+            FARM_MIN_STAKE - 1,
+        );
+        let result = process(&mut context.runtime, &context.payer, &[stake], &[&staker]).await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        // requests made by the staker in the same epoch take a single queue entry
+        let instrs = [
+            farm_stake_instruction(&program_id, &farm, &staker.pubkey(), &wallet, 100),
+            farm_stake_instruction(&program_id, &farm, &staker.pubkey(), &wallet, 150),
+        ];
+        process(&mut context.runtime, &context.payer, &instrs, &[&staker])
+            .await?
+            .unwrap();
+
+        let state = load_farm(&mut context, &farm.farm).await?;
+        let queue = context
+            .runtime
+            .get_account(&farm.request_queue)
+            .await?
+            .unwrap();
+        let queue = state.load_request_queue(Box::new(queue)).unwrap();
+        assert_eq!(queue.len(), 1);
+        assert!(queue.front().unwrap().amount == 250.into());
+
+        next_epoch(&mut context, &farm).await?;
+
+        // dust can only be unstaked along with the rest of the stake
+        let unstake = farm_unstake_instruction(&program_id, &farm, &staker.pubkey(), 50);
+        let result = process(&mut context.runtime, &context.payer, &[unstake], &[&staker]).await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        let instrs = [
+            farm_unstake_instruction(&program_id, &farm, &staker.pubkey(), 200),
+            farm_unstake_instruction(&program_id, &farm, &staker.pubkey(), 100),
+        ];
+        let result = process(&mut context.runtime, &context.payer, &instrs, &[&staker]).await?;
+        assert_eq!(result, custom_error(1, Error::Validation));
+
+        let instrs = [
+            farm_unstake_instruction(&program_id, &farm, &staker.pubkey(), 200),
+            farm_unstake_instruction(&program_id, &farm, &staker.pubkey(), 50),
+        ];
+        process(&mut context.runtime, &context.payer, &instrs, &[&staker])
+            .await?
+            .unwrap();
+
+        let queue = context
+            .runtime
+            .get_account(&farm.request_queue)
+            .await?
+            .unwrap();
+        let queue = state.load_request_queue(Box::new(queue)).unwrap();
+        assert_eq!(queue.len(), 1);
+        assert!(queue.front().unwrap().amount == 250.into());
+
+        next_epoch(&mut context, &farm).await?;
+
+        let withdraw = farm_withdraw_instruction(&program_id, &farm, &staker.pubkey(), &wallet);
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &[withdraw],
+            &[&staker],
+        )
+        .await?
+        .unwrap();
+        assert_eq!(balance(&mut context, &wallet).await?, 1_000);
+
+        // the staker left, so the slot is taken by the next one instead of a new slot
+        let stake = farm_stake_instruction(&program_id, &farm, &next.pubkey(), &next_wallet, 100);
+        process(&mut context.runtime, &context.payer, &[stake], &[&next])
+            .await?
+            .unwrap();
+
+        let state = load_farm(&mut context, &farm.farm).await?;
+        let registry = context
+            .runtime
+            .get_account(&farm.staker_registry)
+            .await?
+            .unwrap();
+        let registry = state.load_staker_registry(Box::new(registry)).unwrap();
+        assert_eq!(registry.stakers().len(), 1);
+        assert_eq!(registry.find(&staker.pubkey()), None);
+        assert_eq!(registry.find(&next.pubkey()), Some(0));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn update_pool_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let pool = create_pool(&mut context, pool_args()).await?;
+        let update = |args: UpdatePoolArgs| Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pool.pool, false),
+                AccountMeta::new_readonly(context.administrator.pubkey(), true),
+            ],
+            data: Method::Simple(simple_stake::Method::UpdatePool(args)).encode(),
+        };
+
+        let extend_topup = update(UpdatePoolArgs {
+            topup_duration: Some((TOPUP_DURATION + 100).into()),
+            stake_target_amount: None,
+            reward_amount: None,
+        });
+        let extend_topup_again = update(UpdatePoolArgs {
+            topup_duration: Some((TOPUP_DURATION + 200).into()),
+            stake_target_amount: None,
+            reward_amount: None,
+        });
+        let raise_target = update(UpdatePoolArgs {
+            topup_duration: None,
+            stake_target_amount: Some(20_000.into()),
+            reward_amount: None,
+        });
+
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &[extend_topup],
+            &[&context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        let stake_pool = load_pool(&mut context, &pool.pool).await?;
+        assert!(stake_pool.topup_duration() == (TOPUP_DURATION + 100).into());
+
+        // reopening topup in the middle of lockup is not allowed
+        warp_past(
+            &mut context,
+            (stake_pool.genesis() + stake_pool.topup_duration()).value(),
+        )
+        .await?;
+
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[extend_topup_again],
+            &[&context.administrator],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &[raise_target],
+            &[&context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        let stake_pool = load_pool(&mut context, &pool.pool).await?;
+        assert!(stake_pool.topup_duration() == (TOPUP_DURATION + 100).into());
+        assert!(stake_pool.stake_target_amount == 20_000.into());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn emission_accrual_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let mut args = pool_args();
+        args.reward_mode = RewardMode::Emission;
+        let pool = create_pool(&mut context, args).await?;
+        let (first, first_wallet) = create_staker(&mut context, 1_000).await?;
+        let (second, second_wallet) = create_staker(&mut context, 3_000).await?;
+
+        let instrs = [
+            add_reward_instruction(&context, &pool, 1_000),
+            stake_instruction(&program_id, &pool, &first.pubkey(), &first_wallet, 1_000),
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&first, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        let stake_pool = load_pool(&mut context, &pool.pool).await?;
+        let first_staked_at = stake_pool.reward_updated_at.value();
+
+        warp_slots(&mut context, 100).await?;
+
+        let stake = stake_instruction(&program_id, &pool, &second.pubkey(), &second_wallet, 3_000);
+        process(&mut context.runtime, &context.payer, &[stake], &[&second])
+            .await?
+            .unwrap();
+
+        let stake_pool = load_pool(&mut context, &pool.pool).await?;
+        let second_staked_at = stake_pool.reward_updated_at.value();
+        let end = (stake_pool.genesis() + stake_pool.lockup_duration()).value();
+        assert!(second_staked_at > first_staked_at);
+
+        // the first staker earns the whole emission until the second one joins,
+        // then a quarter of it, the rate being a token per second
+        let first_reward = (second_staked_at - first_staked_at) + (end - second_staked_at) / 4;
+        let second_reward = 3 * (end - second_staked_at) / 4;
+
+        warp_past_expiration(&mut context, &pool).await?;
+
+        let claim = claim_instruction(&program_id, &pool, &first.pubkey(), &first_wallet);
+        process(&mut context.runtime, &context.payer, &[claim], &[&first])
+            .await?
+            .unwrap();
+        let claim = claim_instruction(&program_id, &pool, &second.pubkey(), &second_wallet);
+        process(&mut context.runtime, &context.payer, &[claim], &[&second])
+            .await?
+            .unwrap();
+
+        // accumulator is rounded down, which can cost each staker a token at most
+        let first_paid = balance(&mut context, &first_wallet).await? as i64 - 1_000;
+        let second_paid = balance(&mut context, &second_wallet).await? as i64 - 3_000;
+        assert!(first_paid <= first_reward && first_paid + 1 >= first_reward);
+        assert!(second_paid <= second_reward && second_paid + 1 >= second_reward);
+
+        let stake_pool = load_pool(&mut context, &pool.pool).await?;
+        assert!(stake_pool.paid_reward_amount == ((first_paid + second_paid) as u64).into());
+        assert!(stake_pool.paid_reward_amount <= stake_pool.deposited_reward_amount);
+        assert_eq!(
+            balance(&mut context, &pool.stake_vault).await?,
+            1_000 - (first_paid + second_paid) as u64
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn emission_lockup_claim_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let mut args = pool_args();
+        args.reward_mode = RewardMode::Emission;
+        let pool = create_pool(&mut context, args).await?;
+        let (staker, staker_wallet) = create_staker(&mut context, 2_000).await?;
+
+        // only a tenth of the reward is deposited up front
+        let instrs = [
+            add_reward_instruction(&context, &pool, 100),
+            stake_instruction(&program_id, &pool, &staker.pubkey(), &staker_wallet, 2_000),
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&staker, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        let stake_pool = load_pool(&mut context, &pool.pool).await?;
+        let staked_at = stake_pool.reward_updated_at.value();
+        let genesis = stake_pool.genesis().value();
+
+        warp_past_topup(&mut context, &pool).await?;
+
+        // accrued reward is paid during lockup, as far as it was deposited
+        let claim = claim_instruction(&program_id, &pool, &staker.pubkey(), &staker_wallet);
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &[claim.clone()],
+            &[&staker],
+        )
+        .await?
+        .unwrap();
+        assert_eq!(balance(&mut context, &staker_wallet).await?, 100);
+
+        let ticket = load_ticket(&mut context, &pool.pool, &staker.pubkey()).await?;
+        assert!(ticket.staked_amount == 2_000.into());
+        assert!(ticket.has_unclaimed_reward());
+        assert_eq!(balance(&mut context, &pool.stake_vault).await?, 2_000);
+
+        // raising the reward emits the increase over what is left of the lockup
+        let update = Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pool.pool, false),
+                AccountMeta::new_readonly(context.administrator.pubkey(), true),
+            ],
+            data: Method::Simple(simple_stake::Method::UpdatePool(UpdatePoolArgs {
+                topup_duration: None,
+                stake_target_amount: None,
+                reward_amount: Some(2_000.into()),
+            }))
+            .encode(),
+        };
+        let instrs = [update, add_reward_instruction(&context, &pool, 1_900)];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        let stake_pool = load_pool(&mut context, &pool.pool).await?;
+        assert!(stake_pool.reward_rate.value() > U64F64::from_num(1));
+        assert!(stake_pool.deposited_reward_amount == 2_000.into());
+
+        warp_past_expiration(&mut context, &pool).await?;
+
+        process(&mut context.runtime, &context.payer, &[claim], &[&staker])
+            .await?
+            .unwrap();
+
+        // nothing was emitted to the pool before the stake, the rest is rounded toward the pool
+        let paid = balance(&mut context, &staker_wallet).await? as i64 - 2_000;
+        let emitted = 2_000 - (staked_at - genesis);
+        assert!(paid <= emitted && paid + 2 >= emitted);
+
+        let stake_pool = load_pool(&mut context, &pool.pool).await?;
+        assert!(stake_pool.paid_reward_amount == (paid as u64).into());
+        assert!(stake_pool.stake_acquired_amount == 0.into());
+        assert_eq!(
+            balance(&mut context, &pool.stake_vault).await?,
+            2_000 - paid as u64
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn emergency_unstake_reward_pot_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let mut args = pool_args();
+        args.early_exit_penalty_bps = 333;
+        let pool = create_pool(&mut context, args).await?;
+
+        let (leaving, leaving_wallet) = create_staker(&mut context, 5_000).await?;
+        let (staying, staying_wallet) = create_staker(&mut context, 5_000).await?;
+
+        let instrs = [
+            stake_instruction(
+                &program_id,
+                &pool,
+                &leaving.pubkey(),
+                &leaving_wallet,
+                5_000,
+            ),
+            stake_instruction(
+                &program_id,
+                &pool,
+                &staying.pubkey(),
+                &staying_wallet,
+                5_000,
+            ),
+            add_reward_instruction(&context, &pool, 1_000),
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&leaving, &staying, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        // during topup stake is removed with a plain unstake
+        let emergency_unstake = emergency_unstake_instruction(
+            &program_id,
+            &pool,
+            &leaving.pubkey(),
+            &leaving_wallet,
+            5_000,
+        );
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[emergency_unstake.clone()],
+            &[&leaving],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        warp_past_topup(&mut context, &pool).await?;
+
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &[emergency_unstake],
+            &[&leaving],
+        )
+        .await?
+        .unwrap();
+
+        // 5_000 * 3.33% = 166.5 is rounded up in favor of the pool
+        assert_eq!(balance(&mut context, &leaving_wallet).await?, 4_833);
+        assert_eq!(balance(&mut context, &pool.stake_vault).await?, 6_167);
+
+        let stake_pool = load_pool(&mut context, &pool.pool).await?;
+        assert!(stake_pool.stake_acquired_amount == 5_000.into());
+        assert!(stake_pool.deposited_reward_amount == 1_167.into());
+
+        // penalty is shared by the stakers who stayed until expiration
+        warp_past_expiration(&mut context, &pool).await?;
+
+        let emergency_unstake = emergency_unstake_instruction(
+            &program_id,
+            &pool,
+            &staying.pubkey(),
+            &staying_wallet,
+            5_000,
+        );
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[emergency_unstake],
+            &[&staying],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        let claim = claim_instruction(&program_id, &pool, &staying.pubkey(), &staying_wallet);
+        process(&mut context.runtime, &context.payer, &[claim], &[&staying])
+            .await?
+            .unwrap();
+        assert_eq!(balance(&mut context, &staying_wallet).await?, 6_167);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn emergency_unstake_treasury_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let (_, treasury_wallet) = create_staker(&mut context, 0).await?;
+
+        let mut args = pool_args();
+        args.early_exit_penalty_bps = 333;
+        args.early_exit_treasury = Some(treasury_wallet);
+        let pool = create_pool(&mut context, args).await?;
+
+        let (staker, staker_wallet) = create_staker(&mut context, 10_000).await?;
+
+        let instrs = [
+            stake_instruction(&program_id, &pool, &staker.pubkey(), &staker_wallet, 10_000),
+            add_reward_instruction(&context, &pool, 1_000),
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&staker, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        warp_past_topup(&mut context, &pool).await?;
+
+        let mut emergency_unstake = emergency_unstake_instruction(
+            &program_id,
+            &pool,
+            &staker.pubkey(),
+            &staker_wallet,
+            1_000,
+        );
+        emergency_unstake
+            .accounts
+            .push(AccountMeta::new(treasury_wallet, false));
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &[emergency_unstake],
+            &[&staker],
+        )
+        .await?
+        .unwrap();
+
+        // 1_000 * 3.33% = 33.3 is rounded up in favor of the pool
+        assert_eq!(balance(&mut context, &staker_wallet).await?, 966);
+        assert_eq!(balance(&mut context, &treasury_wallet).await?, 34);
+        assert_eq!(balance(&mut context, &pool.stake_vault).await?, 10_000);
+
+        let stake_pool = load_pool(&mut context, &pool.pool).await?;
+        assert!(stake_pool.stake_acquired_amount == 9_000.into());
+        assert!(stake_pool.deposited_reward_amount == 1_000.into());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn prefunded_ticket_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let pool = create_pool(&mut context, pool_args()).await?;
+        let (staker, staker_wallet) = create_staker(&mut context, 1_000).await?;
+        let (rich_staker, rich_staker_wallet) = create_staker(&mut context, 1_000).await?;
+
+        let ticket_size = StakerTicket::default_size() as u64;
+        let ticket = ticket_address(&program_id, &pool.pool, &staker.pubkey());
+        let rich_ticket = ticket_address(&program_id, &pool.pool, &rich_staker.pubkey());
+
+        // anyone can send lamports to the address of a ticket before it is created
+        let instrs = [
+            transfer(&context.payer.pubkey(), &ticket, 1_000),
+            transfer(
+                &context.payer.pubkey(),
+                &rich_ticket,
+                minimum_balance(ticket_size) * 2,
+            ),
+        ];
+        process(&mut context.runtime, &context.payer, &instrs, &[])
+            .await?
+            .unwrap();
+
+        let instrs = [
+            stake_instruction(&program_id, &pool, &staker.pubkey(), &staker_wallet, 1_000),
+            stake_instruction(
+                &program_id,
+                &pool,
+                &rich_staker.pubkey(),
+                &rich_staker_wallet,
+                1_000,
+            ),
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&staker, &rich_staker],
+        )
+        .await?
+        .unwrap();
+
+        let account = context.runtime.get_account(&ticket).await?.unwrap();
+        assert_eq!(account.owner, program_id);
+        assert_eq!(account.data.len() as u64, ticket_size);
+        assert_eq!(account.lamports, minimum_balance(ticket_size));
+
+        let account = context.runtime.get_account(&rich_ticket).await?.unwrap();
+        assert_eq!(account.lamports, minimum_balance(ticket_size) * 2);
+
+        for staker in [&staker, &rich_staker].iter() {
+            let ticket = load_ticket(&mut context, &pool.pool, &staker.pubkey()).await?;
+            assert_eq!(ticket.authority, staker.pubkey());
+            assert!(ticket.staked_amount == 1_000.into());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fee_wallet_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+        let administrator = context.administrator.pubkey();
+
+        let (_, fee_wallet) = create_staker(&mut context, 0).await?;
+        let (_, new_fee_wallet) = create_staker(&mut context, 0).await?;
+
+        let mut args = pool_args();
+        args.fee_bps = 333;
+        args.fee_wallet = Some(fee_wallet);
+        let pool = create_pool(&mut context, args).await?;
+        let pool_without_fee = create_pool(&mut context, pool_args()).await?;
+
+        let (staker, staker_wallet) = create_staker(&mut context, 10_000).await?;
+
+        let instrs = [
+            stake_instruction(&program_id, &pool, &staker.pubkey(), &staker_wallet, 10_000),
+            add_reward_instruction(&context, &pool, 1_000),
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&staker, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        let set_fee_wallet = |pool: &PoolKeys, administrator: &Pubkey| Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new(pool.pool, false),
+                AccountMeta::new_readonly(*administrator, true),
+                AccountMeta::new_readonly(new_fee_wallet, false),
+            ],
+            data: Method::Simple(simple_stake::Method::SetFeeWallet).encode(),
+        };
+
+        let impostor = Keypair::new();
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[set_fee_wallet(&pool, &impostor.pubkey())],
+            &[&impostor],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAuthority));
+
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[set_fee_wallet(&pool_without_fee, &administrator)],
+            &[&context.administrator],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        // wallet can be replaced after expiration, when stakers still have to claim
+        warp_past_expiration(&mut context, &pool).await?;
+
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &[set_fee_wallet(&pool, &administrator)],
+            &[&context.administrator],
+        )
+        .await?
+        .unwrap();
+        assert_eq!(
+            load_pool(&mut context, &pool.pool).await?.fee_wallet,
+            new_fee_wallet
+        );
+
+        let mut claim = claim_instruction(&program_id, &pool, &staker.pubkey(), &staker_wallet);
+        claim.accounts.push(AccountMeta::new(fee_wallet, false));
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[claim.clone()],
+            &[&staker],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAccount));
+
+        claim.accounts.pop();
+        claim.accounts.push(AccountMeta::new(new_fee_wallet, false));
+        process(&mut context.runtime, &context.payer, &[claim], &[&staker])
+            .await?
+            .unwrap();
+
+        // 1_000 * 3.33% = 33.3 is rounded down in favor of the staker
+        assert_eq!(balance(&mut context, &staker_wallet).await?, 10_967);
+        assert_eq!(balance(&mut context, &new_fee_wallet).await?, 33);
+        assert_eq!(balance(&mut context, &fee_wallet).await?, 0);
+
+        let stake_pool = load_pool(&mut context, &pool.pool).await?;
+        assert!(stake_pool.paid_fee_amount == 33.into());
+        assert!(stake_pool.paid_reward_amount == 1_000.into());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn staked_event_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let pool = create_pool(&mut context, pool_args()).await?;
+        let (staker, wallet) = create_staker(&mut context, 1_000).await?;
+
+        let stake = stake_instruction(&program_id, &pool, &staker.pubkey(), &wallet, 1_000);
+        process(&mut context.runtime, &context.payer, &[stake], &[&staker])
+            .await?
+            .unwrap();
+
+        let events = parse_log_messages::<Event>(&program_id, &program_logs());
+        assert!(events.contains(&Event::Staked {
+            pool: pool.pool,
+            staker: staker.pubkey(),
+            amount: 1_000.into(),
+        }));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn migrate_test() -> anyhow::Result<()> {
+        let pool = Pubkey::new_unique();
+        let ticket = Pubkey::new_unique();
+        let staker = Keypair::new();
+        let stake_vault = Keypair::new();
+        let staker_pubkey = staker.pubkey();
+        let stake_vault_pubkey = stake_vault.pubkey();
+
+        // v1 program authority is derived from the administrator as well
+        let v1_program_authority = |program_id: &Pubkey, administrator: &Pubkey| {
+            (0u64..)
+                .find_map(|salt| {
+                    Pubkey::create_program_address(
+                        &[pool.as_ref(), administrator.as_ref(), &salt.to_le_bytes()],
+                        program_id,
+                    )
+                    .map(|program_authority| (program_authority, salt))
+                })
+                .unwrap()
+        };
+
+        let mut context = setup_with_accounts(|program_id, mint, administrator| {
+            let (program_authority, program_authority_salt) =
+                v1_program_authority(program_id, administrator);
+            let mut allocator = EntityAllocator::default();
+            let pool_id = allocator.allocate_id();
+            let ticket_id = allocator.allocate_id();
+
+            let state = StakePoolStateV1 {
+                administrator_authority: *administrator,
+                program_authority,
+                stake_mint: *mint,
+                stake_vault: stake_vault_pubkey,
+                program_authority_salt,
+                stake_target_amount: 10_000.into(),
+                stake_acquired_amount: 1_000.into(),
+                reward_amount: 100.into(),
+                deposited_reward_amount: 100.into(),
+                allocator,
+                // expired long before the runtime starts
+                genesis: 0.into(),
+                lockup_duration: LOCKUP_DURATION.into(),
+                topup_duration: TOPUP_DURATION.into(),
+            };
+            let state = unsafe {
+                std::slice::from_raw_parts(
+                    (&state as *const StakePoolStateV1).cast::<u8>(),
+                    size_of::<StakePoolStateV1>(),
+                )
+            };
+
+            let pool_header = EntityHeader {
+                root: pool,
+                id: pool_id,
+                parent_id: pool_id,
+                kind: EntityKind::SimpleStakePool,
+                version: 0,
+            };
+            let ticket_header = EntityHeader {
+                root: pool,
+                id: ticket_id,
+                parent_id: pool_id,
+                kind: EntityKind::SimpleStakeTicket,
+                version: 0,
+            };
+            let ticket_state = [staker_pubkey.as_ref(), &1_000u64.to_le_bytes()].concat();
+
+            vec![
+                v1_entity_account(program_id, pool, pool_header, state),
+                v1_entity_account(program_id, ticket, ticket_header, &ticket_state),
+            ]
+        })
+        .await?;
+        let program_id = context.program_id;
+        let administrator = context.administrator.pubkey();
+        let (program_authority, _) = v1_program_authority(&program_id, &administrator);
+
+        let wallet = Keypair::new();
+        let mut instrs = create_wallet(
+            &context.payer.pubkey(),
+            &stake_vault_pubkey,
+            &context.mint.pubkey(),
+            &program_authority,
+        )
+        .to_vec();
+        instrs.extend(create_wallet(
+            &context.payer.pubkey(),
+            &wallet.pubkey(),
+            &context.mint.pubkey(),
+            &staker_pubkey,
+        ));
+        instrs.push(mint_to(
+            &context.mint.pubkey(),
+            &stake_vault_pubkey,
+            &administrator,
+            1_100,
+        ));
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&stake_vault, &wallet, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        let v1_lamports = context.runtime.get_account(&pool).await?.unwrap().lamports;
+        let administrator_lamports = context
+            .runtime
+            .get_account(&administrator)
+            .await?
+            .unwrap()
+            .lamports;
+
+        let new_pool = Keypair::new();
+        let (new_program_authority, program_authority_salt) =
+            find_program_authority(&program_id, &new_pool.pubkey());
+        let migrate = Instruction {
+            program_id,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new(pool, false),
+                AccountMeta::new(new_pool.pubkey(), false),
+                AccountMeta::new(administrator, true),
+                AccountMeta::new_readonly(program_authority, false),
+                AccountMeta::new_readonly(new_program_authority, false),
+                AccountMeta::new(stake_vault_pubkey, false),
+            ],
+            data: Method::Simple(simple_stake::Method::Migrate {
+                program_authority_salt,
+            })
+            .encode(),
+        };
+        let instrs = [
+            create_account(
+                &context.payer.pubkey(),
+                &new_pool.pubkey(),
+                minimum_balance(StakePool::default_size() as u64),
+                StakePool::default_size() as u64,
+                &program_id,
+            ),
+            migrate,
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&new_pool, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        // v1 pool is closed, its lamports go to the administrator
+        assert!(context.runtime.get_account(&pool).await?.is_none());
+        let account = context.runtime.get_account(&administrator).await?.unwrap();
+        assert_eq!(account.lamports, administrator_lamports + v1_lamports);
+
+        let stake_pool = load_pool(&mut context, &new_pool.pubkey()).await?;
+        assert_eq!(stake_pool.header().version(), StakePool::VERSION);
+        assert!(stake_pool.root() == &pool);
+        assert!(stake_pool.program_authority == new_program_authority);
+        assert!(stake_pool.stake_vault == stake_vault_pubkey);
+        assert!(stake_pool.stake_acquired_amount == 1_000.into());
+        assert!(stake_pool.available_reward() == 100.into());
+
+        let vault = context
+            .runtime
+            .get_account(&stake_vault_pubkey)
+            .await?
+            .unwrap();
+        let vault = WalletAccount::any(Box::new(vault)).unwrap();
+        assert!(vault.authority() == &new_program_authority);
+
+        // the v1 ticket stays a child of the pool and is paid out by the new program authority
+        let keys = PoolKeys {
+            pool: new_pool.pubkey(),
+            program_authority: new_program_authority,
+            stake_vault: stake_vault_pubkey,
+            share_mint: None,
+        };
+        let mut claim = claim_instruction(&program_id, &keys, &staker_pubkey, &wallet.pubkey());
+        claim.accounts[2].pubkey = ticket;
+        process(&mut context.runtime, &context.payer, &[claim], &[&staker])
+            .await?
+            .unwrap();
+
+        assert_eq!(balance(&mut context, &wallet.pubkey()).await?, 1_100);
+        assert_eq!(balance(&mut context, &stake_vault_pubkey).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn transfer_ticket_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+        let payer = context.payer.pubkey();
+
+        let mut args = pool_args();
+        args.max_stake_per_ticket = 1_500.into();
+        let pool = create_pool(&mut context, args).await?;
+        let (staker, staker_wallet) = create_staker(&mut context, 2_000).await?;
+        let (holder, holder_wallet) = create_staker(&mut context, 500).await?;
+        let (full, full_wallet) = create_staker(&mut context, 1_000).await?;
+        let ticket = ticket_address(&program_id, &pool.pool, &staker.pubkey());
+
+        let instrs = [
+            stake_instruction(&program_id, &pool, &staker.pubkey(), &staker_wallet, 1_000),
+            stake_instruction(&program_id, &pool, &holder.pubkey(), &holder_wallet, 500),
+            stake_instruction(&program_id, &pool, &full.pubkey(), &full_wallet, 1_000),
+            add_reward_instruction(&context, &pool, 1_000),
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&staker, &holder, &full, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        // the new staker has to accept the ticket
+        let mut transfer = transfer_ticket_instruction(
+            &program_id,
+            &pool,
+            &staker.pubkey(),
+            &holder.pubkey(),
+            &payer,
+        );
+        transfer.accounts[4].is_signer = false;
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[transfer],
+            &[&staker],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        // merged ticket would hold more than the pool allows
+        let transfer = transfer_ticket_instruction(
+            &program_id,
+            &pool,
+            &staker.pubkey(),
+            &full.pubkey(),
+            &payer,
+        );
+        let result = process(
+            &mut context.runtime,
+            &context.payer,
+            &[transfer],
+            &[&staker, &full],
+        )
+        .await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        let transfer = transfer_ticket_instruction(
+            &program_id,
+            &pool,
+            &staker.pubkey(),
+            &holder.pubkey(),
+            &payer,
+        );
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &[transfer],
+            &[&staker, &holder],
+        )
+        .await?
+        .unwrap();
+
+        // position moved into the ticket of the new staker and the old ticket is closed
+        let holder_ticket = load_ticket(&mut context, &pool.pool, &holder.pubkey()).await?;
+        assert!(holder_ticket.authority == holder.pubkey());
+        assert!(holder_ticket.staked_amount == 1_500.into());
+        assert!(context.runtime.get_account(&ticket).await?.is_none());
+
+        // the previous staker can stake again into a new ticket
+        let stake = stake_instruction(&program_id, &pool, &staker.pubkey(), &staker_wallet, 1_000);
+        process(&mut context.runtime, &context.payer, &[stake], &[&staker])
+            .await?
+            .unwrap();
+
+        warp_past_expiration(&mut context, &pool).await?;
+
+        // 1_500 out of 3_500 staked, the reward share is rounded down
+        let claim = claim_instruction(&program_id, &pool, &holder.pubkey(), &holder_wallet);
+        process(&mut context.runtime, &context.payer, &[claim], &[&holder])
+            .await?
+            .unwrap();
+        assert_eq!(balance(&mut context, &holder_wallet).await?, 1_928);
+
+        let claim = claim_instruction(&program_id, &pool, &staker.pubkey(), &staker_wallet);
+        process(&mut context.runtime, &context.payer, &[claim], &[&staker])
+            .await?
+            .unwrap();
+        assert_eq!(balance(&mut context, &staker_wallet).await?, 1_286);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delegate_claim_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let pool = create_pool(&mut context, pool_args()).await?;
+        let (staker, wallet) = create_staker(&mut context, 1_000).await?;
+        let (delegate, delegate_wallet) = create_staker(&mut context, 0).await?;
+        let impostor = Keypair::new();
+
+        let instrs = [
+            stake_instruction(&program_id, &pool, &staker.pubkey(), &wallet, 1_000),
+            set_delegate_instruction(
+                &program_id,
+                &pool,
+                &staker.pubkey(),
+                Some(delegate.pubkey()),
+            ),
+            add_reward_instruction(&context, &pool, 1_000),
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&staker, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        let ticket = load_ticket(&mut context, &pool.pool, &staker.pubkey()).await?;
+        assert!(ticket.delegate == delegate.pubkey());
+
+        warp_past_expiration(&mut context, &pool).await?;
+
+        let claim = delegate_claim_instruction(
+            &program_id,
+            &pool,
+            &staker.pubkey(),
+            &wallet,
+            &impostor.pubkey(),
+        );
+        let result = process(&mut context.runtime, &context.payer, &[claim], &[&impostor]).await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAuthority));
+
+        // the delegate can only claim into wallets of the staker
+        let claim = delegate_claim_instruction(
+            &program_id,
+            &pool,
+            &staker.pubkey(),
+            &delegate_wallet,
+            &delegate.pubkey(),
+        );
+        let result = process(&mut context.runtime, &context.payer, &[claim], &[&delegate]).await?;
+        assert_eq!(result, custom_error(0, Error::Validation));
+
+        let claim = delegate_claim_instruction(
+            &program_id,
+            &pool,
+            &staker.pubkey(),
+            &wallet,
+            &delegate.pubkey(),
+        );
+        process(&mut context.runtime, &context.payer, &[claim], &[&delegate])
+            .await?
+            .unwrap();
+
+        assert_eq!(balance(&mut context, &wallet).await?, 2_000);
+        assert_eq!(balance(&mut context, &delegate_wallet).await?, 0);
+
+        // ticket is collected and its rent goes to the staker rather than the delegate
+        let ticket = ticket_address(&program_id, &pool.pool, &staker.pubkey());
+        assert!(context.runtime.get_account(&ticket).await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn liquid_pool_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+
+        let mut args = pool_args();
+        args.liquid = true;
+        let pool = create_pool(&mut context, args).await?;
+        let share_mint = pool.share_mint.unwrap();
+
+        let (first, first_wallet) = create_staker(&mut context, 6_000).await?;
+        let (second, second_wallet) = create_staker(&mut context, 2_500).await?;
+        let first_shares = Keypair::new();
+        let second_shares = Keypair::new();
+
+        let mut instrs = create_wallet(
+            &context.payer.pubkey(),
+            &first_shares.pubkey(),
+            &share_mint,
+            &first.pubkey(),
+        )
+        .to_vec();
+        instrs.extend(create_wallet(
+            &context.payer.pubkey(),
+            &second_shares.pubkey(),
+            &share_mint,
+            &second.pubkey(),
+        ));
+        instrs.push(liquid_stake_instruction(
+            &program_id,
+            &pool,
+            &first.pubkey(),
+            &first_wallet,
+            &first_shares.pubkey(),
+            6_000,
+        ));
+        instrs.push(liquid_stake_instruction(
+            &program_id,
+            &pool,
+            &second.pubkey(),
+            &second_wallet,
+            &second_shares.pubkey(),
+            2_500,
+        ));
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&first_shares, &second_shares, &first, &second],
+        )
+        .await?
+        .unwrap();
+
+        // every staked token is backed by a share
+        assert_eq!(supply(&mut context, &share_mint).await?, 8_500);
+        assert_eq!(balance(&mut context, &first_shares.pubkey()).await?, 6_000);
+        assert_eq!(balance(&mut context, &second_shares.pubkey()).await?, 2_500);
+        assert_eq!(balance(&mut context, &pool.stake_vault).await?, 8_500);
+
+        // removing stake during topup burns the shares
+        let unstake = with_shares(
+            unstake_instruction(&program_id, &pool, &second.pubkey(), &second_wallet, 500),
+            &pool,
+            &second_shares.pubkey(),
+        );
+        process(&mut context.runtime, &context.payer, &[unstake], &[&second])
+            .await?
+            .unwrap();
+
+        assert_eq!(supply(&mut context, &share_mint).await?, 8_000);
+        assert_eq!(balance(&mut context, &second_shares.pubkey()).await?, 2_000);
+        assert_eq!(balance(&mut context, &second_wallet).await?, 500);
+
+        let add_reward = add_reward_instruction(&context, &pool, 1_000);
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &[add_reward],
+            &[&context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        warp_past_expiration(&mut context, &pool).await?;
+
+        // claims burn all shares of the holder and pay out principal with a pro-rata reward
+        let claim = with_shares(
+            claim_instruction(&program_id, &pool, &first.pubkey(), &first_wallet),
+            &pool,
+            &first_shares.pubkey(),
+        );
+        process(&mut context.runtime, &context.payer, &[claim], &[&first])
+            .await?
+            .unwrap();
+
+        assert_eq!(supply(&mut context, &share_mint).await?, 2_000);
+        assert_eq!(balance(&mut context, &first_shares.pubkey()).await?, 0);
+        assert_eq!(balance(&mut context, &first_wallet).await?, 6_750);
+
+        let claim = with_shares(
+            claim_instruction(&program_id, &pool, &second.pubkey(), &second_wallet),
+            &pool,
+            &second_shares.pubkey(),
+        );
+        process(&mut context.runtime, &context.payer, &[claim], &[&second])
+            .await?
+            .unwrap();
+
+        assert_eq!(supply(&mut context, &share_mint).await?, 0);
+        assert_eq!(balance(&mut context, &second_wallet).await?, 2_750);
+        assert_eq!(balance(&mut context, &pool.stake_vault).await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rollover_ticket_test() -> anyhow::Result<()> {
+        let mut context = setup().await?;
+        let program_id = context.program_id;
+        let payer = context.payer.pubkey();
+
+        let pool = create_pool(&mut context, pool_args()).await?;
+        let (staker, wallet) = create_staker(&mut context, 1_000).await?;
+        let (attacker, attacker_wallet) = create_staker(&mut context, 1_000).await?;
+
+        let instrs = [
+            stake_instruction(&program_id, &pool, &staker.pubkey(), &wallet, 1_000),
+            stake_instruction(
+                &program_id,
+                &pool,
+                &attacker.pubkey(),
+                &attacker_wallet,
+                1_000,
+            ),
+            set_rollover_instruction(&program_id, &pool, &staker.pubkey()),
+            set_rollover_instruction(&program_id, &pool, &attacker.pubkey()),
+            add_reward_instruction(&context, &pool, 1_000),
+        ];
+        process(
+            &mut context.runtime,
+            &context.payer,
+            &instrs,
+            &[&staker, &attacker, &context.administrator],
+        )
+        .await?
+        .unwrap();
+
+        warp_past_expiration(&mut context, &pool).await?;
+        let successor = create_successor(&mut context, &pool).await?;
+
+        let staker_successor_ticket =
+            ticket_address(&program_id, &successor.pool, &staker.pubkey());
+        let attacker_successor_ticket =
+            ticket_address(&program_id, &successor.pool, &attacker.pubkey());
+
+        let rollover = rollover_ticket_instruction(
+            &program_id,
+            &pool,
+            &successor,
+            &attacker.pubkey(),
+            &attacker_successor_ticket,
+            &payer,
+        );
+        process(&mut context.runtime, &context.payer, &[rollover], &[])
+            .await?
+            .unwrap();
+
+        // stake of the staker can't be moved into a successor ticket of someone else
+        let rollover = rollover_ticket_instruction(
+            &program_id,
+            &pool,
+            &successor,
+            &staker.pubkey(),
+            &attacker_successor_ticket,
+            &payer,
+        );
+        let result = process(&mut context.runtime, &context.payer, &[rollover], &[]).await?;
+        assert_eq!(result, custom_error(0, Error::InvalidAuthority));
+
+        let rollover = rollover_ticket_instruction(
+            &program_id,
+            &pool,
+            &successor,
+            &staker.pubkey(),
+            &staker_successor_ticket,
+            &payer,
+        );
+        process(&mut context.runtime, &context.payer, &[rollover], &[])
+            .await?
+            .unwrap();
+
+        let ticket = load_ticket(&mut context, &successor.pool, &staker.pubkey()).await?;
+        assert!(ticket.authority == staker.pubkey());
+        assert!(ticket.staked_amount == 1_500.into());
+
+        let ticket = load_ticket(&mut context, &successor.pool, &attacker.pubkey()).await?;
+        assert!(ticket.authority == attacker.pubkey());
+        assert!(ticket.staked_amount == 1_500.into());
+
+        assert_eq!(balance(&mut context, &pool.stake_vault).await?, 0);
+        assert_eq!(balance(&mut context, &successor.stake_vault).await?, 3_000);
+
+        Ok(())
+    }
 }
@@ -2,30 +2,35 @@ use std::mem::size_of;
 
 #[cfg(feature = "onchain")]
 use az::CheckedAs;
+use fixed::types::U64F64;
 use solana_api_types::Pubkey;
 #[cfg(feature = "onchain")]
 use solar::{
-    account::onchain::Account,
+    account::onchain::{Account, AsAccount},
     input::AccountSource,
+    log::emit,
     math::ToF64,
     qlog,
-    util::{is_zeroed, timestamp_now, ResultExt},
+    util::{minimum_balance, timestamp_now, ResultExt},
 };
 use solar::{
     account::{AccountFields, AccountFieldsMut},
     math::Checked,
     prelude::AccountBackend,
     reinterpret::as_bytes,
-    spl::{MintAccount, TokenProgram, WalletAccount},
+    spl::{AuthorityType, MintAccount, TokenProgram, WalletAccount},
+    system::SystemProgram,
     util::pubkey_eq,
 };
 #[cfg(feature = "onchain")]
 use solar_macros::parse_accounts;
 
+#[cfg(feature = "onchain")]
+use crate::{allowlist, event::Event};
 use crate::{
     data::{AccountType, Entity, EntityAllocator, EntityKind, HEADER_RESERVED},
     error::Error,
-    impl_entity_simple_deref, TokenAmount,
+    impl_entity_simple_deref, TokenAmount, TokenAmountF64,
 };
 
 pub type StakePoolEntity<B> = Entity<B, StakePool>;
@@ -34,12 +39,65 @@ pub type StakerTicketEntity<B> = Entity<B, StakerTicket>;
 #[derive(Debug, PartialEq, Eq, Clone, parity_scale_codec::Encode, parity_scale_codec::Decode)]
 pub enum Method {
     CreatePool(InitializeArgs),
-    Stake { amount: TokenAmount },
-    Unstake { amount: TokenAmount },
+    CreatePoolWithReward(InitializeArgs),
+    Stake {
+        amount: TokenAmount,
+        allowlist_proof: Option<AllowlistProof>,
+    },
+    Unstake {
+        amount: TokenAmount,
+    },
     ClaimReward,
-    AddReward { amount: TokenAmount },
+    AddReward {
+        amount: TokenAmount,
+    },
+    ClosePool,
+    UpdatePool(UpdatePoolArgs),
+    ProposeAdmin {
+        administrator: Pubkey,
+    },
+    AcceptAdmin,
+    EmergencyUnstake {
+        amount: TokenAmount,
+    },
+    Migrate {
+        program_authority_salt: u64,
+    },
+    /// New staker has to be allowed to hold the ticket stake, so the proof is required
+    /// for pools with an allowlist.
+    TransferTicket {
+        allowlist_proof: Option<AllowlistProof>,
+    },
+    SetTicketDelegate {
+        delegate: Option<Pubkey>,
+    },
+    /// Opens the successor of an expired pool with the same parameters.
+    RolloverPool {
+        program_authority_salt: u64,
+    },
+    SetTicketRollover {
+        rollover: bool,
+    },
+    /// Successor pool has the same allowlist, so the proof is required
+    /// for pools with an allowlist.
+    RolloverTicket {
+        allowlist_proof: Option<AllowlistProof>,
+    },
+    /// Replaces the wallet receiving the protocol fee, so that claims keep working
+    /// if the previous one is closed or frozen.
+    SetFeeWallet,
 }
 
+/// Time after pool expiration once administrator is allowed to close the pool
+/// even if some stakers did not claim their stake.
+pub const CLOSE_GRACE_PERIOD: i64 = 60 * 60 * 24 * 90;
+
+/// Basis points in a whole, used for the early exit penalty.
+pub const MAX_BPS: u16 = 10_000;
+
+/// Largest protocol fee a pool can be created with, in basis points of the reward.
+pub const MAX_FEE_BPS: u16 = 1_000;
+
 #[derive(Debug)]
 pub struct StakePool;
 #[derive(Debug)]
@@ -48,31 +106,96 @@ pub struct StakerTicket;
 #[repr(C)]
 pub struct StakePoolState {
     pub administrator_authority: Pubkey,
+    /// Administrator proposed by the current one, zeroed if there is no pending rotation.
+    pub pending_administrator_authority: Pubkey,
     pub program_authority: Pubkey,
     pub stake_mint: Pubkey,
     pub stake_vault: Pubkey,
+    /// Same as stake mint and vault unless the pool pays rewards in a separate token.
+    pub reward_mint: Pubkey,
+    pub reward_vault: Pubkey,
+    /// Wallet receiving early exit penalties, zeroed if they are added to the reward instead.
+    pub early_exit_treasury: Pubkey,
+    /// Root of the allowlist Merkle tree, zeroed if anyone can stake.
+    pub allowlist_root: [u8; 32],
+    /// Wallet in reward mint receiving the protocol fee, zeroed if there is no fee.
+    pub fee_wallet: Pubkey,
+    /// Mint of pool share tokens held by stakers instead of tickets, zeroed unless
+    /// the pool is liquid.
+    pub share_mint: Pubkey,
+    /// Pool opened with the same parameters once this one expired, zeroed if there is none.
+    pub successor: Pubkey,
     pub program_authority_salt: u64,
 
     pub stake_target_amount: TokenAmount,
     pub stake_acquired_amount: TokenAmount,
     pub reward_amount: TokenAmount,
     pub deposited_reward_amount: TokenAmount,
+    pub paid_reward_amount: TokenAmount,
+    /// Part of `paid_reward_amount` sent to the fee wallet.
+    pub paid_fee_amount: TokenAmount,
+
+    /// Smallest stake a ticket may hold.
+    pub min_stake: TokenAmount,
+    /// Largest stake a ticket may hold, zero if unlimited.
+    pub max_stake_per_ticket: TokenAmount,
 
     pub allocator: EntityAllocator,
 
     pub genesis: Checked<i64>,
     pub lockup_duration: Checked<i64>,
     pub topup_duration: Checked<i64>,
+
+    /// Reward emitted per second, zero for pools paying the reward at expiration.
+    pub reward_rate: TokenAmountF64,
+    /// Reward accrued by a single staked token since genesis.
+    pub reward_per_share: TokenAmountF64,
+    pub reward_updated_at: Checked<i64>,
+
+    /// Part of the stake withheld when it is removed during lockup.
+    pub early_exit_penalty_bps: u16,
+    /// Part of every reward share withheld as the protocol fee.
+    pub fee_bps: u16,
 }
 
 #[repr(C)]
 pub struct StakerTicketState {
     pub authority: Pubkey,
     pub staked_amount: TokenAmount,
+    /// Part of `staked_amount * reward_per_share` that was already accounted for.
+    pub reward_debt: TokenAmountF64,
+    pub unclaimed_reward: TokenAmountF64,
+    /// Key allowed to claim the reward into wallets of the authority, zeroed if there is none.
+    pub delegate: Pubkey,
+    /// Non-zero if the stake is moved into the successor pool at expiration.
+    pub rollover: u8,
+}
+
+/// Layout of pools created before entity layouts were versioned, see [`Entity::migrate`].
+#[repr(C)]
+pub struct StakePoolStateV1 {
+    pub administrator_authority: Pubkey,
+    pub program_authority: Pubkey,
+    pub stake_mint: Pubkey,
+    pub stake_vault: Pubkey,
+    /// Program authority is derived from pool, administrator and this salt.
+    pub program_authority_salt: u64,
+
+    pub stake_target_amount: TokenAmount,
+    pub stake_acquired_amount: TokenAmount,
+    pub reward_amount: TokenAmount,
+    pub deposited_reward_amount: TokenAmount,
+
+    pub allocator: EntityAllocator,
+
+    pub genesis: Checked<i64>,
+    pub lockup_duration: Checked<i64>,
+    pub topup_duration: Checked<i64>,
 }
 
 impl AccountType for StakePool {
     const KIND: EntityKind = EntityKind::SimpleStakePool;
+    const VERSION: u8 = 2;
 
     fn is_valid_size(size: usize) -> bool {
         size == size_of::<StakePoolState>()
@@ -85,13 +208,27 @@ impl AccountType for StakePool {
 
 impl AccountType for StakerTicket {
     const KIND: EntityKind = EntityKind::SimpleStakeTicket;
+    const VERSION: u8 = 2;
 
     fn is_valid_size(size: usize) -> bool {
-        size == size_of::<StakePoolState>()
+        size == size_of::<StakerTicketState>()
     }
 
     fn default_size() -> usize {
-        size_of::<StakePoolState>() + HEADER_RESERVED
+        size_of::<StakerTicketState>() + HEADER_RESERVED
+    }
+
+    /// v1 tickets were sized after the v1 pool, so fields added since then
+    /// fit in and are still zeroed.
+    fn is_compatible(version: u8, size: usize) -> bool {
+        version == 1 && size == size_of::<StakePoolStateV1>()
+    }
+}
+
+impl StakerTicket {
+    /// Seeds of the ticket address, bump seed is appended when deriving it.
+    pub fn seeds<'a>(pool: &'a Pubkey, staker: &'a Pubkey) -> [&'a [u8]; 2] {
+        [pool.as_ref(), staker.as_ref()]
     }
 }
 
@@ -105,12 +242,24 @@ pub struct InitializeArgsAccounts<B: AccountBackend> {
     pub pool: B,
     pub stake_mint: MintAccount<B>,
     pub stake_vault: WalletAccount<B>,
+    pub reward: Option<RewardMintAccounts<B>>,
+    pub share_mint: Option<MintAccount<B>>,
+}
+
+#[derive(Debug)]
+pub struct RewardMintAccounts<B: AccountBackend> {
+    pub reward_mint: MintAccount<B>,
+    pub reward_vault: WalletAccount<B>,
 }
 
 impl<B: AccountBackend> InitializeArgsAccounts<B> {
     #[cfg(feature = "onchain")]
     #[inline]
-    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+    pub fn from_program_input<T: AccountSource<B>>(
+        input: &mut T,
+        with_reward: bool,
+        liquid: bool,
+    ) -> Result<Self, Error> {
         parse_accounts! {
             &administrator_authority,
             &program_authority,
@@ -119,12 +268,35 @@ impl<B: AccountBackend> InitializeArgsAccounts<B> {
             &stake_vault = stake_mint.wallet(this)?
         }
 
+        let reward = if with_reward {
+            parse_accounts! {
+                &reward_mint = MintAccount::any(this)?,
+                &reward_vault = reward_mint.wallet(this)?
+            }
+
+            Some(RewardMintAccounts {
+                reward_mint,
+                reward_vault,
+            })
+        } else {
+            None
+        };
+
+        let share_mint = if liquid {
+            parse_accounts!(&share_mint = MintAccount::any(this)?);
+            Some(share_mint)
+        } else {
+            None
+        };
+
         Ok(Self {
             administrator_authority,
             program_authority,
             pool,
             stake_mint,
             stake_vault,
+            reward,
+            share_mint,
         })
     }
 }
@@ -136,15 +308,66 @@ pub struct InitializeArgs {
     pub topup_duration: Checked<i64>,
     pub target_amount: TokenAmount,
     pub reward_amount: TokenAmount,
+    pub reward_mode: RewardMode,
+    pub early_exit_penalty_bps: u16,
+    /// Wallet in stake mint receiving early exit penalties, if they should not be added
+    /// to the reward of the remaining stakers.
+    pub early_exit_treasury: Option<Pubkey>,
+    pub min_stake: TokenAmount,
+    /// Largest stake a single ticket may hold, zero if unlimited.
+    pub max_stake_per_ticket: TokenAmount,
+    pub allowlist_root: Option<[u8; 32]>,
+    pub fee_bps: u16,
+    /// Wallet in reward mint receiving the protocol fee, required if `fee_bps` is not zero.
+    pub fee_wallet: Option<Pubkey>,
+    /// Whether stakers receive pool share tokens instead of tickets, the share mint is passed
+    /// after the other accounts.
+    pub liquid: bool,
+}
+
+/// Proof that the staker is included in the pool allowlist with `max_allocation`,
+/// see [`crate::allowlist`]. Like `max_stake_per_ticket`, the allocation limits
+/// the ticket of the staker.
+#[derive(Debug, Clone, PartialEq, Eq, parity_scale_codec::Encode, parity_scale_codec::Decode)]
+pub struct AllowlistProof {
+    pub max_allocation: TokenAmount,
+    pub proof: Vec<[u8; 32]>,
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, parity_scale_codec::Encode, parity_scale_codec::Decode,
+)]
+pub enum RewardMode {
+    /// Whole reward is split between stakers once the pool expires.
+    LumpSum,
+    /// Reward is emitted evenly over the lockup duration and can be claimed at any time.
+    Emission,
+}
+
+/// Stake held by the staker, which is a ticket unless the pool is liquid.
+#[derive(Debug)]
+pub enum StakePosition<B: AccountBackend> {
+    Ticket(Entity<B, StakerTicket>),
+    Shares(ShareAccounts<B>),
+}
+
+/// Pool share tokens of the staker, for liquid pools.
+#[derive(Debug)]
+pub struct ShareAccounts<B: AccountBackend> {
+    pub share_mint: MintAccount<B>,
+    pub share_wallet: WalletAccount<B>,
 }
 
 #[derive(Debug)]
 pub struct StakeArgsAccounts<B: AccountBackend> {
     pub token_program: TokenProgram<B>,
+    pub system_program: SystemProgram<B>,
 
     pub pool: Entity<B, StakePool>,
     pub staker: B,
-    pub ticket: Entity<B, StakerTicket>,
+    pub position: StakePosition<B>,
+    /// Only passed for liquid pools, to mint the shares.
+    pub program_authority: Option<B>,
     pub stake_vault: WalletAccount<B>,
     pub source_authority: B,
     pub source_wallet: WalletAccount<B>,
@@ -155,7 +378,7 @@ pub struct UnStakeArgsAccounts<B: AccountBackend> {
     pub token_program: TokenProgram<B>,
 
     pub pool: Entity<B, StakePool>,
-    pub ticket: Entity<B, StakerTicket>,
+    pub position: StakePosition<B>,
     pub staker: B,
     pub program_authority: B,
     pub stake_vault: WalletAccount<B>,
@@ -163,39 +386,204 @@ pub struct UnStakeArgsAccounts<B: AccountBackend> {
 }
 
 #[derive(Debug)]
-pub struct AddRewardArgsAccounts<B: AccountBackend> {
+pub struct ClaimRewardArgsAccounts<B: AccountBackend> {
     pub token_program: TokenProgram<B>,
+
     pub pool: Entity<B, StakePool>,
+    pub position: StakePosition<B>,
+    pub staker: B,
+    pub program_authority: B,
     pub stake_vault: WalletAccount<B>,
+    pub target_wallet: WalletAccount<B>,
+    pub reward: Option<RewardVaultAccounts<B>>,
+    /// Only passed for pools charging the protocol fee.
+    pub fee_wallet: Option<WalletAccount<B>>,
+    /// Only passed if the claim is signed by the ticket delegate instead of the staker.
+    pub delegate: Option<B>,
+}
+
+/// Reward vault along with the wallet receiving tokens from it, only passed for pools
+/// paying rewards in a separate token.
+#[derive(Debug)]
+pub struct RewardVaultAccounts<B: AccountBackend> {
+    pub reward_vault: WalletAccount<B>,
+    pub target_wallet: WalletAccount<B>,
+}
+
+#[derive(Debug)]
+pub struct AddRewardArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+    pub pool: Entity<B, StakePool>,
+    pub reward_vault: WalletAccount<B>,
     pub source_authority: B,
     pub source_wallet: WalletAccount<B>,
 }
 
+/// Parameters that can be changed by the administrator before the pool expires.
+///
+/// Each of them can only be raised, so that stakers are never worse off than they
+/// were when entering the pool.
+#[derive(Debug, Clone, PartialEq, Eq, parity_scale_codec::Encode, parity_scale_codec::Decode)]
+pub struct UpdatePoolArgs {
+    /// Can only be extended while topup is still open, reopening it would let newcomers
+    /// dilute the reward of stakers that are already locked in.
+    pub topup_duration: Option<Checked<i64>>,
+    pub stake_target_amount: Option<TokenAmount>,
+    pub reward_amount: Option<TokenAmount>,
+}
+
+#[derive(Debug)]
+pub struct EmergencyUnstakeArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+
+    pub pool: Entity<B, StakePool>,
+    pub ticket: Entity<B, StakerTicket>,
+    pub staker: B,
+    pub program_authority: B,
+    pub stake_vault: WalletAccount<B>,
+    pub target_wallet: WalletAccount<B>,
+    /// Only passed for pools sending the penalty to a treasury.
+    pub treasury_wallet: Option<WalletAccount<B>>,
+}
+
+#[derive(Debug)]
+pub struct AdministratorArgsAccounts<B: AccountBackend> {
+    pub pool: Entity<B, StakePool>,
+    pub administrator_authority: B,
+}
+
+#[derive(Debug)]
+pub struct SetFeeWalletArgsAccounts<B: AccountBackend> {
+    pub pool: Entity<B, StakePool>,
+    pub administrator_authority: B,
+    pub fee_wallet: WalletAccount<B>,
+}
+
+#[derive(Debug)]
+pub struct TicketArgsAccounts<B: AccountBackend> {
+    pub pool: Entity<B, StakePool>,
+    pub ticket: Entity<B, StakerTicket>,
+    pub staker: B,
+}
+
+#[derive(Debug)]
+pub struct TransferTicketArgsAccounts<B: AccountBackend> {
+    pub system_program: SystemProgram<B>,
+    pub pool: Entity<B, StakePool>,
+    pub ticket: Entity<B, StakerTicket>,
+    pub staker: B,
+    pub new_staker: B,
+    pub new_ticket: B,
+    pub payer: B,
+}
+
+#[derive(Debug)]
+pub struct RolloverPoolArgsAccounts<B: AccountBackend> {
+    pub pool: Entity<B, StakePool>,
+    pub administrator_authority: B,
+    pub successor: B,
+    pub program_authority: B,
+    pub stake_vault: WalletAccount<B>,
+}
+
+#[derive(Debug)]
+pub struct RolloverTicketArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+    pub system_program: SystemProgram<B>,
+
+    pub pool: Entity<B, StakePool>,
+    pub ticket: Entity<B, StakerTicket>,
+    pub staker: B,
+    pub program_authority: B,
+    pub stake_vault: WalletAccount<B>,
+    pub successor: Entity<B, StakePool>,
+    pub successor_ticket: B,
+    pub successor_stake_vault: WalletAccount<B>,
+    pub payer: B,
+    pub fee_wallet: Option<WalletAccount<B>>,
+}
+
+#[derive(Debug)]
+pub struct MigrateArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+    pub pool: Entity<B, StakePool>,
+    pub new_pool: B,
+    pub administrator_authority: B,
+    pub program_authority: B,
+    pub new_program_authority: B,
+    pub stake_vault: WalletAccount<B>,
+}
+
+#[derive(Debug)]
+pub struct ClosePoolArgsAccounts<B: AccountBackend> {
+    pub token_program: TokenProgram<B>,
+    pub pool: Entity<B, StakePool>,
+    pub administrator_authority: B,
+    pub program_authority: B,
+    pub stake_vault: WalletAccount<B>,
+    pub target_wallet: WalletAccount<B>,
+    pub reward: Option<RewardVaultAccounts<B>>,
+}
+
 impl<B: AccountBackend> StakeArgsAccounts<B> {
     #[cfg(feature = "onchain")]
     #[inline]
     pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error>
     where
-        B::Impl: AccountFieldsMut,
+        B: AccountBackend<Impl = Account>,
     {
         let program_id = *input.program_id();
 
         parse_accounts!(
             &token_program = TokenProgram::load(this)?,
+            &system_program = SystemProgram::load(this).ok_or(Error::InvalidAccount)?,
             &mut pool = <Entity<B, StakePool>>::load(&program_id, this)?,
             &staker,
-            &mut ticket = pool.load_or_init_ticket(&staker, this)?,
+        );
+
+        // liquid pools take share accounts and program authority in place of the ticket
+        if pool.is_liquid() {
+            let shares = pool.read_share_accounts(input)?;
+
+            parse_accounts!(
+                &program_authority,
+                &mut stake_vault = pool.stake_vault(this)?,
+                &mut source_authority,
+                &mut source_wallet = pool.stake_wallet(this)?
+            );
+
+            return Ok(Self {
+                token_program,
+                system_program,
+
+                pool,
+                position: StakePosition::Shares(shares),
+                staker,
+                program_authority: Some(program_authority),
+                stake_vault,
+                source_authority,
+                source_wallet,
+            });
+        }
+
+        parse_accounts!(
+            &mut ticket,
             &mut stake_vault = pool.stake_vault(this)?,
-            &source_authority,
+            &mut source_authority,
             &mut source_wallet = pool.stake_wallet(this)?
         );
 
+        let ticket =
+            pool.load_or_init_ticket(&system_program, &staker, &mut source_authority, ticket)?;
+
         Ok(Self {
             token_program,
+            system_program,
 
             pool,
-            ticket,
+            position: StakePosition::Ticket(ticket),
             staker,
+            program_authority: None,
             stake_vault,
             source_authority,
             source_wallet,
@@ -212,7 +600,11 @@ impl<B: AccountBackend> UnStakeArgsAccounts<B> {
         parse_accounts!(
             &token_program = TokenProgram::load(this)?,
             &mut pool = <Entity<B, StakePool>>::load(&program_id, this)?,
-            &mut ticket = pool.load_ticket(this)?,
+        );
+
+        let position = pool.read_position(input)?;
+
+        parse_accounts!(
             &mut staker,
             &program_authority,
             &mut stake_vault = pool.stake_vault(this)?,
@@ -222,11 +614,63 @@ impl<B: AccountBackend> UnStakeArgsAccounts<B> {
         Ok(Self {
             token_program,
             pool,
-            ticket,
+            position,
+            staker,
+            program_authority,
+            stake_vault,
+            target_wallet,
+        })
+    }
+}
+
+impl<B: AccountBackend> ClaimRewardArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline(always)]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts!(
+            &token_program = TokenProgram::load(this)?,
+            &mut pool = <Entity<B, StakePool>>::load(&program_id, this)?,
+        );
+
+        let position = pool.read_position(input)?;
+
+        parse_accounts!(
+            &mut staker,
+            &program_authority,
+            &mut stake_vault = pool.stake_vault(this)?,
+            &mut target_wallet = pool.stake_wallet(this)?
+        );
+
+        let reward = pool.read_reward_vault_accounts(input)?;
+
+        let fee_wallet = if pool.has_fee() {
+            parse_accounts!(&mut fee_wallet = pool.fee_wallet(this)?);
+            Some(fee_wallet)
+        } else {
+            None
+        };
+
+        // share holders always sign, as the shares are burned from their wallet
+        let delegate = if !staker.is_signer() && !pool.is_liquid() {
+            parse_accounts!(&delegate);
+            Some(delegate)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            token_program,
+            pool,
+            position,
             staker,
             program_authority,
             stake_vault,
             target_wallet,
+            reward,
+            fee_wallet,
+            delegate,
         })
     }
 }
@@ -240,58 +684,310 @@ impl<B: AccountBackend> AddRewardArgsAccounts<B> {
         parse_accounts!(
             &token_program = TokenProgram::load(this)?,
             &mut pool = <Entity<B, StakePool>>::load(&program_id, this)?,
-            &mut stake_vault = pool.stake_vault(this)?,
+            &mut reward_vault = pool.reward_vault(this)?,
             &source_authority,
-            &mut source_wallet = pool.stake_wallet(this)?,
+            &mut source_wallet = pool.reward_wallet(this)?,
         );
 
         Ok(Self {
             token_program,
             pool,
-            stake_vault,
+            reward_vault,
             source_authority,
             source_wallet,
         })
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct StakeArgs {
-    pub amount: TokenAmount,
-}
-
-impl<B> Entity<B, StakePool>
-where
-    B: AccountBackend,
-{
+impl<B: AccountBackend> EmergencyUnstakeArgsAccounts<B> {
     #[cfg(feature = "onchain")]
-    #[inline(never)]
-    pub fn initialize<T>(input: &mut T, args: InitializeArgs) -> Result<(), Error>
-    where
-        B::Impl: AccountFieldsMut,
-        T: AccountSource<B>,
-    {
-        let InitializeArgsAccounts {
-            administrator_authority,
-            program_authority,
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts!(
+            &token_program = TokenProgram::load(this)?,
+            &mut pool = <Entity<B, StakePool>>::load(&program_id, this)?,
+            &mut ticket = pool.load_ticket(this)?,
+            &mut staker,
+            &program_authority,
+            &mut stake_vault = pool.stake_vault(this)?,
+            &mut target_wallet = pool.stake_wallet(this)?
+        );
+
+        let treasury_wallet = if pool.has_early_exit_treasury() {
+            parse_accounts!(&mut treasury_wallet = pool.early_exit_treasury(this)?);
+            Some(treasury_wallet)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            token_program,
             pool,
-            stake_mint,
+            ticket,
+            staker,
+            program_authority,
             stake_vault,
-        } = InitializeArgsAccounts::from_program_input(input)?;
+            target_wallet,
+            treasury_wallet,
+        })
+    }
+}
 
-        let mut entity = Self::raw_any(input.program_id(), pool)?;
+impl<B: AccountBackend> AdministratorArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
 
-        let expected_program_authority = Pubkey::create_program_address(
-            &[
-                entity.account().key().as_ref(),
-                administrator_authority.key().as_ref(),
-                &args.program_authority_salt.to_le_bytes(),
-            ],
-            input.program_id(),
-        )
-        .bpf_expect("couldn't derive program authority");
+        parse_accounts!(
+            &mut pool = <Entity<B, StakePool>>::load(&program_id, this)?,
+            &administrator_authority,
+        );
 
-        if !pubkey_eq(program_authority.key(), &expected_program_authority) {
+        Ok(Self {
+            pool,
+            administrator_authority,
+        })
+    }
+}
+
+impl<B: AccountBackend> SetFeeWalletArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts!(
+            &mut pool = <Entity<B, StakePool>>::load(&program_id, this)?,
+            &administrator_authority,
+            &fee_wallet = pool.reward_wallet(this)?,
+        );
+
+        Ok(Self {
+            pool,
+            administrator_authority,
+            fee_wallet,
+        })
+    }
+}
+
+impl<B: AccountBackend> TicketArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts!(
+            &pool = <Entity<B, StakePool>>::load(&program_id, this)?,
+            &mut ticket = pool.load_ticket(this)?,
+            &staker,
+        );
+
+        Ok(Self {
+            pool,
+            ticket,
+            staker,
+        })
+    }
+}
+
+impl<B: AccountBackend> TransferTicketArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts!(
+            &system_program = SystemProgram::load(this).ok_or(Error::InvalidAccount)?,
+            &mut pool = <Entity<B, StakePool>>::load(&program_id, this)?,
+            &mut ticket = pool.load_ticket(this)?,
+            &mut staker,
+            &new_staker,
+            &mut new_ticket,
+            &mut payer,
+        );
+
+        Ok(Self {
+            system_program,
+            pool,
+            ticket,
+            staker,
+            new_staker,
+            new_ticket,
+            payer,
+        })
+    }
+}
+
+impl<B: AccountBackend> RolloverPoolArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts!(
+            &mut pool = <Entity<B, StakePool>>::load(&program_id, this)?,
+            &administrator_authority,
+            &mut successor,
+            &program_authority,
+            &stake_vault = pool.stake_wallet(this)?,
+        );
+
+        Ok(Self {
+            pool,
+            administrator_authority,
+            successor,
+            program_authority,
+            stake_vault,
+        })
+    }
+}
+
+impl<B: AccountBackend> RolloverTicketArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts!(
+            &token_program = TokenProgram::load(this)?,
+            &system_program = SystemProgram::load(this).ok_or(Error::InvalidAccount)?,
+            &mut pool = <Entity<B, StakePool>>::load(&program_id, this)?,
+            &mut ticket = pool.load_ticket(this)?,
+            &mut staker,
+            &program_authority,
+            &mut stake_vault = pool.stake_vault(this)?,
+            &mut successor = <Entity<B, StakePool>>::load(&program_id, this)?,
+            &mut successor_ticket,
+            &mut successor_stake_vault = successor.stake_vault(this)?,
+            &mut payer,
+        );
+
+        let fee_wallet = if pool.has_fee() {
+            parse_accounts!(&mut fee_wallet = pool.fee_wallet(this)?);
+            Some(fee_wallet)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            token_program,
+            system_program,
+            pool,
+            ticket,
+            staker,
+            program_authority,
+            stake_vault,
+            successor,
+            successor_ticket,
+            successor_stake_vault,
+            payer,
+            fee_wallet,
+        })
+    }
+}
+
+impl<B: AccountBackend> MigrateArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts!(
+            &token_program = TokenProgram::load(this)?,
+            &mut pool = <Entity<B, StakePool>>::raw_versioned(&program_id, this)?,
+            &mut new_pool,
+            &mut administrator_authority,
+            &program_authority,
+            &new_program_authority,
+            &mut stake_vault = WalletAccount::any(this)?,
+        );
+
+        Ok(Self {
+            token_program,
+            pool,
+            new_pool,
+            administrator_authority,
+            program_authority,
+            new_program_authority,
+            stake_vault,
+        })
+    }
+}
+
+impl<B: AccountBackend> ClosePoolArgsAccounts<B> {
+    #[cfg(feature = "onchain")]
+    #[inline]
+    pub fn from_program_input<T: AccountSource<B>>(input: &mut T) -> Result<Self, Error> {
+        let program_id = *input.program_id();
+
+        parse_accounts!(
+            &token_program = TokenProgram::load(this)?,
+            &mut pool = <Entity<B, StakePool>>::load(&program_id, this)?,
+            &mut administrator_authority,
+            &program_authority,
+            &mut stake_vault = pool.stake_vault(this)?,
+            &mut target_wallet = pool.stake_wallet(this)?,
+        );
+
+        let reward = pool.read_reward_vault_accounts(input)?;
+
+        Ok(Self {
+            token_program,
+            pool,
+            administrator_authority,
+            program_authority,
+            stake_vault,
+            target_wallet,
+            reward,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StakeArgs {
+    pub amount: TokenAmount,
+}
+
+impl<B> Entity<B, StakePool>
+where
+    B: AccountBackend,
+{
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn initialize<T>(
+        input: &mut T,
+        args: InitializeArgs,
+        with_reward: bool,
+    ) -> Result<(), Error>
+    where
+        B::Impl: AccountFieldsMut,
+        T: AccountSource<B>,
+    {
+        let InitializeArgsAccounts {
+            administrator_authority,
+            program_authority,
+            pool,
+            stake_mint,
+            stake_vault,
+            reward,
+            share_mint,
+        } = InitializeArgsAccounts::from_program_input(input, with_reward, args.liquid)?;
+
+        let mut entity = Self::raw_any(input.program_id(), pool)?;
+
+        let expected_program_authority = Pubkey::create_program_address(
+            &[
+                entity.account().key().as_ref(),
+                &args.program_authority_salt.to_le_bytes(),
+            ],
+            input.program_id(),
+        )
+        .bpf_expect("couldn't derive program authority");
+
+        if !pubkey_eq(program_authority.key(), &expected_program_authority) {
             qlog!("provided program authority does not match expected authority");
             return Err(Error::InvalidAuthority);
         }
@@ -306,6 +1002,31 @@ where
             return Err(Error::InvalidParent);
         }
 
+        let (reward_mint, reward_vault) = match &reward {
+            Some(RewardMintAccounts {
+                reward_mint,
+                reward_vault,
+            }) => {
+                if !pubkey_eq(reward_vault.authority(), &expected_program_authority) {
+                    qlog!("reward vault authority does not match program authority");
+                    return Err(Error::InvalidAuthority);
+                }
+
+                if !pubkey_eq(reward_mint.key(), reward_vault.mint()) {
+                    qlog!("reward vault mint does not match provided reward mint");
+                    return Err(Error::InvalidParent);
+                }
+
+                if pubkey_eq(reward_vault.key(), stake_vault.key()) {
+                    qlog!("reward vault should be separate from stake vault");
+                    return Err(Error::InvalidAccount);
+                }
+
+                (*reward_mint.key(), *reward_vault.key())
+            }
+            None => (*stake_mint.key(), *stake_vault.key()),
+        };
+
         let now = timestamp_now();
 
         if args.topup_duration > args.lockup_duration {
@@ -313,6 +1034,61 @@ where
             return Err(Error::InvalidData);
         }
 
+        if args.max_stake_per_ticket != 0.into() && args.min_stake > args.max_stake_per_ticket {
+            qlog!("min_stake should not exceed max_stake_per_ticket");
+            return Err(Error::InvalidData);
+        }
+
+        if args.early_exit_penalty_bps > MAX_BPS {
+            qlog!("early_exit_penalty_bps should not exceed 100%");
+            return Err(Error::InvalidData);
+        }
+
+        if args.early_exit_penalty_bps != 0
+            && args.early_exit_treasury.is_none()
+            && !pubkey_eq(&reward_vault, stake_vault.key())
+        {
+            qlog!("penalty can only be added to the reward paid from stake vault");
+            return Err(Error::InvalidData);
+        }
+
+        if args.fee_bps > MAX_FEE_BPS {
+            qlog!("fee_bps exceeds the maximum protocol fee");
+            return Err(Error::InvalidData);
+        }
+
+        if args.fee_bps != 0 && args.fee_wallet.is_none() {
+            qlog!("fee_wallet is required to charge the protocol fee");
+            return Err(Error::InvalidData);
+        }
+
+        if let Some(share_mint) = &share_mint {
+            // shares are fungible, so neither per-staker limits nor emission can be tracked
+            if args.reward_mode != RewardMode::LumpSum
+                || args.max_stake_per_ticket != 0.into()
+                || args.allowlist_root.is_some()
+                || args.early_exit_penalty_bps != 0
+            {
+                qlog!("liquid pools only support lump sum reward without per-staker limits");
+                return Err(Error::InvalidData);
+            }
+
+            if share_mint.mint_authority() != Some(&expected_program_authority) {
+                qlog!("share mint authority does not match program authority");
+                return Err(Error::InvalidAuthority);
+            }
+
+            if share_mint.freeze_authority().is_some() {
+                qlog!("share mint should not have a freeze authority");
+                return Err(Error::InvalidAuthority);
+            }
+
+            if share_mint.supply() != 0.into() || share_mint.decimals() != stake_mint.decimals() {
+                qlog!("share mint should be empty and have decimals of stake mint");
+                return Err(Error::InvalidMint);
+            }
+        }
+
         entity.program_authority = *program_authority.key();
         entity.administrator_authority = *administrator_authority.key();
         entity.genesis = now;
@@ -323,17 +1099,55 @@ where
         entity.stake_target_amount = args.target_amount;
         entity.reward_amount = args.reward_amount;
 
+        if args.reward_mode == RewardMode::Emission {
+            if args.lockup_duration <= 0.into() || args.reward_amount == 0.into() {
+                qlog!("emission requires positive lockup duration and reward");
+                return Err(Error::InvalidData);
+            }
+
+            // rate is rounded down, so that the pool never emits more than the reward amount
+            entity.reward_rate =
+                args.reward_amount.to_u64f64() / args.lockup_duration.value().to_u64f64();
+        }
+        entity.reward_updated_at = now;
+
+        entity.early_exit_penalty_bps = args.early_exit_penalty_bps;
+        entity.early_exit_treasury = args.early_exit_treasury.unwrap_or_default();
+
+        entity.min_stake = args.min_stake;
+        entity.max_stake_per_ticket = args.max_stake_per_ticket;
+        entity.allowlist_root = args.allowlist_root.unwrap_or_default();
+
+        if args.fee_bps != 0 {
+            entity.fee_bps = args.fee_bps;
+            entity.fee_wallet = args.fee_wallet.unwrap_or_default();
+        }
+
+        if let Some(share_mint) = &share_mint {
+            entity.share_mint = *share_mint.key();
+        }
+
         entity.stake_mint = *stake_mint.key();
         entity.stake_vault = *stake_vault.key();
+        entity.reward_mint = reward_mint;
+        entity.reward_vault = reward_vault;
 
         let id = entity.allocator.allocate_id();
         let entity_key = *entity.account().key();
         let header = entity.header_mut();
         header.kind = EntityKind::SimpleStakePool;
+        header.version = StakePool::VERSION;
         header.id = id;
         header.parent_id = id;
         header.root = entity_key;
 
+        emit(&Event::PoolCreated {
+            pool: entity_key,
+            administrator: entity.administrator_authority,
+            stake_mint: entity.stake_mint,
+            reward_mint: entity.reward_mint,
+        });
+
         Ok(())
     }
 
@@ -367,344 +1181,2085 @@ where
         now > self.genesis() + self.lockup_duration()
     }
 
+    /// Whether a successor pool was opened after this one expired.
     #[inline]
-    pub fn authority_seeds(&self) -> [&[u8]; 3] {
-        [
-            self.account().key().as_ref(),
-            self.administrator_authority.as_ref(),
-            as_bytes(&self.program_authority_salt),
-        ]
+    pub fn has_successor(&self) -> bool {
+        self.successor != Pubkey::default()
     }
 
+    /// Whether the pool emits the reward over time instead of paying it at expiration.
     #[inline]
-    pub fn stake_mint(&self, account: B) -> Result<MintAccount<B>, Error> {
-        let mint = MintAccount::any(account)?;
-
-        if !pubkey_eq(&self.stake_mint, mint.key()) {
-            return Err(Error::InvalidMint);
-        }
-
-        Ok(mint)
+    pub fn is_emitting(&self) -> bool {
+        self.reward_rate.value() != U64F64::from_bits(0)
     }
 
+    /// Deposited reward that was not paid out yet.
     #[inline]
-    pub fn stake_wallet(&self, account: B) -> Result<WalletAccount<B>, Error> {
-        let wallet = WalletAccount::any(account)?;
+    pub fn available_reward(&self) -> TokenAmount {
+        self.deposited_reward_amount - self.paid_reward_amount
+    }
 
-        if !pubkey_eq(&self.stake_mint, wallet.mint()) {
-            return Err(Error::InvalidMint);
-        }
+    /// Accrues reward emitted since the last update into `reward_per_share`.
+    ///
+    /// Emission stops once the pool expires.
+    #[cfg(feature = "onchain")]
+    pub fn update_reward(&mut self, now: Checked<i64>)
+    where
+        B::Impl: AccountFieldsMut,
+    {
+        let until = now.min(self.genesis() + self.lockup_duration());
 
-        Ok(wallet)
-    }
+        if until <= self.reward_updated_at {
+            return;
+        }
 
-    #[inline]
-    pub fn stake_vault(&self, account: B) -> Result<WalletAccount<B>, Error> {
-        let wallet = WalletAccount::any(account)?;
+        if self.stake_acquired_amount != 0.into() {
+            let elapsed = (until - self.reward_updated_at).value().to_u64f64();
 
-        if !pubkey_eq(&self.stake_vault, wallet.key()) {
-            return Err(Error::InvalidAccount);
+            // truncating division rounds the accumulator down, toward the pool
+            self.reward_per_share +=
+                self.reward_rate * elapsed / self.stake_acquired_amount.to_u64f64();
         }
 
-        Ok(wallet)
+        self.reward_updated_at = until;
     }
 
-    #[inline]
-    pub fn load_ticket(&self, ticket: B) -> Result<Entity<B, StakerTicket>, Error> {
-        let ticket = Entity::<B, StakerTicket>::raw_any(self.account().owner(), ticket)?;
-        if ticket.header().kind == EntityKind::SimpleStakeTicket {
-            if !ticket.is_child(self) {
-                Err(Error::InvalidParent)
-            } else {
-                Ok(ticket)
+    /// Raises the reward amount, emission pools emit the increase over what is left of the lockup.
+    #[cfg(feature = "onchain")]
+    fn increase_reward(&mut self, increase: TokenAmount, now: Checked<i64>) -> Result<(), Error>
+    where
+        B::Impl: AccountFieldsMut,
+    {
+        if self.is_emitting() {
+            self.update_reward(now);
+
+            let remaining = self.genesis() + self.lockup_duration() - now;
+            if remaining <= 0.into() {
+                qlog!("pool no longer emits reward");
+                return Err(Error::Validation);
+            }
+
+            self.reward_rate += increase.to_u64f64() / remaining.value().to_u64f64();
+        }
+
+        self.reward_amount += increase;
+
+        Ok(())
+    }
+
+    /// Whether only stakers included in the allowlist can stake.
+    #[inline]
+    pub fn has_allowlist(&self) -> bool {
+        self.allowlist_root != [0; 32]
+    }
+
+    /// Largest stake the ticket of `authority` may hold, checking the allowlist proof
+    /// if the pool has one.
+    #[cfg(feature = "onchain")]
+    fn max_stake(
+        &self,
+        authority: &Pubkey,
+        allowlist_proof: Option<&AllowlistProof>,
+    ) -> Result<TokenAmount, Error> {
+        let mut max_stake = if self.max_stake_per_ticket == 0.into() {
+            u64::MAX.into()
+        } else {
+            self.max_stake_per_ticket
+        };
+
+        if self.has_allowlist() {
+            let allowlist_proof = allowlist_proof.ok_or_else(|| {
+                qlog!("allowlist proof is required");
+                Error::Validation
+            })?;
+
+            let leaf = allowlist::leaf(authority, allowlist_proof.max_allocation);
+            if !allowlist::verify(&self.allowlist_root, leaf, &allowlist_proof.proof) {
+                qlog!("staker is not included in the allowlist");
+                return Err(Error::Validation);
             }
+
+            max_stake = max_stake.min(allowlist_proof.max_allocation);
+        }
+
+        Ok(max_stake)
+    }
+
+    /// Whether early exit penalties are sent to a treasury instead of the reward.
+    #[inline]
+    pub fn has_early_exit_treasury(&self) -> bool {
+        self.early_exit_treasury != Pubkey::default()
+    }
+
+    /// Part of `amount` withheld from a staker leaving during lockup, rounded up.
+    #[inline]
+    pub fn early_exit_penalty(&self, amount: TokenAmount) -> TokenAmount {
+        let bps = u128::from(self.early_exit_penalty_bps);
+        let max_bps = u128::from(MAX_BPS);
+
+        // penalty never exceeds the amount, so it always fits back into u64
+        let penalty = (u128::from(amount.value()) * bps + max_bps - 1) / max_bps;
+        (penalty as u64).into()
+    }
+
+    /// Whether part of every reward share is sent to the fee wallet.
+    #[inline]
+    pub fn has_fee(&self) -> bool {
+        self.fee_bps != 0
+    }
+
+    /// Protocol fee withheld from `reward`, rounded down in favor of the staker.
+    #[inline]
+    pub fn fee(&self, reward: TokenAmount) -> TokenAmount {
+        let bps = u128::from(self.fee_bps);
+        let max_bps = u128::from(MAX_BPS);
+
+        // fee never exceeds the reward, so it always fits back into u64
+        let fee = u128::from(reward.value()) * bps / max_bps;
+        (fee as u64).into()
+    }
+
+    /// Whether any part of the reward was deposited into the pool.
+    ///
+    /// Stakers of a pool that expired unfunded are allowed to take their principal back.
+    #[inline]
+    pub fn is_funded(&self) -> bool {
+        self.deposited_reward_amount != 0.into()
+    }
+
+    /// Seeds of the program authority, which do not depend on the administrator,
+    /// so that it stays the same when administrator is rotated.
+    #[inline]
+    pub fn authority_seeds(&self) -> [&[u8]; 2] {
+        [
+            self.account().key().as_ref(),
+            as_bytes(&self.program_authority_salt),
+        ]
+    }
+
+    #[cfg(feature = "onchain")]
+    pub fn check_administrator(&self, administrator_authority: &B) -> Result<(), Error> {
+        if !pubkey_eq(&self.administrator_authority, administrator_authority.key()) {
+            qlog!("wrong administrator provided");
+            return Err(Error::InvalidAuthority);
+        }
+
+        if !administrator_authority.is_signer() {
+            qlog!("the administrator is expected to sign");
+            return Err(Error::Validation);
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn stake_mint(&self, account: B) -> Result<MintAccount<B>, Error> {
+        let mint = MintAccount::any(account)?;
+
+        if !pubkey_eq(&self.stake_mint, mint.key()) {
+            return Err(Error::InvalidMint);
+        }
+
+        Ok(mint)
+    }
+
+    #[inline]
+    pub fn stake_wallet(&self, account: B) -> Result<WalletAccount<B>, Error> {
+        let wallet = WalletAccount::any(account)?;
+
+        if !pubkey_eq(&self.stake_mint, wallet.mint()) {
+            return Err(Error::InvalidMint);
+        }
+
+        Ok(wallet)
+    }
+
+    #[inline]
+    pub fn early_exit_treasury(&self, account: B) -> Result<WalletAccount<B>, Error> {
+        let wallet = self.stake_wallet(account)?;
+
+        if !pubkey_eq(&self.early_exit_treasury, wallet.key()) {
+            return Err(Error::InvalidAccount);
+        }
+
+        Ok(wallet)
+    }
+
+    #[inline]
+    pub fn stake_vault(&self, account: B) -> Result<WalletAccount<B>, Error> {
+        let wallet = WalletAccount::any(account)?;
+
+        if !pubkey_eq(&self.stake_vault, wallet.key()) {
+            return Err(Error::InvalidAccount);
+        }
+
+        Ok(wallet)
+    }
+
+    /// Whether rewards are paid in a separate token from a separate vault.
+    #[inline]
+    pub fn has_reward_vault(&self) -> bool {
+        !pubkey_eq(&self.reward_vault, &self.stake_vault)
+    }
+
+    #[inline]
+    pub fn reward_wallet(&self, account: B) -> Result<WalletAccount<B>, Error> {
+        let wallet = WalletAccount::any(account)?;
+
+        if !pubkey_eq(&self.reward_mint, wallet.mint()) {
+            return Err(Error::InvalidMint);
+        }
+
+        Ok(wallet)
+    }
+
+    #[inline]
+    pub fn fee_wallet(&self, account: B) -> Result<WalletAccount<B>, Error> {
+        let wallet = self.reward_wallet(account)?;
+
+        if !pubkey_eq(&self.fee_wallet, wallet.key()) {
+            return Err(Error::InvalidAccount);
+        }
+
+        Ok(wallet)
+    }
+
+    /// Whether stakers hold pool share tokens instead of tickets.
+    #[inline]
+    pub fn is_liquid(&self) -> bool {
+        self.share_mint != Pubkey::default()
+    }
+
+    #[inline]
+    pub fn share_mint(&self, account: B) -> Result<MintAccount<B>, Error> {
+        let mint = MintAccount::any(account)?;
+
+        if !pubkey_eq(&self.share_mint, mint.key()) {
+            return Err(Error::InvalidMint);
+        }
+
+        Ok(mint)
+    }
+
+    #[inline]
+    pub fn share_wallet(&self, account: B) -> Result<WalletAccount<B>, Error> {
+        let wallet = WalletAccount::any(account)?;
+
+        if !pubkey_eq(&self.share_mint, wallet.mint()) {
+            return Err(Error::InvalidMint);
+        }
+
+        Ok(wallet)
+    }
+
+    #[inline]
+    pub fn reward_vault(&self, account: B) -> Result<WalletAccount<B>, Error> {
+        let wallet = WalletAccount::any(account)?;
+
+        if !pubkey_eq(&self.reward_vault, wallet.key()) {
+            return Err(Error::InvalidAccount);
+        }
+
+        Ok(wallet)
+    }
+
+    /// Reads reward vault and target wallet if the pool has a separate reward vault.
+    #[cfg(feature = "onchain")]
+    #[inline]
+    fn read_reward_vault_accounts<T: AccountSource<B>>(
+        &self,
+        input: &mut T,
+    ) -> Result<Option<RewardVaultAccounts<B>>, Error> {
+        if !self.has_reward_vault() {
+            return Ok(None);
+        }
+
+        parse_accounts!(
+            &mut reward_vault = self.reward_vault(this)?,
+            &mut target_wallet = self.reward_wallet(this)?
+        );
+
+        Ok(Some(RewardVaultAccounts {
+            reward_vault,
+            target_wallet,
+        }))
+    }
+
+    #[cfg(feature = "onchain")]
+    #[inline]
+    fn read_share_accounts<T: AccountSource<B>>(
+        &self,
+        input: &mut T,
+    ) -> Result<ShareAccounts<B>, Error> {
+        parse_accounts!(
+            &mut share_mint = self.share_mint(this)?,
+            &mut share_wallet = self.share_wallet(this)?
+        );
+
+        Ok(ShareAccounts {
+            share_mint,
+            share_wallet,
+        })
+    }
+
+    /// Reads ticket of the staker, or the share accounts if the pool is liquid.
+    #[cfg(feature = "onchain")]
+    #[inline]
+    fn read_position<T: AccountSource<B>>(&self, input: &mut T) -> Result<StakePosition<B>, Error> {
+        if self.is_liquid() {
+            return Ok(StakePosition::Shares(self.read_share_accounts(input)?));
+        }
+
+        parse_accounts!(&mut ticket = self.load_ticket(this)?);
+
+        Ok(StakePosition::Ticket(ticket))
+    }
+
+    #[inline]
+    pub fn load_ticket(&self, ticket: B) -> Result<Entity<B, StakerTicket>, Error> {
+        let ticket = Entity::<B, StakerTicket>::raw_initialized(self.account().owner(), ticket)?;
+        if !ticket.is_child(self) {
+            Err(Error::InvalidParent)
         } else {
-            Err(Error::InvalidKind)
+            Ok(ticket)
         }
     }
 
+    /// Loads ticket of the staker, creating it at the address derived
+    /// from [`StakerTicket::seeds`] on the first stake, paid by `payer`.
     #[cfg(feature = "onchain")]
     #[inline]
     fn load_or_init_ticket(
         &mut self,
+        system_program: &SystemProgram<B>,
         authority: &B,
-        ticket: B,
+        payer: &mut B,
+        mut ticket: B,
     ) -> Result<Entity<B, StakerTicket>, Error>
     where
-        B::Impl: AccountFieldsMut,
+        B: AccountBackend<Impl = Account>,
+    {
+        if !pubkey_eq(ticket.owner(), solana_api_types::system::ID) {
+            let ticket = self.load_ticket(ticket)?;
+
+            if !pubkey_eq(&ticket.authority, authority.key()) {
+                qlog!("ticket belongs to another staker");
+                return Err(Error::InvalidAuthority);
+            }
+
+            return Ok(ticket);
+        }
+
+        let program_id = *self.account().owner();
+        let pool_key = *self.account().key();
+        let (ticket_address, bump) = Pubkey::find_program_address(
+            &StakerTicket::seeds(&pool_key, authority.key()),
+            &program_id,
+        )
+        .ok_or(Error::InvalidAccount)?;
+
+        if !pubkey_eq(ticket.key(), &ticket_address) {
+            qlog!("ticket is not derived from pool and staker");
+            return Err(Error::InvalidAccount);
+        }
+
+        let space = StakerTicket::default_size() as u64;
+        let lamports = minimum_balance(space);
+        let bump = [bump];
+        let seeds: &[&[u8]] = &[pool_key.as_ref(), authority.key().as_ref(), &bump];
+
+        if ticket.lamports() == 0 {
+            system_program
+                .create_account(payer, &mut ticket, lamports, space, &program_id, &[seeds])
+                .bpf_expect("create account failed");
+        } else {
+            // anyone can send lamports to the ticket address, which makes `create_account` fail,
+            // so the ticket is topped up to rent exemption and taken over instead
+            if ticket.lamports() < lamports {
+                let missing = lamports - ticket.lamports();
+                system_program
+                    .transfer(payer, &mut ticket, missing)
+                    .bpf_expect("transfer failed");
+            }
+
+            system_program
+                .allocate(&mut ticket, space, &[seeds])
+                .bpf_expect("allocate failed");
+            system_program
+                .assign(&mut ticket, &program_id, &[seeds])
+                .bpf_expect("assign failed");
+        }
+
+        let mut ticket = Entity::<B, StakerTicket>::raw_any(&program_id, ticket)?;
+
+        let header = ticket.header_mut();
+        header.id = self.allocator.allocate_id();
+        header.parent_id = self.header().id;
+        header.root = *self.root();
+        header.kind = EntityKind::SimpleStakeTicket;
+        header.version = StakerTicket::VERSION;
+
+        ticket.authority = *authority.key();
+
+        Ok(ticket)
+    }
+
+    /// Liquid pools mint a share for every staked token instead of recording it in the ticket.
+    ///
+    /// Input accounts:
+    /// SPL Token Program
+    /// System Program
+    /// Pool
+    /// Staker
+    /// Staker Ticket (derived from pool and staker, unless pool is liquid)
+    /// SPL Token Mint share (if pool is liquid)
+    /// SPL Token Wallet share (if pool is liquid)
+    /// Program Authority (if pool is liquid)
+    /// SPL Token Wallet stake vault
+    /// Source Authority (signed, pays for the ticket)
+    /// SPL Token Wallet source
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn add_stake<T>(
+        input: &mut T,
+        amount: TokenAmount,
+        allowlist_proof: Option<AllowlistProof>,
+    ) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let StakeArgsAccounts {
+            token_program,
+            mut pool,
+            position,
+            program_authority,
+            mut stake_vault,
+            source_authority,
+            mut source_wallet,
+            ..
+        } = StakeArgsAccounts::from_program_input(input)?;
+
+        if source_wallet.amount() < amount {
+            qlog!("not enough funds in wallet");
+            return Err(Error::Validation);
+        }
+
+        let now = timestamp_now();
+
+        if !pool.can_topup(now) {
+            qlog!("pool is locked and funds can no longer be added");
+            return Err(Error::Validation);
+        }
+
+        let (staked_amount, max_stake) = match &position {
+            StakePosition::Ticket(ticket) => (
+                ticket.staked_amount,
+                pool.max_stake(&ticket.authority, allowlist_proof.as_ref())?,
+            ),
+            // liquid pools have no per-staker limits, so every stake is checked on its own
+            StakePosition::Shares(_) => (0.into(), u64::MAX.into()),
+        };
+
+        if staked_amount >= max_stake || amount > max_stake - staked_amount {
+            qlog!("stake would exceed the ticket maximum");
+            return Err(Error::Validation);
+        }
+
+        let transfer_amount = amount.min(pool.stake_target_amount - pool.stake_acquired_amount);
+
+        if transfer_amount == 0.into() {
+            qlog!("pool is full");
+            return Err(Error::Validation);
+        }
+
+        if staked_amount + transfer_amount < pool.min_stake {
+            qlog!("stake is below pool minimum");
+            return Err(Error::Validation);
+        }
+
+        let amount_before = stake_vault.amount();
+        token_program
+            .transfer(
+                &mut source_wallet,
+                &mut stake_vault,
+                transfer_amount.value(),
+                &source_authority,
+                &[],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("transfer failed");
+        let amount_after = stake_vault.amount();
+
+        assert!(amount_after - amount_before == transfer_amount);
+
+        pool.update_reward(now);
+        pool.stake_acquired_amount += transfer_amount;
+
+        let staker = match position {
+            StakePosition::Ticket(mut ticket) => {
+                ticket.set_staked_amount(staked_amount + transfer_amount, pool.reward_per_share);
+                ticket.authority
+            }
+            StakePosition::Shares(mut shares) => {
+                let program_authority = program_authority.bpf_expect("no program authority");
+                shares.mint(
+                    &token_program,
+                    transfer_amount,
+                    &program_authority,
+                    &pool.authority_seeds(),
+                );
+                *shares.share_wallet.authority()
+            }
+        };
+
+        emit(&Event::Staked {
+            pool: *pool.account().key(),
+            staker,
+            amount: transfer_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Stake can be removed while the pool can be topped up, or after it expired
+    /// without any reward deposited.
+    ///
+    /// Liquid pools burn a share for every unstaked token.
+    ///
+    /// Input accounts:
+    /// SPL Token Program
+    /// Pool
+    /// Staker Ticket (unless pool is liquid)
+    /// SPL Token Mint share (if pool is liquid)
+    /// SPL Token Wallet share (if pool is liquid)
+    /// Staker (signed)
+    /// Program Authority
+    /// SPL Token Wallet stake vault
+    /// SPL Token Wallet target
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn remove_stake<T>(input: &mut T, amount: TokenAmount) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let UnStakeArgsAccounts {
+            token_program,
+            mut pool,
+            mut staker,
+            position,
+            program_authority,
+            mut stake_vault,
+            mut target_wallet,
+        } = UnStakeArgsAccounts::from_program_input(input)?;
+
+        position.check_staker(&staker)?;
+
+        let now = timestamp_now();
+
+        if !pool.can_topup(now) && !(pool.is_expired(now) && !pool.is_funded()) {
+            qlog!("pool is locked and funds can no longer be removed");
+            return Err(Error::Validation);
+        }
+
+        let transfer_amount = amount.min(position.staked_amount());
+
+        let mut ticket = match position {
+            StakePosition::Ticket(ticket) => Some(ticket),
+            StakePosition::Shares(mut shares) => {
+                shares.burn(&token_program, transfer_amount, &staker);
+                None
+            }
+        };
+
+        let seeds = pool.authority_seeds();
+        let amount_before = stake_vault.amount();
+        token_program
+            .transfer(
+                &mut stake_vault,
+                &mut target_wallet,
+                transfer_amount.value(),
+                &program_authority,
+                &[&seeds],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("transfer failed");
+        let amount_after = stake_vault.amount();
+
+        assert!(amount_before - amount_after == transfer_amount);
+
+        pool.update_reward(now);
+        pool.stake_acquired_amount -= transfer_amount;
+
+        if let Some(ticket) = &mut ticket {
+            let staked_amount = ticket.staked_amount - transfer_amount;
+            ticket.set_staked_amount(staked_amount, pool.reward_per_share);
+            ticket.collect(&mut staker)?;
+        }
+
+        emit(&Event::Unstaked {
+            pool: *pool.account().key(),
+            staker: *staker.key(),
+            amount: transfer_amount,
+            penalty: 0.into(),
+        });
+
+        Ok(())
+    }
+
+    /// Removes stake from a locked pool, withholding the early exit penalty.
+    ///
+    /// Penalty is sent to the treasury if the pool has one, otherwise it is added
+    /// to the reward of the remaining stakers.
+    ///
+    /// Input accounts:
+    /// SPL Token Program
+    /// Pool
+    /// Staker Ticket
+    /// Staker (signed)
+    /// Program Authority
+    /// SPL Token Wallet stake vault
+    /// SPL Token Wallet target
+    /// SPL Token Wallet treasury (if pool has early exit treasury)
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn emergency_unstake<T>(input: &mut T, amount: TokenAmount) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let EmergencyUnstakeArgsAccounts {
+            token_program,
+            mut pool,
+            mut ticket,
+            mut staker,
+            program_authority,
+            mut stake_vault,
+            mut target_wallet,
+            treasury_wallet,
+        } = EmergencyUnstakeArgsAccounts::from_program_input(input)?;
+
+        if !pubkey_eq(&ticket.authority, staker.key()) {
+            qlog!("wrong staker provided");
+            return Err(Error::Validation);
+        }
+
+        if !staker.is_signer() {
+            qlog!("the staker is expected to sign");
+            return Err(Error::Validation);
+        }
+
+        let now = timestamp_now();
+
+        if pool.can_topup(now) || pool.is_expired(now) {
+            qlog!("pool is not locked, stake can be removed without penalty");
+            return Err(Error::Validation);
+        }
+
+        let unstake_amount = amount.min(ticket.staked_amount);
+
+        if unstake_amount == 0.into() {
+            qlog!("nothing to unstake");
+            return Err(Error::Validation);
+        }
+
+        let penalty = pool.early_exit_penalty(unstake_amount);
+        let transfer_amount = unstake_amount - penalty;
+
+        pool.update_reward(now);
+        pool.stake_acquired_amount -= unstake_amount;
+
+        let staked_amount = ticket.staked_amount - unstake_amount;
+        ticket.set_staked_amount(staked_amount, pool.reward_per_share);
+
+        let seeds = pool.authority_seeds();
+        let amount_before = stake_vault.amount();
+        token_program
+            .transfer(
+                &mut stake_vault,
+                &mut target_wallet,
+                transfer_amount.value(),
+                &program_authority,
+                &[&seeds],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("transfer failed");
+
+        match treasury_wallet {
+            Some(mut treasury_wallet) => {
+                token_program
+                    .transfer(
+                        &mut stake_vault,
+                        &mut treasury_wallet,
+                        penalty.value(),
+                        &program_authority,
+                        &[&seeds],
+                    )
+                    .bpf_expect("call failed")
+                    .bpf_expect("transfer failed");
+            }
+            None => {
+                // penalty stays in the vault and is shared by the remaining stakers
+                pool.increase_reward(penalty, now)?;
+                pool.deposited_reward_amount += penalty;
+            }
+        }
+        let amount_after = stake_vault.amount();
+
+        let withdrawn_amount = if pool.has_early_exit_treasury() {
+            unstake_amount
+        } else {
+            transfer_amount
+        };
+        assert!(amount_before - amount_after == withdrawn_amount);
+
+        ticket.collect(&mut staker)?;
+
+        emit(&Event::Unstaked {
+            pool: *pool.account().key(),
+            staker: *staker.key(),
+            amount: unstake_amount,
+            penalty,
+        });
+
+        Ok(())
+    }
+
+    /// Pays the reward share and, once the pool is expired, the principal to the staker.
+    ///
+    /// Claim can be signed by the ticket delegate instead, as long as everything
+    /// is paid into wallets of the staker. Liquid pools burn all shares held by the staker
+    /// and pay the principal and the reward share for them.
+    ///
+    /// Input accounts:
+    /// SPL Token Program
+    /// Pool
+    /// Staker Ticket (unless pool is liquid)
+    /// SPL Token Mint share (if pool is liquid)
+    /// SPL Token Wallet share (if pool is liquid)
+    /// Staker (signed, unless delegate signs)
+    /// Program Authority
+    /// SPL Token Wallet stake vault
+    /// SPL Token Wallet target
+    /// SPL Token Wallet reward vault (if pool has reward vault)
+    /// SPL Token Wallet reward target (if pool has reward vault)
+    /// SPL Token Wallet fee (if pool charges the protocol fee)
+    /// Delegate (signed, if staker does not sign)
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn claim_reward<T>(input: &mut T) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let ClaimRewardArgsAccounts {
+            token_program,
+            mut pool,
+            mut staker,
+            mut position,
+            program_authority,
+            mut stake_vault,
+            mut target_wallet,
+            mut reward,
+            fee_wallet,
+            delegate,
+        } = ClaimRewardArgsAccounts::from_program_input(input)?;
+
+        if let (StakePosition::Ticket(ticket), Some(delegate)) = (&position, &delegate) {
+            if !pubkey_eq(&ticket.authority, staker.key()) {
+                qlog!("wrong staker provided");
+                return Err(Error::Validation);
+            }
+
+            if !ticket.has_delegate() || !pubkey_eq(&ticket.delegate, delegate.key()) {
+                qlog!("wrong delegate provided");
+                return Err(Error::InvalidAuthority);
+            }
+
+            if !delegate.is_signer() {
+                qlog!("the staker or the delegate is expected to sign");
+                return Err(Error::Validation);
+            }
+
+            let reward_target_wallet = reward.as_ref().map(|reward| &reward.target_wallet);
+            if !pubkey_eq(target_wallet.authority(), staker.key())
+                || reward_target_wallet
+                    .map_or(false, |wallet| !pubkey_eq(wallet.authority(), staker.key()))
+            {
+                qlog!("delegate can only claim into wallets of the staker");
+                return Err(Error::Validation);
+            }
+        } else {
+            position.check_staker(&staker)?;
+        }
+
+        let now = timestamp_now();
+        let is_expired = pool.is_expired(now);
+
+        let (reward_share, unstake_amount) = if pool.is_emitting() {
+            // accrued reward can be claimed at any time, principal only after expiration
+            pool.update_reward(now);
+
+            let ticket = position
+                .ticket_mut()
+                .bpf_expect("liquid pools never emit the reward");
+
+            let unstake_amount = if is_expired {
+                ticket.staked_amount
+            } else {
+                0.into()
+            };
+            let staked_amount = ticket.staked_amount - unstake_amount;
+            ticket.set_staked_amount(staked_amount, pool.reward_per_share);
+
+            // whatever can't be paid yet stays in the ticket until more reward is deposited
+            let reward_share = round_down(ticket.unclaimed_reward).min(pool.available_reward());
+            ticket.unclaimed_reward -= reward_share.to_u64f64();
+
+            (reward_share, unstake_amount)
+        } else {
+            if !is_expired {
+                qlog!("cannot claim pool reward yet");
+                return Err(Error::Validation);
+            }
+
+            // rewards are split between stakers according to what was actually deposited,
+            // so that an underfunded pool never pays out of other stakers' principal
+            if pool.stake_acquired_amount == 0.into() {
+                qlog!("pool holds no stake to share the reward between");
+                return Err(Error::Validation);
+            }
+
+            let unstake_amount = position.staked_amount();
+            let staked_amount = unstake_amount.to_u64f64();
+            let stake_acquired_amount = pool.stake_acquired_amount.to_u64f64();
+            let available_reward = pool.available_reward().to_u64f64();
+
+            let share = staked_amount / stake_acquired_amount;
+            let reward_share = round_down(share * available_reward);
+
+            if let Some(ticket) = position.ticket_mut() {
+                ticket.staked_amount = 0.into();
+            }
+
+            (reward_share, unstake_amount)
+        };
+
+        if reward_share == 0.into() && unstake_amount == 0.into() {
+            qlog!("nothing to claim");
+            return Err(Error::Validation);
+        }
+
+        let seeds = pool.authority_seeds();
+
+        let fee_amount = pool.fee(reward_share);
+        let staker_reward = reward_share - fee_amount;
+
+        if let Some(mut fee_wallet) = fee_wallet {
+            if fee_amount != 0.into() {
+                let reward_vault = match &mut reward {
+                    Some(reward) => &mut reward.reward_vault,
+                    None => &mut stake_vault,
+                };
+
+                let amount_before = reward_vault.amount();
+                token_program
+                    .transfer(
+                        &mut *reward_vault,
+                        &mut fee_wallet,
+                        fee_amount.value(),
+                        &program_authority,
+                        &[&seeds],
+                    )
+                    .bpf_expect("call failed")
+                    .bpf_expect("transfer failed");
+                let amount_after = reward_vault.amount();
+
+                assert!(amount_before - amount_after == fee_amount);
+            }
+        }
+
+        // principal and reward share the same vault unless the pool pays in a separate token
+        let transfer_amount = match reward {
+            Some(RewardVaultAccounts {
+                mut reward_vault,
+                target_wallet: mut reward_target_wallet,
+            }) => {
+                let amount_before = reward_vault.amount();
+                token_program
+                    .transfer(
+                        &mut reward_vault,
+                        &mut reward_target_wallet,
+                        staker_reward.value(),
+                        &program_authority,
+                        &[&seeds],
+                    )
+                    .bpf_expect("call failed")
+                    .bpf_expect("transfer failed");
+                let amount_after = reward_vault.amount();
+
+                assert!(amount_before - amount_after == staker_reward);
+
+                unstake_amount
+            }
+            None => unstake_amount + staker_reward,
+        };
+
+        let amount_before = stake_vault.amount();
+        token_program
+            .transfer(
+                &mut stake_vault,
+                &mut target_wallet,
+                transfer_amount.value(),
+                &program_authority,
+                &[&seeds],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("transfer failed");
+        let amount_after = stake_vault.amount();
+
+        assert!(amount_before - amount_after == transfer_amount);
+
+        // claimed stake leaves the pool, which is what lets `close` tell that every staker
+        // was paid out. Lump sum shares of the remaining stakers do not change, as for
+        // stake `s` out of `A` with reward `D`, after `a` is claimed along with `a / A * D`
+        // the share is `s / (A - a) * (D - a / A * D) == s / A * D`
+        pool.stake_acquired_amount -= unstake_amount;
+        pool.paid_reward_amount += reward_share;
+        pool.paid_fee_amount += fee_amount;
+
+        match position {
+            StakePosition::Ticket(mut ticket) => {
+                ticket.collect(&mut staker)?;
+            }
+            StakePosition::Shares(mut shares) => {
+                shares.burn(&token_program, unstake_amount, &staker);
+            }
+        }
+
+        if reward_share != 0.into() {
+            emit(&Event::RewardClaimed {
+                pool: *pool.account().key(),
+                staker: *staker.key(),
+                amount: staker_reward,
+                fee: fee_amount,
+            });
+        }
+
+        if unstake_amount != 0.into() {
+            emit(&Event::Unstaked {
+                pool: *pool.account().key(),
+                staker: *staker.key(),
+                amount: unstake_amount,
+                penalty: 0.into(),
+            });
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn add_reward<T>(input: &mut T, amount: TokenAmount) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let AddRewardArgsAccounts {
+            token_program,
+            mut pool,
+            mut reward_vault,
+            source_authority,
+            mut source_wallet,
+        } = AddRewardArgsAccounts::from_program_input(input)?;
+
+        let transfer_amount = amount
+            .min(pool.reward_amount - pool.deposited_reward_amount)
+            .min(source_wallet.amount());
+
+        if transfer_amount == 0.into() {
+            qlog!("no reward to deposit");
+            return Err(Error::Validation);
+        }
+
+        let now = timestamp_now();
+
+        if pool.is_expired(now) {
+            qlog!("reward cannot be added to a pool that has expired");
+            return Err(Error::Validation);
+        }
+
+        let amount_before = reward_vault.amount();
+        token_program
+            .transfer(
+                &mut source_wallet,
+                &mut reward_vault,
+                transfer_amount.value(),
+                &source_authority,
+                &[],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("transfer failed");
+        let amount_after = reward_vault.amount();
+        assert!(amount_after - amount_before == transfer_amount);
+
+        pool.deposited_reward_amount += transfer_amount;
+        assert!(pool.deposited_reward_amount <= pool.reward_amount);
+
+        emit(&Event::RewardAdded {
+            pool: *pool.account().key(),
+            amount: transfer_amount,
+        });
+
+        Ok(())
+    }
+
+    /// Raises pool parameters, lockup duration can never be changed.
+    ///
+    /// Input accounts:
+    /// Pool
+    /// Administrator Authority (signed)
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn update<T>(input: &mut T, args: UpdatePoolArgs) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let AdministratorArgsAccounts {
+            mut pool,
+            administrator_authority,
+        } = AdministratorArgsAccounts::from_program_input(input)?;
+
+        pool.check_administrator(&administrator_authority)?;
+
+        let now = timestamp_now();
+
+        if pool.is_expired(now) {
+            qlog!("expired pool cannot be updated");
+            return Err(Error::Validation);
+        }
+
+        if let Some(topup_duration) = args.topup_duration {
+            if !pool.can_topup(now) {
+                qlog!("topup_duration cannot be extended after topup has ended");
+                return Err(Error::Validation);
+            }
+
+            if topup_duration < pool.topup_duration || topup_duration > pool.lockup_duration {
+                qlog!("topup_duration can only be extended up to lockup_duration");
+                return Err(Error::InvalidData);
+            }
+
+            pool.topup_duration = topup_duration;
+        }
+
+        if let Some(stake_target_amount) = args.stake_target_amount {
+            if stake_target_amount < pool.stake_target_amount {
+                qlog!("stake_target_amount can only be raised");
+                return Err(Error::InvalidData);
+            }
+
+            pool.stake_target_amount = stake_target_amount;
+        }
+
+        if let Some(reward_amount) = args.reward_amount {
+            if reward_amount < pool.reward_amount {
+                qlog!("reward_amount can only be raised");
+                return Err(Error::InvalidData);
+            }
+
+            let increase = reward_amount - pool.reward_amount;
+            pool.increase_reward(increase, now)?;
+        }
+
+        Ok(())
+    }
+
+    /// First step of administrator rotation, the proposed administrator has to accept it.
+    ///
+    /// Input accounts:
+    /// Pool
+    /// Administrator Authority (signed)
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn propose_administrator<T>(input: &mut T, administrator: Pubkey) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let AdministratorArgsAccounts {
+            mut pool,
+            administrator_authority,
+        } = AdministratorArgsAccounts::from_program_input(input)?;
+
+        pool.check_administrator(&administrator_authority)?;
+
+        pool.pending_administrator_authority = administrator;
+
+        Ok(())
+    }
+
+    /// Input accounts:
+    /// Pool
+    /// Proposed Administrator Authority (signed)
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn accept_administrator<T>(input: &mut T) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let AdministratorArgsAccounts {
+            mut pool,
+            administrator_authority,
+        } = AdministratorArgsAccounts::from_program_input(input)?;
+
+        if pool.pending_administrator_authority == Pubkey::default()
+            || !pubkey_eq(
+                &pool.pending_administrator_authority,
+                administrator_authority.key(),
+            )
+        {
+            qlog!("administrator was not proposed");
+            return Err(Error::InvalidAuthority);
+        }
+
+        if !administrator_authority.is_signer() {
+            qlog!("the administrator is expected to sign");
+            return Err(Error::Validation);
+        }
+
+        pool.administrator_authority = *administrator_authority.key();
+        pool.pending_administrator_authority = Pubkey::default();
+
+        Ok(())
+    }
+
+    /// Input accounts:
+    /// Pool
+    /// Administrator Authority (signed)
+    /// SPL Token Wallet fee (in reward mint)
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn set_fee_wallet<T>(input: &mut T) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let SetFeeWalletArgsAccounts {
+            mut pool,
+            administrator_authority,
+            fee_wallet,
+        } = SetFeeWalletArgsAccounts::from_program_input(input)?;
+
+        pool.check_administrator(&administrator_authority)?;
+
+        if !pool.has_fee() {
+            qlog!("pool does not charge the protocol fee");
+            return Err(Error::Validation);
+        }
+
+        pool.fee_wallet = *fee_wallet.key();
+
+        Ok(())
+    }
+
+    /// Closes an expired pool, sending whatever is left in the stake vault to the administrator.
+    ///
+    /// Pool can be closed only after expiration, once every staker has claimed their stake
+    /// or after [`CLOSE_GRACE_PERIOD`] has passed since expiration.
+    ///
+    /// Input accounts:
+    /// SPL Token Program
+    /// Pool
+    /// Administrator Authority (signed, receives rent)
+    /// Program Authority
+    /// SPL Token Wallet stake vault
+    /// SPL Token Wallet target
+    /// SPL Token Wallet reward vault (if pool has separate reward vault)
+    /// SPL Token Wallet reward target (if pool has separate reward vault)
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn close<T>(input: &mut T) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let ClosePoolArgsAccounts {
+            token_program,
+            mut pool,
+            mut administrator_authority,
+            program_authority,
+            mut stake_vault,
+            mut target_wallet,
+            reward,
+        } = ClosePoolArgsAccounts::from_program_input(input)?;
+
+        pool.check_administrator(&administrator_authority)?;
+
+        let now = timestamp_now();
+
+        if !pool.is_expired(now) {
+            qlog!("pool can be closed only after expiration");
+            return Err(Error::Validation);
+        }
+
+        if pool.stake_acquired_amount != 0.into()
+            && now <= pool.genesis() + pool.lockup_duration() + CLOSE_GRACE_PERIOD.into()
+        {
+            qlog!("pool still holds stake and grace period has not passed yet");
+            return Err(Error::Validation);
+        }
+
+        {
+            let seeds = pool.authority_seeds();
+
+            Self::sweep_vault(
+                &token_program,
+                &mut stake_vault,
+                &mut target_wallet,
+                &mut administrator_authority,
+                &program_authority,
+                &seeds,
+            );
+
+            if let Some(RewardVaultAccounts {
+                mut reward_vault,
+                target_wallet: mut reward_target_wallet,
+            }) = reward
+            {
+                Self::sweep_vault(
+                    &token_program,
+                    &mut reward_vault,
+                    &mut reward_target_wallet,
+                    &mut administrator_authority,
+                    &program_authority,
+                    &seeds,
+                );
+            }
+        }
+
+        pool.header_mut().kind = EntityKind::None;
+        administrator_authority
+            .set_lamports(administrator_authority.lamports() + pool.account().lamports());
+        pool.account_mut().set_lamports(0);
+
+        Ok(())
+    }
+
+    /// Hands the ticket position over to the new staker, who has to accept it by signing.
+    ///
+    /// Position is merged into the ticket of the new staker, which is created if needed,
+    /// so that every staker keeps a single ticket derived from the pool and their key.
+    /// Merged ticket is limited like any other and keeps the delegate and the rollover
+    /// opt-in of the new staker. Ticket of the staker is closed.
+    ///
+    /// Input accounts:
+    /// System Program
+    /// Pool
+    /// Staker Ticket
+    /// Staker (signed, receives rent of the ticket)
+    /// New Staker (signed)
+    /// New Staker Ticket (derived from pool and new staker)
+    /// Payer (signed, pays for the new staker ticket)
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn transfer_ticket<T>(
+        input: &mut T,
+        allowlist_proof: Option<AllowlistProof>,
+    ) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let TransferTicketArgsAccounts {
+            system_program,
+            mut pool,
+            mut ticket,
+            mut staker,
+            new_staker,
+            new_ticket,
+            mut payer,
+        } = TransferTicketArgsAccounts::from_program_input(input)?;
+
+        ticket.check_staker(&staker)?;
+
+        if !new_staker.is_signer() {
+            qlog!("the new staker is expected to sign");
+            return Err(Error::Validation);
+        }
+
+        if pubkey_eq(staker.key(), new_staker.key()) {
+            qlog!("ticket already belongs to the new staker");
+            return Err(Error::Validation);
+        }
+
+        let mut new_ticket =
+            pool.load_or_init_ticket(&system_program, &new_staker, &mut payer, new_ticket)?;
+
+        let staked_amount = new_ticket.staked_amount + ticket.staked_amount;
+        let max_stake = pool.max_stake(new_staker.key(), allowlist_proof.as_ref())?;
+
+        if staked_amount > max_stake {
+            qlog!("new staker is not allowed to hold the ticket stake");
+            return Err(Error::Validation);
+        }
+
+        // reward accrued by either ticket so far is settled before their stake is merged
+        let now = timestamp_now();
+        pool.update_reward(now);
+
+        ticket.set_staked_amount(0.into(), pool.reward_per_share);
+        new_ticket.set_staked_amount(staked_amount, pool.reward_per_share);
+        new_ticket.unclaimed_reward += ticket.unclaimed_reward;
+        ticket.unclaimed_reward = U64F64::from_num(0).into();
+
+        ticket.collect(&mut staker)?;
+
+        emit(&Event::TicketTransferred {
+            pool: *pool.account().key(),
+            ticket: *new_ticket.account().key(),
+            staker: *staker.key(),
+            new_staker: *new_staker.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Input accounts:
+    /// Pool
+    /// Staker Ticket
+    /// Staker (signed)
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn set_ticket_delegate<T>(input: &mut T, delegate: Option<Pubkey>) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let TicketArgsAccounts {
+            mut ticket, staker, ..
+        } = TicketArgsAccounts::from_program_input(input)?;
+
+        ticket.check_staker(&staker)?;
+
+        ticket.delegate = delegate.unwrap_or_default();
+
+        Ok(())
+    }
+
+    /// Opens the successor of an expired pool, with the same parameters and a new lockup
+    /// starting now. The reward has to be deposited into the successor again.
+    ///
+    /// Successor shares the root entity of the pool and becomes its child, so that both
+    /// tickets and pools of the series are linked through their headers.
+    ///
+    /// Input accounts:
+    /// Pool
+    /// Administrator Authority (signed)
+    /// Successor Pool
+    /// Program Authority (derived from successor pool and `program_authority_salt`)
+    /// SPL Token Wallet successor stake vault
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn rollover<T>(input: &mut T, program_authority_salt: u64) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
+    {
+        let RolloverPoolArgsAccounts {
+            mut pool,
+            administrator_authority,
+            successor,
+            program_authority,
+            stake_vault,
+        } = RolloverPoolArgsAccounts::from_program_input(input)?;
+
+        pool.check_administrator(&administrator_authority)?;
+
+        let now = timestamp_now();
+
+        if !pool.is_expired(now) {
+            qlog!("only expired pools can be rolled over");
+            return Err(Error::Validation);
+        }
+
+        if pool.has_successor() {
+            qlog!("pool already has a successor");
+            return Err(Error::Validation);
+        }
+
+        // rolled over reward is staked, so it has to be paid in stake mint to a ticket
+        if pool.is_liquid() || pool.has_reward_vault() {
+            qlog!("only pools paying reward in stake mint to tickets can be rolled over");
+            return Err(Error::InvalidData);
+        }
+
+        let mut successor = Self::raw_any(input.program_id(), successor)?;
+
+        if successor.header().kind != EntityKind::None {
+            qlog!("successor pool is already initialized");
+            return Err(Error::InvalidKind);
+        }
+
+        let successor_key = *successor.account().key();
+        let expected_program_authority = Pubkey::create_program_address(
+            &[
+                successor_key.as_ref(),
+                &program_authority_salt.to_le_bytes(),
+            ],
+            input.program_id(),
+        )
+        .bpf_expect("couldn't derive program authority");
+
+        if !pubkey_eq(program_authority.key(), &expected_program_authority) {
+            qlog!("provided program authority does not match expected authority");
+            return Err(Error::InvalidAuthority);
+        }
+
+        if !pubkey_eq(stake_vault.authority(), &expected_program_authority) {
+            qlog!("stake vault authority does not match program authority");
+            return Err(Error::InvalidAuthority);
+        }
+
+        successor.administrator_authority = pool.administrator_authority;
+        successor.program_authority = expected_program_authority;
+        successor.program_authority_salt = program_authority_salt;
+        successor.stake_mint = pool.stake_mint;
+        successor.stake_vault = *stake_vault.key();
+        successor.reward_mint = pool.stake_mint;
+        successor.reward_vault = *stake_vault.key();
+        successor.early_exit_treasury = pool.early_exit_treasury;
+        successor.allowlist_root = pool.allowlist_root;
+        successor.fee_wallet = pool.fee_wallet;
+
+        successor.stake_target_amount = pool.stake_target_amount;
+        successor.reward_amount = pool.reward_amount;
+        successor.min_stake = pool.min_stake;
+        successor.max_stake_per_ticket = pool.max_stake_per_ticket;
+
+        successor.genesis = now;
+        successor.lockup_duration = pool.lockup_duration;
+        successor.topup_duration = pool.topup_duration;
+
+        // reward added to the pool during lockup is emitted over the whole lockup of the successor
+        if pool.is_emitting() {
+            successor.reward_rate =
+                pool.reward_amount.to_u64f64() / pool.lockup_duration.value().to_u64f64();
+        }
+        successor.reward_updated_at = now;
+
+        successor.early_exit_penalty_bps = pool.early_exit_penalty_bps;
+        successor.fee_bps = pool.fee_bps;
+
+        // ids keep being allocated from the same sequence, so they stay unique in the series
+        let id = pool.allocator.allocate_id();
+        successor.allocator = pool.allocator.clone();
+
+        let pool_key = *pool.account().key();
+        let root = *pool.root();
+        let parent_id = pool.id();
+        let header = successor.header_mut();
+        header.kind = EntityKind::SimpleStakePool;
+        header.version = StakePool::VERSION;
+        header.id = id;
+        header.parent_id = parent_id;
+        header.root = root;
+
+        pool.successor = successor_key;
+
+        emit(&Event::PoolRolledOver {
+            pool: pool_key,
+            successor: successor_key,
+        });
+
+        Ok(())
+    }
+
+    /// Input accounts:
+    /// Pool
+    /// Staker Ticket
+    /// Staker (signed)
+    #[cfg(feature = "onchain")]
+    #[inline(never)]
+    pub fn set_ticket_rollover<T>(input: &mut T, rollover: bool) -> Result<(), Error>
+    where
+        B: AccountBackend<Impl = Account>,
+        T: AccountSource<B>,
     {
-        let mut ticket = Entity::<B, StakerTicket>::raw_any(self.account().owner(), ticket)?;
+        let TicketArgsAccounts {
+            mut ticket, staker, ..
+        } = TicketArgsAccounts::from_program_input(input)?;
 
-        if ticket.header().kind == EntityKind::SimpleStakeTicket {
-            if !ticket.is_child(self) {
-                Err(Error::InvalidParent)
-            } else {
-                Ok(ticket)
-            }
-        } else if ticket.header().kind == EntityKind::None {
-            if !is_zeroed(ticket.account().data()) {
-                Err(Error::InvalidData)
-            } else {
-                let header = ticket.header_mut();
-                header.id = self.allocator.allocate_id();
-                header.parent_id = self.header().id;
-                header.root = *self.account().key();
-                header.kind = EntityKind::SimpleStakeTicket;
+        ticket.check_staker(&staker)?;
 
-                ticket.authority = *authority.key();
+        ticket.rollover = rollover as u8;
 
-                Ok(ticket)
-            }
-        } else {
-            Err(Error::InvalidKind)
-        }
+        Ok(())
     }
 
+    /// Moves principal and reward of a ticket that opted in to the rollover into the successor
+    /// pool, as long as it is still open for stake. The ticket is closed afterwards.
+    ///
+    /// Anyone can roll the ticket over, rent of the successor ticket is paid by `payer`
+    /// and the successor ticket stays opted in. Successor ticket has to belong to the staker,
+    /// otherwise the stake could be moved into a ticket of someone else.
+    ///
+    /// Input accounts:
+    /// SPL Token Program
+    /// System Program
+    /// Pool
+    /// Staker Ticket
+    /// Staker (receives rent of the ticket)
+    /// Program Authority
+    /// SPL Token Wallet stake vault
+    /// Successor Pool
+    /// Successor Staker Ticket (derived from successor pool and staker)
+    /// SPL Token Wallet successor stake vault
+    /// Payer (signed, pays for the successor ticket)
+    /// SPL Token Wallet fee (if pool charges a fee)
     #[cfg(feature = "onchain")]
     #[inline(never)]
-    pub fn add_stake<T>(input: &mut T, amount: TokenAmount) -> Result<(), Error>
+    pub fn rollover_ticket<T>(
+        input: &mut T,
+        allowlist_proof: Option<AllowlistProof>,
+    ) -> Result<(), Error>
     where
         B: AccountBackend<Impl = Account>,
         T: AccountSource<B>,
     {
-        let StakeArgsAccounts {
+        let RolloverTicketArgsAccounts {
             token_program,
+            system_program,
             mut pool,
             mut ticket,
+            mut staker,
+            program_authority,
             mut stake_vault,
-            source_authority,
-            mut source_wallet,
-            ..
-        } = StakeArgsAccounts::from_program_input(input)?;
+            mut successor,
+            successor_ticket,
+            mut successor_stake_vault,
+            mut payer,
+            fee_wallet,
+        } = RolloverTicketArgsAccounts::from_program_input(input)?;
 
-        if source_wallet.amount() < amount {
-            qlog!("not enough funds in wallet");
+        if !pubkey_eq(&ticket.authority, staker.key()) {
+            qlog!("wrong staker provided");
             return Err(Error::Validation);
         }
 
+        if !ticket.rolls_over() {
+            qlog!("staker did not opt in to the rollover");
+            return Err(Error::Validation);
+        }
+
+        if !pubkey_eq(&pool.successor, successor.account().key()) || !successor.is_child(&pool) {
+            qlog!("wrong successor pool provided");
+            return Err(Error::InvalidParent);
+        }
+
         let now = timestamp_now();
 
-        if !pool.can_topup(now) {
-            qlog!("pool is locked and funds can no longer be added");
+        if !pool.is_expired(now) {
+            qlog!("pool has not expired yet");
             return Err(Error::Validation);
         }
 
-        let transfer_amount = amount.min(pool.stake_target_amount - pool.stake_acquired_amount);
+        if !successor.can_topup(now) {
+            qlog!("successor pool is locked and funds can no longer be added");
+            return Err(Error::Validation);
+        }
 
-        if transfer_amount == 0.into() {
-            qlog!("pool is full");
+        if ticket.staked_amount == 0.into() {
+            qlog!("ticket holds no stake to roll over");
             return Err(Error::Validation);
         }
 
-        let amount_before = stake_vault.amount();
-        token_program
-            .transfer(
-                &mut source_wallet,
-                &mut stake_vault,
-                transfer_amount.value(),
-                &source_authority,
-                &[],
-            )
-            .bpf_expect("call failed")
-            .bpf_expect("transfer failed");
-        let amount_after = stake_vault.amount();
+        let principal = ticket.staked_amount;
+        let reward_share = if pool.is_emitting() {
+            pool.update_reward(now);
+            ticket.set_staked_amount(0.into(), pool.reward_per_share);
 
-        assert!(amount_after - amount_before == transfer_amount);
+            let reward_share = round_down(ticket.unclaimed_reward).min(pool.available_reward());
+            ticket.unclaimed_reward -= reward_share.to_u64f64();
 
-        pool.stake_acquired_amount += transfer_amount;
-        ticket.staked_amount += transfer_amount;
+            reward_share
+        } else {
+            if pool.stake_acquired_amount == 0.into() {
+                qlog!("pool holds no stake to share the reward between");
+                return Err(Error::Validation);
+            }
+
+            let share = principal.to_u64f64() / pool.stake_acquired_amount.to_u64f64();
+            ticket.staked_amount = 0.into();
+
+            round_down(share * pool.available_reward().to_u64f64())
+        };
+
+        let fee_amount = pool.fee(reward_share);
+        let staker_reward = reward_share - fee_amount;
+        let transfer_amount = principal + staker_reward;
+
+        let mut successor_ticket = successor.load_or_init_ticket(
+            &system_program,
+            &staker,
+            &mut payer,
+            successor_ticket,
+        )?;
+
+        let staked_amount = successor_ticket.staked_amount + transfer_amount;
+        let max_stake = successor.max_stake(staker.key(), allowlist_proof.as_ref())?;
+
+        if staked_amount > max_stake
+            || successor.stake_acquired_amount + transfer_amount > successor.stake_target_amount
+        {
+            qlog!("successor pool cannot take the whole stake");
+            return Err(Error::Validation);
+        }
+
+        {
+            let seeds = pool.authority_seeds();
+
+            if let Some(mut fee_wallet) = fee_wallet {
+                if fee_amount != 0.into() {
+                    let amount_before = stake_vault.amount();
+                    token_program
+                        .transfer(
+                            &mut stake_vault,
+                            &mut fee_wallet,
+                            fee_amount.value(),
+                            &program_authority,
+                            &[&seeds],
+                        )
+                        .bpf_expect("call failed")
+                        .bpf_expect("transfer failed");
+                    let amount_after = stake_vault.amount();
+
+                    assert!(amount_before - amount_after == fee_amount);
+                }
+            }
+
+            let amount_before = successor_stake_vault.amount();
+            token_program
+                .transfer(
+                    &mut stake_vault,
+                    &mut successor_stake_vault,
+                    transfer_amount.value(),
+                    &program_authority,
+                    &[&seeds],
+                )
+                .bpf_expect("call failed")
+                .bpf_expect("transfer failed");
+            let amount_after = successor_stake_vault.amount();
+
+            assert!(amount_after - amount_before == transfer_amount);
+        }
+
+        pool.stake_acquired_amount -= principal;
+        pool.paid_reward_amount += reward_share;
+        pool.paid_fee_amount += fee_amount;
+
+        successor.update_reward(now);
+        successor.stake_acquired_amount += transfer_amount;
+        successor_ticket.set_staked_amount(staked_amount, successor.reward_per_share);
+        successor_ticket.rollover = 1;
+
+        ticket.collect(&mut staker)?;
+
+        emit(&Event::TicketRolledOver {
+            pool: *pool.account().key(),
+            successor: *successor.account().key(),
+            staker: *staker.key(),
+            principal,
+            reward: staker_reward,
+            fee: fee_amount,
+        });
 
         Ok(())
     }
 
+    /// Moves a pool created with the v1 layout into a new account sized for the current one.
+    /// The pool can't be upgraded in place, as the runtime the program is built against
+    /// rejects any change of account data length made by a program, so the pool address
+    /// changes and the v1 pool is closed.
+    ///
+    /// New pool keeps the header of the v1 one, so that its tickets stay its children.
+    /// Tickets opened after the migration are derived from the new pool address.
+    /// v1 program authority was derived from the administrator as well, so the stake vault
+    /// is handed over to the authority derived from new pool and `program_authority_salt`.
+    /// Stakers who claimed before the migration stay counted in `stake_acquired_amount`,
+    /// which keeps the shares of the remaining ones the same.
+    ///
+    /// Input accounts:
+    /// SPL Token Program
+    /// Pool (v1)
+    /// New Pool
+    /// Administrator Authority (signed, receives lamports of the v1 pool)
+    /// Program Authority (v1)
+    /// New Program Authority (derived from new pool and `program_authority_salt`)
+    /// SPL Token Wallet stake vault
     #[cfg(feature = "onchain")]
     #[inline(never)]
-    pub fn remove_stake<T>(input: &mut T, amount: TokenAmount) -> Result<(), Error>
+    pub fn migrate<T>(input: &mut T, program_authority_salt: u64) -> Result<(), Error>
     where
         B: AccountBackend<Impl = Account>,
         T: AccountSource<B>,
     {
-        let UnStakeArgsAccounts {
+        let MigrateArgsAccounts {
             token_program,
             mut pool,
-            mut staker,
-            mut ticket,
+            new_pool,
+            mut administrator_authority,
             program_authority,
+            new_program_authority,
             mut stake_vault,
-            mut target_wallet,
-        } = UnStakeArgsAccounts::from_program_input(input)?;
+        } = MigrateArgsAccounts::from_program_input(input)?;
 
-        if !pubkey_eq(&ticket.authority, staker.key()) {
-            qlog!("wrong staker provided");
-            return Err(Error::Validation);
+        let v1 = pool.v1_state().ok_or_else(|| {
+            qlog!("pool does not use v1 layout");
+            Error::InvalidVersion
+        })?;
+
+        if !pubkey_eq(&v1.administrator_authority, administrator_authority.key()) {
+            qlog!("wrong administrator provided");
+            return Err(Error::InvalidAuthority);
         }
 
-        if !staker.is_signer() {
-            qlog!("the staker is expected to sign");
+        if !administrator_authority.is_signer() {
+            qlog!("the administrator is expected to sign");
             return Err(Error::Validation);
         }
 
-        let now = timestamp_now();
+        if !pubkey_eq(&v1.program_authority, program_authority.key()) {
+            qlog!("wrong program authority provided");
+            return Err(Error::InvalidAuthority);
+        }
 
-        if !pool.can_topup(now) {
-            qlog!("pool is locked and funds can no longer be removed");
-            return Err(Error::Validation);
+        if !pubkey_eq(&v1.stake_vault, stake_vault.key()) {
+            qlog!("wrong stake vault provided");
+            return Err(Error::InvalidAccount);
         }
 
-        let transfer_amount = amount.min(ticket.staked_amount);
+        let mut new_pool = Self::raw_any(input.program_id(), new_pool)?;
 
-        let seeds = pool.authority_seeds();
-        let amount_before = stake_vault.amount();
-        token_program
-            .transfer(
-                &mut stake_vault,
-                &mut target_wallet,
-                transfer_amount.value(),
-                &program_authority,
-                &[&seeds],
-            )
-            .bpf_expect("call failed")
-            .bpf_expect("transfer failed");
-        let amount_after = stake_vault.amount();
+        if new_pool.header().kind != EntityKind::None {
+            qlog!("new pool is already initialized");
+            return Err(Error::InvalidKind);
+        }
 
-        assert!(amount_before - amount_after == transfer_amount);
+        let pool_key = *pool.account().key();
+        let expected_program_authority = Pubkey::create_program_address(
+            &[
+                new_pool.account().key().as_ref(),
+                &program_authority_salt.to_le_bytes(),
+            ],
+            input.program_id(),
+        )
+        .bpf_expect("couldn't derive program authority");
 
-        pool.stake_acquired_amount -= transfer_amount;
-        ticket.staked_amount -= transfer_amount;
-        ticket.collect(&mut staker)?;
+        if !pubkey_eq(new_program_authority.key(), &expected_program_authority) {
+            qlog!("provided program authority does not match expected authority");
+            return Err(Error::InvalidAuthority);
+        }
+
+        {
+            let seeds = [
+                pool_key.as_ref(),
+                v1.administrator_authority.as_ref(),
+                as_bytes(&v1.program_authority_salt),
+            ];
+
+            token_program
+                .set_authority(
+                    &mut stake_vault,
+                    AuthorityType::AccountOwner,
+                    Some(&expected_program_authority),
+                    &program_authority,
+                    &[&seeds],
+                )
+                .bpf_expect("call failed")
+                .bpf_expect("set authority failed");
+        }
+
+        // new pool is already sized for the current layout
+        new_pool.upgrade_v1(v1, |_| {});
+
+        new_pool.program_authority = expected_program_authority;
+        new_pool.program_authority_salt = program_authority_salt;
+
+        let root = *pool.root();
+        let id = pool.id();
+        let parent_id = pool.parent_id();
+        let header = new_pool.header_mut();
+        header.kind = EntityKind::SimpleStakePool;
+        header.version = StakePool::VERSION;
+        header.id = id;
+        header.parent_id = parent_id;
+        header.root = root;
+
+        pool.header_mut().kind = EntityKind::None;
+        administrator_authority
+            .set_lamports(administrator_authority.lamports() + pool.account().lamports());
+        pool.account_mut().set_lamports(0);
 
         Ok(())
     }
 
-    #[cfg(feature = "onchain")]
-    #[inline(never)]
-    pub fn claim_reward<T>(input: &mut T) -> Result<(), Error>
+    /// Copy of the state of a pool that still uses the v1 layout.
+    pub fn v1_state(&self) -> Option<StakePoolStateV1> {
+        if self.header().version() != 1 || self.body().len() != size_of::<StakePoolStateV1>() {
+            return None;
+        }
+
+        // body is aligned and exactly the size of the v1 layout
+        Some(unsafe { std::ptr::read(self.body().as_ptr() as *const StakePoolStateV1) })
+    }
+
+    /// Rewrites the pool with the current layout, `resize` has to grow account data
+    /// to [`StakePool::default_size`] unless it is sized for the current layout already.
+    ///
+    /// Reward of a v1 pool is paid at expiration from the stake vault.
+    /// Header version is left for the caller to update.
+    fn upgrade_v1(&mut self, v1: StakePoolStateV1, resize: impl FnOnce(&mut B))
     where
-        B: AccountBackend<Impl = Account>,
-        T: AccountSource<B>,
+        B::Impl: AccountFieldsMut,
     {
-        let UnStakeArgsAccounts {
-            token_program,
-            pool,
-            mut staker,
-            mut ticket,
-            program_authority,
-            mut stake_vault,
-            mut target_wallet,
-        } = UnStakeArgsAccounts::from_program_input(input)?;
+        resize(self.account_mut());
+        assert!(StakePool::is_valid_size(self.body().len()));
 
-        if !pubkey_eq(&ticket.authority, staker.key()) {
-            qlog!("wrong staker provided");
-            return Err(Error::Validation);
+        for byte in self.body_mut() {
+            *byte = 0;
         }
 
-        if !staker.is_signer() {
-            qlog!("the staker is expected to sign");
-            return Err(Error::Validation);
+        self.administrator_authority = v1.administrator_authority;
+        self.program_authority = v1.program_authority;
+        self.stake_mint = v1.stake_mint;
+        self.stake_vault = v1.stake_vault;
+        self.reward_mint = v1.stake_mint;
+        self.reward_vault = v1.stake_vault;
+        self.program_authority_salt = v1.program_authority_salt;
+
+        self.stake_target_amount = v1.stake_target_amount;
+        self.stake_acquired_amount = v1.stake_acquired_amount;
+        self.reward_amount = v1.reward_amount;
+        self.deposited_reward_amount = v1.deposited_reward_amount;
+
+        self.allocator = v1.allocator;
+
+        self.genesis = v1.genesis;
+        self.lockup_duration = v1.lockup_duration;
+        self.topup_duration = v1.topup_duration;
+        self.reward_updated_at = v1.genesis;
+    }
+
+    /// Loads pool stored with either layout, pools that were not migrated yet are upgraded
+    /// in memory only, which requires `resize` to grow account data to [`StakePool::default_size`].
+    ///
+    /// Header version of such pools stays the same, so that they can be told apart.
+    pub fn load_versioned(
+        program_id: &Pubkey,
+        account: B,
+        resize: impl FnOnce(&mut B),
+    ) -> Result<Self, Error>
+    where
+        B::Impl: AccountFieldsMut,
+    {
+        let mut pool = Self::raw_versioned(program_id, account)?;
+
+        match pool.v1_state() {
+            Some(v1) => pool.upgrade_v1(v1, resize),
+            None => pool.check_layout()?,
         }
 
-        let now = timestamp_now();
+        Ok(pool)
+    }
 
-        if !pool.is_expired(now) {
-            qlog!("cannot claim pool reward yet");
-            return Err(Error::Validation);
+    /// Moves everything left in `vault` to `target_wallet` and closes the vault.
+    #[cfg(feature = "onchain")]
+    fn sweep_vault(
+        token_program: &TokenProgram<B>,
+        vault: &mut WalletAccount<B>,
+        target_wallet: &mut WalletAccount<B>,
+        rent_beneficiary: &mut B,
+        program_authority: &B,
+        seeds: &[&[u8]],
+    ) where
+        B: AccountBackend<Impl = Account>,
+    {
+        let remaining_amount = vault.amount();
+
+        if remaining_amount != 0.into() {
+            token_program
+                .transfer(
+                    vault,
+                    target_wallet,
+                    remaining_amount.value(),
+                    program_authority,
+                    &[seeds],
+                )
+                .bpf_expect("call failed")
+                .bpf_expect("transfer failed");
         }
 
-        let staked_amount = ticket.staked_amount.to_u64f64();
-        let stake_acquired_amount = pool.stake_acquired_amount.to_u64f64();
-        let reward_amount = pool.reward_amount.to_u64f64();
+        token_program
+            .close_account(vault, rent_beneficiary, program_authority, &[seeds])
+            .bpf_expect("call failed")
+            .bpf_expect("close failed");
+    }
+
+    pub fn load(program_id: &Pubkey, account: B) -> Result<Self, Error> {
+        Self::raw_initialized(program_id, account)
+    }
+}
 
-        let share = staked_amount / stake_acquired_amount;
-        let reward_share = share * reward_amount;
+/// Converts reward to token amount, dropping the fractional part in favour of the pool.
+#[cfg(feature = "onchain")]
+#[inline]
+fn round_down(amount: TokenAmountF64) -> TokenAmount {
+    amount
+        .value()
+        .floor()
+        .checked_as::<u64>()
+        .bpf_unwrap()
+        .into()
+}
 
-        let transfer_amount = (staked_amount + reward_share)
-            .checked_as::<TokenAmount>()
-            .bpf_unwrap();
+impl<B: AccountBackend> StakePosition<B> {
+    /// Stake held by the position, shares are worth a single staked token each.
+    #[inline]
+    pub fn staked_amount(&self) -> TokenAmount {
+        match self {
+            StakePosition::Ticket(ticket) => ticket.staked_amount,
+            StakePosition::Shares(shares) => shares.share_wallet.amount(),
+        }
+    }
 
-        let seeds = pool.authority_seeds();
-        let amount_before = stake_vault.amount();
+    #[inline]
+    pub fn ticket_mut(&mut self) -> Option<&mut Entity<B, StakerTicket>> {
+        match self {
+            StakePosition::Ticket(ticket) => Some(ticket),
+            StakePosition::Shares(_) => None,
+        }
+    }
+
+    #[cfg(feature = "onchain")]
+    pub fn check_staker(&self, staker: &B) -> Result<(), Error> {
+        match self {
+            StakePosition::Ticket(ticket) => ticket.check_staker(staker),
+            StakePosition::Shares(shares) => {
+                if !pubkey_eq(shares.share_wallet.authority(), staker.key()) {
+                    qlog!("wrong staker provided");
+                    return Err(Error::Validation);
+                }
+
+                if !staker.is_signer() {
+                    qlog!("the staker is expected to sign");
+                    return Err(Error::Validation);
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(feature = "onchain")]
+impl<B: AccountBackend<Impl = Account>> ShareAccounts<B> {
+    pub fn mint(
+        &mut self,
+        token_program: &TokenProgram<B>,
+        amount: TokenAmount,
+        program_authority: &B,
+        seeds: &[&[u8]],
+    ) {
+        let supply_before = self.share_mint.supply();
         token_program
-            .transfer(
-                &mut stake_vault,
-                &mut target_wallet,
-                transfer_amount.value(),
-                &program_authority,
-                &[&seeds],
+            .mint_to(
+                &mut self.share_mint,
+                &mut self.share_wallet,
+                amount.value(),
+                program_authority,
+                &[seeds],
             )
             .bpf_expect("call failed")
-            .bpf_expect("transfer failed");
-        let amount_after = stake_vault.amount();
+            .bpf_expect("mint failed");
+        let supply_after = self.share_mint.supply();
 
-        assert!(amount_before - amount_after == transfer_amount);
+        assert!(supply_after - supply_before == amount);
+    }
 
-        ticket.staked_amount = 0.into();
-        assert!(ticket.collect(&mut staker)?);
+    pub fn burn(&mut self, token_program: &TokenProgram<B>, amount: TokenAmount, staker: &B) {
+        let supply_before = self.share_mint.supply();
+        token_program
+            .burn(
+                &mut self.share_wallet,
+                &mut self.share_mint,
+                amount.value(),
+                staker,
+                &[],
+            )
+            .bpf_expect("call failed")
+            .bpf_expect("burn failed");
+        let supply_after = self.share_mint.supply();
 
-        Ok(())
+        assert!(supply_before - supply_after == amount);
     }
+}
 
+impl<B: AccountBackend> Entity<B, StakerTicket> {
+    /// Updates staked amount, moving reward accrued so far into `unclaimed_reward`.
     #[cfg(feature = "onchain")]
-    #[inline(never)]
-    pub fn add_reward<T>(input: &mut T, amount: TokenAmount) -> Result<(), Error>
+    pub fn set_staked_amount(&mut self, amount: TokenAmount, reward_per_share: TokenAmountF64)
     where
-        B: AccountBackend<Impl = Account>,
-        T: AccountSource<B>,
+        B::Impl: AccountFieldsMut,
     {
-        let AddRewardArgsAccounts {
-            token_program,
-            mut pool,
-            mut stake_vault,
-            source_authority,
-            mut source_wallet,
-        } = AddRewardArgsAccounts::from_program_input(input)?;
+        let accrued_reward = self.staked_amount.to_u64f64() * reward_per_share;
+        self.unclaimed_reward += accrued_reward - self.reward_debt;
 
-        let transfer_amount = amount
-            .min(pool.reward_amount - pool.deposited_reward_amount)
-            .min(source_wallet.amount());
+        self.staked_amount = amount;
+        self.reward_debt = amount.to_u64f64() * reward_per_share;
+    }
 
-        if transfer_amount == 0.into() {
-            qlog!("no reward to deposit");
+    #[cfg(feature = "onchain")]
+    pub fn check_staker(&self, staker: &B) -> Result<(), Error> {
+        if !pubkey_eq(&self.authority, staker.key()) {
+            qlog!("wrong staker provided");
             return Err(Error::Validation);
         }
 
-        let now = timestamp_now();
-
-        if pool.is_expired(now) {
-            qlog!("reward cannot be added to a pool that has expired");
+        if !staker.is_signer() {
+            qlog!("the staker is expected to sign");
             return Err(Error::Validation);
         }
 
-        let amount_before = stake_vault.amount();
-        token_program
-            .transfer(
-                &mut source_wallet,
-                &mut stake_vault,
-                transfer_amount.value(),
-                &source_authority,
-                &[],
-            )
-            .bpf_expect("call failed")
-            .bpf_expect("transfer failed");
-        let amount_after = stake_vault.amount();
-        assert!(amount_after - amount_before == transfer_amount);
+        Ok(())
+    }
 
-        pool.deposited_reward_amount += transfer_amount;
-        assert!(pool.deposited_reward_amount <= pool.reward_amount);
+    /// Whether the staker opted in to moving the stake into the successor pool.
+    #[inline]
+    pub fn rolls_over(&self) -> bool {
+        self.rollover != 0
+    }
 
-        Ok(())
+    /// Whether someone besides the staker may claim the reward.
+    #[inline]
+    pub fn has_delegate(&self) -> bool {
+        self.delegate != Pubkey::default()
     }
 
-    pub fn load(program_id: &Pubkey, account: B) -> Result<Self, Error> {
-        Self::raw_initialized(program_id, account)
+    /// Whether the ticket holds a reward of at least a single token.
+    #[inline]
+    pub fn has_unclaimed_reward(&self) -> bool {
+        self.unclaimed_reward.value() >= U64F64::from_num(1)
     }
-}
 
-impl<B: AccountBackend> Entity<B, StakerTicket> {
     pub fn collect(&mut self, beneficiary: &mut B) -> Result<bool, Error>
     where
         B: AccountFieldsMut,
     {
-        if self.staked_amount == 0.into() {
+        if self.staked_amount == 0.into() && !self.has_unclaimed_reward() {
             beneficiary.set_lamports(beneficiary.lamports() + self.account().lamports());
             self.account_mut().set_lamports(0);
             Ok(true)
@@ -0,0 +1,110 @@
+//! Structured program events, logged as base64-encoded SCALE values under [`EVENT_PREFIX`].
+
+use parity_scale_codec::Decode;
+
+use crate::{Pubkey, UiTransactionStatusMeta};
+
+/// Prefix of log messages carrying program events.
+pub const EVENT_PREFIX: &str = "event: ";
+
+const PROGRAM_LOG_PREFIX: &str = "Program log: ";
+
+/// Extracts events emitted by `program_id` from transaction logs.
+pub fn parse_events<E: Decode>(program_id: &Pubkey, meta: &UiTransactionStatusMeta) -> Vec<E> {
+    meta.log_messages
+        .as_deref()
+        .map(|logs| parse_log_messages(program_id, logs))
+        .unwrap_or_default()
+}
+
+/// Same as [`parse_events`], for raw log messages.
+///
+/// Messages logged by other programs, including the ones invoked by `program_id`,
+/// are skipped along with messages that can't be decoded as `E`.
+pub fn parse_log_messages<E: Decode>(program_id: &Pubkey, logs: &[String]) -> Vec<E> {
+    let program_id = program_id.to_string();
+
+    let mut invoke_stack = vec![];
+    let mut events = vec![];
+
+    for log in logs {
+        if let Some(message) = log.strip_prefix(PROGRAM_LOG_PREFIX) {
+            if invoke_stack.last() != Some(&program_id.as_str()) {
+                continue;
+            }
+
+            if let Some(event) = message.strip_prefix(EVENT_PREFIX).and_then(decode_event) {
+                events.push(event);
+            }
+        } else if let Some(message) = log.strip_prefix("Program ") {
+            let mut words = message.split(' ');
+
+            match (words.next(), words.next()) {
+                (Some(program), Some("invoke")) => invoke_stack.push(program),
+                (Some(_), Some("success")) | (Some(_), Some("failed:")) => {
+                    invoke_stack.pop();
+                }
+                _ => {}
+            }
+        }
+    }
+
+    events
+}
+
+fn decode_event<E: Decode>(data: &str) -> Option<E> {
+    let bytes = base64::decode(data).ok()?;
+    let mut input = bytes.as_slice();
+    let event = E::decode(&mut input).ok()?;
+
+    if input.is_empty() {
+        Some(event)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use parity_scale_codec::Encode;
+
+    use crate::Pubkey;
+
+    use super::{parse_log_messages, EVENT_PREFIX};
+
+    fn event_log(event: &(u8, u64)) -> String {
+        format!(
+            "Program log: {}{}",
+            EVENT_PREFIX,
+            base64::encode(event.encode())
+        )
+    }
+
+    #[test]
+    fn parse_program_events() {
+        let program = Pubkey::new([1; 32]);
+        let other = Pubkey::new([2; 32]);
+
+        let logs = vec![
+            format!("Program {} invoke [1]", program),
+            event_log(&(1, 100)),
+            format!("Program {} invoke [2]", other),
+            event_log(&(2, 200)),
+            format!("Program {} consumed 1000 of 200000 compute units", other),
+            format!("Program {} success", other),
+            "Program log: unrelated message".to_string(),
+            format!("Program log: {}not base64", EVENT_PREFIX),
+            event_log(&(3, 300)),
+            format!("Program {} success", program),
+            format!("Program {} invoke [1]", other),
+            event_log(&(4, 400)),
+            format!("Program {} failed: custom program error: 0x1", other),
+        ];
+
+        let events = parse_log_messages::<(u8, u64)>(&program, &logs);
+        assert_eq!(events, vec![(1, 100), (3, 300)]);
+
+        let events = parse_log_messages::<(u8, u64)>(&other, &logs);
+        assert_eq!(events, vec![(2, 200), (4, 400)]);
+    }
+}
@@ -41,6 +41,38 @@ mod hasher {
     }
 }
 
+/// Computes SHA-256 hash of concatenated `vals`, using the syscall when running on-chain.
+#[cfg(any(feature = "crypto", target_arch = "bpf"))]
+pub fn hashv(vals: &[&[u8]]) -> Hash {
+    #[cfg(not(target_arch = "bpf"))]
+    {
+        let mut hasher = Hasher::default();
+        hasher.hashv(vals);
+        hasher.result()
+    }
+
+    #[cfg(target_arch = "bpf")]
+    {
+        extern "C" {
+            fn sol_sha256(vals: *const u8, val_len: u64, hash_result: *mut u8) -> u64;
+        }
+        let mut hash_result = [0; HASH_BYTES];
+        unsafe {
+            sol_sha256(
+                vals as *const _ as *const u8,
+                vals.len() as u64,
+                &mut hash_result as *mut _ as *mut u8,
+            );
+        }
+        Hash::new_from_array(hash_result)
+    }
+}
+
+#[cfg(any(feature = "crypto", target_arch = "bpf"))]
+pub fn hash(val: &[u8]) -> Hash {
+    hashv(&[val])
+}
+
 impl AsRef<[u8]> for Hash {
     fn as_ref(&self) -> &[u8] {
         &self.0[..]
@@ -9,8 +9,9 @@ use serde::{Deserialize, Serialize};
 
 pub mod entrypoint;
 mod error;
+pub mod event;
 mod faucet;
-mod hash;
+pub mod hash;
 mod instruction;
 mod message;
 pub mod program;
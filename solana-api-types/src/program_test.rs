@@ -1,14 +1,122 @@
-use crate::{Account, Hash, Keypair, Pubkey, Transaction};
-use solana_program_test::BanksClient;
-use solana_sdk::process_instruction::ProcessInstructionWithContext;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, Once},
+    thread::{self, ThreadId},
+};
 
-use crate::sdk_proxy::ToSdk;
+use crate::{
+    sysvar::clock::Clock, Account, Hash, Keypair, Pubkey, Slot, Transaction, TransactionError,
+};
+use lazy_static::lazy_static;
+use log::Log;
+use solana_program_test::{BanksClient, ProgramTestContext};
+use solana_sdk::{
+    entrypoint::ProcessInstruction,
+    instruction::InstructionError,
+    process_instruction::{stable_log, InvokeContext, ProcessInstructionWithContext},
+    transport::TransportError,
+};
+
+use crate::sdk_proxy::{FromSdk, ToSdk};
+
+/// Target of the runtime logger that program logs are written to.
+const PROGRAM_LOG_TARGET: &str = "solana_runtime::message_processor";
+
+lazy_static! {
+    static ref PROGRAM_LOGS: Mutex<HashMap<ThreadId, Vec<String>>> = Mutex::new(HashMap::new());
+}
+
+/// Records program logs per bank thread and passes everything to `env_logger`.
+struct LogCapture {
+    inner: env_logger::Logger,
+}
+
+impl Log for LogCapture {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target() == PROGRAM_LOG_TARGET || self.inner.enabled(metadata)
+    }
+
+    fn log(&self, record: &log::Record) {
+        if record.target() == PROGRAM_LOG_TARGET && record.level() == log::Level::Debug {
+            PROGRAM_LOGS
+                .lock()
+                .unwrap()
+                .entry(thread::current().id())
+                .or_default()
+                .push(record.args().to_string());
+        }
+
+        if self.inner.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush()
+    }
+}
+
+/// Log messages of the transactions processed by every runtime of this process so far.
+///
+/// Messages of each runtime are kept together, so the result can be passed to
+/// [`crate::event::parse_log_messages`].
+pub fn program_logs() -> Vec<String> {
+    PROGRAM_LOGS
+        .lock()
+        .unwrap()
+        .values()
+        .flatten()
+        .cloned()
+        .collect()
+}
+
+/// Same as [`solana_program_test::builtin_process_instruction`], but logs the invocation
+/// result the way the BPF loader does, so that events can be attributed to the program.
+pub fn builtin_process_instruction(
+    process_instruction: ProcessInstruction,
+    program_id: &solana_sdk::pubkey::Pubkey,
+    input: &[u8],
+    invoke_context: &mut dyn InvokeContext,
+) -> Result<(), InstructionError> {
+    let logger = invoke_context.get_logger();
+    stable_log::program_invoke(&logger, program_id, invoke_context.invoke_depth());
+
+    let result = solana_program_test::builtin_process_instruction(
+        process_instruction,
+        program_id,
+        input,
+        invoke_context,
+    );
+
+    match &result {
+        Ok(()) => stable_log::program_success(&logger, program_id),
+        Err(err) => stable_log::program_failure(&logger, program_id, err),
+    }
+
+    result
+}
 
-#[derive(Default)]
 pub struct ProgramTest {
     inner: solana_program_test::ProgramTest,
 }
 
+impl Default for ProgramTest {
+    fn default() -> Self {
+        // Has to win over the logger installed by `solana_program_test::ProgramTest::default`.
+        static CAPTURE: Once = Once::new();
+        CAPTURE.call_once(|| {
+            let _ = log::set_boxed_logger(Box::new(LogCapture {
+                inner: env_logger::Logger::from_default_env(),
+            }));
+        });
+
+        let inner = solana_program_test::ProgramTest::default();
+        log::set_max_level(log::max_level().max(log::LevelFilter::Debug));
+
+        Self { inner }
+    }
+}
+
 impl ProgramTest {
     pub fn add_program(
         &mut self,
@@ -19,18 +127,49 @@ impl ProgramTest {
         self.inner.add_program(name, pk.to_sdk(), handler)
     }
 
+    /// Adds `account` to the genesis, at the address it was given.
+    pub fn add_account(&mut self, account: Account) {
+        self.inner
+            .add_account(account.pubkey.to_sdk(), account.to_sdk())
+    }
+
     pub async fn start(self) -> (Runtime, Keypair, Hash) {
         let (client, keypair, hash) = self.inner.start().await;
 
         let keypair = Keypair::from_bytes(&keypair.to_bytes()).unwrap();
         let hash = Hash(hash.0);
 
-        (Runtime { client }, keypair, hash)
+        (
+            Runtime {
+                client,
+                context: None,
+            },
+            keypair,
+            hash,
+        )
+    }
+
+    /// Same as [`ProgramTest::start`], but the returned runtime is able to warp to a later slot.
+    pub async fn start_with_context(self) -> (Runtime, Keypair, Hash) {
+        let context = self.inner.start_with_context().await;
+
+        let keypair = Keypair::from_bytes(&context.payer.to_bytes()).unwrap();
+        let hash = Hash(context.last_blockhash.0);
+
+        (
+            Runtime {
+                client: context.banks_client.clone(),
+                context: Some(context),
+            },
+            keypair,
+            hash,
+        )
     }
 }
 
 pub struct Runtime {
     client: BanksClient,
+    context: Option<ProgramTestContext>,
 }
 
 impl Runtime {
@@ -44,6 +183,19 @@ impl Runtime {
             .map_err(|err| err.into())
     }
 
+    /// Processes transaction, returning the error it was rejected with separately from
+    /// transport failures.
+    pub async fn try_process_transaction(
+        &mut self,
+        transaction: Transaction,
+    ) -> Result<Result<(), TransactionError>, anyhow::Error> {
+        match self.client.process_transaction(transaction.to_sdk()).await {
+            Ok(()) => Ok(Ok(())),
+            Err(TransportError::TransactionError(err)) => Ok(Err(TransactionError::from_sdk(&err))),
+            Err(err) => Err(err.into()),
+        }
+    }
+
     pub async fn get_account(&mut self, pk: &Pubkey) -> Result<Option<Account>, anyhow::Error> {
         self.client
             .get_account(pk.to_sdk())
@@ -60,4 +212,34 @@ impl Runtime {
             })
             .map_err(|err| err.into())
     }
+
+    pub async fn get_clock(&mut self) -> Result<Clock, anyhow::Error> {
+        self.client
+            .get_sysvar::<solana_program::sysvar::clock::Clock>()
+            .await
+            .map(|clock| Clock::from_sdk(&clock))
+            .map_err(|err| err.into())
+    }
+
+    pub async fn get_recent_blockhash(&mut self) -> Result<Hash, anyhow::Error> {
+        self.client
+            .get_recent_blockhash()
+            .await
+            .map(|hash| Hash(hash.0))
+            .map_err(|err| err.into())
+    }
+
+    /// Moves the bank forward to `slot`, advancing the clock along with it.
+    ///
+    /// Only available for runtimes created with [`ProgramTest::start_with_context`].
+    pub fn warp_to_slot(&mut self, slot: Slot) -> Result<(), anyhow::Error> {
+        let context = self
+            .context
+            .as_mut()
+            .ok_or_else(|| anyhow::anyhow!("runtime was started without context"))?;
+
+        context
+            .warp_to_slot(slot)
+            .map_err(|err| anyhow::anyhow!("could not warp to slot {}: {:?}", slot, err))
+    }
 }
@@ -15,7 +15,20 @@ pub const MAX_SEEDS: usize = 16;
 const PDA_MARKER: &[u8; 21] = b"ProgramDerivedAddress";
 
 #[repr(transparent)]
-#[derive(Serialize, Deserialize, Clone, Copy, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[derive(
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Default,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+    Hash,
+    parity_scale_codec::Encode,
+    parity_scale_codec::Decode,
+)]
 pub struct Pubkey([u8; 32]);
 
 impl Pubkey {
@@ -93,6 +106,31 @@ impl Pubkey {
         }
     }
 
+    /// Finds a valid program address along with its bump seed, which is appended to `seeds`.
+    ///
+    /// Bump seeds are tried starting from `u8::MAX`, so the result is deterministic.
+    #[cfg(any(feature = "extended", target_arch = "bpf"))]
+    pub fn find_program_address(seeds: &[&[u8]], program_id: &Pubkey) -> Option<(Pubkey, u8)> {
+        if seeds.len() >= MAX_SEEDS {
+            return None;
+        }
+
+        for bump in (0..=u8::MAX).rev() {
+            let bump_seed = [bump];
+            let mut seeds_with_bump: [&[u8]; MAX_SEEDS] = [&[]; MAX_SEEDS];
+            seeds_with_bump[..seeds.len()].copy_from_slice(seeds);
+            seeds_with_bump[seeds.len()] = &bump_seed;
+
+            if let Some(pk) =
+                Self::create_program_address(&seeds_with_bump[..=seeds.len()], program_id)
+            {
+                return Some((pk, bump));
+            }
+        }
+
+        None
+    }
+
     #[cfg(feature = "extended")]
     pub fn is_on_curve(&self) -> bool {
         curve25519_dalek::edwards::CompressedEdwardsY::from_slice(self.0.as_ref())
@@ -1,7 +1,8 @@
 use crate::{
     program::ProgramError,
     sysvar::{clock::Clock, rent::Rent},
-    AccountMeta, CompiledInstruction, Hash, Instruction, Message, Pubkey, Signature, Transaction,
+    Account, AccountMeta, CompiledInstruction, Hash, Instruction, Message, Pubkey, Signature,
+    Transaction, TransactionError,
 };
 
 pub trait ToSdk {
@@ -40,6 +41,20 @@ impl ToSdk for Hash {
     }
 }
 
+impl ToSdk for Account {
+    type Original = solana_sdk::account::Account;
+
+    fn to_sdk(&self) -> Self::Original {
+        solana_sdk::account::Account {
+            lamports: self.lamports,
+            data: self.data.clone(),
+            owner: self.owner.to_sdk(),
+            executable: self.executable,
+            rent_epoch: self.rent_epoch,
+        }
+    }
+}
+
 impl ToSdk for AccountMeta {
     type Original = solana_sdk::instruction::AccountMeta;
 
@@ -139,3 +154,13 @@ impl FromSdk for ProgramError {
         Self::from(u64::from(sdk.clone()))
     }
 }
+
+impl FromSdk for TransactionError {
+    type Original = solana_sdk::transaction::TransactionError;
+
+    fn from_sdk(sdk: &Self::Original) -> Self {
+        // both enums share the same layout, so the serialized form is interchangeable
+        let data = bincode::serialize(sdk).unwrap();
+        bincode::deserialize(&data).unwrap()
+    }
+}
@@ -21,6 +21,7 @@ pub mod math;
 pub mod mem;
 pub mod reinterpret;
 pub mod spl;
+pub mod system;
 pub mod time;
 pub mod util;
 
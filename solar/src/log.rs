@@ -10,8 +10,9 @@
 use std::mem::MaybeUninit;
 
 use itoap::write_to_ptr;
+use parity_scale_codec::Encode;
 
-use solana_api_types::program::ProgramError;
+use solana_api_types::{event::EVENT_PREFIX, program::ProgramError};
 
 use crate::mem::memcpy;
 
@@ -45,6 +46,40 @@ impl<const S: usize> Logger<S> {
         self.cursor += unsafe { write_to_ptr(self.buf.as_mut_ptr().add(self.cursor).cast(), i) };
     }
 
+    /// Pushes `bytes` encoded as padded standard base64.
+    pub fn push_base64(&mut self, bytes: &[u8]) {
+        const ALPHABET: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+        assert!(self.cursor + (bytes.len() + 2) / 3 * 4 <= S);
+
+        for chunk in bytes.chunks(3) {
+            let b0 = chunk[0] as usize;
+            let b1 = chunk.get(1).copied().unwrap_or(0) as usize;
+            let b2 = chunk.get(2).copied().unwrap_or(0) as usize;
+
+            let encoded = [
+                ALPHABET[b0 >> 2],
+                ALPHABET[((b0 & 0b11) << 4) | (b1 >> 4)],
+                if chunk.len() > 1 {
+                    ALPHABET[((b1 & 0b1111) << 2) | (b2 >> 6)]
+                } else {
+                    b'='
+                },
+                if chunk.len() > 2 {
+                    ALPHABET[b2 & 0b111111]
+                } else {
+                    b'='
+                },
+            ];
+
+            for byte in encoded.iter() {
+                self.buf[self.cursor] = MaybeUninit::new(*byte);
+                self.cursor += 1;
+            }
+        }
+    }
+
     pub fn log(&self) {
         if cfg!(target_arch = "bpf") {
             unsafe {
@@ -110,6 +145,17 @@ macro_rules! qlog {
     }
 }
 
+/// Logs SCALE-encoded `event` as base64 under [`EVENT_PREFIX`], so that it can be
+/// extracted from transaction logs with [`solana_api_types::event::parse_events`].
+pub fn emit<E: Encode>(event: &E) {
+    event.using_encoded(|bytes| {
+        let mut logger = Logger::<512>::default();
+        logger.push_str(EVENT_PREFIX);
+        logger.push_base64(bytes);
+        logger.log();
+    })
+}
+
 macro_rules! impl_loggable_int {
     ($($i:ty),+) => {
         $(
@@ -156,3 +202,26 @@ impl Loggable for ProgramError {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Logger;
+
+    fn encode(bytes: &[u8]) -> String {
+        let mut logger = Logger::<16>::default();
+        logger.push_base64(bytes);
+
+        let buf =
+            unsafe { std::slice::from_raw_parts(logger.buf.as_ptr().cast::<u8>(), logger.cursor) };
+        String::from_utf8(buf.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn push_base64() {
+        let bytes = [0x00, 0xff, 0x10, 0x7e, 0xa5, 0x3c];
+
+        for len in 0..=bytes.len() {
+            assert_eq!(encode(&bytes[..len]), base64::encode(&bytes[..len]));
+        }
+    }
+}
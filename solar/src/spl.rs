@@ -144,6 +144,25 @@ pub enum SplReadError {
     InvalidMint,
 }
 
+impl SplReadError {
+    pub fn code(&self) -> u32 {
+        match self {
+            SplReadError::InvalidData => 0,
+            SplReadError::InvalidOwner => 1,
+            SplReadError::InvalidMint => 2,
+        }
+    }
+
+    pub fn from_code(code: u32) -> Option<Self> {
+        match code {
+            0 => Some(SplReadError::InvalidData),
+            1 => Some(SplReadError::InvalidOwner),
+            2 => Some(SplReadError::InvalidMint),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MintAccount<B: AccountBackend> {
     account: B,
@@ -286,6 +305,106 @@ impl<T: AccountBackend> TokenProgram<T> {
             seeds,
         ))
     }
+
+    #[inline(never)]
+    pub fn mint_to(
+        &self,
+        mint: &mut MintAccount<T>,
+        to: &mut WalletAccount<T>,
+        amount: u64,
+        authority: &T,
+        seeds: &[&[&[u8]]],
+    ) -> Result<Result<(), TokenError>, ProgramError>
+    where
+        T: AccountBackend<Impl = crate::account::onchain::Account>,
+    {
+        let mut invoker = crate::invoke::Invoker::<4>::new();
+        invoker.push(mint);
+        invoker.push(to);
+        invoker.push_signed(authority);
+
+        Self::handle_result(invoker.invoke_signed(
+            self.backend(),
+            &TokenInstruction::MintTo { amount }.pack_static_vec(),
+            seeds,
+        ))
+    }
+
+    #[inline(never)]
+    pub fn burn(
+        &self,
+        from: &mut WalletAccount<T>,
+        mint: &mut MintAccount<T>,
+        amount: u64,
+        authority: &T,
+        seeds: &[&[&[u8]]],
+    ) -> Result<Result<(), TokenError>, ProgramError>
+    where
+        T: AccountBackend<Impl = crate::account::onchain::Account>,
+    {
+        let mut invoker = crate::invoke::Invoker::<4>::new();
+        invoker.push(from);
+        invoker.push(mint);
+        invoker.push_signed(authority);
+
+        Self::handle_result(invoker.invoke_signed(
+            self.backend(),
+            &TokenInstruction::Burn { amount }.pack_static_vec(),
+            seeds,
+        ))
+    }
+
+    #[inline(never)]
+    pub fn set_authority(
+        &self,
+        account: &mut WalletAccount<T>,
+        authority_type: AuthorityType,
+        new_authority: Option<&Pubkey>,
+        authority: &T,
+        seeds: &[&[&[u8]]],
+    ) -> Result<Result<(), TokenError>, ProgramError>
+    where
+        T: AccountBackend<Impl = crate::account::onchain::Account>,
+    {
+        let mut invoker = crate::invoke::Invoker::<3>::new();
+        invoker.push(account);
+        invoker.push_signed(authority);
+
+        Self::handle_result(
+            invoker.invoke_signed(
+                self.backend(),
+                &TokenInstruction::SetAuthority {
+                    authority_type,
+                    new_authority: new_authority.copied(),
+                }
+                .pack_static_vec(),
+                seeds,
+            ),
+        )
+    }
+
+    #[inline(never)]
+    pub fn close_account(
+        &self,
+        account: &mut WalletAccount<T>,
+        destination: &mut T,
+        authority: &T,
+        seeds: &[&[&[u8]]],
+    ) -> Result<Result<(), TokenError>, ProgramError>
+    where
+        T: AccountBackend<Impl = crate::account::onchain::Account>,
+    {
+        let mut invoker = crate::invoke::Invoker::<4>::new();
+        invoker.push(account);
+        invoker.push(destination);
+        invoker.push_signed(authority);
+
+        Self::handle_result(invoker.invoke_signed(
+            self.backend(),
+            &TokenInstruction::CloseAccount.pack_static_vec(),
+            seeds,
+        ))
+    }
 }
 
 forward_account_backend!(TokenProgram, account);
@@ -387,6 +506,20 @@ impl TokenError {
             Self::Unknown
         }
     }
+
+    pub fn code(&self) -> u32 {
+        *self as u32
+    }
+
+    /// Inverse of [`TokenError::code`], unlike [`TokenError::from`] it rejects codes
+    /// that no variant is encoded with.
+    pub fn from_code(code: u32) -> Option<Self> {
+        if code <= Self::Unknown.code() {
+            Some(Self::from(code))
+        } else {
+            None
+        }
+    }
 }
 
 fn write_pubkey<W: Write>(mut writer: W, pubkey: &Pubkey) -> std::io::Result<()> {
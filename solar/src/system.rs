@@ -0,0 +1,124 @@
+use solana_api_types::system::ID;
+#[cfg(feature = "onchain")]
+use solana_api_types::{program::ProgramResult, Pubkey};
+
+#[cfg(feature = "onchain")]
+use crate::account::onchain::AsAccount;
+use crate::{
+    account::{AccountBackend, AccountFields},
+    forward_account_backend,
+    util::pubkey_eq,
+};
+
+#[derive(Debug)]
+pub struct SystemProgram<B> {
+    account: B,
+}
+
+impl<B: AccountBackend> SystemProgram<B> {
+    pub fn load(account: B) -> Option<Self> {
+        if !pubkey_eq(account.key(), ID) {
+            None
+        } else {
+            Some(Self { account })
+        }
+    }
+
+    pub fn account(&self) -> &B {
+        &self.account
+    }
+}
+
+#[cfg(feature = "onchain")]
+impl<T: AccountBackend> SystemProgram<T> {
+    /// Creates `account` owned by `owner`, funded by `payer`.
+    ///
+    /// Program derived accounts are created by passing their seeds, data length of
+    /// `account` is updated to `space` afterwards.
+    #[inline(never)]
+    pub fn create_account(
+        &self,
+        payer: &mut T,
+        account: &mut T,
+        lamports: u64,
+        space: u64,
+        owner: &Pubkey,
+        seeds: &[&[&[u8]]],
+    ) -> ProgramResult
+    where
+        T: AccountBackend<Impl = crate::account::onchain::Account>,
+    {
+        let mut data = [0; 52];
+        data[4..12].copy_from_slice(&lamports.to_le_bytes());
+        data[12..20].copy_from_slice(&space.to_le_bytes());
+        data[20..52].copy_from_slice(owner.as_ref());
+
+        {
+            let mut invoker = crate::invoke::Invoker::<3>::new();
+            invoker.push_signed(&mut *payer);
+            invoker.push_signed(&mut *account);
+            invoker.invoke_signed(self.backend(), &data, seeds)?;
+        }
+
+        account.as_account_mut().data_len = space as usize;
+
+        Ok(())
+    }
+
+    /// Allocates `space` bytes of data for `account`, which has to be owned by the System
+    /// program and hold no data yet.
+    ///
+    /// Data length of `account` is updated to `space` afterwards.
+    #[inline(never)]
+    pub fn allocate(&self, account: &mut T, space: u64, seeds: &[&[&[u8]]]) -> ProgramResult
+    where
+        T: AccountBackend<Impl = crate::account::onchain::Account>,
+    {
+        let mut data = [0; 12];
+        data[0..4].copy_from_slice(&8u32.to_le_bytes());
+        data[4..12].copy_from_slice(&space.to_le_bytes());
+
+        {
+            let mut invoker = crate::invoke::Invoker::<2>::new();
+            invoker.push_signed(&mut *account);
+            invoker.invoke_signed(self.backend(), &data, seeds)?;
+        }
+
+        account.as_account_mut().data_len = space as usize;
+
+        Ok(())
+    }
+
+    /// Assigns `account`, which has to be owned by the System program, to `owner`.
+    #[inline(never)]
+    pub fn assign(&self, account: &mut T, owner: &Pubkey, seeds: &[&[&[u8]]]) -> ProgramResult
+    where
+        T: AccountBackend<Impl = crate::account::onchain::Account>,
+    {
+        let mut data = [0; 36];
+        data[0..4].copy_from_slice(&1u32.to_le_bytes());
+        data[4..36].copy_from_slice(owner.as_ref());
+
+        let mut invoker = crate::invoke::Invoker::<2>::new();
+        invoker.push_signed(&mut *account);
+        invoker.invoke_signed(self.backend(), &data, seeds)
+    }
+
+    /// Transfers `lamports` from `from`, which has to be owned by the System program.
+    #[inline(never)]
+    pub fn transfer(&self, from: &mut T, to: &mut T, lamports: u64) -> ProgramResult
+    where
+        T: AccountBackend<Impl = crate::account::onchain::Account>,
+    {
+        let mut data = [0; 12];
+        data[0..4].copy_from_slice(&2u32.to_le_bytes());
+        data[4..12].copy_from_slice(&lamports.to_le_bytes());
+
+        let mut invoker = crate::invoke::Invoker::<3>::new();
+        invoker.push_signed(&mut *from);
+        invoker.push(&mut *to);
+        invoker.invoke_signed(self.backend(), &data, &[])
+    }
+}
+
+forward_account_backend!(SystemProgram, account);
@@ -20,7 +20,10 @@ use solana_api_types::{
     RpcSimulateTransactionResult, Signature, SignatureInfo, Signer, Slot, Transaction,
     TransactionStatus, UiAccount,
 };
-use x5margin_program::data::EntityKind;
+use x5margin_program::{
+    data::{AccountType, EntityKind},
+    simple_stake::StakePool,
+};
 
 pub trait ResultExt<T> {
     fn into_js_value(self) -> Result<T, JsValue>;
@@ -385,6 +388,15 @@ where
     future_to_promise(fut.map_err(|err| err.into()))
 }
 
+/// Same as [`return_promise`], but resolves to an exported object instead of its serialized form,
+/// so that JS can call its methods.
+fn return_object_promise<T>(fut: impl Future<Output = Result<T, ClientError>> + 'static) -> Promise
+where
+    T: Into<JsValue>,
+{
+    future_to_promise(fut.map_ok(Into::into).map_err(|err| err.into()))
+}
+
 #[wasm_bindgen]
 #[derive(Clone)]
 pub struct ApiClient {
@@ -615,9 +627,8 @@ impl RawPoolClient {
             .get_program_accounts(
                 program,
                 Some(RpcProgramAccountsConfig {
+                    // pools of both layout versions are loaded, so data size is not filtered
                     filters: Some(vec![
-                        // header size up to kind
-                        RpcFilterType::DataSize(49),
                         // skip all the parts that are not kind
                         RpcFilterType::Memcmp(Memcmp {
                             offset: 48,
@@ -779,6 +790,75 @@ impl PoolClient {
     }
 }
 
+#[derive(Clone)]
+pub struct RawLockerClient {
+    inner: RawApiClient,
+}
+
+impl RawLockerClient {
+    async fn load_locker(
+        &self,
+        program: Pubkey,
+        locker: Pubkey,
+    ) -> Result<LockerEntity, ClientError> {
+        let locker = self.inner.get_account_info(locker, None).await?;
+        let locker = LockerEntity::load(&program, Box::new(locker))
+            .map_err(|err| ClientError::from(ClientErrorKind::Custom(format!("{:?}", err))))?;
+
+        Ok(locker)
+    }
+
+    async fn load_vesting(
+        &self,
+        program: Pubkey,
+        vesting: Pubkey,
+    ) -> Result<VestingEntity, ClientError> {
+        let vesting = self.inner.get_account_info(vesting, None).await?;
+        let vesting = VestingEntity::load(&program, Box::new(vesting))
+            .map_err(|err| ClientError::from(ClientErrorKind::Custom(format!("{:?}", err))))?;
+
+        Ok(vesting)
+    }
+}
+
+#[wasm_bindgen]
+pub struct LockerClient {
+    inner: RawLockerClient,
+    program: Pk,
+}
+
+#[wasm_bindgen]
+impl LockerClient {
+    pub fn new(client: ApiClient, program: Pk) -> Self {
+        Self {
+            inner: RawLockerClient {
+                inner: client.inner,
+            },
+            program,
+        }
+    }
+
+    /// Resolves to a `LockerEntity`.
+    pub fn load_locker(&self, locker: Pk) -> Promise {
+        let client = self.inner.clone();
+        let program = self.program.to_pubkey();
+
+        let fut = async move { client.load_locker(program, locker.to_pubkey()).await };
+
+        return_object_promise(fut)
+    }
+
+    /// Resolves to a `VestingEntity`.
+    pub fn load_vesting(&self, vesting: Pk) -> Promise {
+        let client = self.inner.clone();
+        let program = self.program.to_pubkey();
+
+        let fut = async move { client.load_vesting(program, vesting.to_pubkey()).await };
+
+        return_object_promise(fut)
+    }
+}
+
 #[wasm_bindgen]
 pub struct StakePoolEntity {
     entity: x5margin_program::simple_stake::StakePoolEntity<Box<Account>>,
@@ -795,11 +875,16 @@ impl Serialize for StakePoolEntity {
 }
 
 impl StakePoolEntity {
+    /// Loads pool stored with either layout, see [`StakePoolEntity::needs_migration`].
     pub fn load(
         program: &Pubkey,
         pool: Box<Account>,
     ) -> Result<Self, x5margin_program::error::Error> {
-        let stake_pool = x5margin_program::simple_stake::StakePoolEntity::load(program, pool)?;
+        let stake_pool = x5margin_program::simple_stake::StakePoolEntity::load_versioned(
+            program,
+            pool,
+            |pool| pool.data.resize(StakePool::default_size(), 0),
+        )?;
 
         Ok(Self {
             entity: stake_pool,
@@ -815,36 +900,158 @@ impl StakePoolEntity {
             .load_ticket(ticket)
             .map(|entity| StakerTicketEntity { entity })
     }
-}
 
-#[wasm_bindgen]
-impl StakePoolEntity {
-    pub fn stake(
+    fn ticket_pubkey(&self, staker: &Pubkey) -> Pubkey {
+        let pool = self.entity.account().key();
+        let (ticket, _) = Pubkey::find_program_address(
+            &x5margin_program::simple_stake::StakerTicket::seeds(pool, staker),
+            &self.program,
+        )
+        .expect("no valid ticket address");
+
+        ticket
+    }
+
+    fn stake_instruction(
         &self,
         amount: u64,
         staker_key: Pk,
-        staker_ticket_key: Pk,
         aux_wallet_key: Pk,
-    ) -> Instr {
+        allowlist_proof: Option<x5margin_program::simple_stake::AllowlistProof>,
+        share_wallet_key: Option<Pk>,
+    ) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new_readonly(*solar::spl::ID, false),
+            AccountMeta::new_readonly(*solana_api_types::system::ID, false),
+            AccountMeta::new(*self.entity.account().key(), false),
+            AccountMeta::new_readonly(staker_key.to_pubkey(), false),
+        ];
+
+        // liquid pools mint shares in place of the ticket
+        match share_wallet_key {
+            Some(share_wallet_key) => {
+                accounts.push(AccountMeta::new(self.entity.share_mint, false));
+                accounts.push(AccountMeta::new(share_wallet_key.to_pubkey(), false));
+                accounts.push(AccountMeta::new_readonly(
+                    self.entity.program_authority,
+                    false,
+                ));
+            }
+            None => {
+                accounts.push(AccountMeta::new(
+                    self.ticket_pubkey(staker_key.as_ref()),
+                    false,
+                ));
+            }
+        }
+
+        accounts.extend_from_slice(&[
+            AccountMeta::new(self.entity.stake_vault, false),
+            AccountMeta::new(self.entity.administrator_authority, true),
+            AccountMeta::new(aux_wallet_key.to_pubkey(), false),
+        ]);
+
+        Instruction {
+            program_id: self.program,
+            accounts,
+            data: x5margin_program::Method::Simple(x5margin_program::simple_stake::Method::Stake {
+                amount: amount.into(),
+                allowlist_proof,
+            })
+            .encode(),
+        }
+    }
+
+    fn close_pool_instruction(&self, target_wallet: Pk) -> Instruction {
         Instruction {
             program_id: self.program,
             accounts: vec![
                 AccountMeta::new_readonly(*solar::spl::ID, false),
                 AccountMeta::new(*self.entity.account().key(), false),
-                AccountMeta::new_readonly(staker_key.to_pubkey(), false),
-                AccountMeta::new(staker_ticket_key.to_pubkey(), false),
+                AccountMeta::new(self.entity.administrator_authority, true),
+                AccountMeta::new_readonly(self.entity.program_authority, false),
                 AccountMeta::new(self.entity.stake_vault, false),
-                AccountMeta::new_readonly(self.entity.administrator_authority, true),
-                AccountMeta::new(aux_wallet_key.to_pubkey(), false),
+                AccountMeta::new(target_wallet.to_pubkey(), false),
             ],
-            data: x5margin_program::Method::Simple(x5margin_program::simple_stake::Method::Stake {
-                amount: amount.into(),
-            })
+            data: x5margin_program::Method::Simple(
+                x5margin_program::simple_stake::Method::ClosePool,
+            )
             .encode(),
         }
+    }
+}
+
+#[wasm_bindgen]
+impl StakePoolEntity {
+    /// Address of the staker ticket, it is created on the first stake.
+    /// Tickets transferred to the staker keep the address of their previous staker.
+    pub fn ticket_address(&self, staker: Pk) -> Pk {
+        Pk(self.ticket_pubkey(staker.as_ref()))
+    }
+
+    pub fn stake(&self, amount: u64, staker_key: Pk, aux_wallet_key: Pk) -> Instr {
+        self.stake_instruction(amount, staker_key, aux_wallet_key, None, None)
+            .into()
+    }
+
+    /// Same as `stake`, for liquid pools minting shares into `share_wallet_key`.
+    pub fn stake_liquid(
+        &self,
+        amount: u64,
+        staker_key: Pk,
+        aux_wallet_key: Pk,
+        share_wallet_key: Pk,
+    ) -> Instr {
+        self.stake_instruction(
+            amount,
+            staker_key,
+            aux_wallet_key,
+            None,
+            Some(share_wallet_key),
+        )
         .into()
     }
 
+    /// Same as `stake`, for pools with an allowlist.
+    ///
+    /// `proof` is a concatenation of 32-byte sibling hashes from the leaf up to the root.
+    pub fn stake_allowlisted(
+        &self,
+        amount: u64,
+        staker_key: Pk,
+        aux_wallet_key: Pk,
+        max_allocation: u64,
+        proof: Vec<u8>,
+    ) -> Result<Instr, JsValue> {
+        if proof.len() % 32 != 0 {
+            return Err(JsValue::from_str("proof length should be a multiple of 32"));
+        }
+
+        let proof = proof
+            .chunks(32)
+            .map(|node| {
+                let mut buf = [0; 32];
+                buf.copy_from_slice(node);
+                buf
+            })
+            .collect();
+
+        let allowlist_proof = x5margin_program::simple_stake::AllowlistProof {
+            max_allocation: max_allocation.into(),
+            proof,
+        };
+
+        Ok(self
+            .stake_instruction(
+                amount,
+                staker_key,
+                aux_wallet_key,
+                Some(allowlist_proof),
+                None,
+            )
+            .into())
+    }
+
     pub fn unstake(&self, amount: u64) -> Instr {
         Instruction {
             program_id: self.program,
@@ -884,6 +1091,288 @@ impl StakePoolEntity {
         .into()
     }
 
+    pub fn close_pool(&self, target_wallet: Pk) -> Instr {
+        self.close_pool_instruction(target_wallet).into()
+    }
+
+    /// Merges the ticket of `staker` into the one of `new_staker`, both stakers have to sign.
+    pub fn transfer_ticket(&self, staker: Pk, new_staker: Pk, payer: Pk) -> Instr {
+        Instruction {
+            program_id: self.program,
+            accounts: vec![
+                AccountMeta::new_readonly(*solana_api_types::system::ID, false),
+                AccountMeta::new(*self.entity.account().key(), false),
+                AccountMeta::new(self.ticket_pubkey(staker.as_ref()), false),
+                AccountMeta::new(staker.to_pubkey(), true),
+                AccountMeta::new_readonly(new_staker.to_pubkey(), true),
+                AccountMeta::new(self.ticket_pubkey(new_staker.as_ref()), false),
+                AccountMeta::new(payer.to_pubkey(), true),
+            ],
+            data: x5margin_program::Method::Simple(
+                x5margin_program::simple_stake::Method::TransferTicket {
+                    allowlist_proof: None,
+                },
+            )
+            .encode(),
+        }
+        .into()
+    }
+
+    /// Allows `delegate` to claim the reward into wallets of the staker,
+    /// passing nothing removes the current delegate.
+    pub fn set_ticket_delegate(&self, ticket: Pk, staker: Pk, delegate: Option<Pk>) -> Instr {
+        Instruction {
+            program_id: self.program,
+            accounts: vec![
+                AccountMeta::new_readonly(*self.entity.account().key(), false),
+                AccountMeta::new(ticket.to_pubkey(), false),
+                AccountMeta::new_readonly(staker.to_pubkey(), true),
+            ],
+            data: x5margin_program::Method::Simple(
+                x5margin_program::simple_stake::Method::SetTicketDelegate {
+                    delegate: delegate.map(|delegate| delegate.to_pubkey()),
+                },
+            )
+            .encode(),
+        }
+        .into()
+    }
+
+    /// Opens the successor of the expired pool at `successor_key`, which has to be allocated
+    /// already, with `stake_vault_key` owned by the program authority of the successor.
+    pub fn rollover_pool(&self, successor_key: Pk, stake_vault_key: Pk) -> Instr {
+        let authority = ProgramAuthority::new(successor_key, Pk(self.program));
+        Instruction {
+            program_id: self.program,
+            accounts: vec![
+                AccountMeta::new(*self.entity.account().key(), false),
+                AccountMeta::new_readonly(self.entity.administrator_authority, true),
+                AccountMeta::new(successor_key.to_pubkey(), false),
+                AccountMeta::new_readonly(authority.pk.to_pubkey(), false),
+                AccountMeta::new_readonly(stake_vault_key.to_pubkey(), false),
+            ],
+            data: x5margin_program::Method::Simple(
+                x5margin_program::simple_stake::Method::RolloverPool {
+                    program_authority_salt: authority.salt,
+                },
+            )
+            .encode(),
+        }
+        .into()
+    }
+
+    /// Opts the ticket in or out of moving its stake into the successor pool at expiration.
+    pub fn set_ticket_rollover(&self, ticket: Pk, staker: Pk, rollover: bool) -> Instr {
+        Instruction {
+            program_id: self.program,
+            accounts: vec![
+                AccountMeta::new_readonly(*self.entity.account().key(), false),
+                AccountMeta::new(ticket.to_pubkey(), false),
+                AccountMeta::new_readonly(staker.to_pubkey(), true),
+            ],
+            data: x5margin_program::Method::Simple(
+                x5margin_program::simple_stake::Method::SetTicketRollover { rollover },
+            )
+            .encode(),
+        }
+        .into()
+    }
+
+    /// Moves stake of the opted in ticket into `successor`, can be sent by anyone.
+    pub fn rollover_ticket(
+        &self,
+        ticket: Pk,
+        staker: Pk,
+        successor: &StakePoolEntity,
+        payer: Pk,
+    ) -> Instr {
+        let mut accounts = vec![
+            AccountMeta::new_readonly(*solar::spl::ID, false),
+            AccountMeta::new_readonly(*solana_api_types::system::ID, false),
+            AccountMeta::new(*self.entity.account().key(), false),
+            AccountMeta::new(ticket.to_pubkey(), false),
+            AccountMeta::new(staker.to_pubkey(), false),
+            AccountMeta::new_readonly(self.entity.program_authority, false),
+            AccountMeta::new(self.entity.stake_vault, false),
+            AccountMeta::new(*successor.entity.account().key(), false),
+            AccountMeta::new(successor.ticket_pubkey(staker.as_ref()), false),
+            AccountMeta::new(successor.entity.stake_vault, false),
+            AccountMeta::new(payer.to_pubkey(), true),
+        ];
+
+        if self.entity.has_fee() {
+            accounts.push(AccountMeta::new(self.entity.fee_wallet, false));
+        }
+
+        Instruction {
+            program_id: self.program,
+            accounts,
+            data: x5margin_program::Method::Simple(
+                x5margin_program::simple_stake::Method::RolloverTicket {
+                    allowlist_proof: None,
+                },
+            )
+            .encode(),
+        }
+        .into()
+    }
+
+    pub fn successor(&self) -> Option<Pk> {
+        if self.entity.has_successor() {
+            Some(Pk(self.entity.successor))
+        } else {
+            None
+        }
+    }
+
+    /// Layout version the pool is stored with.
+    pub fn version(&self) -> u8 {
+        self.entity.header().version()
+    }
+
+    /// Whether the pool has to be migrated before the program accepts it.
+    pub fn needs_migration(&self) -> bool {
+        self.version() < StakePool::VERSION
+    }
+
+    /// Moves the pool into `new_pool_key` with the current layout, handing the stake vault
+    /// over to the program authority of the new pool. The account has to be created
+    /// beforehand, the same way as for a new pool.
+    ///
+    /// The pool address changes: once migrated, the pool has to be loaded from
+    /// `new_pool_key`, and the v1 address no longer exists. Existing tickets keep working
+    /// with the new pool, new ones are derived from `new_pool_key`.
+    pub fn migrate(&self, new_pool_key: Pk) -> Instr {
+        let authority = ProgramAuthority::new(new_pool_key, Pk(self.program));
+        Instruction {
+            program_id: self.program,
+            accounts: vec![
+                AccountMeta::new_readonly(*solar::spl::ID, false),
+                AccountMeta::new(*self.entity.account().key(), false),
+                AccountMeta::new(new_pool_key.to_pubkey(), false),
+                AccountMeta::new(self.entity.administrator_authority, true),
+                AccountMeta::new_readonly(self.entity.program_authority, false),
+                AccountMeta::new_readonly(authority.pk.to_pubkey(), false),
+                AccountMeta::new(self.entity.stake_vault, false),
+            ],
+            data: x5margin_program::Method::Simple(
+                x5margin_program::simple_stake::Method::Migrate {
+                    program_authority_salt: authority.salt,
+                },
+            )
+            .encode(),
+        }
+        .into()
+    }
+
+    /// Same as `close_pool`, for pools paying rewards from a separate vault.
+    pub fn close_pool_with_reward(&self, target_wallet: Pk, reward_target_wallet: Pk) -> Instr {
+        let mut instr = self.close_pool_instruction(target_wallet);
+        instr
+            .accounts
+            .push(AccountMeta::new(self.entity.reward_vault, false));
+        instr
+            .accounts
+            .push(AccountMeta::new(reward_target_wallet.to_pubkey(), false));
+        instr.into()
+    }
+
+    pub fn update_pool(
+        &self,
+        topup_duration: Option<i64>,
+        stake_target_amount: Option<u64>,
+        reward_amount: Option<u64>,
+    ) -> Instr {
+        Instruction {
+            program_id: self.program,
+            accounts: vec![
+                AccountMeta::new(*self.entity.account().key(), false),
+                AccountMeta::new_readonly(self.entity.administrator_authority, true),
+            ],
+            data: x5margin_program::Method::Simple(
+                x5margin_program::simple_stake::Method::UpdatePool(
+                    x5margin_program::simple_stake::UpdatePoolArgs {
+                        topup_duration: topup_duration.map(Into::into),
+                        stake_target_amount: stake_target_amount.map(Into::into),
+                        reward_amount: reward_amount.map(Into::into),
+                    },
+                ),
+            )
+            .encode(),
+        }
+        .into()
+    }
+
+    pub fn propose_admin(&self, administrator: Pk) -> Instr {
+        Instruction {
+            program_id: self.program,
+            accounts: vec![
+                AccountMeta::new(*self.entity.account().key(), false),
+                AccountMeta::new_readonly(self.entity.administrator_authority, true),
+            ],
+            data: x5margin_program::Method::Simple(
+                x5margin_program::simple_stake::Method::ProposeAdmin {
+                    administrator: administrator.to_pubkey(),
+                },
+            )
+            .encode(),
+        }
+        .into()
+    }
+
+    /// Sends the protocol fee of further claims to `fee_wallet` in reward mint.
+    pub fn set_fee_wallet(&self, fee_wallet: Pk) -> Instr {
+        Instruction {
+            program_id: self.program,
+            accounts: vec![
+                AccountMeta::new(*self.entity.account().key(), false),
+                AccountMeta::new_readonly(self.entity.administrator_authority, true),
+                AccountMeta::new_readonly(fee_wallet.to_pubkey(), false),
+            ],
+            data: x5margin_program::Method::Simple(
+                x5margin_program::simple_stake::Method::SetFeeWallet,
+            )
+            .encode(),
+        }
+        .into()
+    }
+
+    pub fn accept_admin(&self) -> Instr {
+        Instruction {
+            program_id: self.program,
+            accounts: vec![
+                AccountMeta::new(*self.entity.account().key(), false),
+                AccountMeta::new_readonly(self.entity.pending_administrator_authority, true),
+            ],
+            data: x5margin_program::Method::Simple(
+                x5margin_program::simple_stake::Method::AcceptAdmin,
+            )
+            .encode(),
+        }
+        .into()
+    }
+
+    pub fn stake_mint(&self) -> Pk {
+        Pk(self.entity.stake_mint)
+    }
+
+    pub fn reward_mint(&self) -> Pk {
+        Pk(self.entity.reward_mint)
+    }
+
+    pub fn has_reward_vault(&self) -> bool {
+        self.entity.has_reward_vault()
+    }
+
+    /// Whether stakers get pool share tokens instead of tickets.
+    pub fn is_liquid(&self) -> bool {
+        self.entity.is_liquid()
+    }
+
+    pub fn share_mint(&self) -> Pk {
+        Pk(self.entity.share_mint)
+    }
+
     pub fn max_pool_size(&self) -> u64 {
         self.entity.stake_target_amount.value()
     }
@@ -897,7 +1386,35 @@ impl StakePoolEntity {
     }
 
     pub fn rewards_remaining(&self) -> u64 {
-        self.entity.deposited_reward_amount.value()
+        self.entity.available_reward().value()
+    }
+
+    pub fn is_emitting(&self) -> bool {
+        self.entity.is_emitting()
+    }
+
+    pub fn early_exit_penalty_bps(&self) -> u16 {
+        self.entity.early_exit_penalty_bps
+    }
+
+    pub fn min_stake(&self) -> u64 {
+        self.entity.min_stake.value()
+    }
+
+    pub fn max_stake_per_ticket(&self) -> u64 {
+        self.entity.max_stake_per_ticket.value()
+    }
+
+    pub fn has_allowlist(&self) -> bool {
+        self.entity.has_allowlist()
+    }
+
+    pub fn fee_bps(&self) -> u16 {
+        self.entity.fee_bps
+    }
+
+    pub fn paid_fee_amount(&self) -> u64 {
+        self.entity.paid_fee_amount.value()
     }
 
     pub fn start_date(&self) -> i64 {
@@ -923,6 +1440,114 @@ impl Serialize for StakerTicketEntity {
     }
 }
 
+#[wasm_bindgen]
+impl StakerTicketEntity {
+    pub fn authority(&self) -> Pk {
+        Pk(self.entity.authority)
+    }
+
+    pub fn staked_amount(&self) -> u64 {
+        self.entity.staked_amount.value()
+    }
+
+    pub fn delegate(&self) -> Option<Pk> {
+        if self.entity.has_delegate() {
+            Some(Pk(self.entity.delegate))
+        } else {
+            None
+        }
+    }
+
+    /// Whether the stake is moved into the successor pool at expiration.
+    pub fn rollover(&self) -> bool {
+        self.entity.rolls_over()
+    }
+}
+
+#[wasm_bindgen]
+pub struct VestingEntity {
+    entity: token_locker::vesting::VestingEntity<Box<Account>>,
+}
+
+impl Serialize for VestingEntity {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.entity.account().serialize(serializer)
+    }
+}
+
+impl VestingEntity {
+    pub fn load(
+        program: &Pubkey,
+        vesting: Box<Account>,
+    ) -> Result<Self, token_locker::error::Error> {
+        let entity = token_locker::vesting::VestingEntity::load(program, vesting)?;
+
+        Ok(Self { entity })
+    }
+}
+
+#[wasm_bindgen]
+impl VestingEntity {
+    /// Loads vesting from an account fetched with `ApiClient`.
+    pub fn from_account(program: Pk, account: JsValue) -> Result<VestingEntity, JsValue> {
+        let account: Account = account.into_serde().into_js_value()?;
+
+        Self::load(program.as_ref(), Box::new(account))
+            .map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+    }
+
+    pub fn total_amount(&self) -> u64 {
+        self.entity.total_amount.value()
+    }
+
+    pub fn claimed_amount(&self) -> u64 {
+        self.entity.claimed_amount.value()
+    }
+
+    pub fn start_date(&self) -> i64 {
+        self.entity.start_date.into()
+    }
+
+    pub fn cliff_date(&self) -> i64 {
+        self.entity.cliff_date.into()
+    }
+
+    pub fn end_date(&self) -> i64 {
+        self.entity.end_date.into()
+    }
+
+    pub fn period(&self) -> i64 {
+        self.entity.period.into()
+    }
+
+    pub fn vested_amount(&self, now: i64) -> u64 {
+        self.entity.vested_amount(now.into()).value()
+    }
+
+    pub fn claimable(&self, now: i64) -> u64 {
+        self.entity.claimable(now.into()).value()
+    }
+
+    pub fn is_revocable(&self) -> bool {
+        self.entity.is_revocable()
+    }
+
+    pub fn claim(&self, destination_wallet: Pk) -> Instr {
+        self.entity
+            .claim_instruction(destination_wallet.to_pubkey())
+            .into()
+    }
+
+    pub fn revoke(&self, treasury_wallet: Pk) -> Instr {
+        self.entity
+            .revoke_instruction(treasury_wallet.to_pubkey())
+            .into()
+    }
+}
+
 #[wasm_bindgen]
 #[derive(Clone)]
 pub struct Instr(Instruction);
@@ -1020,15 +1645,11 @@ pub struct ProgramAuthority {
 }
 
 impl ProgramAuthority {
-    pub fn new(key: Pk, administrator_key: Pk, program_id: Pk) -> Self {
+    pub fn new(key: Pk, program_id: Pk) -> Self {
         let mut salt: u64 = 0;
         let pk = loop {
             let pk = Pubkey::create_program_address(
-                &[
-                    key.as_ref().as_ref(),
-                    administrator_key.as_ref().as_ref(),
-                    &salt.to_le_bytes(),
-                ],
+                &[key.as_ref().as_ref(), &salt.to_le_bytes()],
                 program_id.as_ref(),
             );
 
@@ -1050,6 +1671,15 @@ pub struct CreatePoolArgs {
     topup_duration: i64,
     reward_amount: u64,
     target_amount: u64,
+    emission: bool,
+    early_exit_penalty_bps: u16,
+    early_exit_treasury: Option<Pubkey>,
+    min_stake: u64,
+    max_stake_per_ticket: u64,
+    allowlist_root: Option<[u8; 32]>,
+    fee_bps: u16,
+    fee_wallet: Option<Pubkey>,
+    share_mint: Option<Pubkey>,
 }
 
 #[wasm_bindgen]
@@ -1059,13 +1689,61 @@ impl CreatePoolArgs {
         topup_duration: i64,
         reward_amount: u64,
         target_amount: u64,
+        emission: bool,
+        early_exit_penalty_bps: u16,
     ) -> Self {
         Self {
             lockup_duration,
             topup_duration,
             reward_amount,
             target_amount,
+            emission,
+            early_exit_penalty_bps,
+            early_exit_treasury: None,
+            min_stake: 0,
+            max_stake_per_ticket: 0,
+            allowlist_root: None,
+            fee_bps: 0,
+            fee_wallet: None,
+            share_mint: None,
+        }
+    }
+
+    /// Withholds `fee_bps` of every reward share, sending it to `fee_wallet` in reward mint.
+    pub fn set_fee(&mut self, fee_bps: u16, fee_wallet: Pk) {
+        self.fee_bps = fee_bps;
+        self.fee_wallet = Some(fee_wallet.to_pubkey());
+    }
+
+    /// Limits stake of every ticket, zero `max_stake_per_ticket` means no upper limit.
+    pub fn set_stake_limits(&mut self, min_stake: u64, max_stake_per_ticket: u64) {
+        self.min_stake = min_stake;
+        self.max_stake_per_ticket = max_stake_per_ticket;
+    }
+
+    /// Restricts staking to the allowlist with the given 32-byte Merkle root.
+    pub fn set_allowlist_root(&mut self, root: Vec<u8>) -> Result<(), JsValue> {
+        if root.len() != 32 {
+            return Err(JsValue::from_str("allowlist root should be 32 bytes long"));
         }
+
+        let mut buf = [0; 32];
+        buf.copy_from_slice(&root);
+        self.allowlist_root = Some(buf);
+
+        Ok(())
+    }
+
+    /// Sends early exit penalties to `treasury` instead of adding them to the reward.
+    pub fn set_early_exit_treasury(&mut self, treasury: Pk) {
+        self.early_exit_treasury = Some(treasury.to_pubkey());
+    }
+
+    /// Makes the pool liquid, minting `share_mint` tokens to stakers in place of tickets.
+    ///
+    /// Mint authority of `share_mint` should be the program authority of the pool.
+    pub fn set_share_mint(&mut self, share_mint: Pk) {
+        self.share_mint = Some(share_mint.to_pubkey());
     }
 }
 
@@ -1078,7 +1756,7 @@ pub fn create_pool_instruction(
     stake_vault_key: Pk,
     program_id: Pk,
 ) -> Instr {
-    let authority = ProgramAuthority::new(pool_key, administrator_key, program_id);
+    let authority = ProgramAuthority::new(pool_key, program_id);
     Instruction {
         program_id: program_id.to_pubkey(),
         accounts: vec![
@@ -1087,7 +1765,13 @@ pub fn create_pool_instruction(
             AccountMeta::new(pool_key.to_pubkey(), false),
             AccountMeta::new_readonly(stake_mint_key.to_pubkey(), false),
             AccountMeta::new_readonly(stake_vault_key.to_pubkey(), false),
-        ],
+        ]
+        .into_iter()
+        .chain(
+            args.share_mint
+                .map(|mint| AccountMeta::new_readonly(mint, false)),
+        )
+        .collect(),
         data: x5margin_program::Method::Simple(x5margin_program::simple_stake::Method::CreatePool(
             x5margin_program::simple_stake::InitializeArgs {
                 program_authority_salt: authority.salt,
@@ -1095,6 +1779,19 @@ pub fn create_pool_instruction(
                 topup_duration: args.topup_duration.into(),
                 reward_amount: args.reward_amount.into(),
                 target_amount: args.target_amount.into(),
+                reward_mode: if args.emission {
+                    x5margin_program::simple_stake::RewardMode::Emission
+                } else {
+                    x5margin_program::simple_stake::RewardMode::LumpSum
+                },
+                early_exit_penalty_bps: args.early_exit_penalty_bps,
+                early_exit_treasury: args.early_exit_treasury,
+                min_stake: args.min_stake.into(),
+                max_stake_per_ticket: args.max_stake_per_ticket.into(),
+                allowlist_root: args.allowlist_root,
+                fee_bps: args.fee_bps,
+                fee_wallet: args.fee_wallet,
+                liquid: args.share_mint.is_some(),
             },
         ))
         .encode(),
@@ -1102,41 +1799,258 @@ pub fn create_pool_instruction(
     .into()
 }
 
-/*
 #[wasm_bindgen]
 pub struct LockerInstructionBuilder {
     program_id: Pk,
     locker: Pk,
-    administrator_key: Pk,
-    authority: ProgramAuthority,
 }
 
 #[wasm_bindgen]
 impl LockerInstructionBuilder {
-    pub fn new(program_id: Pk, locker: Pk, administrator_key: Pk) -> Self {
-        Self {
-            program_id,
-            locker,
-            administrator_key,
-            authority: ProgramAuthority::new(locker, administrator_key, program_id),
-        }
+    pub fn new(program_id: Pk, locker: Pk) -> Self {
+        Self { program_id, locker }
     }
 
-    pub fn create_token_lock(&self, unlock_date: i64, amount: u64) -> Instr {
-        Instruction {
-            program_id: self.program_id.to_pubkey(),
-            accounts: vec![
-                AccountMeta::new_readonly(self.administrator_key.to_pubkey(), false),
-                AccountMeta::new_readonly(self.locker.to_pubkey(), false),
-                AccountMeta::new(self.locker.to_pubkey(), false),
-                // TODO
-            ],
-            data: todo!(),
-        }
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_token_lock(
+        &self,
+        vault: Pk,
+        owner: Pk,
+        source_wallet: Pk,
+        source_mint: Pk,
+        source_authority: Pk,
+        unlock_date: i64,
+        amount: u64,
+    ) -> Instructions {
+        self.create(
+            vault,
+            owner,
+            source_wallet,
+            source_mint,
+            source_authority,
+            unlock_date,
+            amount,
+            None,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_revocable_token_lock(
+        &self,
+        vault: Pk,
+        owner: Pk,
+        source_wallet: Pk,
+        source_mint: Pk,
+        source_authority: Pk,
+        unlock_date: i64,
+        amount: u64,
+        revoke_authority: Pk,
+    ) -> Instructions {
+        self.create(
+            vault,
+            owner,
+            source_wallet,
+            source_mint,
+            source_authority,
+            unlock_date,
+            amount,
+            Some(revoke_authority.to_pubkey()),
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_token_lock_with_receipt(
+        &self,
+        vault: Pk,
+        receipt_mint: Pk,
+        receipt_wallet: Pk,
+        owner: Pk,
+        source_wallet: Pk,
+        source_mint: Pk,
+        source_authority: Pk,
+        unlock_date: i64,
+        amount: u64,
+    ) -> Instructions {
+        token_locker::TokenLockEntity::<Box<Account>>::create_with_receipt_instruction(
+            self.program_id.to_pubkey(),
+            self.locker.to_pubkey(),
+            vault.to_pubkey(),
+            receipt_mint.to_pubkey(),
+            receipt_wallet.to_pubkey(),
+            owner.to_pubkey(),
+            source_wallet.to_pubkey(),
+            source_mint.to_pubkey(),
+            source_authority.to_pubkey(),
+            unlock_date.into(),
+            amount.into(),
+            None,
+        )
         .into()
     }
 }
-*/
+
+impl LockerInstructionBuilder {
+    #[allow(clippy::too_many_arguments)]
+    fn create(
+        &self,
+        vault: Pk,
+        owner: Pk,
+        source_wallet: Pk,
+        source_mint: Pk,
+        source_authority: Pk,
+        unlock_date: i64,
+        amount: u64,
+        revoke_authority: Option<Pubkey>,
+    ) -> Instructions {
+        token_locker::TokenLockEntity::<Box<Account>>::create_instruction(
+            self.program_id.to_pubkey(),
+            self.locker.to_pubkey(),
+            vault.to_pubkey(),
+            owner.to_pubkey(),
+            source_wallet.to_pubkey(),
+            source_mint.to_pubkey(),
+            source_authority.to_pubkey(),
+            unlock_date.into(),
+            amount.into(),
+            revoke_authority,
+        )
+        .into()
+    }
+}
+
+#[wasm_bindgen]
+pub struct LockerEntity {
+    entity: token_locker::TokenLockEntity<Box<Account>>,
+}
+
+impl Serialize for LockerEntity {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        self.entity.account().serialize(serializer)
+    }
+}
+
+impl LockerEntity {
+    pub fn load(
+        program: &Pubkey,
+        locker: Box<Account>,
+    ) -> Result<Self, token_locker::error::Error> {
+        let entity = token_locker::TokenLockEntity::load(program, locker)?;
+
+        Ok(Self { entity })
+    }
+}
+
+#[wasm_bindgen]
+impl LockerEntity {
+    /// Loads locker from an account fetched with `ApiClient`.
+    pub fn from_account(program: Pk, account: JsValue) -> Result<LockerEntity, JsValue> {
+        let account: Account = account.into_serde().into_js_value()?;
+
+        Self::load(program.as_ref(), Box::new(account))
+            .map_err(|err| JsValue::from_str(&format!("{:?}", err)))
+    }
+
+    pub fn relock(&self, unlock_date: i64) -> Instr {
+        self.entity.relock_instruction(unlock_date.into()).into()
+    }
+
+    pub fn withdraw(&self, destination_wallet: Pk, amount: u64) -> Instr {
+        self.entity
+            .withdraw_instruction(destination_wallet.to_pubkey(), amount.into())
+            .into()
+    }
+
+    pub fn relock_with_receipt(&self, holder: Pk, receipt_wallet: Pk, unlock_date: i64) -> Instr {
+        self.entity
+            .relock_with_receipt_instruction(
+                holder.to_pubkey(),
+                receipt_wallet.to_pubkey(),
+                unlock_date.into(),
+            )
+            .into()
+    }
+
+    pub fn withdraw_with_receipt(
+        &self,
+        holder: Pk,
+        receipt_wallet: Pk,
+        destination_wallet: Pk,
+        amount: u64,
+    ) -> Instr {
+        self.entity
+            .withdraw_with_receipt_instruction(
+                holder.to_pubkey(),
+                receipt_wallet.to_pubkey(),
+                destination_wallet.to_pubkey(),
+                amount.into(),
+            )
+            .into()
+    }
+
+    pub fn increment(&self, source_wallet: Pk, source_authority: Pk, amount: u64) -> Instr {
+        self.entity
+            .increment_instruction(
+                source_wallet.to_pubkey(),
+                source_authority.to_pubkey(),
+                amount.into(),
+            )
+            .into()
+    }
+
+    pub fn split(&self, payer: Pk, new_locker: Pk, new_vault: Pk, amount: u64) -> Instructions {
+        self.entity
+            .split_instruction(
+                payer.to_pubkey(),
+                new_locker.to_pubkey(),
+                new_vault.to_pubkey(),
+                amount.into(),
+            )
+            .into()
+    }
+
+    pub fn merge(&self, source: &LockerEntity) -> Instr {
+        self.entity.merge_instruction(&source.entity).into()
+    }
+
+    pub fn revoke(&self, treasury_wallet: Pk) -> Instr {
+        self.entity
+            .revoke_instruction(treasury_wallet.to_pubkey())
+            .into()
+    }
+
+    pub fn change_owner(&self, new_owner: Pk) -> Instr {
+        self.entity
+            .change_owner_instruction(new_owner.to_pubkey())
+            .into()
+    }
+
+    pub fn owner(&self) -> Pk {
+        Pk(self.entity.owner)
+    }
+
+    pub fn mint(&self) -> Pk {
+        Pk(self.entity.mint)
+    }
+
+    pub fn vault(&self) -> Pk {
+        Pk(self.entity.vault)
+    }
+
+    pub fn release_date(&self) -> i64 {
+        self.entity.release_date.into()
+    }
+
+    pub fn receipt_mint(&self) -> Option<Pk> {
+        if self.entity.has_receipt() {
+            Some(Pk(self.entity.receipt_mint))
+        } else {
+            None
+        }
+    }
+}
 
 #[wasm_bindgen]
 pub struct Hash(solana_api_types::Hash);